@@ -119,8 +119,8 @@ framebuffer_ignore_alpha=1
 disable_overscan=1
 EOF
 
-cat >"$OUT_CMDLINE" <<'EOF'
-EOF
+# Kernel command line (docs/params.md); the firmware adds its own arguments.
+printf '%s\n' "${KERNEL_ARGS:-}" >"$OUT_CMDLINE"
 
 if [ "$USE_MTOOLS" -eq 0 ]; then
   STAGE_DIR="$(mktemp -d)"
@@ -8,6 +8,11 @@ DTB="${DTB_PATH:-$DTB_DEFAULT}"
 QEMU_RAM="${QEMU_RAM:-1G}"
 QEMU_LOG="${QEMU_LOG:-mmu,int}"
 QEMU_LOG_FILE="${QEMU_LOG_FILE:-$ROOT_DIR/qemu.log}"
+# Kernel command line, e.g. KERNEL_ARGS="loglevel=8 maxcpus=1" (docs/params.md).
+APPEND=()
+if [ -n "${KERNEL_ARGS:-}" ]; then
+  APPEND=(-append "$KERNEL_ARGS")
+fi
 : > "$QEMU_LOG_FILE"
 if [ "${SKIP_BUILD:-0}" != "1" ]; then
   "$ROOT_DIR/scripts/build-qemu.sh"
@@ -25,6 +30,7 @@ qemu-system-aarch64 \
   -smp 4 \
   -kernel "$KERNEL" \
   -dtb "$DTB" \
+  ${APPEND[@]+"${APPEND[@]}"} \
   -d "$QEMU_LOG" \
   -D "$QEMU_LOG_FILE" \
   -serial stdio \
@@ -1,6 +1,34 @@
 use core::arch::asm;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::driver;
+use crate::drivers::device::{Device, Driver, ProbeError};
 
 static mut TICK_TICKS: u64 = 0;
+static mut TICK_MS: u64 = 0;
+
+// Interrupt of the non-secure physical timer, as its controller numbers it;
+// u32::MAX until the timer node is probed.
+static TIMER_IRQ: AtomicU32 = AtomicU32::new(u32::MAX);
+
+driver!(static ARCH_TIMER = Driver {
+    name: "arch-timer",
+    compatible: &["arm,armv8-timer", "arm,armv7-timer"],
+    probe,
+});
+
+fn probe(dev: Device) -> Result<(), ProbeError> {
+    // Interrupts are listed secure, non-secure, virtual, hypervisor; the
+    // tick runs on the non-secure physical timer.
+    let irq = dev.irq(1)?;
+    TIMER_IRQ.store(irq.number, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn irq() -> Option<u32> {
+    let irq = TIMER_IRQ.load(Ordering::Relaxed);
+    (irq != u32::MAX).then_some(irq)
+}
 
 #[inline(always)]
 fn counter() -> u64 {
@@ -24,6 +52,7 @@ pub fn init_tick(ms: u64) {
     // Program the per-core timer tick interval.
     let ticks = (frequency() * ms) / 1000;
     unsafe {
+        TICK_MS = ms;
         TICK_TICKS = ticks.max(1);
         set_timer(TICK_TICKS);
     }
@@ -49,6 +78,28 @@ unsafe fn set_timer(ticks: u64) {
     );
 }
 
+pub fn tick_ms() -> u64 {
+    unsafe { TICK_MS }
+}
+
+pub fn uptime_ms() -> u64 {
+    // Milliseconds since the counter started (reset at power-on).
+    let freq = frequency();
+    if freq == 0 {
+        return 0;
+    }
+    ((counter() as u128 * 1000) / freq as u128) as u64
+}
+
+pub fn uptime_us() -> u64 {
+    // Microseconds since the counter started; usable before `init_tick`.
+    let freq = frequency();
+    if freq == 0 {
+        return 0;
+    }
+    ((counter() as u128 * 1_000_000) / freq as u128) as u64
+}
+
 pub fn delay_ms(ms: u64) {
     // Busy-wait delay for early boot or polling loops.
     let ticks = (frequency() * ms) / 1000;
@@ -1,8 +1,13 @@
+pub mod block;
+pub mod console;
+pub mod device;
 pub mod framebuffer;
 pub mod keyboard;
 pub mod local_intc;
 #[cfg(feature = "rpi5")]
 pub mod gic;
 pub mod mailbox;
+pub mod memdev;
 pub mod mmio;
+pub mod ramdisk;
 pub mod uart;
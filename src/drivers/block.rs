@@ -0,0 +1,79 @@
+use alloc::vec::Vec;
+
+use crate::kernel::vfs::devfs::DevNum;
+use crate::util::sync::SpinLock;
+
+pub type DeviceId = u32;
+
+pub const MAX_BLOCK_DEVICES: usize = 8;
+
+// Well-known block major numbers (Linux values).
+pub const MAJOR_RAMDISK: u32 = 1;
+
+// A device of fixed-size blocks. The rest of the kernel reaches it through
+// the page cache (`pagecache::read_device`/`write_device`), which keeps one
+// block per page, so blocks are at most a page.
+pub trait BlockDevice: Sync {
+    fn block_size(&self) -> usize;
+    fn block_count(&self) -> u64;
+    fn read_block(&self, block: u64, buf: &mut [u8]) -> bool;
+    fn write_block(&self, block: u64, buf: &[u8]) -> bool;
+}
+
+// A registered device, published as /dev/<name>.
+#[derive(Copy, Clone)]
+pub struct BlockNode {
+    pub name: &'static str,
+    pub dev: DevNum,
+    pub driver: &'static dyn BlockDevice,
+}
+
+impl BlockNode {
+    pub fn size(&self) -> u64 {
+        self.driver.block_count() * self.driver.block_size() as u64
+    }
+}
+
+static DEVICES: SpinLock<[Option<BlockNode>; MAX_BLOCK_DEVICES]> =
+    SpinLock::new([None; MAX_BLOCK_DEVICES]);
+
+pub fn register(
+    name: &'static str,
+    dev: DevNum,
+    driver: &'static dyn BlockDevice,
+) -> Option<DeviceId> {
+    // Claim the first free slot; the slot index is the device ID the page
+    // cache keys its blocks by. Names and numbers must be unique.
+    let mut devices = DEVICES.lock();
+    if devices
+        .iter()
+        .flatten()
+        .any(|node| node.name == name || node.dev == dev)
+    {
+        return None;
+    }
+    let idx = devices.iter().position(|slot| slot.is_none())?;
+    devices[idx] = Some(BlockNode { name, dev, driver });
+    Some(idx as DeviceId)
+}
+
+pub fn get(id: DeviceId) -> Option<BlockNode> {
+    DEVICES.lock().get(id as usize).copied().flatten()
+}
+
+pub fn lookup(name: &[u8]) -> Option<DeviceId> {
+    DEVICES
+        .lock()
+        .iter()
+        .position(|slot| slot.is_some_and(|node| node.name.as_bytes() == name))
+        .map(|idx| idx as DeviceId)
+}
+
+pub fn list() -> Vec<(DeviceId, BlockNode)> {
+    let devices = *DEVICES.lock();
+    devices
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, node)| node.map(|node| (idx as DeviceId, node)))
+        .collect()
+}
@@ -0,0 +1,238 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::drivers::framebuffer::{self, MAX_VTS};
+use crate::drivers::{keyboard, uart};
+use crate::kernel::klog;
+use crate::kernel::process::{ProcessId, WaitQueue};
+use crate::kernel::tty::{self, TtyDriver, WinSize};
+use crate::kernel::vfs::devfs::{self, CharDevice, DevNum, MAJOR_TTY, MAJOR_TTYAUX};
+use crate::kernel::vfs::file::FileId;
+use crate::kernel::vfs::{VfsError, VfsResult};
+use crate::util::sync::SpinLock;
+
+const MINOR_CONSOLE: u32 = 1;
+
+// ralix-specific: load a PSF1/PSF2 font on every virtual console. `arg`
+// points at a `FontImage` holding the font file.
+pub const KDSETPSF: u64 = 0x4B80;
+// Text or graphics mode of the display (Linux values). KDSETMODE takes the
+// mode as `arg`; KDGETMODE stores it as an int at `arg`.
+pub const KDSETMODE: u64 = 0x4B3A;
+pub const KDGETMODE: u64 = 0x4B3B;
+pub const KD_TEXT: u64 = 0;
+pub const KD_GRAPHICS: u64 = 1;
+// Font files are small; this bounds what the kernel copies in.
+const MAX_FONT_SIZE: usize = 256 * 1024;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct FontImage {
+    pub data: *const u8,
+    pub len: usize,
+}
+
+const VT_NAMES: [&str; MAX_VTS] = ["tty1", "tty2", "tty3", "tty4", "tty5", "tty6"];
+
+// Index of the virtual console that owns the screen and the keyboard.
+static ACTIVE_VT: AtomicUsize = AtomicUsize::new(0);
+// Readers of background consoles, woken when one of them comes to the front.
+static SWITCH_WAIT: SpinLock<WaitQueue> = SpinLock::new(WaitQueue::new());
+// The open console file that put the display in graphics mode. Closing it
+// brings the consoles back, so they never wait on a program that has let go
+// of the display.
+static GRAPHICS_OWNER: SpinLock<Option<FileId>> = SpinLock::new(None);
+
+// A virtual console: keyboard in while it is in front, its framebuffer
+// console (or the UART) out, with the TTY line discipline in between.
+struct VtTty {
+    vt: usize,
+}
+
+static VT_TTYS: [VtTty; MAX_VTS] = [
+    VtTty { vt: 0 },
+    VtTty { vt: 1 },
+    VtTty { vt: 2 },
+    VtTty { vt: 3 },
+    VtTty { vt: 4 },
+    VtTty { vt: 5 },
+];
+
+// /dev/console: opening it lands on the `console=tty<n>` console, or on
+// the one in front for tty0 or no choice, as on Linux.
+struct ConsoleAlias;
+
+static CONSOLE_ALIAS: ConsoleAlias = ConsoleAlias;
+
+impl TtyDriver for VtTty {
+    fn write(&self, _line: usize, buf: &[u8]) {
+        write_vt(self.vt, buf);
+    }
+
+    fn receive(&self, _line: usize, buf: &mut [u8]) -> usize {
+        if !owns_keyboard(self.vt) {
+            return 0;
+        }
+        keyboard::read(buf)
+    }
+
+    fn input_ready(&self, _line: usize, waiter: Option<ProcessId>) -> bool {
+        // Checked under SWITCH_WAIT so a switch cannot slip in unnoticed.
+        let mut switch_wait = SWITCH_WAIT.lock();
+        if !owns_keyboard(self.vt) {
+            if let Some(pid) = waiter {
+                switch_wait.add(pid);
+            }
+            return false;
+        }
+        drop(switch_wait);
+        keyboard::readable(waiter)
+    }
+
+    fn close(&self, _line: usize, file: FileId) {
+        if *GRAPHICS_OWNER.lock() == Some(file) {
+            let _ = set_mode(file, KD_TEXT);
+        }
+    }
+
+    fn ioctl(&self, _line: usize, file: FileId, cmd: u64, arg: u64) -> VfsResult<u64> {
+        match cmd {
+            KDSETMODE => return set_mode(file, arg),
+            KDGETMODE => {
+                if arg == 0 {
+                    return Err(VfsError::BadAddress);
+                }
+                let mode = if framebuffer::graphics() {
+                    KD_GRAPHICS
+                } else {
+                    KD_TEXT
+                };
+                unsafe { (arg as *mut i32).write_unaligned(mode as i32) };
+                return Ok(0);
+            }
+            KDSETPSF => {
+                if arg == 0 {
+                    return Err(VfsError::BadAddress);
+                }
+            }
+            _ => return Err(VfsError::NotTty),
+        }
+        let image = unsafe { (arg as *const FontImage).read_unaligned() };
+        if image.data.is_null() || image.len > MAX_FONT_SIZE {
+            return Err(VfsError::InvalidArgument);
+        }
+        let bytes = unsafe { core::slice::from_raw_parts(image.data, image.len) };
+        framebuffer::load_font(bytes)?;
+        Ok(0)
+    }
+
+    fn winsize(&self, _line: usize) -> WinSize {
+        // This console's own size, in front or not; 80x24 on the UART.
+        let mut size = (80, 24);
+        framebuffer::with_vt(self.vt, |console| size = console.size());
+        WinSize {
+            row: size.1 as u16,
+            col: size.0 as u16,
+            ..WinSize::default()
+        }
+    }
+}
+
+impl CharDevice for ConsoleAlias {
+    fn open(&self, _minor: u32) -> VfsResult<Option<DevNum>> {
+        let vt = match klog::console_vt() {
+            0 => ACTIVE_VT.load(Ordering::Acquire) + 1,
+            vt => vt,
+        };
+        devfs::open(DevNum::new(MAJOR_TTY, vt as u32)).map(Some)
+    }
+
+    // Never reached: opening /dev/console always ends up on a ttyN.
+    fn read(&self, _minor: u32, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::NoDevice)
+    }
+
+    fn write(&self, _minor: u32, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::NoDevice)
+    }
+}
+
+fn owns_keyboard(vt: usize) -> bool {
+    // In graphics mode the keyboard belongs to whoever reads /dev/kbd0,
+    // such as the display server.
+    ACTIVE_VT.load(Ordering::Acquire) == vt && !framebuffer::graphics()
+}
+
+fn write_vt(vt: usize, buf: &[u8]) {
+    // Without a framebuffer the UART stands in for whichever console is in
+    // front; output to the others is dropped. Bytes go out as given; the
+    // TTY has already done CRLF translation.
+    let drawn = framebuffer::with_vt(vt, |console| {
+        for &b in buf {
+            console.write_byte(b);
+        }
+    });
+    if !drawn && ACTIVE_VT.load(Ordering::Acquire) == vt {
+        for &b in buf {
+            uart::write_byte(b);
+        }
+    }
+}
+
+pub fn switch_vt(vt: usize) -> bool {
+    // Bring `vt` to the front. Called from the timer interrupt, so it only
+    // tries the locks and fails if either is busy; the keyboard retries on
+    // its next poll.
+    if vt >= MAX_VTS {
+        return true;
+    }
+    let Some(mut switch_wait) = SWITCH_WAIT.try_lock() else {
+        return false;
+    };
+    if !framebuffer::show(vt) {
+        return false;
+    }
+    ACTIVE_VT.store(vt, Ordering::Release);
+    let waiters = switch_wait.take();
+    drop(switch_wait);
+    waiters.wake_all();
+    true
+}
+
+fn set_mode(file: FileId, mode: u64) -> VfsResult<u64> {
+    // The mode belongs to the display, not to one console. Console readers
+    // wait out graphics mode like a background console.
+    let graphics = match mode {
+        KD_TEXT => false,
+        KD_GRAPHICS => true,
+        _ => return Err(VfsError::InvalidArgument),
+    };
+    let mut switch_wait = SWITCH_WAIT.lock();
+    if !framebuffer::set_graphics(graphics) {
+        return Err(VfsError::NoDevice);
+    }
+    *GRAPHICS_OWNER.lock() = graphics.then_some(file);
+    let waiters = switch_wait.take();
+    drop(switch_wait);
+    waiters.wake_all();
+    Ok(0)
+}
+
+pub fn scroll(dir: isize) {
+    // Scroll the console in front by half a screen into its scrollback.
+    framebuffer::try_with_console(|console| {
+        let half = (console.size().1 / 2).max(1) as isize;
+        console.scroll_view(dir * half);
+    });
+}
+
+pub fn register_devices() {
+    for (name, tty) in VT_NAMES.iter().zip(VT_TTYS.iter()) {
+        let minor = tty.vt as u32 + 1;
+        tty::register(name, DevNum::new(MAJOR_TTY, minor), tty);
+    }
+    devfs::register_char(
+        "console",
+        DevNum::new(MAJOR_TTYAUX, MINOR_CONSOLE),
+        &CONSOLE_ALIAS,
+    );
+}
@@ -0,0 +1,374 @@
+// Device tree driven driver model. Every enabled node with a `compatible`
+// becomes a device under the nearest ancestor that is one. Drivers declared
+// with `driver!` are matched against `compatible` and probed with the node's
+// resources; a probe missing another device is deferred and retried once
+// something else has bound (docs/drivers.md).
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::mm::devtree::{self, Interrupt, Node};
+use crate::mm::dtb;
+use crate::util::sync::SpinLock;
+use crate::{kdebug, kinfo, kwarn};
+
+pub struct Driver {
+    pub name: &'static str,
+    // Compatible strings this driver handles.
+    pub compatible: &'static [&'static str],
+    pub probe: fn(Device) -> Result<(), ProbeError>,
+}
+
+#[macro_export]
+macro_rules! driver {
+    ($vis:vis static $ident:ident = $driver:expr) => {
+        #[used]
+        #[link_section = ".kdrivers"]
+        $vis static $ident: $crate::drivers::device::Driver = $driver;
+    };
+}
+
+// Bounds of the .kdrivers section (linker.ld).
+extern "C" {
+    static __kdrivers_start: u8;
+    static __kdrivers_end: u8;
+}
+
+fn drivers() -> &'static [Driver] {
+    unsafe {
+        let start = core::ptr::addr_of!(__kdrivers_start) as usize;
+        let end = core::ptr::addr_of!(__kdrivers_end) as usize;
+        let count = (end - start) / core::mem::size_of::<Driver>();
+        core::slice::from_raw_parts(start as *const Driver, count)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProbeError {
+    // A device this one needs is not bound yet; probe again later.
+    Defer,
+    // The node lacks a resource the driver needs, or its supplier failed.
+    NoResource,
+    // The driver does not handle this instance.
+    Unsupported,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum State {
+    NoDriver,
+    // Matched but not probed yet.
+    Pending,
+    Deferred,
+    Bound,
+    Failed(ProbeError),
+}
+
+impl State {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            State::NoDriver => "no driver",
+            State::Pending => "pending",
+            State::Deferred => "deferred",
+            State::Bound => "bound",
+            State::Failed(ProbeError::Unsupported) => "unsupported",
+            State::Failed(_) => "failed",
+        }
+    }
+}
+
+struct DeviceRec {
+    node: Node,
+    // Node name, with ".N" added if a sibling device has the same one.
+    name: String,
+    parent: Option<usize>,
+    driver: Option<&'static Driver>,
+    state: State,
+}
+
+// Filled once by `init`; entries are never removed.
+static DEVICES: SpinLock<Vec<DeviceRec>> = SpinLock::new(Vec::new());
+
+// Handle to a device, by its index in the device list.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Device(usize);
+
+#[derive(Copy, Clone, Debug)]
+pub struct Mmio {
+    pub base: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Irq {
+    // Interrupt number as the controller's driver numbers it.
+    pub number: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Clock {
+    // Rate of a fixed clock; None when only the provider's driver knows it.
+    pub rate: Option<u32>,
+}
+
+pub fn init() -> bool {
+    // Create a device for every enabled node with a `compatible` (the root
+    // describes the board, not a device), then probe the matched ones.
+    // False without a device tree.
+    let Some(root) = devtree::root() else {
+        return false;
+    };
+    {
+        let mut devices = DEVICES.lock();
+        for node in devtree::all_nodes() {
+            if node == root || !node.has(b"compatible") || !node.is_available() {
+                continue;
+            }
+            let parent = find_parent(&devices, node);
+            let name = unique_name(&devices, parent, node.name());
+            let driver = match_driver(node);
+            devices.push(DeviceRec {
+                node,
+                name,
+                parent,
+                driver,
+                state: if driver.is_some() {
+                    State::Pending
+                } else {
+                    State::NoDriver
+                },
+            });
+        }
+        kinfo!("device: {} devices", devices.len());
+    }
+    probe_all();
+    true
+}
+
+fn find_parent(devices: &[DeviceRec], node: Node) -> Option<usize> {
+    // Nearest ancestor that is a device. Ancestors come first in blob order.
+    let mut cur = node.parent();
+    while let Some(ancestor) = cur {
+        if let Some(index) = devices.iter().rposition(|dev| dev.node == ancestor) {
+            return Some(index);
+        }
+        cur = ancestor.parent();
+    }
+    None
+}
+
+fn unique_name(devices: &[DeviceRec], parent: Option<usize>, name: &[u8]) -> String {
+    let mut name = String::from(core::str::from_utf8(name).unwrap_or("?"));
+    let taken = |name: &str| {
+        devices
+            .iter()
+            .any(|dev| dev.parent == parent && dev.name == name)
+    };
+    if taken(&name) {
+        let base = name.clone();
+        let mut n = 1;
+        loop {
+            name = alloc::format!("{}.{}", base, n);
+            if !taken(&name) {
+                break;
+            }
+            n += 1;
+        }
+    }
+    name
+}
+
+fn match_driver(node: Node) -> Option<&'static Driver> {
+    // The node lists its compatible strings most specific first.
+    node.strings(b"compatible").find_map(|compat| {
+        drivers()
+            .iter()
+            .find(|driver| driver.compatible.iter().any(|c| c.as_bytes() == compat))
+    })
+}
+
+fn probe_all() {
+    // Probe every pending device, then retry the deferred ones for as long
+    // as some probe succeeds.
+    loop {
+        let mut bound = 0;
+        let mut index = 0;
+        while let Some((dev, driver)) = next_to_probe(&mut index) {
+            // The lock is not held here: probes look up other devices.
+            let result = (driver.probe)(dev);
+            let state = match result {
+                Ok(()) => State::Bound,
+                Err(ProbeError::Defer) => State::Deferred,
+                Err(err) => State::Failed(err),
+            };
+            DEVICES.lock()[dev.0].state = state;
+            let path = dev.node().path();
+            match result {
+                Ok(()) => {
+                    bound += 1;
+                    kinfo!("device: {} bound to {}", path, driver.name);
+                }
+                Err(ProbeError::Defer) => kdebug!("device: {} deferred", path),
+                Err(ProbeError::Unsupported) => {
+                    kdebug!("device: {} not handled by {}", path, driver.name)
+                }
+                Err(err) => kwarn!(
+                    "device: {} probe by {} failed: {:?}",
+                    path,
+                    driver.name,
+                    err
+                ),
+            }
+        }
+        if bound == 0 {
+            break;
+        }
+    }
+    for dev in all() {
+        if dev.state() == State::Deferred {
+            kwarn!("device: {} still waiting for a supplier", dev.node().path());
+        }
+    }
+}
+
+fn next_to_probe(index: &mut usize) -> Option<(Device, &'static Driver)> {
+    // Next device at or after `index` that is pending or deferred.
+    let devices = DEVICES.lock();
+    while *index < devices.len() {
+        let i = *index;
+        *index += 1;
+        let rec = &devices[i];
+        if matches!(rec.state, State::Pending | State::Deferred) {
+            return Some((Device(i), rec.driver?));
+        }
+    }
+    None
+}
+
+pub fn all() -> impl Iterator<Item = Device> {
+    (0..DEVICES.lock().len()).map(Device)
+}
+
+pub fn find(node: Node) -> Option<Device> {
+    DEVICES
+        .lock()
+        .iter()
+        .position(|dev| dev.node == node)
+        .map(Device)
+}
+
+pub fn children(parent: Option<Device>) -> Vec<Device> {
+    // Devices directly under `parent`, or the top-level ones for None.
+    let parent = parent.map(|dev| dev.0);
+    DEVICES
+        .lock()
+        .iter()
+        .enumerate()
+        .filter(|(_, dev)| dev.parent == parent)
+        .map(|(index, _)| Device(index))
+        .collect()
+}
+
+impl Device {
+    pub fn index(self) -> usize {
+        self.0
+    }
+
+    pub fn node(self) -> Node {
+        DEVICES.lock()[self.0].node
+    }
+
+    pub fn name(self) -> String {
+        DEVICES.lock()[self.0].name.clone()
+    }
+
+    pub fn parent(self) -> Option<Device> {
+        DEVICES.lock()[self.0].parent.map(Device)
+    }
+
+    pub fn driver_name(self) -> Option<&'static str> {
+        DEVICES.lock()[self.0].driver.map(|driver| driver.name)
+    }
+
+    pub fn state(self) -> State {
+        DEVICES.lock()[self.0].state
+    }
+
+    pub fn mmio(self, index: usize) -> Result<Mmio, ProbeError> {
+        // Start of `reg` entry `index` as a CPU physical address.
+        let (base, _) = dtb::phys_reg(self.node(), index).ok_or(ProbeError::NoResource)?;
+        Ok(Mmio {
+            base: base as usize,
+        })
+    }
+
+    pub fn irq(self, index: usize) -> Result<Irq, ProbeError> {
+        // Interrupt `index`; defers until its controller is bound.
+        let spec = self
+            .node()
+            .interrupts()
+            .nth(index)
+            .ok_or(ProbeError::NoResource)?;
+        require(spec.controller)?;
+        Ok(Irq {
+            number: irq_number(&spec).ok_or(ProbeError::NoResource)?,
+        })
+    }
+
+    pub fn clock(self, index: usize) -> Result<Clock, ProbeError> {
+        // Entry `index` of `clocks`; defers until its provider is bound.
+        let provider = self
+            .node()
+            .phandles(b"clocks", b"#clock-cells")
+            .nth(index)
+            .ok_or(ProbeError::NoResource)?;
+        require(provider)?;
+        let fixed = provider.is_compatible(b"fixed-clock");
+        Ok(Clock {
+            rate: fixed.then(|| provider.u32(b"clock-frequency")).flatten(),
+        })
+    }
+
+    pub fn supplier(self, prop: &[u8], cells: &'static [u8]) -> Result<Node, ProbeError> {
+        // Node named by the first phandle in `prop` (`mboxes` with
+        // `#mbox-cells`, ...); defers until it is bound.
+        let node = self
+            .node()
+            .phandles(prop, cells)
+            .next()
+            .ok_or(ProbeError::NoResource)?;
+        require(node)?;
+        Ok(node)
+    }
+}
+
+fn require(node: Node) -> Result<(), ProbeError> {
+    // A supplier is ready once bound, or if no driver has to set it up.
+    let Some(dev) = find(node) else {
+        return Ok(());
+    };
+    match dev.state() {
+        State::NoDriver | State::Bound => Ok(()),
+        State::Pending | State::Deferred => Err(ProbeError::Defer),
+        State::Failed(_) => Err(ProbeError::NoResource),
+    }
+}
+
+fn irq_number(spec: &Interrupt) -> Option<u32> {
+    let controller = spec.controller;
+    if controller.is_compatible(b"arm,gic-400") || controller.is_compatible(b"arm,cortex-a15-gic") {
+        // <type number flags>: SPIs are numbered from 32, PPIs from 16.
+        let base = match spec.cell(0)? {
+            0 => 32,
+            1 => 16,
+            _ => return None,
+        };
+        return Some(base + spec.cell(1)?);
+    }
+    if controller.is_compatible(b"brcm,bcm2836-armctrl-ic")
+        || controller.is_compatible(b"brcm,bcm2835-armctrl-ic")
+    {
+        // <bank number>, 32 interrupts per bank.
+        return Some(spec.cell(0)? * 32 + spec.cell(1)?);
+    }
+    // The per-core controller and one-cell controllers: the first cell.
+    spec.cell(0)
+}
@@ -1,13 +1,37 @@
-use core::cell::UnsafeCell;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::fmt;
-use core::ptr::{copy, write_volatile};
+use core::ptr::{copy_nonoverlapping, null_mut, write_volatile};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::driver;
+use crate::drivers::device::{Device, Driver, ProbeError};
 use crate::drivers::mailbox;
-use crate::gfx::font;
-use crate::mm::layout::phys_to_virt;
+use crate::gfx::ansi::{Action, Csi, Parser};
+use crate::gfx::font::Font;
+#[cfg(feature = "splash")]
+use crate::gfx::image::Image;
+use crate::gfx::pixel;
+use crate::gfx::psf;
+#[cfg(feature = "splash")]
+use crate::gfx::surface::Surface;
+use crate::gfx::utf8::Decoder;
+use crate::kernel::params::{self, BoolParam, FnParam};
+use crate::kernel::vfs::devfs::{self, CharDevice, DevNum, MAJOR_FB};
+use crate::kernel::vfs::file::FileId;
+use crate::kernel::vfs::{VfsError, VfsResult};
+use crate::kernel_param;
+use crate::mm::layout::{phys_to_virt, virt_to_phys, PAGE_SIZE};
+use crate::mm::{frame, paging};
 use crate::platform::simplefb::{SimpleFbFormat, SimpleFbInfo};
 use crate::util::sync::SpinLock;
 
+// The console font: 8x16 PSF2 covering ASCII, Latin-1, box drawing and block
+// elements. The 8x8 built-in font is only a fallback.
+static DEFAULT_FONT: &[u8] = include_bytes!("../../assets/font8x16.psf");
+
 const TAG_SET_PHYS_WH: u32 = 0x0004_8003;
 const TAG_SET_VIRT_WH: u32 = 0x0004_8004;
 const TAG_SET_VIRT_OFFSET: u32 = 0x0004_8009;
@@ -15,51 +39,228 @@ const TAG_SET_DEPTH: u32 = 0x0004_8005;
 const TAG_SET_PIXEL_ORDER: u32 = 0x0004_8006;
 const TAG_ALLOCATE_BUFFER: u32 = 0x0004_0001;
 const TAG_GET_PITCH: u32 = 0x0004_0008;
+const TAG_WAIT_VSYNC: u32 = 0x0004_800e;
 
 const REQUEST: u32 = 0x0000_0000;
 
 #[repr(C, align(16))]
 struct MailboxBuffer {
-    buf: UnsafeCell<[u32; 35]>,
+    buf: [u32; 35],
 }
 
-unsafe impl Sync for MailboxBuffer {}
-
-static MBOX: MailboxBuffer = MailboxBuffer {
-    buf: UnsafeCell::new([0; 35]),
-};
+// Its own lock, so a vsync wait does not hold up console output.
+static MBOX: SpinLock<MailboxBuffer> = SpinLock::new(MailboxBuffer { buf: [0; 35] });
 
 pub struct Framebuffer {
     ptr: *mut u8,
     width: u32,
     height: u32,
     pitch: u32,
+    format: SimpleFbFormat,
+    // Screens in the virtual framebuffer, and which one is displayed. The
+    // console always draws into the first.
+    buffers: u32,
+    front: u32,
+    // Optional copy of the screen in cacheable RAM (`width * 4` bytes per
+    // row). Drawing goes there and `flush` copies the dirty rectangle out,
+    // so the device memory is only ever written in bulk and never read.
+    shadow: *mut u32,
+    dirty: Option<Rect>,
+}
+
+// Pixel rectangle [x0, x1) x [y0, y1).
+#[derive(Copy, Clone, Debug)]
+struct Rect {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
 }
 
 unsafe impl Send for Framebuffer {}
 
+// FBIOGET_INFO: geometry of /dev/fb0 for programs that map it (ralix-specific).
+pub const FBIOGET_INFO: u64 = 0x4680;
+// FBIOSET_MODE: change resolution, depth and buffer count (`FbMode` at `arg`).
+pub const FBIOSET_MODE: u64 = 0x4681;
+// FBIOFLIP: display buffer `arg & 0xff`; with FB_FLIP_VSYNC set, return
+// once it is on screen. Only in graphics mode.
+pub const FBIOFLIP: u64 = 0x4682;
+pub const FB_FLIP_VSYNC: u64 = 1 << 8;
+// FBIO_WAITFORVSYNC (Linux value): wait for the next vertical blank.
+pub const FBIO_WAITFORVSYNC: u64 = 0x4620;
+// Limits on modes asked for with FBIOSET_MODE.
+pub const MAX_FB_BUFFERS: u32 = 2;
+const MAX_FB_SIZE: u32 = 4096;
+
+// `FbInfo::format` values.
+pub const FB_FORMAT_X8R8G8B8: u32 = 0;
+pub const FB_FORMAT_A8R8G8B8: u32 = 1;
+pub const FB_FORMAT_R5G6B5: u32 = 2;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FbInfo {
+    pub width: u32,
+    pub height: u32,
+    // Bytes per row.
+    pub pitch: u32,
+    pub format: u32,
+    // Screens stacked in the framebuffer (1 or 2) and the one displayed;
+    // screen n starts `n * height` rows down.
+    pub buffers: u32,
+    pub front: u32,
+    // Bytes to map: `pitch * height * buffers`, rounded up to whole pages.
+    pub size: u64,
+}
+
+// FBIOSET_MODE argument (ralix-specific, 0x4681).
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FbMode {
+    pub width: u32,
+    pub height: u32,
+    // Bits per pixel: 32 (X8R8G8B8) or 16 (R5G6B5).
+    pub depth: u32,
+    // 2 allocates a back buffer for FBIOFLIP.
+    pub buffers: u32,
+}
+
+impl FbInfo {
+    pub fn pixel_format(&self) -> Option<SimpleFbFormat> {
+        match self.format {
+            FB_FORMAT_X8R8G8B8 => Some(SimpleFbFormat::X8R8G8B8),
+            FB_FORMAT_A8R8G8B8 => Some(SimpleFbFormat::A8R8G8B8),
+            FB_FORMAT_R5G6B5 => Some(SimpleFbFormat::R5G6B5),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum InitError {
     MailboxCallFailed,
     NoFramebuffer,
     NoPitch,
     InvalidSimpleFb,
+    UnsupportedFormat,
+}
+
+// Virtual consoles sharing the screen; only the active one draws.
+pub const MAX_VTS: usize = 6;
+// Scrollback kept per console, in lines; `scrollback=` changes it.
+pub const DEFAULT_SCROLLBACK: usize = 200;
+const MAX_SCROLLBACK: usize = 2000;
+
+static SCROLLBACK: AtomicUsize = AtomicUsize::new(DEFAULT_SCROLLBACK);
+
+kernel_param!(static SCROLLBACK_LINES: FnParam = FnParam::new(set_scrollback_param), "scrollback");
+// Draw through a RAM shadow buffer; `fb.shadow=off` writes straight to the
+// device instead. 16-bit screens always get one.
+kernel_param!(static SHADOW: BoolParam = BoolParam::new(true), "fb.shadow");
+
+fn set_scrollback_param(value: Option<&'static [u8]>) -> bool {
+    match value.and_then(params::parse_u32) {
+        Some(lines) if lines as usize <= MAX_SCROLLBACK => {
+            set_scrollback(lines as usize);
+            true
+        }
+        _ => false,
+    }
 }
 
 struct ConsoleState {
-    console: Option<Console>,
+    fb: Option<Framebuffer>,
+    consoles: Vec<Console>,
+    active: usize,
+    // The cells currently on screen, so painting skips unchanged ones.
+    // Empty when the screen must be repainted from scratch.
+    shown: Vec<Cell>,
+    // A program has taken over the display (KD_GRAPHICS); consoles keep
+    // their text but leave the screen alone.
+    graphics: bool,
 }
 
-static CONSOLE: SpinLock<ConsoleState> = SpinLock::new(ConsoleState { console: None });
+static CONSOLE: SpinLock<ConsoleState> = SpinLock::new(ConsoleState {
+    fb: None,
+    consoles: Vec::new(),
+    active: 0,
+    shown: Vec::new(),
+    graphics: false,
+});
+
+// One character cell with its colours already resolved.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Cell {
+    c: char,
+    fg: u32,
+    bg: u32,
+}
 
 pub struct Console {
-    fb: Framebuffer,
+    // Screen size in pixels.
+    screen_w: usize,
+    screen_h: usize,
+    font: Arc<Font>,
+    // Glyphs are drawn `scale` times their size; a cell is `cell_w` by
+    // `cell_h` pixels.
+    scale: usize,
+    cell_w: usize,
+    cell_h: usize,
+    utf8: Decoder,
+    // Text contents. All editing, scrolling included, happens here; the
+    // screen is painted from the cells of rows marked dirty.
+    cells: Vec<Cell>,
+    dirty: Vec<bool>,
+    // Lines that scrolled off the top, oldest first, `cols` cells each.
+    history: VecDeque<Cell>,
+    history_max: usize,
+    // How many lines the view is scrolled back into the history.
+    view: usize,
     col: usize,
     row: usize,
     cols: usize,
     rows: usize,
+    // Default colours, used until SGR picks others.
     fg: u32,
     bg: u32,
+    attr: Attr,
+    // Set after printing in the last column: the next glyph wraps first.
+    wrap_pending: bool,
+    autowrap: bool,
+    // Scroll region (DECSTBM), inclusive rows.
+    top: usize,
+    bottom: usize,
+    saved: SavedCursor,
+    parser: Parser,
+}
+
+fn set_offset(y: u32, vsync: bool) -> bool {
+    // Show the screen starting at row `y` of the virtual framebuffer; with
+    // `vsync` the call returns once it is on screen. Firmware that does not
+    // know the vsync tag ignores it.
+    let mut mbox = MBOX.lock();
+    let buf = &mut mbox.buf;
+    buf[0] = (buf.len() * 4) as u32;
+    buf[1] = REQUEST;
+
+    buf[2] = TAG_SET_VIRT_OFFSET;
+    buf[3] = 8;
+    buf[4] = 8;
+    buf[5] = 0;
+    buf[6] = y;
+
+    let mut end = 7;
+    if vsync {
+        buf[7] = TAG_WAIT_VSYNC;
+        buf[8] = 4;
+        buf[9] = 4;
+        buf[10] = 0;
+        end = 11;
+    }
+    buf[end] = 0;
+
+    mailbox::call(buf.as_mut_ptr()) && buf[6] == y
 }
 
 impl Framebuffer {
@@ -69,78 +270,105 @@ impl Framebuffer {
     }
 
     pub fn init_with_mode(width: u32, height: u32) -> Result<Self, InitError> {
+        Self::allocate(width, height, 32, 1)
+    }
+
+    fn allocate(width: u32, height: u32, depth: u32, buffers: u32) -> Result<Self, InitError> {
         // Use mailbox property tags to allocate and configure the framebuffer.
-        unsafe {
-            let buf = &mut *MBOX.buf.get();
-
-            buf[0] = (buf.len() * 4) as u32;
-            buf[1] = REQUEST;
-
-            buf[2] = TAG_SET_PHYS_WH;
-            buf[3] = 8;
-            buf[4] = 8;
-            buf[5] = width;
-            buf[6] = height;
-
-            buf[7] = TAG_SET_VIRT_WH;
-            buf[8] = 8;
-            buf[9] = 8;
-            buf[10] = width;
-            buf[11] = height;
-
-            buf[12] = TAG_SET_VIRT_OFFSET;
-            buf[13] = 8;
-            buf[14] = 8;
-            buf[15] = 0;
-            buf[16] = 0;
-
-            buf[17] = TAG_SET_DEPTH;
-            buf[18] = 4;
-            buf[19] = 4;
-            buf[20] = 32;
-
-            buf[21] = TAG_SET_PIXEL_ORDER;
-            buf[22] = 4;
-            buf[23] = 4;
-            buf[24] = 1; // RGB
-
-            buf[25] = TAG_ALLOCATE_BUFFER;
-            buf[26] = 8;
-            buf[27] = 8;
-            buf[28] = 4096;
-            buf[29] = 0;
-
-            buf[30] = TAG_GET_PITCH;
-            buf[31] = 4;
-            buf[32] = 4;
-            buf[33] = 0;
-
-            buf[34] = 0;
-
-            if !mailbox::call(buf.as_mut_ptr()) {
-                return Err(InitError::MailboxCallFailed);
-            }
+        // The virtual screen is `buffers` screens tall, for page flipping.
+        let mut mbox = MBOX.lock();
+        let buf = &mut mbox.buf;
+        buf[0] = (buf.len() * 4) as u32;
+        buf[1] = REQUEST;
 
-            let fb_bus = buf[28];
-            let pitch = buf[33];
-            if fb_bus == 0 {
-                return Err(InitError::NoFramebuffer);
-            }
-            if pitch == 0 {
-                return Err(InitError::NoPitch);
-            }
+        buf[2] = TAG_SET_PHYS_WH;
+        buf[3] = 8;
+        buf[4] = 8;
+        buf[5] = width;
+        buf[6] = height;
+
+        buf[7] = TAG_SET_VIRT_WH;
+        buf[8] = 8;
+        buf[9] = 8;
+        buf[10] = width;
+        buf[11] = height * buffers;
+
+        buf[12] = TAG_SET_VIRT_OFFSET;
+        buf[13] = 8;
+        buf[14] = 8;
+        buf[15] = 0;
+        buf[16] = 0;
+
+        buf[17] = TAG_SET_DEPTH;
+        buf[18] = 4;
+        buf[19] = 4;
+        buf[20] = depth;
+
+        buf[21] = TAG_SET_PIXEL_ORDER;
+        buf[22] = 4;
+        buf[23] = 4;
+        buf[24] = 1; // RGB
+
+        buf[25] = TAG_ALLOCATE_BUFFER;
+        buf[26] = 8;
+        buf[27] = 8;
+        buf[28] = 4096;
+        buf[29] = 0;
 
-            let fb_ptr = phys_to_virt(mailbox::vc_to_arm(fb_bus) as u64) as *mut u8;
-            let out_width = if buf[10] != 0 { buf[10] } else { width };
-            let out_height = if buf[11] != 0 { buf[11] } else { height };
+        buf[30] = TAG_GET_PITCH;
+        buf[31] = 4;
+        buf[32] = 4;
+        buf[33] = 0;
 
-            Ok(Self {
-                ptr: fb_ptr,
-                width: out_width,
-                height: out_height,
-                pitch,
-            })
+        buf[34] = 0;
+
+        if !mailbox::call(buf.as_mut_ptr()) {
+            return Err(InitError::MailboxCallFailed);
+        }
+
+        let fb_bus = buf[28];
+        let pitch = buf[33];
+        if fb_bus == 0 {
+            return Err(InitError::NoFramebuffer);
         }
+        if pitch == 0 {
+            return Err(InitError::NoPitch);
+        }
+        let format = match buf[20] {
+            32 => SimpleFbFormat::X8R8G8B8,
+            16 => SimpleFbFormat::R5G6B5,
+            _ => return Err(InitError::UnsupportedFormat),
+        };
+
+        let fb_ptr = phys_to_virt(mailbox::vc_to_arm(fb_bus) as u64) as *mut u8;
+        let out_width = if buf[5] != 0 { buf[5] } else { width };
+        let out_height = if buf[6] != 0 { buf[6] } else { height };
+        // The firmware may not grant the taller virtual screen.
+        let buffers = if buf[11] >= out_height * buffers {
+            buffers
+        } else {
+            1
+        };
+
+        Ok(Self {
+            ptr: fb_ptr,
+            width: out_width,
+            height: out_height,
+            pitch,
+            format,
+            buffers,
+            front: 0,
+            shadow: null_mut(),
+            dirty: None,
+        })
+    }
+
+    fn pan(&mut self, buffer: u32, vsync: bool) -> bool {
+        if !set_offset(buffer * self.height, vsync) {
+            return false;
+        }
+        self.front = buffer;
+        true
     }
 
     pub fn init_from_simplefb(info: &SimpleFbInfo) -> Result<Self, InitError> {
@@ -148,145 +376,872 @@ impl Framebuffer {
         if info.addr == 0 || info.width == 0 || info.height == 0 || info.stride == 0 {
             return Err(InitError::InvalidSimpleFb);
         }
-        match info.format {
-            SimpleFbFormat::X8R8G8B8 | SimpleFbFormat::A8R8G8B8 => {}
-        }
         let ptr = phys_to_virt(info.addr) as *mut u8;
         Ok(Self {
             ptr,
             width: info.width,
             height: info.height,
             pitch: info.stride,
+            format: info.format,
+            buffers: 1,
+            front: 0,
+            shadow: null_mut(),
+            dirty: None,
         })
     }
 
-    pub fn clear(&mut self, color: u32) {
-        // Fill the entire framebuffer with a solid color.
-        for y in 0..self.height {
-            for x in 0..self.width {
-                self.put_pixel(x, y, color);
+    pub fn info(&self) -> FbInfo {
+        let size = self.pitch as usize * self.height as usize * self.buffers as usize;
+        FbInfo {
+            width: self.width,
+            height: self.height,
+            pitch: self.pitch,
+            format: match self.format {
+                SimpleFbFormat::X8R8G8B8 => FB_FORMAT_X8R8G8B8,
+                SimpleFbFormat::A8R8G8B8 => FB_FORMAT_A8R8G8B8,
+                SimpleFbFormat::R5G6B5 => FB_FORMAT_R5G6B5,
+            },
+            buffers: self.buffers,
+            front: self.front,
+            size: size.next_multiple_of(PAGE_SIZE) as u64,
+        }
+    }
+
+    fn wants_shadow(&self) -> bool {
+        SHADOW.get() || self.format == SimpleFbFormat::R5G6B5
+    }
+
+    fn shadow_pages(&self) -> usize {
+        (self.width as usize * self.height as usize * 4).div_ceil(PAGE_SIZE)
+    }
+
+    pub fn set_shadow(&mut self, enabled: bool) -> bool {
+        // Switch drawing to (or away from) a RAM shadow buffer. Enabling
+        // reads the screen back once; false if there is no memory for it.
+        // 16-bit screens are only drawn through the shadow, which converts.
+        if enabled == !self.shadow.is_null() {
+            return true;
+        }
+        if !enabled && self.format == SimpleFbFormat::R5G6B5 {
+            return false;
+        }
+        if !enabled {
+            self.flush();
+            self.free_shadow();
+            return true;
+        }
+        let Some(paddr) = frame::alloc_contiguous(self.shadow_pages()) else {
+            return false;
+        };
+        self.shadow = phys_to_virt(paddr) as *mut u32;
+        let width = self.width as usize;
+        for y in 0..self.height as usize {
+            let src = unsafe { self.ptr.add(y * self.pitch as usize) };
+            let dst = unsafe { self.shadow.add(y * width) };
+            if self.format == SimpleFbFormat::R5G6B5 {
+                for x in 0..width {
+                    let raw = unsafe { (src as *const u16).add(x).read_volatile() };
+                    let color = pixel::decode(self.format, raw as u32);
+                    unsafe { dst.add(x).write(color & 0x00ff_ffff) };
+                }
+            } else {
+                unsafe { copy_nonoverlapping(src as *const u32, dst, width) };
             }
         }
+        true
     }
 
-    fn scroll_rows(&mut self, rows: usize, bg: u32) {
-        // Scroll the framebuffer up by the specified number of rows.
-        if rows == 0 {
+    fn free_shadow(&mut self) {
+        if self.shadow.is_null() {
             return;
         }
-        let height = self.height as usize;
-        if rows >= height {
-            self.clear(bg);
+        let paddr = virt_to_phys(self.shadow as usize);
+        for page in 0..self.shadow_pages() {
+            frame::free_frame(paddr + (page * PAGE_SIZE) as u64);
+        }
+        self.shadow = null_mut();
+        self.dirty = None;
+    }
+
+    fn row(&mut self, y: usize) -> *mut u32 {
+        // Start of pixel row `y` in whatever drawing targets.
+        if self.shadow.is_null() {
+            unsafe { self.ptr.add(y * self.pitch as usize) as *mut u32 }
+        } else {
+            unsafe { self.shadow.add(y * self.width as usize) }
+        }
+    }
+
+    fn mark(&mut self, x: usize, y: usize, w: usize, h: usize) {
+        // Grow the dirty rectangle; only tracked with a shadow buffer.
+        if self.shadow.is_null() || w == 0 || h == 0 {
             return;
         }
+        let rect = Rect {
+            x0: x,
+            y0: y,
+            x1: x + w,
+            y1: y + h,
+        };
+        self.dirty = Some(match self.dirty {
+            Some(d) => Rect {
+                x0: d.x0.min(rect.x0),
+                y0: d.y0.min(rect.y0),
+                x1: d.x1.max(rect.x1),
+                y1: d.y1.max(rect.y1),
+            },
+            None => rect,
+        });
+    }
 
-        let bytes_per_row = self.pitch as usize;
-        let src = unsafe { self.ptr.add(rows * bytes_per_row) };
-        let dst = self.ptr;
-        let copy_bytes = (height - rows) * bytes_per_row;
-        unsafe {
-            copy(src, dst, copy_bytes);
+    pub fn flush(&mut self) {
+        // Copy the dirty part of the shadow buffer to the device.
+        let Some(rect) = self.dirty.take() else {
+            return;
+        };
+        let width = self.width as usize;
+        let pitch = self.pitch as usize;
+        for y in rect.y0..rect.y1 {
+            let src = unsafe { self.shadow.add(y * width + rect.x0) };
+            let dst = unsafe { self.ptr.add(y * pitch) };
+            if self.format == SimpleFbFormat::R5G6B5 {
+                let dst = dst as *mut u16;
+                for x in rect.x0..rect.x1 {
+                    let color = unsafe { src.add(x - rect.x0).read() };
+                    let raw = pixel::encode(self.format, color) as u16;
+                    unsafe { write_volatile(dst.add(x), raw) };
+                }
+            } else {
+                unsafe {
+                    copy_nonoverlapping(src, (dst as *mut u32).add(rect.x0), rect.x1 - rect.x0)
+                };
+            }
         }
+    }
+
+    #[cfg(feature = "splash")]
+    fn surface(&mut self) -> Surface<'_> {
+        // Drawing target for the rasterizer: the shadow buffer (always
+        // 32-bit) or buffer 0 of the device. The whole screen is marked
+        // dirty, so a `flush` afterwards shows what was drawn.
+        let (width, height) = (self.width, self.height);
+        self.mark(0, 0, width as usize, height as usize);
+        let surface = if self.shadow.is_null() {
+            unsafe { Surface::from_raw(self.ptr, width, height, self.pitch, self.format) }
+        } else {
+            let pitch = width * 4;
+            let ptr = self.shadow as *mut u8;
+            unsafe { Surface::from_raw(ptr, width, height, pitch, SimpleFbFormat::X8R8G8B8) }
+        };
+        surface.expect("framebuffer geometry is valid")
+    }
+
+    pub fn clear(&mut self, color: u32) {
+        // Fill the entire framebuffer with a solid color.
+        self.fill_rect(0, 0, self.width as usize, self.height as usize, color);
+    }
 
-        let start = height - rows;
-        for y in start..height {
-            let row_ptr = unsafe { self.ptr.add(y * bytes_per_row) as *mut u32 };
-            for x in 0..self.width {
-                unsafe { write_volatile(row_ptr.add(x as usize), bg) };
+    fn fill_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: u32) {
+        // Fill a pixel rectangle, clipped to the screen.
+        let x_end = (x + w).min(self.width as usize);
+        let y_end = (y + h).min(self.height as usize);
+        if x >= x_end || y >= y_end {
+            return;
+        }
+        let shadow = !self.shadow.is_null();
+        for py in y..y_end {
+            let row = self.row(py);
+            for px in x..x_end {
+                unsafe {
+                    if shadow {
+                        row.add(px).write(color);
+                    } else {
+                        write_volatile(row.add(px), color);
+                    }
+                }
             }
         }
+        self.mark(x, y, x_end - x, y_end - y);
     }
 
     #[allow(dead_code)]
-    pub fn write_str(&mut self, mut x: usize, mut y: usize, s: &str, fg: u32, bg: u32) {
+    pub fn write_str(
+        &mut self,
+        font: &Font,
+        mut x: usize,
+        mut y: usize,
+        s: &str,
+        fg: u32,
+        bg: u32,
+    ) {
         // Render a string at the given pixel position.
-        for b in s.bytes() {
-            if b == b'\n' {
-                y += font::FONT_HEIGHT;
+        for ch in s.chars() {
+            if ch == '\n' {
+                y += font.height;
                 x = 0;
                 continue;
             }
-            self.draw_char(x, y, b, fg, bg);
-            x += font::FONT_WIDTH;
+            self.draw_glyph(x, y, font, ch, 1, fg, bg);
+            x += font.width;
         }
     }
 
-    fn draw_char(&mut self, x: usize, y: usize, c: u8, fg: u32, bg: u32) {
-        let glyph = font::glyph(c);
-        for (row, bits) in glyph.iter().enumerate() {
-            let y0 = y + row * 2;
-            for dy in 0..2 {
-                let py = y0 + dy;
-                if py >= self.height as usize {
-                    continue;
-                }
-                for col in 0..8 {
-                    let px = x + col;
-                    if px >= self.width as usize {
-                        continue;
-                    }
-                    let on = (bits >> (7 - col)) & 1 != 0;
-                    self.put_pixel(px as u32, py as u32, if on { fg } else { bg });
-                }
+    #[allow(clippy::too_many_arguments)]
+    fn draw_glyph(
+        &mut self,
+        x: usize,
+        y: usize,
+        font: &Font,
+        ch: char,
+        scale: usize,
+        fg: u32,
+        bg: u32,
+    ) {
+        // Each glyph row is expanded once into `line` and copied out for
+        // every pixel row it covers; this is the hot path of every redraw.
+        // Each font pixel becomes a `scale`-sized square.
+        let glyph = font.glyph(ch);
+        let width = (self.width as usize)
+            .saturating_sub(x)
+            .min(font.width * scale)
+            .min(MAX_CELL_WIDTH);
+        let height = (self.height as usize)
+            .saturating_sub(y)
+            .min(font.height * scale);
+        let mut line = [0u32; MAX_CELL_WIDTH];
+        for (row, bits) in glyph.chunks_exact(font.row_bytes()).enumerate() {
+            for (px, pixel) in line[..width].iter_mut().enumerate() {
+                let col = px / scale;
+                let on = bits[col / 8] & (0x80 >> (col % 8)) != 0;
+                *pixel = if on { fg } else { bg };
+            }
+            for py in row * scale..((row + 1) * scale).min(height) {
+                let dst = self.row(y + py);
+                unsafe { copy_nonoverlapping(line.as_ptr(), dst.add(x), width) };
             }
         }
+        self.mark(x, y, width, height);
     }
+}
+
+// Terminal colours as set by SGR, resolved to pixels at draw time.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Color {
+    Default,
+    Indexed(u8),
+    Rgb(u32),
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Attr {
+    fg: Color,
+    bg: Color,
+    bold: bool,
+    inverse: bool,
+}
 
-    fn put_pixel(&mut self, x: u32, y: u32, color: u32) {
-        let offset = (y * self.pitch) + (x * 4);
-        unsafe {
-            write_volatile(self.ptr.add(offset as usize) as *mut u32, color);
+impl Attr {
+    const DEFAULT: Attr = Attr {
+        fg: Color::Default,
+        bg: Color::Default,
+        bold: false,
+        inverse: false,
+    };
+}
+
+#[derive(Copy, Clone, Debug)]
+struct SavedCursor {
+    col: usize,
+    row: usize,
+    attr: Attr,
+}
+
+// Glyphs are scaled up while at least this many text rows still fit, so
+// small fonts stay readable on high-resolution displays.
+const MIN_ROWS: usize = 48;
+const MAX_SCALE: usize = 4;
+// Widest cell `draw_glyph` handles: the largest PSF glyph at full scale.
+const MAX_CELL_WIDTH: usize = 64 * MAX_SCALE;
+
+fn font_scale(screen_h: usize, font: &Font) -> usize {
+    (screen_h / (font.height * MIN_ROWS)).clamp(1, MAX_SCALE)
+}
+
+// The 16 standard colours (VGA text palette, as on the Linux console).
+const PALETTE: [u32; 16] = [
+    0x00_0000, 0xaa_0000, 0x00_aa00, 0xaa_5500, 0x00_00aa, 0xaa_00aa, 0x00_aaaa, 0xaa_aaaa,
+    0x55_5555, 0xff_5555, 0x55_ff55, 0xff_ff55, 0x55_55ff, 0xff_55ff, 0x55_ffff, 0xff_ffff,
+];
+
+fn palette(idx: u8) -> u32 {
+    // xterm's 256 colours: the 16 above, a 6x6x6 cube and a grey ramp.
+    match idx {
+        0..=15 => PALETTE[idx as usize],
+        16..=231 => {
+            let i = (idx - 16) as u32;
+            let level = |v: u32| if v == 0 { 0 } else { 55 + 40 * v };
+            (level(i / 36) << 16) | (level(i / 6 % 6) << 8) | level(i % 6)
+        }
+        _ => {
+            let grey = 8 + 10 * (idx - 232) as u32;
+            (grey << 16) | (grey << 8) | grey
         }
     }
 }
 
 impl Console {
-    fn new(fb: Framebuffer, fg: u32, bg: u32) -> Self {
-        let cols = (fb.width as usize) / font::FONT_WIDTH;
-        let rows = (fb.height as usize) / font::FONT_HEIGHT;
+    fn new(screen_w: usize, screen_h: usize, font: Arc<Font>, fg: u32, bg: u32) -> Self {
+        let scale = font_scale(screen_h, &font);
+        let (cell_w, cell_h) = (font.width * scale, font.height * scale);
+        let cols = (screen_w / cell_w).max(1);
+        let rows = (screen_h / cell_h).max(1);
+        let blank = Cell { c: ' ', fg, bg };
         Self {
-            fb,
+            screen_w,
+            screen_h,
+            font,
+            scale,
+            cell_w,
+            cell_h,
+            utf8: Decoder::new(),
+            cells: vec![blank; cols * rows],
+            dirty: vec![true; rows],
+            history: VecDeque::new(),
+            history_max: SCROLLBACK.load(Ordering::Relaxed),
+            view: 0,
             col: 0,
             row: 0,
-            cols: cols.max(1),
-            rows: rows.max(1),
+            cols,
+            rows,
             fg,
             bg,
+            attr: Attr::DEFAULT,
+            wrap_pending: false,
+            autowrap: true,
+            top: 0,
+            bottom: rows - 1,
+            saved: SavedCursor {
+                col: 0,
+                row: 0,
+                attr: Attr::DEFAULT,
+            },
+            parser: Parser::new(),
         }
     }
 
-    fn newline(&mut self) {
-        self.col = 0;
-        self.row += 1;
-        if self.row >= self.rows {
-            self.fb.scroll_rows(font::FONT_HEIGHT, self.bg);
-            self.row = self.rows - 1;
+    fn colors(&self) -> (u32, u32) {
+        // Pixel colours for the current attributes. Bold brightens the eight
+        // base colours, since the font has no bold face.
+        let mut fg = match self.attr.fg {
+            Color::Default => self.fg,
+            Color::Indexed(idx) if self.attr.bold && idx < 8 => palette(idx + 8),
+            Color::Indexed(idx) => palette(idx),
+            Color::Rgb(rgb) => rgb,
+        };
+        let mut bg = self.erase_color();
+        if self.attr.inverse {
+            core::mem::swap(&mut fg, &mut bg);
         }
+        (fg, bg)
     }
 
-    fn put_char(&mut self, c: u8) {
-        if c == b'\n' {
-            self.newline();
+    fn erase_color(&self) -> u32 {
+        // Erased cells take the current background, as on xterm.
+        match self.attr.bg {
+            Color::Default => self.bg,
+            Color::Indexed(idx) => palette(idx),
+            Color::Rgb(rgb) => rgb,
+        }
+    }
+
+    fn erase(&mut self, row: usize, from: usize, to: usize) {
+        // Blank columns [from, to) of a row.
+        let to = to.min(self.cols);
+        if from >= to || row >= self.rows {
             return;
         }
-        let x = self.col * font::FONT_WIDTH;
-        let y = self.row * font::FONT_HEIGHT;
-        self.fb.draw_char(x, y, c, self.fg, self.bg);
-        self.col += 1;
-        if self.col >= self.cols {
-            self.newline();
+        let color = self.erase_color();
+        let blank = Cell {
+            c: ' ',
+            fg: self.fg,
+            bg: color,
+        };
+        self.cells[row * self.cols + from..row * self.cols + to].fill(blank);
+        self.dirty[row] = true;
+    }
+
+    fn erase_rows(&mut self, from: usize, to: usize) {
+        // Blank rows [from, to).
+        for row in from..to.min(self.rows) {
+            self.erase(row, 0, self.cols);
+        }
+    }
+
+    fn scroll_up(&mut self, top: usize, bottom: usize, n: usize) {
+        // Move rows top..=bottom up by n; blank rows appear at the bottom.
+        let n = n.min(bottom + 1 - top);
+        let keep = bottom + 1 - top - n;
+        if top == 0 {
+            self.save_history(n);
+        }
+        self.move_lines(top, top + n, keep);
+        self.erase_rows(bottom + 1 - n, bottom + 1);
+    }
+
+    fn scroll_down(&mut self, top: usize, bottom: usize, n: usize) {
+        let n = n.min(bottom + 1 - top);
+        let keep = bottom + 1 - top - n;
+        self.move_lines(top + n, top, keep);
+        self.erase_rows(top, top + n);
+    }
+
+    fn move_lines(&mut self, to: usize, from: usize, count: usize) {
+        // Copy `count` text rows from row `from` to row `to`.
+        let cols = self.cols;
+        self.cells
+            .copy_within(from * cols..(from + count) * cols, to * cols);
+        self.dirty[to..to + count].fill(true);
+    }
+
+    fn linefeed(&mut self) {
+        // At the bottom margin the scroll region moves up instead.
+        self.wrap_pending = false;
+        if self.row == self.bottom {
+            self.scroll_up(self.top, self.bottom, 1);
+        } else if self.row + 1 < self.rows {
+            self.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.wrap_pending = false;
+        if self.row == self.top {
+            self.scroll_down(self.top, self.bottom, 1);
+        } else if self.row > 0 {
+            self.row -= 1;
         }
     }
 
+    fn move_to(&mut self, row: usize, col: usize) {
+        self.row = row.min(self.rows - 1);
+        self.col = col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn print(&mut self, c: char) {
+        // Printing in the last column defers the wrap until the next glyph,
+        // so a full-width line does not scroll early.
+        if self.wrap_pending {
+            self.col = 0;
+            self.linefeed();
+        }
+        let (fg, bg) = self.colors();
+        self.cells[self.row * self.cols + self.col] = Cell { c, fg, bg };
+        self.dirty[self.row] = true;
+        if self.col + 1 < self.cols {
+            self.col += 1;
+        } else {
+            self.wrap_pending = self.autowrap;
+        }
+    }
+
+    fn control(&mut self, c: u8) {
+        match c {
+            // There is no speaker, so the bell is silent.
+            0x07 => {}
+            0x08 => {
+                self.wrap_pending = false;
+                self.col = self.col.saturating_sub(1);
+            }
+            b'\t' => {
+                let next = (self.col / 8 + 1) * 8;
+                self.move_to(self.row, next);
+            }
+            b'\n' | 0x0b | 0x0c => self.linefeed(),
+            b'\r' => {
+                self.wrap_pending = false;
+                self.col = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn escape(&mut self, c: u8) {
+        match c {
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            b'D' => self.linefeed(),
+            b'E' => {
+                self.col = 0;
+                self.linefeed();
+            }
+            b'M' => self.reverse_index(),
+            b'c' => self.reset(),
+            _ => {}
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = SavedCursor {
+            col: self.col,
+            row: self.row,
+            attr: self.attr,
+        };
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = self.saved;
+        self.attr = saved.attr;
+        self.move_to(saved.row, saved.col);
+    }
+
+    fn reset(&mut self) {
+        // RIS: default attributes and margins, blank screen, cursor home.
+        self.attr = Attr::DEFAULT;
+        self.autowrap = true;
+        self.top = 0;
+        self.bottom = self.rows - 1;
+        self.erase_rows(0, self.rows);
+        self.move_to(0, 0);
+        self.save_cursor();
+    }
+
+    fn csi(&mut self, csi: &Csi) {
+        let n = csi.param(0, 1) as usize;
+        match (csi.private, csi.final_byte) {
+            (None, b'A') => {
+                // Vertical moves stop at the margin when they start inside it.
+                let limit = if self.row >= self.top { self.top } else { 0 };
+                self.move_to(self.row.saturating_sub(n).max(limit), self.col);
+            }
+            (None, b'B') | (None, b'e') => {
+                let limit = if self.row <= self.bottom {
+                    self.bottom
+                } else {
+                    self.rows - 1
+                };
+                self.move_to((self.row + n).min(limit), self.col);
+            }
+            (None, b'C') | (None, b'a') => self.move_to(self.row, self.col + n),
+            (None, b'D') => self.move_to(self.row, self.col.saturating_sub(n)),
+            (None, b'E') => {
+                self.move_to((self.row + n).min(self.bottom.max(self.row)), 0);
+            }
+            (None, b'F') => {
+                let limit = if self.row >= self.top { self.top } else { 0 };
+                self.move_to(self.row.saturating_sub(n).max(limit), 0);
+            }
+            (None, b'G') | (None, b'`') => self.move_to(self.row, n - 1),
+            (None, b'd') => self.move_to(n - 1, self.col),
+            (None, b'H') | (None, b'f') => {
+                let col = csi.param(1, 1) as usize;
+                self.move_to(n - 1, col - 1);
+            }
+            (None, b'J') => match csi.param(0, 0) {
+                0 => {
+                    self.erase(self.row, self.col, self.cols);
+                    self.erase_rows(self.row + 1, self.rows);
+                }
+                1 => {
+                    self.erase_rows(0, self.row);
+                    self.erase(self.row, 0, self.col + 1);
+                }
+                2 => self.erase_rows(0, self.rows),
+                // As on xterm, 3 clears the scrollback instead.
+                3 => self.history.clear(),
+                _ => {}
+            },
+            (None, b'K') => match csi.param(0, 0) {
+                0 => self.erase(self.row, self.col, self.cols),
+                1 => self.erase(self.row, 0, self.col + 1),
+                2 => self.erase(self.row, 0, self.cols),
+                _ => {}
+            },
+            (None, b'@') => {
+                // Insert blanks at the cursor, pushing the rest of the line right.
+                let n = n.min(self.cols - self.col);
+                self.move_cells(self.col + n, self.col, self.cols - self.col - n);
+                self.erase(self.row, self.col, self.col + n);
+            }
+            (None, b'P') => {
+                let n = n.min(self.cols - self.col);
+                self.move_cells(self.col, self.col + n, self.cols - self.col - n);
+                self.erase(self.row, self.cols - n, self.cols);
+            }
+            (None, b'X') => self.erase(self.row, self.col, self.col + n),
+            (None, b'L') if (self.top..=self.bottom).contains(&self.row) => {
+                self.scroll_down(self.row, self.bottom, n);
+                self.col = 0;
+            }
+            (None, b'M') if (self.top..=self.bottom).contains(&self.row) => {
+                self.scroll_up(self.row, self.bottom, n);
+                self.col = 0;
+            }
+            (None, b'S') => self.scroll_up(self.top, self.bottom, n),
+            (None, b'T') => self.scroll_down(self.top, self.bottom, n),
+            (None, b'm') => self.sgr(csi.params()),
+            (None, b'r') => {
+                let top = csi.param(0, 1) as usize - 1;
+                let bottom = (csi.param(1, self.rows as u16) as usize).min(self.rows) - 1;
+                if top < bottom {
+                    self.top = top;
+                    self.bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            }
+            (None, b's') => self.save_cursor(),
+            (None, b'u') => self.restore_cursor(),
+            (Some(b'?'), b'h') | (Some(b'?'), b'l') => {
+                // DEC private modes; only autowrap (7) is honoured. The text
+                // cursor is not drawn, so ?25 is accepted and ignored.
+                if csi.params().contains(&7) {
+                    self.autowrap = csi.final_byte == b'h';
+                    self.wrap_pending = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn sgr(&mut self, params: &[u16]) {
+        // Select Graphic Rendition; no parameters means reset.
+        if params.is_empty() {
+            self.attr = Attr::DEFAULT;
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => self.attr = Attr::DEFAULT,
+                1 => self.attr.bold = true,
+                22 => self.attr.bold = false,
+                7 => self.attr.inverse = true,
+                27 => self.attr.inverse = false,
+                p @ 30..=37 => self.attr.fg = Color::Indexed((p - 30) as u8),
+                39 => self.attr.fg = Color::Default,
+                p @ 40..=47 => self.attr.bg = Color::Indexed((p - 40) as u8),
+                49 => self.attr.bg = Color::Default,
+                p @ 90..=97 => self.attr.fg = Color::Indexed((p - 90 + 8) as u8),
+                p @ 100..=107 => self.attr.bg = Color::Indexed((p - 100 + 8) as u8),
+                p @ (38 | 48) => {
+                    // 38;5;n and 38;2;r;g;b (48 for the background).
+                    let (color, used) = extended_color(&params[i + 1..]);
+                    if let Some(color) = color {
+                        if p == 38 {
+                            self.attr.fg = color;
+                        } else {
+                            self.attr.bg = color;
+                        }
+                    }
+                    i += used;
+                }
+                // Underline, blink and the rest are not rendered.
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn move_cells(&mut self, to: usize, from: usize, count: usize) {
+        // Shift `count` cells of the cursor row horizontally.
+        if count == 0 {
+            return;
+        }
+        let start = self.row * self.cols;
+        self.cells
+            .copy_within(start + from..start + from + count, start + to);
+        self.dirty[self.row] = true;
+    }
+
     pub fn write_byte(&mut self, b: u8) {
-        self.put_char(b);
+        // Bytes go through the VT100/xterm parser; see gfx::ansi. Output
+        // snaps a scrolled-back view to the live screen.
+        if self.view > 0 {
+            self.view = 0;
+            self.dirty.fill(true);
+        }
+        // Printable bytes are UTF-8; a sequence cut short by a control or
+        // escape shows as U+FFFD.
+        let action = self.parser.advance(b);
+        let mut utf8 = self.utf8;
+        match action {
+            Some(Action::Print(c)) => utf8.advance(c, |ch| self.print(ch)),
+            Some(_) => utf8.flush(|ch| self.print(ch)),
+            None => {}
+        }
+        self.utf8 = utf8;
+        match action {
+            Some(Action::Control(c)) => self.control(c),
+            Some(Action::Escape(c)) => self.escape(c),
+            Some(Action::Csi(csi)) => self.csi(&csi),
+            _ => {}
+        }
+    }
+
+    fn set_screen(&mut self, screen_w: usize, screen_h: usize) {
+        // New screen size in pixels; the font scale may change with it.
+        self.screen_w = screen_w;
+        self.screen_h = screen_h;
+        self.set_font(self.font.clone());
+    }
+
+    fn set_font(&mut self, font: Arc<Font>) {
+        // New cell size: the text grid is rebuilt around the cursor row.
+        self.scale = font_scale(self.screen_h, &font);
+        self.cell_w = font.width * self.scale;
+        self.cell_h = font.height * self.scale;
+        self.font = font;
+        let cols = (self.screen_w / self.cell_w).max(1);
+        let rows = (self.screen_h / self.cell_h).max(1);
+        self.resize(cols, rows);
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        // Keep the top-left of the old text, dropping rows above the cursor
+        // if it would fall off the bottom. Scrollback survives only if the
+        // width is unchanged.
+        let blank = Cell {
+            c: ' ',
+            fg: self.fg,
+            bg: self.bg,
+        };
+        let skip = (self.row + 1).saturating_sub(rows);
+        let mut cells = vec![blank; cols * rows];
+        for row in 0..rows.min(self.rows - skip) {
+            let width = cols.min(self.cols);
+            let src = (row + skip) * self.cols;
+            cells[row * cols..row * cols + width].copy_from_slice(&self.cells[src..src + width]);
+        }
+        if cols != self.cols {
+            self.history.clear();
+        }
+        self.cells = cells;
+        self.dirty = vec![true; rows];
+        self.cols = cols;
+        self.rows = rows;
+        self.row -= skip;
+        self.col = self.col.min(cols - 1);
+        self.top = 0;
+        self.bottom = rows - 1;
+        self.saved.row = self.saved.row.min(rows - 1);
+        self.saved.col = self.saved.col.min(cols - 1);
+        self.wrap_pending = false;
+        self.view = 0;
+    }
+
+    fn history_lines(&self) -> usize {
+        self.history.len() / self.cols
+    }
+
+    fn save_history(&mut self, rows: usize) {
+        // Keep the top `rows` rows of the screen before they scroll away.
+        if self.history_max == 0 {
+            return;
+        }
+        for row in 0..rows {
+            if self.history_lines() == self.history_max {
+                self.history.drain(..self.cols);
+            }
+            let start = row * self.cols;
+            self.history
+                .extend(self.cells[start..start + self.cols].iter().copied());
+        }
+    }
+
+    fn set_history_max(&mut self, lines: usize) {
+        self.history_max = lines;
+        let excess = self.history_lines().saturating_sub(lines);
+        self.history.drain(..excess * self.cols);
+        self.view = self.view.min(self.history_lines());
+        self.dirty.fill(true);
+    }
+
+    fn view_cell(&self, row: usize, col: usize) -> Cell {
+        // Cell at screen position (row, col) of the current view: history
+        // lines first, then the live screen.
+        let line = self.history_lines() - self.view + row;
+        if line < self.history_lines() {
+            self.history[line * self.cols + col]
+        } else {
+            self.cells[(line - self.history_lines()) * self.cols + col]
+        }
+    }
+
+    fn paint(&mut self, fb: &mut Framebuffer, shown: &mut Vec<Cell>) {
+        // Draw the cells of dirty rows that differ from what is on screen.
+        // An empty `shown` repaints everything, margins included.
+        let full = shown.len() != self.cols * self.rows;
+        if full {
+            fb.clear(self.bg);
+            shown.clear();
+            shown.resize(
+                self.cols * self.rows,
+                Cell {
+                    c: ' ',
+                    fg: self.fg,
+                    bg: self.bg,
+                },
+            );
+        }
+        for row in 0..self.rows {
+            if !full && !self.dirty[row] {
+                continue;
+            }
+            self.dirty[row] = false;
+            for col in 0..self.cols {
+                let cell = self.view_cell(row, col);
+                let idx = row * self.cols + col;
+                if !full && shown[idx] == cell {
+                    continue;
+                }
+                shown[idx] = cell;
+                let (x, y) = (col * self.cell_w, row * self.cell_h);
+                fb.draw_glyph(x, y, &self.font, cell.c, self.scale, cell.fg, cell.bg);
+            }
+        }
+        fb.flush();
+    }
+
+    pub fn scroll_view(&mut self, lines: isize) {
+        // Move the view back (positive) or forward into the scrollback.
+        let view = self
+            .view
+            .saturating_add_signed(lines)
+            .min(self.history_lines());
+        if view != self.view {
+            self.view = view;
+            self.dirty.fill(true);
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        // Text grid as (columns, rows).
+        (self.cols, self.rows)
+    }
+}
+
+fn extended_color(params: &[u16]) -> (Option<Color>, usize) {
+    // The colour after an SGR 38/48, and how many parameters it took.
+    match params {
+        [5, idx, ..] => (Some(Color::Indexed((*idx).min(255) as u8)), 2),
+        [2, r, g, b, ..] => {
+            let channel = |v: u16| v.min(255) as u32;
+            let rgb = (channel(*r) << 16) | (channel(*g) << 8) | channel(*b);
+            (Some(Color::Rgb(rgb)), 4)
+        }
+        [5] | [2, ..] => (None, params.len()),
+        _ => (None, 0),
     }
 }
 
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Kernel text uses bare newlines; the terminal needs CR as well.
         for b in s.bytes() {
-            self.put_char(b);
+            if b == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(b);
         }
         Ok(())
     }
@@ -303,34 +1258,67 @@ pub fn init_console_with_mode(
     fg: u32,
     bg: u32,
 ) -> Result<(u32, u32), InitError> {
-    let mut fb = Framebuffer::init_with_mode(width, height)?;
+    let fb = Framebuffer::init_with_mode(width, height)?;
     let out_width = fb.width;
     let out_height = fb.height;
-    fb.clear(bg);
-    let mut state = CONSOLE.lock();
-    state.console = Some(Console::new(fb, fg, bg));
+    install_consoles(fb, fg, bg)?;
     Ok((out_width, out_height))
 }
 
 pub fn init_console_from_simplefb(info: &SimpleFbInfo, fg: u32, bg: u32) -> Result<(u32, u32), InitError> {
-    let mut fb = Framebuffer::init_from_simplefb(info)?;
-    fb.clear(bg);
+    let fb = Framebuffer::init_from_simplefb(info)?;
     let out_width = fb.width;
     let out_height = fb.height;
-    let mut state = CONSOLE.lock();
-    state.console = Some(Console::new(fb, fg, bg));
+    install_consoles(fb, fg, bg)?;
     Ok((out_width, out_height))
 }
 
+fn install_consoles(mut fb: Framebuffer, fg: u32, bg: u32) -> Result<(), InitError> {
+    // Every virtual console gets its text buffer up front, so switching
+    // never allocates. Drawing goes through a shadow buffer, unless turned
+    // off by `fb.shadow`, when there is memory for one.
+    if !fb.set_shadow(fb.wants_shadow()) && fb.format == SimpleFbFormat::R5G6B5 {
+        return Err(InitError::UnsupportedFormat);
+    }
+    fb.clear(bg);
+    let font = Arc::new(psf::parse(DEFAULT_FONT).unwrap_or_else(|_| Font::builtin()));
+    let (width, height) = (fb.width as usize, fb.height as usize);
+    let consoles = (0..MAX_VTS)
+        .map(|_| Console::new(width, height, font.clone(), fg, bg))
+        .collect();
+    let mut state = CONSOLE.lock();
+    state.fb = Some(fb);
+    state.consoles = consoles;
+    state.shown.clear();
+    Ok(())
+}
+
+fn run<F: FnOnce(&mut Console)>(state: &mut ConsoleState, vt: usize, f: F) -> bool {
+    // Run `f` on console `vt`, then bring the screen up to date if it is
+    // the one on display.
+    let ConsoleState {
+        fb,
+        consoles,
+        active,
+        shown,
+        graphics,
+    } = state;
+    let Some(console) = consoles.get_mut(vt) else {
+        return false;
+    };
+    f(console);
+    if let (true, false, Some(fb)) = (vt == *active, *graphics, fb.as_mut()) {
+        console.paint(fb, shown);
+    }
+    true
+}
+
 #[allow(dead_code)]
 pub fn with_console<F: FnOnce(&mut Console)>(f: F) -> bool {
+    // Run `f` on the visible console.
     let mut state = CONSOLE.lock();
-    if let Some(console) = state.console.as_mut() {
-        f(console);
-        true
-    } else {
-        false
-    }
+    let active = state.active;
+    run(&mut state, active, f)
 }
 
 pub fn try_with_console<F: FnOnce(&mut Console)>(f: F) -> bool {
@@ -338,10 +1326,290 @@ pub fn try_with_console<F: FnOnce(&mut Console)>(f: F) -> bool {
         Some(guard) => guard,
         None => return false,
     };
-    if let Some(console) = guard.console.as_mut() {
-        f(console);
-        true
+    let active = guard.active;
+    run(&mut guard, active, f)
+}
+
+pub fn try_with_vt<F: FnOnce(&mut Console)>(vt: usize, f: F) -> bool {
+    // `with_vt`, but false at once if the console is busy.
+    let Some(mut state) = CONSOLE.try_lock() else {
+        return false;
+    };
+    run(&mut state, vt, f)
+}
+
+pub fn with_vt<F: FnOnce(&mut Console)>(vt: usize, f: F) -> bool {
+    // Run `f` on virtual console `vt`, visible or not.
+    run(&mut CONSOLE.lock(), vt, f)
+}
+
+pub fn set_font(font: Font) -> bool {
+    // Switch every console to `font`; false before the framebuffer is up.
+    let font = Arc::new(font);
+    let mut state = CONSOLE.lock();
+    for console in state.consoles.iter_mut() {
+        console.set_font(font.clone());
+    }
+    state.shown.clear();
+    let active = state.active;
+    run(&mut state, active, |_| {})
+}
+
+pub fn load_font(bytes: &[u8]) -> VfsResult<()> {
+    // Install a PSF1/PSF2 font from the file's contents.
+    let font = psf::parse(bytes).map_err(|_| VfsError::InvalidArgument)?;
+    if set_font(font) {
+        Ok(())
     } else {
-        false
+        Err(VfsError::NoDevice)
     }
 }
+
+pub fn set_scrollback(lines: usize) {
+    // Scrollback length for every console; existing history is trimmed.
+    SCROLLBACK.store(lines, Ordering::Relaxed);
+    let mut state = CONSOLE.lock();
+    for console in state.consoles.iter_mut() {
+        console.set_history_max(lines);
+    }
+}
+
+pub fn set_graphics(graphics: bool) -> bool {
+    // Suspend console drawing while a program owns the screen; leaving
+    // graphics mode repaints the active console. False without a screen.
+    let mut state = CONSOLE.lock();
+    if state.fb.is_none() {
+        return false;
+    }
+    if state.graphics != graphics {
+        state.graphics = graphics;
+        state.shown.clear();
+        // Back to the buffer the console draws in.
+        if let Some(fb) = state.fb.as_mut().filter(|fb| fb.front != 0) {
+            fb.pan(0, false);
+        }
+        let active = state.active;
+        run(&mut state, active, |_| {});
+    }
+    true
+}
+
+#[cfg(feature = "splash")]
+pub fn show_splash(image: &mut Image, bg: u32) -> bool {
+    // Draw `image` centred on `bg` and hold the screen in graphics mode,
+    // so console output collects in the cells until `set_graphics(false)`.
+    let mut state = CONSOLE.lock();
+    let Some(fb) = state.fb.as_mut() else {
+        return false;
+    };
+    if fb.front != 0 {
+        fb.pan(0, false);
+    }
+    let x = (fb.width as i32 - image.width() as i32) / 2;
+    let y = (fb.height as i32 - image.height() as i32) / 2;
+    let src = image.surface();
+    let mut screen = fb.surface();
+    screen.clear(bg);
+    screen.blit_blend(&src, src.bounds(), x, y);
+    fb.flush();
+    state.graphics = true;
+    state.shown.clear();
+    true
+}
+
+pub fn graphics() -> bool {
+    CONSOLE.lock().graphics
+}
+
+pub fn set_mode(mode: FbMode) -> VfsResult<FbInfo> {
+    // Reallocate the framebuffer through the mailbox and lay every console
+    // out again for the new size. Existing mappings of the old buffer
+    // become stale.
+    let depth_ok = matches!(mode.depth, 16 | 32);
+    let size_ok =
+        (1..=MAX_FB_SIZE).contains(&mode.width) && (1..=MAX_FB_SIZE).contains(&mode.height);
+    if !depth_ok || !size_ok || !(1..=MAX_FB_BUFFERS).contains(&mode.buffers) {
+        return Err(VfsError::InvalidArgument);
+    }
+    let mut state = CONSOLE.lock();
+    let old = state.fb.as_ref().ok_or(VfsError::NoDevice)?;
+    let (old_w, old_h) = (old.width, old.height);
+    let result = Framebuffer::allocate(mode.width, mode.height, mode.depth, mode.buffers)
+        .map_err(|_| VfsError::IoError)
+        .and_then(|mut fb| {
+            if fb.set_shadow(fb.wants_shadow()) || fb.format != SimpleFbFormat::R5G6B5 {
+                Ok(fb)
+            } else {
+                Err(VfsError::NoSpace)
+            }
+        });
+    match result {
+        Ok(fb) => {
+            let info = fb.info();
+            replace_framebuffer(&mut state, fb);
+            Ok(info)
+        }
+        Err(err) => {
+            // The firmware may have switched already; get back to a mode
+            // the console can draw on.
+            if let Ok(mut fb) = Framebuffer::allocate(old_w, old_h, 32, 1) {
+                fb.set_shadow(fb.wants_shadow());
+                replace_framebuffer(&mut state, fb);
+            }
+            Err(err)
+        }
+    }
+}
+
+fn replace_framebuffer(state: &mut ConsoleState, fb: Framebuffer) {
+    // Install `fb` and repaint. The old buffer is not flushed: the new one
+    // may be the same memory.
+    let (width, height) = (fb.width as usize, fb.height as usize);
+    if let Some(mut old) = state.fb.replace(fb) {
+        old.free_shadow();
+    }
+    for console in state.consoles.iter_mut() {
+        console.set_screen(width, height);
+    }
+    state.shown.clear();
+    let active = state.active;
+    run(state, active, |_| {});
+}
+
+pub fn flip(buffer: u32, vsync: bool) -> VfsResult<()> {
+    // Display another buffer of a double-buffered mode. The console only
+    // draws into buffer 0, so this needs graphics mode. The console lock is
+    // dropped for the mailbox call, which may wait out a vsync.
+    let y = {
+        let state = CONSOLE.lock();
+        if !state.graphics {
+            return Err(VfsError::Busy);
+        }
+        let fb = state.fb.as_ref().ok_or(VfsError::NoDevice)?;
+        if buffer >= fb.buffers {
+            return Err(VfsError::InvalidArgument);
+        }
+        buffer * fb.height
+    };
+    if !set_offset(y, vsync) {
+        return Err(VfsError::IoError);
+    }
+    if let Some(fb) = CONSOLE.lock().fb.as_mut() {
+        fb.front = buffer;
+    }
+    Ok(())
+}
+
+pub fn wait_vsync() -> VfsResult<()> {
+    let y = {
+        let state = CONSOLE.lock();
+        let fb = state.fb.as_ref().ok_or(VfsError::NoDevice)?;
+        fb.front * fb.height
+    };
+    if set_offset(y, true) {
+        Ok(())
+    } else {
+        Err(VfsError::IoError)
+    }
+}
+
+pub fn show(vt: usize) -> bool {
+    // Make `vt` the visible console and repaint it. Returns false if the
+    // console is busy; callers in interrupt context retry later.
+    let Some(mut state) = CONSOLE.try_lock() else {
+        return false;
+    };
+    if state.active == vt {
+        return true;
+    }
+    state.active = vt;
+    state.shown.clear();
+    run(&mut state, vt, |_| {});
+    true
+}
+
+struct FbDevice;
+
+static FB_DEVICE: FbDevice = FbDevice;
+
+impl CharDevice for FbDevice {
+    fn read(&self, _minor: u32, _buf: &mut [u8]) -> VfsResult<usize> {
+        Ok(0)
+    }
+
+    fn write(&self, _minor: u32, buf: &[u8]) -> VfsResult<usize> {
+        // Text written to fb0 is rendered by the console. Waits out a busy
+        // console; fails only when there is none.
+        let wrote = with_console(|console| {
+            for &b in buf {
+                console.write_byte(b);
+            }
+        });
+        if wrote {
+            Ok(buf.len())
+        } else {
+            Err(VfsError::NoDevice)
+        }
+    }
+
+    fn ioctl(&self, _minor: u32, _file: FileId, cmd: u64, arg: u64) -> VfsResult<u64> {
+        match cmd {
+            FBIOGET_INFO => {
+                if arg == 0 {
+                    return Err(VfsError::BadAddress);
+                }
+                let info = CONSOLE
+                    .lock()
+                    .fb
+                    .as_ref()
+                    .map(Framebuffer::info)
+                    .ok_or(VfsError::NoDevice)?;
+                unsafe { (arg as *mut FbInfo).write_unaligned(info) };
+            }
+            FBIOSET_MODE => {
+                if arg == 0 {
+                    return Err(VfsError::BadAddress);
+                }
+                set_mode(unsafe { (arg as *const FbMode).read_unaligned() })?;
+            }
+            FBIOFLIP => flip((arg & 0xff) as u32, arg & FB_FLIP_VSYNC != 0)?,
+            FBIO_WAITFORVSYNC => wait_vsync()?,
+            _ => return Err(VfsError::NotTty),
+        }
+        Ok(0)
+    }
+
+    fn mmap(&self, _minor: u32, offset: u64, len: u64) -> VfsResult<u64> {
+        // The device memory itself, mapped write-combining. The console
+        // keeps drawing into it unless it is put in graphics mode.
+        let (pa, size) = {
+            let state = CONSOLE.lock();
+            let fb = state.fb.as_ref().ok_or(VfsError::NoDevice)?;
+            (virt_to_phys(fb.ptr as usize), fb.info().size)
+        };
+        if !offset.is_multiple_of(PAGE_SIZE as u64)
+            || offset.checked_add(len).is_none_or(|end| end > size)
+        {
+            return Err(VfsError::InvalidArgument);
+        }
+        let base = paging::map_write_combining(pa, size).ok_or(VfsError::NoSpace)?;
+        Ok(base as u64 + offset)
+    }
+}
+
+// The framebuffer is the firmware's, reached through its mailbox.
+driver!(static FIRMWARE = Driver {
+    name: "rpi-firmware",
+    compatible: &["raspberrypi,bcm2835-firmware"],
+    probe,
+});
+
+fn probe(dev: Device) -> Result<(), ProbeError> {
+    dev.supplier(b"mboxes", b"#mbox-cells")?;
+    register_devices();
+    Ok(())
+}
+
+pub fn register_devices() {
+    devfs::register_char("fb0", DevNum::new(MAJOR_FB, 0), &FB_DEVICE);
+}
@@ -1,3 +1,8 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::aarch64::timer;
+use crate::driver;
+use crate::drivers::device::{Device, Driver, ProbeError};
 use crate::drivers::mmio::{read32, write32};
 use crate::platform::board::{GICC_BASE, GICD_BASE};
 
@@ -16,40 +21,69 @@ const GICC_EOIR: usize = 0x010;
 const SPURIOUS_IRQ: u32 = 1023;
 const TIMER_PPI: u32 = 30; // CNTPNS
 
+// The board's addresses until the device tree's GIC is probed.
+static DIST_BASE: AtomicUsize = AtomicUsize::new(GICD_BASE);
+static CPU_BASE: AtomicUsize = AtomicUsize::new(GICC_BASE);
+
+driver!(static GIC = Driver {
+    name: "gic-400",
+    compatible: &["arm,gic-400", "arm,cortex-a15-gic"],
+    probe,
+});
+
+fn probe(dev: Device) -> Result<(), ProbeError> {
+    // `reg` is the distributor, then the CPU interface.
+    let dist = dev.mmio(0)?;
+    let cpu = dev.mmio(1)?;
+    DIST_BASE.store(dist.base, Ordering::Relaxed);
+    CPU_BASE.store(cpu.base, Ordering::Relaxed);
+    Ok(())
+}
+
+#[inline(always)]
+fn gicd() -> usize {
+    DIST_BASE.load(Ordering::Relaxed)
+}
+
+#[inline(always)]
+fn gicc() -> usize {
+    CPU_BASE.load(Ordering::Relaxed)
+}
+
 pub fn init_dist() {
     // Initialize the distributor (CPU0 only).
     unsafe {
-        write32(GICD_BASE + GICD_CTLR, 0);
+        write32(gicd() + GICD_CTLR, 0);
         // Mark SGIs/PPIs as non-secure group 1.
-        write32(GICD_BASE + GICD_IGROUPR0, 0xFFFF_FFFF);
+        write32(gicd() + GICD_IGROUPR0, 0xFFFF_FFFF);
         // Disable all SGIs/PPIs before enabling the timer.
-        write32(GICD_BASE + GICD_ICENABLER0, 0xFFFF_FFFF);
+        write32(gicd() + GICD_ICENABLER0, 0xFFFF_FFFF);
         // Set priority for the timer PPI.
-        set_priority(TIMER_PPI, 0x80);
+        set_priority(timer_irq_id(), 0x80);
         // Enable group0+group1.
-        write32(GICD_BASE + GICD_CTLR, 0x3);
+        write32(gicd() + GICD_CTLR, 0x3);
     }
 }
 
 pub fn init_cpu() {
     // Initialize the per-CPU interface.
     unsafe {
-        write32(GICC_BASE + GICC_CTLR, 0);
-        write32(GICC_BASE + GICC_PMR, 0xFF);
-        write32(GICC_BASE + GICC_BPR, 0);
+        write32(gicc() + GICC_CTLR, 0);
+        write32(gicc() + GICC_PMR, 0xFF);
+        write32(gicc() + GICC_BPR, 0);
         // Enable group0+group1 at the CPU interface.
-        write32(GICC_BASE + GICC_CTLR, 0x3);
+        write32(gicc() + GICC_CTLR, 0x3);
     }
     // Banked SGI/PPI configuration for this CPU.
     unsafe {
-        write32(GICD_BASE + GICD_IGROUPR0, 0xFFFF_FFFF);
+        write32(gicd() + GICD_IGROUPR0, 0xFFFF_FFFF);
     }
-    set_priority(TIMER_PPI, 0x80);
+    set_priority(timer_irq_id(), 0x80);
     enable_timer_ppi();
 }
 
 pub fn ack_irq() -> Option<u32> {
-    let iar = unsafe { read32(GICC_BASE + GICC_IAR) };
+    let iar = unsafe { read32(gicc() + GICC_IAR) };
     let id = iar & 0x3ff;
     if id == SPURIOUS_IRQ {
         None
@@ -60,19 +94,19 @@ pub fn ack_irq() -> Option<u32> {
 
 pub fn end_irq(id: u32) {
     unsafe {
-        write32(GICC_BASE + GICC_EOIR, id);
+        write32(gicc() + GICC_EOIR, id);
     }
 }
 
 fn enable_timer_ppi() {
     unsafe {
         // Enable PPI for the generic timer (banked per CPU).
-        write32(GICD_BASE + GICD_ISENABLER0, 1u32 << TIMER_PPI);
+        write32(gicd() + GICD_ISENABLER0, 1u32 << timer_irq_id());
     }
 }
 
 fn set_priority(irq: u32, prio: u8) {
-    let reg = GICD_BASE + GICD_IPRIORITYR + ((irq & !3) as usize);
+    let reg = gicd() + GICD_IPRIORITYR + ((irq & !3) as usize);
     let shift = (irq & 3) * 8;
     unsafe {
         let mut val = read32(reg);
@@ -83,5 +117,5 @@ fn set_priority(irq: u32, prio: u8) {
 }
 
 pub fn timer_irq_id() -> u32 {
-    TIMER_PPI
+    timer::irq().unwrap_or(TIMER_PPI)
 }
@@ -1,7 +1,14 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::kernel::process::{self, ProcessId, WaitQueue};
+use crate::kernel::vfs::devfs::{self, CharDevice, DevNum, MAJOR_INPUT};
+use crate::kernel::vfs::{VfsError, VfsResult, POLLIN};
 use crate::util::sync::SpinLock;
 
 #[cfg(any(feature = "qemu", feature = "rpi5"))]
-use crate::drivers::uart;
+use crate::arch::aarch64::timer;
+#[cfg(any(feature = "qemu", feature = "rpi5"))]
+use crate::drivers::{console, uart};
 
 const BUF_SIZE: usize = 256;
 
@@ -23,7 +30,6 @@ impl RingBuffer {
         }
     }
 
-    #[allow(dead_code)]
     fn push(&mut self, b: u8) -> bool {
         if self.len == BUF_SIZE {
             return false;
@@ -45,7 +51,105 @@ impl RingBuffer {
     }
 }
 
+#[derive(Copy, Clone, Debug)]
+enum Hotkey {
+    SwitchVt(usize),
+    // Scroll the console view by half a screen; 1 is back, -1 forward.
+    Scroll(isize),
+}
+
+// Key chords handled by the console, in xterm's encoding: Alt+F1..Alt+F6
+// switch virtual consoles and Shift+PageUp/PageDown scroll back.
+const CHORDS: [(&[u8], Hotkey); 8] = [
+    (b"\x1b[1;3P", Hotkey::SwitchVt(0)),
+    (b"\x1b[1;3Q", Hotkey::SwitchVt(1)),
+    (b"\x1b[1;3R", Hotkey::SwitchVt(2)),
+    (b"\x1b[1;3S", Hotkey::SwitchVt(3)),
+    (b"\x1b[15;3~", Hotkey::SwitchVt(4)),
+    (b"\x1b[17;3~", Hotkey::SwitchVt(5)),
+    (b"\x1b[5;2~", Hotkey::Scroll(1)),
+    (b"\x1b[6;2~", Hotkey::Scroll(-1)),
+];
+const CHORD_MAX: usize = 8;
+// A partial chord older than this was a plain Escape key.
+const CHORD_TIMEOUT_MS: u64 = 50;
+
+// Bytes that may be the start of a chord, held back from readers.
+struct Chord {
+    buf: [u8; CHORD_MAX],
+    len: usize,
+    since_ms: u64,
+    // Hotkey waiting to run; a busy console switch is retried on the next poll.
+    pending: Option<Hotkey>,
+}
+
+impl Chord {
+    const fn new() -> Self {
+        Self {
+            buf: [0; CHORD_MAX],
+            len: 0,
+            since_ms: 0,
+            pending: None,
+        }
+    }
+
+    fn feed(&mut self, b: u8, out: &mut RingBuffer) {
+        // Hold bytes while they prefix a chord; hand them over otherwise.
+        if self.len == 0 {
+            if b != 0x1b {
+                out.push(b);
+                return;
+            }
+            self.since_ms = timer::uptime_ms();
+        }
+        self.buf[self.len] = b;
+        self.len += 1;
+        let held = &self.buf[..self.len];
+        if let Some((_, hotkey)) = CHORDS.iter().find(|(chord, _)| *chord == held) {
+            self.len = 0;
+            self.pending = Some(*hotkey);
+            return;
+        }
+        if CHORDS.iter().any(|(chord, _)| chord.starts_with(held)) {
+            return;
+        }
+        // A new ESC may start a chord of its own.
+        let restart = b == 0x1b && self.len > 1;
+        let keep = if restart { self.len - 1 } else { self.len };
+        self.flush_n(keep, out);
+        if restart {
+            self.buf[0] = b;
+            self.len = 1;
+            self.since_ms = timer::uptime_ms();
+        }
+    }
+
+    fn expire(&mut self, out: &mut RingBuffer) {
+        if self.len > 0 && timer::uptime_ms() - self.since_ms >= CHORD_TIMEOUT_MS {
+            self.flush_n(self.len, out);
+        }
+    }
+
+    fn flush_n(&mut self, n: usize, out: &mut RingBuffer) {
+        for &b in &self.buf[..n] {
+            out.push(b);
+        }
+        self.len = 0;
+    }
+}
+
 static INPUT_BUF: SpinLock<RingBuffer> = SpinLock::new(RingBuffer::new());
+// Pollers waiting for input; only touched with INPUT_BUF held.
+static INPUT_WAIT: SpinLock<WaitQueue> = SpinLock::new(WaitQueue::new());
+// Hotkey chords; only touched with INPUT_BUF held.
+static CHORD: SpinLock<Chord> = SpinLock::new(Chord::new());
+// Copy of every byte the UART receives, taken ahead of the chord filter,
+// for /dev/ttyAMA0. Only kept while it is open, so the consoles never lose
+// input to it and a new opener sees no stale bytes.
+static RAW_BUF: SpinLock<RingBuffer> = SpinLock::new(RingBuffer::new());
+// Raw readers waiting for input; only touched with RAW_BUF held.
+static RAW_WAIT: SpinLock<WaitQueue> = SpinLock::new(WaitQueue::new());
+static RAW_OPEN: AtomicUsize = AtomicUsize::new(0);
 
 pub fn poll() {
     // Poll the UART for input and push bytes into the ring buffer.
@@ -55,26 +159,77 @@ pub fn poll() {
             Some(buf) => buf,
             None => return,
         };
+        let mut raw = match RAW_BUF.try_lock() {
+            Some(raw) => raw,
+            None => return,
+        };
+        let tee = RAW_OPEN.load(Ordering::Acquire) > 0;
+        let mut chord = CHORD.lock();
         let mut spins = 0usize;
+        let before = buf.len;
+        let raw_before = raw.len;
         loop {
-            let mut byte = match uart::read_byte_nonblocking() {
+            // Bytes are stored raw; the TTY layer maps CR to NL.
+            if buf.len + CHORD_MAX > BUF_SIZE {
+                break;
+            }
+            let byte = match uart::read_byte_nonblocking() {
                 Some(b) => b,
                 None => break,
             };
-            if byte == b'\r' {
-                byte = b'\n';
-            }
-            if !buf.push(byte) {
-                break;
+            // A full raw buffer drops bytes rather than stall the consoles.
+            if tee {
+                raw.push(byte);
             }
+            chord.feed(byte, &mut buf);
             spins += 1;
             if spins >= BUF_SIZE {
                 break;
             }
         }
+        chord.expire(&mut buf);
+        let done = match chord.pending {
+            Some(Hotkey::SwitchVt(vt)) => console::switch_vt(vt),
+            Some(Hotkey::Scroll(dir)) => {
+                console::scroll(dir);
+                true
+            }
+            None => true,
+        };
+        if done {
+            chord.pending = None;
+        }
+        drop(chord);
+        let waiters = if buf.len > before {
+            INPUT_WAIT.lock().take()
+        } else {
+            WaitQueue::new()
+        };
+        drop(buf);
+        let raw_waiters = if raw.len > raw_before {
+            RAW_WAIT.lock().take()
+        } else {
+            WaitQueue::new()
+        };
+        drop(raw);
+        waiters.wake_all();
+        raw_waiters.wake_all();
     }
 }
 
+pub fn readable(waiter: Option<ProcessId>) -> bool {
+    // True with input buffered; otherwise `waiter` is woken when some arrives.
+    poll();
+    let buf = INPUT_BUF.lock();
+    if buf.len > 0 {
+        return true;
+    }
+    if let Some(pid) = waiter {
+        INPUT_WAIT.lock().add(pid);
+    }
+    false
+}
+
 pub fn read(out: &mut [u8]) -> usize {
     // Read buffered input into the provided slice.
     poll();
@@ -91,3 +246,73 @@ pub fn read(out: &mut [u8]) -> usize {
     }
     count
 }
+
+pub fn open_raw() {
+    RAW_OPEN.fetch_add(1, Ordering::AcqRel);
+}
+
+pub fn close_raw() {
+    // The last close drops whatever nobody read.
+    if RAW_OPEN.fetch_sub(1, Ordering::AcqRel) == 1 {
+        *RAW_BUF.lock() = RingBuffer::new();
+    }
+}
+
+pub fn raw_readable(waiter: Option<ProcessId>) -> bool {
+    // Like `readable`, for the raw UART copy.
+    poll();
+    let buf = RAW_BUF.lock();
+    if buf.len > 0 {
+        return true;
+    }
+    if let Some(pid) = waiter {
+        RAW_WAIT.lock().add(pid);
+    }
+    false
+}
+
+pub fn read_raw(out: &mut [u8]) -> usize {
+    poll();
+    let mut buf = RAW_BUF.lock();
+    let mut count = 0;
+    for slot in out.iter_mut() {
+        match buf.pop() {
+            Some(b) => {
+                *slot = b;
+                count += 1;
+            }
+            None => break,
+        }
+    }
+    count
+}
+
+struct KeyboardDevice;
+
+static KEYBOARD_DEVICE: KeyboardDevice = KeyboardDevice;
+
+impl CharDevice for KeyboardDevice {
+    fn read(&self, _minor: u32, buf: &mut [u8]) -> VfsResult<usize> {
+        // Wait for input rather than report end of file.
+        match read(buf) {
+            0 if !buf.is_empty() && !readable(process::current_pid()) => Err(VfsError::WouldBlock),
+            n => Ok(n),
+        }
+    }
+
+    fn write(&self, _minor: u32, _buf: &[u8]) -> VfsResult<usize> {
+        Ok(0)
+    }
+
+    fn poll(&self, _minor: u32, waiter: Option<ProcessId>) -> u16 {
+        if readable(waiter) {
+            POLLIN
+        } else {
+            0
+        }
+    }
+}
+
+pub fn register_devices() {
+    devfs::register_char("kbd0", DevNum::new(MAJOR_INPUT, 0), &KEYBOARD_DEVICE);
+}
@@ -1,4 +1,13 @@
 #[cfg(feature = "qemu")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "qemu")]
+use crate::arch::aarch64::timer;
+#[cfg(feature = "qemu")]
+use crate::driver;
+#[cfg(feature = "qemu")]
+use crate::drivers::device::{Device, Driver, ProbeError};
+#[cfg(feature = "qemu")]
 use crate::drivers::mmio::{read32, write32};
 
 #[cfg(feature = "qemu")]
@@ -10,22 +19,44 @@ const IRQ_SOURCE_OFFSET: usize = 0x60;
 #[cfg(feature = "qemu")]
 const CORE_STRIDE: usize = 0x4;
 #[cfg(feature = "qemu")]
-const CNTP_IRQ_BIT: u32 = 1 << 1; // CNTPNS
+const CNTP_IRQ: u32 = 1; // CNTPNS
+
+// The board's address until the device tree's controller is probed.
+#[cfg(feature = "qemu")]
+static BASE: AtomicUsize = AtomicUsize::new(LOCAL_BASE);
+
+#[cfg(feature = "qemu")]
+driver!(static LOCAL_INTC = Driver {
+    name: "bcm2836-l1-intc",
+    compatible: &["brcm,bcm2836-l1-intc"],
+    probe,
+});
+
+#[cfg(feature = "qemu")]
+fn probe(dev: Device) -> Result<(), ProbeError> {
+    BASE.store(dev.mmio(0)?.base, Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(feature = "qemu")]
+fn timer_irq_bit() -> u32 {
+    1 << timer::irq().unwrap_or(CNTP_IRQ)
+}
 
 #[cfg(feature = "qemu")]
 pub fn enable_generic_timer_irq(core: usize) {
     // Route the generic timer interrupt to the specified core (QEMU).
-    let addr = LOCAL_BASE + TIMER_INT_CTRL_OFFSET + (core * CORE_STRIDE);
+    let addr = BASE.load(Ordering::Relaxed) + TIMER_INT_CTRL_OFFSET + (core * CORE_STRIDE);
     unsafe {
-        write32(addr, CNTP_IRQ_BIT);
+        write32(addr, timer_irq_bit());
     }
 }
 
 #[cfg(feature = "qemu")]
 pub fn generic_timer_pending(core: usize) -> bool {
     // Check if the generic timer IRQ is pending for this core.
-    let addr = LOCAL_BASE + IRQ_SOURCE_OFFSET + (core * CORE_STRIDE);
-    unsafe { (read32(addr) & CNTP_IRQ_BIT) != 0 }
+    let addr = BASE.load(Ordering::Relaxed) + IRQ_SOURCE_OFFSET + (core * CORE_STRIDE);
+    unsafe { (read32(addr) & timer_irq_bit()) != 0 }
 }
 
 #[cfg(not(feature = "qemu"))]
@@ -1,5 +1,7 @@
-use core::sync::atomic::{compiler_fence, Ordering};
+use core::sync::atomic::{compiler_fence, AtomicUsize, Ordering};
 
+use crate::driver;
+use crate::drivers::device::{Device, Driver, ProbeError};
 use crate::drivers::mmio::{read32, write32};
 use crate::platform::board::{MBOX_BASE, VC_MEM_BASE, VC_MEM_MASK};
 
@@ -14,6 +16,20 @@ const MBOX_CH_PROPERTY: u32 = 8;
 const MBOX_RESPONSE_OK: u32 = 0x8000_0000;
 const SPIN_LIMIT: usize = 1_000_000;
 
+// The board's address until the device tree's mailbox is probed.
+static BASE: AtomicUsize = AtomicUsize::new(MBOX_BASE);
+
+driver!(static MAILBOX = Driver {
+    name: "bcm2835-mbox",
+    compatible: &["brcm,bcm2835-mbox"],
+    probe,
+});
+
+fn probe(dev: Device) -> Result<(), ProbeError> {
+    BASE.store(dev.mmio(0)?.base, Ordering::Relaxed);
+    Ok(())
+}
+
 pub fn call(buffer: *mut u32) -> bool {
     // Perform a mailbox property channel call with the provided buffer.
     let addr = buffer as usize;
@@ -22,28 +38,29 @@ pub fn call(buffer: *mut u32) -> bool {
     }
 
     let bus_addr = arm_to_vc(addr) | MBOX_CH_PROPERTY;
+    let base = BASE.load(Ordering::Relaxed);
 
     compiler_fence(Ordering::SeqCst);
 
     unsafe {
         let mut spins = 0usize;
-        while read32(MBOX_BASE + MBOX_STATUS) & MBOX_STATUS_FULL != 0 {
+        while read32(base + MBOX_STATUS) & MBOX_STATUS_FULL != 0 {
             spins += 1;
             if spins >= SPIN_LIMIT {
                 return false;
             }
         }
-        write32(MBOX_BASE + MBOX_WRITE, bus_addr);
+        write32(base + MBOX_WRITE, bus_addr);
 
         let mut loops = 0usize;
         loop {
-            while read32(MBOX_BASE + MBOX_STATUS) & MBOX_STATUS_EMPTY != 0 {
+            while read32(base + MBOX_STATUS) & MBOX_STATUS_EMPTY != 0 {
                 spins += 1;
                 if spins >= SPIN_LIMIT {
                     return false;
                 }
             }
-            let resp = read32(MBOX_BASE + MBOX_READ);
+            let resp = read32(base + MBOX_READ);
             if (resp & 0xF) == MBOX_CH_PROPERTY && (resp & !0xF) == (bus_addr & !0xF) {
                 let status = core::ptr::read_volatile(buffer.add(1));
                 return status == MBOX_RESPONSE_OK;
@@ -0,0 +1,39 @@
+use crate::kernel::vfs::devfs::{self, CharDevice, DevNum, MAJOR_MEM};
+use crate::kernel::vfs::{VfsError, VfsResult};
+
+const MINOR_NULL: u32 = 3;
+const MINOR_ZERO: u32 = 5;
+const MINOR_FULL: u32 = 7;
+
+struct MemDevice;
+
+static MEM_DEVICE: MemDevice = MemDevice;
+
+impl CharDevice for MemDevice {
+    fn read(&self, minor: u32, buf: &mut [u8]) -> VfsResult<usize> {
+        match minor {
+            // /dev/null is always at EOF.
+            MINOR_NULL => Ok(0),
+            // /dev/zero and /dev/full read as an endless stream of zeroes.
+            MINOR_ZERO | MINOR_FULL => {
+                buf.fill(0);
+                Ok(buf.len())
+            }
+            _ => Err(VfsError::NoDevice),
+        }
+    }
+
+    fn write(&self, minor: u32, buf: &[u8]) -> VfsResult<usize> {
+        match minor {
+            MINOR_NULL | MINOR_ZERO => Ok(buf.len()),
+            MINOR_FULL => Err(VfsError::NoSpace),
+            _ => Err(VfsError::NoDevice),
+        }
+    }
+}
+
+pub fn register_devices() {
+    devfs::register_char("null", DevNum::new(MAJOR_MEM, MINOR_NULL), &MEM_DEVICE);
+    devfs::register_char("zero", DevNum::new(MAJOR_MEM, MINOR_ZERO), &MEM_DEVICE);
+    devfs::register_char("full", DevNum::new(MAJOR_MEM, MINOR_FULL), &MEM_DEVICE);
+}
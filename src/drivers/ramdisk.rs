@@ -0,0 +1,84 @@
+// RAM-backed block device, /dev/ram0, like Linux's brd. Blocks take a frame
+// on first write; blocks never written read as zeroes.
+
+use alloc::collections::BTreeMap;
+
+use crate::drivers::block::{self, BlockDevice, MAJOR_RAMDISK};
+use crate::kernel::params::U32Param;
+use crate::kernel::vfs::devfs::DevNum;
+use crate::kernel_param;
+use crate::mm::frame;
+use crate::mm::layout::{phys_to_virt, PAGE_SIZE};
+use crate::util::sync::SpinLock;
+use crate::{kinfo, kwarn};
+
+// Size of /dev/ram0 in KiB, as Linux's ramdisk_size; 0 leaves it out.
+kernel_param!(
+    static RAMDISK_SIZE: U32Param = U32Param::range(16 * 1024, 0, 1024 * 1024),
+    "ramdisk_size"
+);
+
+struct RamDisk {
+    // Frame holding each block written so far.
+    blocks: SpinLock<BTreeMap<u64, u64>>,
+}
+
+static RAM0: RamDisk = RamDisk {
+    blocks: SpinLock::new(BTreeMap::new()),
+};
+
+fn block_data(paddr: u64) -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(phys_to_virt(paddr) as *mut u8, PAGE_SIZE) }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        PAGE_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        RAMDISK_SIZE.get() as u64 * 1024 / PAGE_SIZE as u64
+    }
+
+    fn read_block(&self, block: u64, buf: &mut [u8]) -> bool {
+        if block >= self.block_count() {
+            return false;
+        }
+        match self.blocks.lock().get(&block) {
+            Some(&paddr) => buf.copy_from_slice(&block_data(paddr)[..buf.len()]),
+            None => buf.fill(0),
+        }
+        true
+    }
+
+    fn write_block(&self, block: u64, buf: &[u8]) -> bool {
+        // Called with the page cache locked, so the frame comes from
+        // `try_alloc_frame`: reclaiming would need that lock.
+        if block >= self.block_count() {
+            return false;
+        }
+        let mut blocks = self.blocks.lock();
+        let paddr = match blocks.get(&block) {
+            Some(&paddr) => paddr,
+            None => {
+                let Some(paddr) = frame::try_alloc_frame() else {
+                    return false;
+                };
+                blocks.insert(block, paddr);
+                paddr
+            }
+        };
+        block_data(paddr)[..buf.len()].copy_from_slice(buf);
+        true
+    }
+}
+
+pub fn register_devices() {
+    if RAMDISK_SIZE.get() == 0 {
+        return;
+    }
+    match block::register("ram0", DevNum::new(MAJOR_RAMDISK, 0), &RAM0) {
+        Some(_) => kinfo!("ram0: {} KiB RAM disk", RAMDISK_SIZE.get()),
+        None => kwarn!("ram0: no free block device slot"),
+    }
+}
@@ -3,7 +3,14 @@
 use core::fmt;
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+use crate::driver;
+use crate::drivers::device::{Clock, Device, Driver, ProbeError};
+use crate::drivers::keyboard;
 use crate::drivers::mmio::{read32, write32};
+use crate::kernel::process::{self, ProcessId};
+use crate::kernel::vfs::devfs::{self, CharDevice, DevNum, MAJOR_AMA};
+use crate::kernel::vfs::file::FileId;
+use crate::kernel::vfs::{VfsError, VfsResult, POLLIN, POLLOUT};
 use crate::platform::board::UART_BASE;
 use crate::util::sync::SpinLock;
 
@@ -12,6 +19,7 @@ use crate::platform::board::GPIO_BASE;
 
 const UART_DR: usize = 0x00;
 const UART_FR: usize = 0x18;
+const UART_FR_BUSY: u32 = 1 << 3;
 const UART_IBRD: usize = 0x24;
 const UART_FBRD: usize = 0x28;
 const UART_LCRH: usize = 0x2C;
@@ -28,6 +36,10 @@ const GPPUDCLK0: usize = 0x98;
 
 pub struct Uart;
 
+struct UartDevice;
+
+static UART_DEVICE: UartDevice = UartDevice;
+
 static UART_LOCK: SpinLock<()> = SpinLock::new(());
 #[cfg(feature = "qemu")]
 static UART_BASE_ADDR: AtomicUsize = AtomicUsize::new(UART_BASE);
@@ -188,6 +200,97 @@ impl fmt::Write for Uart {
     }
 }
 
+impl CharDevice for UartDevice {
+    fn read(&self, _minor: u32, buf: &mut [u8]) -> VfsResult<usize> {
+        // The timer tick drains RX into the keyboard driver, which keeps an
+        // unfiltered copy for us while the device is open. With nothing
+        // there yet the caller waits for the tick that brings some.
+        match keyboard::read_raw(buf) {
+            0 if !buf.is_empty() && !keyboard::raw_readable(process::current_pid()) => {
+                Err(VfsError::WouldBlock)
+            }
+            n => Ok(n),
+        }
+    }
+
+    fn write(&self, _minor: u32, buf: &[u8]) -> VfsResult<usize> {
+        // Raw device: bytes go out untranslated.
+        for &b in buf {
+            write_byte(b);
+        }
+        Ok(buf.len())
+    }
+
+    fn poll(&self, _minor: u32, waiter: Option<ProcessId>) -> u16 {
+        if keyboard::raw_readable(waiter) {
+            POLLIN | POLLOUT
+        } else {
+            POLLOUT
+        }
+    }
+
+    fn open(&self, _minor: u32) -> VfsResult<Option<DevNum>> {
+        keyboard::open_raw();
+        Ok(None)
+    }
+
+    fn close(&self, _minor: u32, _file: FileId) {
+        keyboard::close_raw();
+    }
+}
+
+driver!(static PL011 = Driver {
+    name: "pl011",
+    compatible: &["arm,pl011", "arm,pl011-axi"],
+    probe,
+});
+
+fn probe(dev: Device) -> Result<(), ProbeError> {
+    // Only the console UART is driven; it becomes ttyAMA0. Early init only
+    // saw a `clock-frequency` on the node; a fixed clock in `clocks` (the
+    // Pi 5 has one) sets the baud divisors right.
+    if dev.mmio(0)?.base != uart_base() {
+        return Err(ProbeError::Unsupported);
+    }
+    match dev.clock(0) {
+        Ok(Clock {
+            rate: Some(rate), ..
+        }) => set_clock(rate),
+        Ok(_) | Err(ProbeError::NoResource) => {}
+        Err(err) => return Err(err),
+    }
+    register_devices();
+    Ok(())
+}
+
+fn set_clock(clock_hz: u32) {
+    // Reprogram the baud rate for a newly learned reference clock, unless
+    // the firmware set the UART up.
+    if UART_CLOCK_HZ.swap(clock_hz as usize, Ordering::Relaxed) == clock_hz as usize
+        || UART_SKIP_INIT.load(Ordering::Relaxed)
+        || !is_ready()
+    {
+        return;
+    }
+    let _guard = UART_LOCK.lock();
+    let base = uart_base();
+    let (ibrd, fbrd) = baud_divisors(clock_hz, 115_200);
+    unsafe {
+        // Let the FIFO drain; new divisors take effect on the LCRH write.
+        let mut spins = 0u32;
+        while read32(reg_addr(base, UART_FR)) & UART_FR_BUSY != 0 && spins < 1_000_000 {
+            spins += 1;
+        }
+        write32(reg_addr(base, UART_IBRD), ibrd);
+        write32(reg_addr(base, UART_FBRD), fbrd);
+        write32(reg_addr(base, UART_LCRH), read32(reg_addr(base, UART_LCRH)));
+    }
+}
+
+pub fn register_devices() {
+    devfs::register_char("ttyAMA0", DevNum::new(MAJOR_AMA, 64), &UART_DEVICE);
+}
+
 pub fn with_uart<F: FnOnce(&mut Uart)>(f: F) {
     // Serialize access to the UART to avoid interleaved output.
     if !is_ready() {
@@ -1 +1,10 @@
+pub mod ansi;
+pub mod bmp;
 pub mod font;
+pub mod image;
+pub mod inflate;
+pub mod pixel;
+pub mod png;
+pub mod psf;
+pub mod surface;
+pub mod utf8;
@@ -0,0 +1,209 @@
+// VT100/xterm escape sequence parser. It only splits the byte stream into
+// printable bytes, control characters and sequences; `drivers::framebuffer`
+// decides what they do.
+
+pub const MAX_PARAMS: usize = 16;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    // After ESC plus an intermediate byte such as '(' in "ESC ( B".
+    EscapeIntermediate,
+    Csi,
+    // Malformed CSI: swallowed up to its final byte.
+    CsiIgnore,
+    // OSC, DCS and friends: strings are skipped up to BEL or ST.
+    String,
+    StringEscape,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Csi {
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    // '?' (DEC private) or another marker in front of the parameters.
+    pub private: Option<u8>,
+    pub final_byte: u8,
+}
+
+impl Csi {
+    const fn new() -> Self {
+        Self {
+            params: [0; MAX_PARAMS],
+            len: 0,
+            private: None,
+            final_byte: 0,
+        }
+    }
+
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+
+    pub fn param(&self, idx: usize, default: u16) -> u16 {
+        // Missing and zero parameters take the default, as VT100 counts do.
+        match self.params().get(idx) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Action {
+    Print(u8),
+    // C0 control character (BS, HT, LF, CR, BEL, ...).
+    Control(u8),
+    // ESC followed by a final byte ('7', '8', 'D', 'M', 'E', 'c').
+    Escape(u8),
+    Csi(Csi),
+}
+
+pub struct Parser {
+    state: State,
+    csi: Csi,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            csi: Csi::new(),
+        }
+    }
+
+    pub fn advance(&mut self, b: u8) -> Option<Action> {
+        // Feed one byte; returns what it completed, if anything. CAN and SUB
+        // abort a sequence and ESC restarts one from any state.
+        match b {
+            0x18 | 0x1a => {
+                self.state = State::Ground;
+                return None;
+            }
+            0x1b if self.state == State::String => {
+                self.state = State::StringEscape;
+                return None;
+            }
+            0x1b => {
+                self.state = State::Escape;
+                return None;
+            }
+            _ => {}
+        }
+        match self.state {
+            State::Ground => Some(match b {
+                0x00..=0x1f | 0x7f => Action::Control(b),
+                _ => Action::Print(b),
+            }),
+            State::Escape => self.escape(b),
+            State::EscapeIntermediate => {
+                // Charset designations and the like are not supported.
+                if (0x30..=0x7e).contains(&b) {
+                    self.state = State::Ground;
+                }
+                None
+            }
+            State::Csi => self.csi(b),
+            State::CsiIgnore => {
+                if (0x40..=0x7e).contains(&b) {
+                    self.state = State::Ground;
+                }
+                None
+            }
+            State::String => {
+                if b == 0x07 {
+                    self.state = State::Ground;
+                }
+                None
+            }
+            State::StringEscape => {
+                // ESC \ (ST) ends the string; anything else keeps skipping it.
+                self.state = if b == b'\\' {
+                    State::Ground
+                } else {
+                    State::String
+                };
+                None
+            }
+        }
+    }
+
+    fn escape(&mut self, b: u8) -> Option<Action> {
+        match b {
+            b'[' => {
+                self.csi = Csi::new();
+                self.state = State::Csi;
+                None
+            }
+            b']' | b'P' | b'X' | b'^' | b'_' => {
+                self.state = State::String;
+                None
+            }
+            0x20..=0x2f => {
+                self.state = State::EscapeIntermediate;
+                None
+            }
+            // C0 controls still act inside a sequence.
+            0x00..=0x1f => Some(Action::Control(b)),
+            _ => {
+                self.state = State::Ground;
+                Some(Action::Escape(b))
+            }
+        }
+    }
+
+    fn csi(&mut self, b: u8) -> Option<Action> {
+        match b {
+            b'0'..=b'9' => {
+                if self.csi.len == 0 {
+                    self.csi.len = 1;
+                }
+                let param = &mut self.csi.params[self.csi.len - 1];
+                *param = param.saturating_mul(10).saturating_add((b - b'0') as u16);
+                None
+            }
+            // ':' separates sub-parameters (38:2:r:g:b); they are read like ';'.
+            b';' | b':' => {
+                if self.csi.len == 0 {
+                    self.csi.len = 1;
+                }
+                if self.csi.len == MAX_PARAMS {
+                    self.state = State::CsiIgnore;
+                } else {
+                    self.csi.len += 1;
+                }
+                None
+            }
+            b'<'..=b'?' => {
+                if self.csi.len == 0 && self.csi.private.is_none() {
+                    self.csi.private = Some(b);
+                } else {
+                    self.state = State::CsiIgnore;
+                }
+                None
+            }
+            0x20..=0x2f => {
+                // Intermediate bytes select sequences that are not supported.
+                self.state = State::CsiIgnore;
+                None
+            }
+            0x40..=0x7e => {
+                self.state = State::Ground;
+                self.csi.final_byte = b;
+                Some(Action::Csi(self.csi))
+            }
+            0x00..=0x1f => Some(Action::Control(b)),
+            _ => {
+                self.state = State::CsiIgnore;
+                None
+            }
+        }
+    }
+}
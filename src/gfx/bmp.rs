@@ -0,0 +1,144 @@
+// Windows bitmap decoder: uncompressed (BI_RGB) and BI_BITFIELDS images at
+// 1, 4, 8, 16, 24 and 32 bits per pixel, bottom-up or top-down. RLE and
+// embedded JPEG/PNG bitmaps are not supported.
+
+use super::image::{Image, ImageError};
+use super::pixel;
+
+const FILE_HEADER_SIZE: usize = 14;
+// BITMAPINFOHEADER; later versions extend it, the older OS/2 one is smaller.
+const INFO_HEADER_SIZE: usize = 40;
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+fn u16_at(bytes: &[u8], off: usize) -> Result<u16, ImageError> {
+    let b = bytes.get(off..off + 2).ok_or(ImageError::Truncated)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(bytes: &[u8], off: usize) -> Result<u32, ImageError> {
+    let b = bytes.get(off..off + 4).ok_or(ImageError::Truncated)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// A channel given by a bit mask, widened to 8 bits.
+#[derive(Copy, Clone)]
+struct Channel {
+    mask: u32,
+    shift: u32,
+    max: u32,
+}
+
+impl Channel {
+    fn new(mask: u32) -> Self {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        Self {
+            mask,
+            shift,
+            max: (mask >> shift).max(1),
+        }
+    }
+
+    fn get(&self, px: u32, default: u32) -> u32 {
+        if self.mask == 0 {
+            return default;
+        }
+        // Widened: a mask of more than 24 bits overflows `* 255` in u32.
+        let value = ((px & self.mask) >> self.shift) as u64;
+        (value * 255 / self.max as u64) as u32
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    let offset = u32_at(bytes, 10)? as usize;
+    let header_size = u32_at(bytes, FILE_HEADER_SIZE)? as usize;
+    if header_size < INFO_HEADER_SIZE {
+        return Err(ImageError::Unsupported);
+    }
+    let info = FILE_HEADER_SIZE;
+    let width = u32_at(bytes, info + 4)? as i32;
+    let height = u32_at(bytes, info + 8)? as i32;
+    let bpp = u16_at(bytes, info + 14)?;
+    let compression = u32_at(bytes, info + 16)?;
+    let colors_used = u32_at(bytes, info + 32)? as usize;
+    if width <= 0 || height == 0 {
+        return Err(ImageError::Corrupt);
+    }
+    let (width, top_down) = (width as u32, height < 0);
+    let height = height.unsigned_abs();
+
+    // Masks follow a 40-byte header, or sit inside the larger ones.
+    let (red, green, blue, alpha) = match (compression, bpp) {
+        (BI_BITFIELDS, 16 | 32) => {
+            let masks = info + INFO_HEADER_SIZE;
+            let alpha = if header_size >= 56 {
+                u32_at(bytes, masks + 12)?
+            } else {
+                0
+            };
+            (
+                u32_at(bytes, masks)?,
+                u32_at(bytes, masks + 4)?,
+                u32_at(bytes, masks + 8)?,
+                alpha,
+            )
+        }
+        (BI_RGB, 16) => (0x7c00, 0x03e0, 0x001f, 0),
+        (BI_RGB, 32) => (0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0),
+        (BI_RGB, 1 | 4 | 8 | 24) => (0, 0, 0, 0),
+        _ => return Err(ImageError::Unsupported),
+    };
+    let channels = [red, green, blue, alpha].map(Channel::new);
+
+    let mut palette = [0u32; 256];
+    if bpp <= 8 {
+        let entries = match colors_used {
+            0 => 1 << bpp,
+            n => n.min(1 << bpp),
+        };
+        let start = info + header_size;
+        for (idx, entry) in palette.iter_mut().take(entries).enumerate() {
+            // Palette entries are B, G, R, reserved.
+            let bgr = u32_at(bytes, start + idx * 4)?;
+            *entry = 0xff00_0000 | (bgr & 0x00ff_ffff);
+        }
+    }
+
+    // Allocating first also bounds the size for the checks below.
+    let mut image = Image::new(width, height)?;
+    let stride = (width as usize * bpp as usize).div_ceil(32) * 4;
+    let needed = offset + stride * height as usize;
+    if bytes.len() < needed {
+        return Err(ImageError::Truncated);
+    }
+    for y in 0..height {
+        let src_row = if top_down { y } else { height - 1 - y };
+        let row = &bytes[offset + src_row as usize * stride..][..stride];
+        for x in 0..width {
+            let color = match bpp {
+                1 | 4 | 8 => {
+                    let bit = x as usize * bpp as usize;
+                    let byte = row[bit / 8];
+                    let idx = (byte >> (8 - bpp as usize - bit % 8)) & ((1u16 << bpp) - 1) as u8;
+                    palette[idx as usize]
+                }
+                24 => {
+                    let p = &row[x as usize * 3..];
+                    pixel::rgb(p[2], p[1], p[0])
+                }
+                _ => {
+                    let px = if bpp == 16 {
+                        u16::from_le_bytes([row[x as usize * 2], row[x as usize * 2 + 1]]) as u32
+                    } else {
+                        let p = &row[x as usize * 4..];
+                        u32::from_le_bytes([p[0], p[1], p[2], p[3]])
+                    };
+                    let [r, g, b, a] = channels;
+                    r.get(px, 0) << 16 | g.get(px, 0) << 8 | b.get(px, 0) | a.get(px, 255) << 24
+                }
+            };
+            image.set(x, y, color);
+        }
+    }
+    Ok(image)
+}
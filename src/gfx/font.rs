@@ -1,6 +1,8 @@
-// 8x8 ASCII font (Menlo size 8, 1-bit render)
-pub const FONT_WIDTH: usize = 8;
-pub const FONT_HEIGHT: usize = 16;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+// Built-in 8x8 ASCII font (Menlo size 8, 1-bit render)
+const BUILTIN_SIZE: usize = 8;
 
 const FONT: [[u8; 8]; 128] = [
     [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // 0x00
@@ -98,7 +100,7 @@ const FONT: [[u8; 8]; 128] = [
     [0x00, 0x40, 0x20, 0x20, 0x30, 0x10, 0x10, 0x08], // 0x5C
     [0x30, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x30], // 0x5D
     [0x00, 0x10, 0x38, 0x48, 0x00, 0x00, 0x00, 0x00], // 0x5E
-    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // 0x5F
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7E], // 0x5F
     [0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // 0x60
     [0x00, 0x00, 0x00, 0x3C, 0x3C, 0x24, 0x3C, 0x00], // 0x61
     [0x20, 0x20, 0x20, 0x38, 0x24, 0x24, 0x38, 0x00], // 0x62
@@ -133,11 +135,79 @@ const FONT: [[u8; 8]; 128] = [
     [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // 0x7F
 ];
 
-#[inline]
-pub fn glyph(c: u8) -> [u8; 8] {
-    if c < 128 {
-        FONT[c as usize]
-    } else {
-        FONT[b"?"[0] as usize]
+// Hollow box shown for code points the built-in font lacks.
+const BUILTIN_REPLACEMENT: [u8; 8] = [0x00, 0x7E, 0x42, 0x42, 0x42, 0x42, 0x7E, 0x00];
+
+// A bitmap font: `count` glyphs of `height` rows, each row padded to whole
+// bytes with the leftmost pixel in the top bit.
+pub struct Font {
+    pub width: usize,
+    pub height: usize,
+    row_bytes: usize,
+    count: usize,
+    data: Vec<u8>,
+    // Code point to glyph. Fonts without a Unicode table map glyph n to
+    // code point n.
+    map: Option<BTreeMap<char, u32>>,
+    replacement: usize,
+}
+
+impl Font {
+    pub fn new(
+        width: usize,
+        height: usize,
+        data: Vec<u8>,
+        map: Option<BTreeMap<char, u32>>,
+    ) -> Self {
+        let row_bytes = width.div_ceil(8);
+        let count = data.len() / (row_bytes * height).max(1);
+        let mut font = Self {
+            width,
+            height,
+            row_bytes,
+            count,
+            data,
+            map,
+            replacement: 0,
+        };
+        // U+FFFD if the font has it, else '?', else glyph 0.
+        font.replacement = font
+            .index('\u{fffd}')
+            .or_else(|| font.index('?'))
+            .unwrap_or(0);
+        font
+    }
+
+    pub fn builtin() -> Self {
+        // The compiled-in ASCII font, plus a box as glyph 128 for the rest.
+        let mut data = Vec::with_capacity((FONT.len() + 1) * BUILTIN_SIZE);
+        for glyph in FONT.iter().chain(core::iter::once(&BUILTIN_REPLACEMENT)) {
+            data.extend_from_slice(glyph);
+        }
+        let mut map = BTreeMap::new();
+        for c in 0x20u8..0x7f {
+            map.insert(c as char, c as u32);
+        }
+        map.insert('\u{fffd}', FONT.len() as u32);
+        Self::new(BUILTIN_SIZE, BUILTIN_SIZE, data, Some(map))
+    }
+
+    pub fn row_bytes(&self) -> usize {
+        self.row_bytes
+    }
+
+    fn index(&self, ch: char) -> Option<usize> {
+        let idx = match &self.map {
+            Some(map) => *map.get(&ch)? as usize,
+            None => ch as usize,
+        };
+        (idx < self.count).then_some(idx)
+    }
+
+    pub fn glyph(&self, ch: char) -> &[u8] {
+        // Bitmap for `ch`, or the replacement glyph if the font lacks it.
+        let idx = self.index(ch).unwrap_or(self.replacement);
+        let size = self.row_bytes * self.height;
+        &self.data[idx * size..(idx + 1) * size]
     }
 }
@@ -0,0 +1,80 @@
+// Decoded images: A8R8G8B8 pixels in memory, ready to blit through a
+// `Surface`. `decode` picks the format from the file's magic bytes.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::pixel::Format;
+use super::surface::Surface;
+use super::{bmp, png};
+
+// Pixel memory of one image; the kernel heap is small.
+pub const MAX_IMAGE_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ImageError {
+    BadMagic,
+    Truncated,
+    Corrupt,
+    Unsupported,
+    TooLarge,
+}
+
+pub struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Result<Self, ImageError> {
+        // A transparent image, or TooLarge past `MAX_IMAGE_BYTES`.
+        let bytes = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .filter(|&bytes| bytes <= MAX_IMAGE_BYTES)
+            .ok_or(ImageError::TooLarge)?;
+        if bytes == 0 {
+            return Err(ImageError::Unsupported);
+        }
+        Ok(Self {
+            width,
+            height,
+            data: vec![0; bytes],
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn surface(&mut self) -> Surface<'_> {
+        Surface::new(
+            &mut self.data,
+            self.width,
+            self.height,
+            self.width * 4,
+            Format::A8R8G8B8,
+        )
+        .expect("image buffer matches its size")
+    }
+
+    pub(super) fn set(&mut self, x: u32, y: u32, color: u32) {
+        let off = (y as usize * self.width as usize + x as usize) * 4;
+        self.data[off..off + 4].copy_from_slice(&color.to_le_bytes());
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    if bytes.starts_with(&png::SIGNATURE) {
+        png::decode(bytes)
+    } else if bytes.starts_with(b"BM") {
+        bmp::decode(bytes)
+    } else {
+        Err(ImageError::BadMagic)
+    }
+}
@@ -0,0 +1,313 @@
+// zlib/DEFLATE decompressor (RFC 1950/1951) for PNG image data: stored,
+// fixed-Huffman and dynamic-Huffman blocks. Output is bounded by the caller.
+
+use alloc::vec::Vec;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InflateError {
+    Truncated,
+    Corrupt,
+    // More output than the caller allowed.
+    TooLarge,
+}
+
+const MAX_BITS: usize = 15;
+
+// Base lengths and extra bits of length codes 257..285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// Base distances and extra bits of distance codes 0..29.
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order in which code length code lengths are stored.
+const CLEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+    nbits: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            bit: 0,
+            nbits: 0,
+        }
+    }
+
+    fn need(&mut self, n: u32) -> Result<(), InflateError> {
+        while self.nbits < n {
+            let byte = *self.data.get(self.pos).ok_or(InflateError::Truncated)?;
+            self.pos += 1;
+            self.bit |= (byte as u32) << self.nbits;
+            self.nbits += 8;
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, n: u32) -> Result<u32, InflateError> {
+        // `n` bits, least significant first; n <= 16.
+        if n == 0 {
+            return Ok(0);
+        }
+        self.need(n)?;
+        let value = self.bit & ((1 << n) - 1);
+        self.bit >>= n;
+        self.nbits -= n;
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        // Drop the rest of the current byte.
+        let drop = self.nbits % 8;
+        self.bit >>= drop;
+        self.nbits -= drop;
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], InflateError> {
+        // Whole bytes after `align`; buffered bits are always whole bytes.
+        let back = (self.nbits / 8) as usize;
+        self.pos -= back;
+        self.bit = 0;
+        self.nbits = 0;
+        let out = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or(InflateError::Truncated)?;
+        self.pos += n;
+        Ok(out)
+    }
+}
+
+// Canonical Huffman code: symbol counts per length and symbols by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        // Over-subscribed codes are corrupt; incomplete ones are allowed.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(InflateError::Corrupt);
+            }
+        }
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = alloc::vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, InflateError> {
+        // Walk the code one bit at a time (codes are stored MSB first).
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..=MAX_BITS {
+            code |= bits.read(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::Corrupt)
+    }
+}
+
+pub fn zlib(data: &[u8], limit: usize) -> Result<Vec<u8>, InflateError> {
+    // Check the 2-byte header and the Adler-32 trailer around a DEFLATE
+    // stream. Preset dictionaries are not supported.
+    let header = data.get(..2).ok_or(InflateError::Truncated)?;
+    let (cmf, flg) = (header[0], header[1]);
+    if cmf & 0x0f != 8 || (cmf as u16 * 256 + flg as u16) % 31 != 0 || flg & 0x20 != 0 {
+        return Err(InflateError::Corrupt);
+    }
+    let (out, used) = inflate(&data[2..], limit)?;
+    let trailer = data
+        .get(2 + used..2 + used + 4)
+        .ok_or(InflateError::Truncated)?;
+    if u32::from_be_bytes(trailer.try_into().unwrap()) != adler32(&out) {
+        return Err(InflateError::Corrupt);
+    }
+    Ok(out)
+}
+
+pub fn inflate(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), InflateError> {
+    // Raw DEFLATE; returns the output and the input bytes consumed.
+    let mut bits = Bits::new(data);
+    let mut out = Vec::new();
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let len = bits.bytes(4)?;
+                let n = u16::from_le_bytes([len[0], len[1]]);
+                let check = u16::from_le_bytes([len[2], len[3]]);
+                if n != !check {
+                    return Err(InflateError::Corrupt);
+                }
+                if out.len() + n as usize > limit {
+                    return Err(InflateError::TooLarge);
+                }
+                out.extend_from_slice(bits.bytes(n as usize)?);
+            }
+            1 => {
+                let (lit, dist) = fixed_codes()?;
+                block(&mut bits, &mut out, &lit, &dist, limit)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic_codes(&mut bits)?;
+                block(&mut bits, &mut out, &lit, &dist, limit)?;
+            }
+            _ => return Err(InflateError::Corrupt),
+        }
+        if last {
+            break;
+        }
+    }
+    bits.align();
+    let used = bits.pos - (bits.nbits / 8) as usize;
+    Ok((out, used))
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), InflateError> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), InflateError> {
+    let nlen = bits.read(5)? as usize + 257;
+    let ndist = bits.read(5)? as usize + 1;
+    let nclen = bits.read(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 {
+        return Err(InflateError::Corrupt);
+    }
+    let mut clens = [0u8; 19];
+    for &idx in &CLEN_ORDER[..nclen] {
+        clens[idx] = bits.read(3)? as u8;
+    }
+    let clen = Huffman::new(&clens)?;
+    let mut lengths = [0u8; 286 + 30];
+    let mut n = 0;
+    while n < nlen + ndist {
+        let symbol = clen.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let prev = *lengths[..n].last().ok_or(InflateError::Corrupt)?;
+                (prev, 3 + bits.read(2)? as usize)
+            }
+            17 => (0, 3 + bits.read(3)? as usize),
+            _ => (0, 11 + bits.read(7)? as usize),
+        };
+        let end = n + repeat;
+        if end > nlen + ndist {
+            return Err(InflateError::Corrupt);
+        }
+        lengths[n..end].fill(value);
+        n = end;
+    }
+    if lengths[256] == 0 {
+        return Err(InflateError::Corrupt);
+    }
+    let lit = Huffman::new(&lengths[..nlen])?;
+    let dist = Huffman::new(&lengths[nlen..nlen + ndist])?;
+    Ok((lit, dist))
+}
+
+fn block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    lit: &Huffman,
+    dist: &Huffman,
+    limit: usize,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = lit.decode(bits)? as usize;
+        if symbol < 256 {
+            if out.len() == limit {
+                return Err(InflateError::TooLarge);
+            }
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let idx = symbol - 257;
+        if idx >= LENGTH_BASE.len() {
+            return Err(InflateError::Corrupt);
+        }
+        let len = LENGTH_BASE[idx] as usize + bits.read(LENGTH_EXTRA[idx] as u32)? as usize;
+        let code = dist.decode(bits)? as usize;
+        if code >= DIST_BASE.len() {
+            return Err(InflateError::Corrupt);
+        }
+        let back = DIST_BASE[code] as usize + bits.read(DIST_EXTRA[code] as u32)? as usize;
+        if back > out.len() {
+            return Err(InflateError::Corrupt);
+        }
+        if out.len() + len > limit {
+            return Err(InflateError::TooLarge);
+        }
+        // Byte by byte: the copy may overlap what it produces.
+        let start = out.len() - back;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
@@ -0,0 +1,73 @@
+// Pixel formats and colour arithmetic. Colours are passed around as
+// 0xAARRGGBB words; formats without alpha read back as opaque.
+
+pub use crate::platform::simplefb::SimpleFbFormat as Format;
+
+pub const fn argb(a: u8, r: u8, g: u8, b: u8) -> u32 {
+    (a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+pub const fn rgb(r: u8, g: u8, b: u8) -> u32 {
+    argb(0xff, r, g, b)
+}
+
+pub const fn alpha(color: u32) -> u8 {
+    (color >> 24) as u8
+}
+
+pub fn encode(format: Format, color: u32) -> u32 {
+    // Colour to the raw pixel value stored in memory.
+    match format {
+        Format::X8R8G8B8 => color & 0x00ff_ffff,
+        Format::A8R8G8B8 => color,
+        Format::R5G6B5 => {
+            let r = (color >> 19) & 0x1f;
+            let g = (color >> 10) & 0x3f;
+            let b = (color >> 3) & 0x1f;
+            r << 11 | g << 5 | b
+        }
+    }
+}
+
+pub fn decode(format: Format, raw: u32) -> u32 {
+    // Raw pixel value to a colour. RGB565 channels are widened by
+    // repeating their top bits, so white stays 0xffffff.
+    match format {
+        Format::X8R8G8B8 => raw | 0xff00_0000,
+        Format::A8R8G8B8 => raw,
+        Format::R5G6B5 => {
+            let r = (raw >> 11) & 0x1f;
+            let g = (raw >> 5) & 0x3f;
+            let b = raw & 0x1f;
+            let r = r << 3 | r >> 2;
+            let g = g << 2 | g >> 4;
+            let b = b << 3 | b >> 2;
+            0xff00_0000 | r << 16 | g << 8 | b
+        }
+    }
+}
+
+pub fn convert(from: Format, to: Format, raw: u32) -> u32 {
+    if from == to {
+        raw
+    } else {
+        encode(to, decode(from, raw))
+    }
+}
+
+pub fn blend(dst: u32, src: u32) -> u32 {
+    // `src` over `dst` with straight (non-premultiplied) alpha.
+    let a = alpha(src) as u32;
+    match a {
+        0xff => return src,
+        0 => return dst,
+        _ => {}
+    }
+    let mix = |shift: u32| {
+        let s = (src >> shift) & 0xff;
+        let d = (dst >> shift) & 0xff;
+        (s * a + d * (255 - a) + 127) / 255
+    };
+    let out_a = a + (alpha(dst) as u32 * (255 - a) + 127) / 255;
+    out_a << 24 | mix(16) << 16 | mix(8) << 8 | mix(0)
+}
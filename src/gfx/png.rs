@@ -0,0 +1,251 @@
+// PNG decoder: every colour type and bit depth, palettes and tRNS
+// transparency. Interlaced (Adam7) images are not supported and chunk CRCs
+// are not checked; the zlib stream's own checksum is.
+
+use alloc::vec::Vec;
+
+use super::image::{Image, ImageError, MAX_IMAGE_BYTES};
+use super::inflate::{self, InflateError};
+use super::pixel;
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const PALETTE: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+struct Header {
+    width: u32,
+    height: u32,
+    depth: u8,
+    color: u8,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color {
+            GRAY | PALETTE => 1,
+            GRAY_ALPHA => 2,
+            RGB => 3,
+            _ => 4,
+        }
+    }
+
+    fn stride(&self) -> usize {
+        // Bytes per row, without the filter byte.
+        (self.width as usize * self.channels() * self.depth as usize).div_ceil(8)
+    }
+}
+
+impl From<InflateError> for ImageError {
+    fn from(err: InflateError) -> Self {
+        match err {
+            InflateError::Truncated => ImageError::Truncated,
+            InflateError::Corrupt => ImageError::Corrupt,
+            InflateError::TooLarge => ImageError::TooLarge,
+        }
+    }
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, ImageError> {
+    if data.len() < 13 {
+        return Err(ImageError::Truncated);
+    }
+    let header = Header {
+        width: be32(&data[0..]),
+        height: be32(&data[4..]),
+        depth: data[8],
+        color: data[9],
+    };
+    let depth_ok = match header.color {
+        GRAY => matches!(header.depth, 1 | 2 | 4 | 8 | 16),
+        PALETTE => matches!(header.depth, 1 | 2 | 4 | 8),
+        RGB | GRAY_ALPHA | RGBA => matches!(header.depth, 8 | 16),
+        _ => false,
+    };
+    // Compression and filter method 0 are the only ones defined.
+    if !depth_ok || data[10] != 0 || data[11] != 0 {
+        return Err(ImageError::Corrupt);
+    }
+    if data[12] != 0 {
+        return Err(ImageError::Unsupported);
+    }
+    Ok(header)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut pos = SIGNATURE.len();
+    let mut header = None;
+    let mut palette: Vec<u32> = Vec::new();
+    // Colour key from tRNS for gray and RGB images, as 16-bit samples.
+    let mut key: Option<[u16; 3]> = None;
+    let mut idat = Vec::new();
+    loop {
+        let head = bytes.get(pos..pos + 8).ok_or(ImageError::Truncated)?;
+        let len = be32(head) as usize;
+        let kind = &head[4..8];
+        let data = bytes
+            .get(pos + 8..pos + 8 + len)
+            .ok_or(ImageError::Truncated)?;
+        pos += 12 + len;
+        match kind {
+            b"IHDR" => header = Some(parse_header(data)?),
+            b"PLTE" => {
+                let (entries, _) = data.as_chunks::<3>();
+                palette = entries
+                    .iter()
+                    .map(|&[r, g, b]| pixel::rgb(r, g, b))
+                    .collect();
+            }
+            b"tRNS" => match header.as_ref().map(|h| h.color) {
+                Some(PALETTE) => {
+                    for (entry, &alpha) in palette.iter_mut().zip(data) {
+                        *entry = (*entry & 0x00ff_ffff) | (alpha as u32) << 24;
+                    }
+                }
+                Some(GRAY) if data.len() >= 2 => {
+                    let gray = u16::from_be_bytes([data[0], data[1]]);
+                    key = Some([gray; 3]);
+                }
+                Some(RGB) if data.len() >= 6 => {
+                    let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+                    key = Some([sample(0), sample(2), sample(4)]);
+                }
+                _ => {}
+            },
+            b"IDAT" => {
+                if idat.len() + len > MAX_IMAGE_BYTES {
+                    return Err(ImageError::TooLarge);
+                }
+                idat.extend_from_slice(data);
+            }
+            b"IEND" => break,
+            // Unknown critical chunks (upper-case first letter) change how
+            // the image must be read; ancillary ones can be skipped.
+            _ if kind[0].is_ascii_uppercase() => return Err(ImageError::Unsupported),
+            _ => {}
+        }
+    }
+    let header = header.ok_or(ImageError::Corrupt)?;
+    if header.color == PALETTE && palette.is_empty() {
+        return Err(ImageError::Corrupt);
+    }
+    let mut image = Image::new(header.width, header.height)?;
+    let stride = header.stride();
+    let raw_len = (stride + 1) * header.height as usize;
+    let mut raw = inflate::zlib(&idat, raw_len)?;
+    drop(idat);
+    if raw.len() != raw_len {
+        return Err(ImageError::Truncated);
+    }
+    unfilter(&mut raw, &header)?;
+    for y in 0..header.height {
+        let row = &raw[y as usize * (stride + 1) + 1..][..stride];
+        for x in 0..header.width {
+            image.set(x, y, color(row, x as usize, &header, &palette, key));
+        }
+    }
+    Ok(image)
+}
+
+fn unfilter(raw: &mut [u8], header: &Header) -> Result<(), ImageError> {
+    // Undo the per-row filters in place. `bpp` is the distance to the
+    // byte of the previous pixel, at least 1.
+    let stride = header.stride();
+    let bpp = (header.channels() * header.depth as usize).div_ceil(8);
+    for y in 0..header.height as usize {
+        let (done, rest) = raw.split_at_mut(y * (stride + 1));
+        let prev = if y == 0 {
+            None
+        } else {
+            Some(&done[done.len() - stride..])
+        };
+        let (filter, row) = rest[..stride + 1].split_first_mut().unwrap();
+        for i in 0..stride {
+            let a = if i >= bpp { row[i - bpp] } else { 0 };
+            let b = prev.map_or(0, |p| p[i]);
+            let c = match prev {
+                Some(p) if i >= bpp => p[i - bpp],
+                _ => 0,
+            };
+            let pred = match *filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(ImageError::Corrupt),
+            };
+            row[i] = row[i].wrapping_add(pred);
+        }
+    }
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn sample(row: &[u8], idx: usize, depth: u8) -> u16 {
+    // Sample `idx` of a row, at its own bit depth.
+    match depth {
+        16 => u16::from_be_bytes([row[idx * 2], row[idx * 2 + 1]]),
+        8 => row[idx] as u16,
+        _ => {
+            let bit = idx * depth as usize;
+            let shift = 8 - depth as usize - bit % 8;
+            ((row[bit / 8] >> shift) & ((1u8 << depth) - 1)) as u16
+        }
+    }
+}
+
+fn color(row: &[u8], x: usize, header: &Header, palette: &[u32], key: Option<[u16; 3]>) -> u32 {
+    let depth = header.depth;
+    let channels = header.channels();
+    let s = |c: usize| sample(row, x * channels + c, depth);
+    // Samples scaled to 8 bits.
+    let to8 = |v: u16| -> u32 {
+        match depth {
+            16 => (v >> 8) as u32,
+            8 => v as u32,
+            _ => v as u32 * 255 / ((1u32 << depth) - 1),
+        }
+    };
+    match header.color {
+        PALETTE => palette.get(s(0) as usize).copied().unwrap_or(0),
+        GRAY => {
+            let v = s(0);
+            let alpha = if key == Some([v; 3]) { 0 } else { 0xff };
+            let g = to8(v);
+            alpha << 24 | g << 16 | g << 8 | g
+        }
+        GRAY_ALPHA => {
+            let g = to8(s(0));
+            to8(s(1)) << 24 | g << 16 | g << 8 | g
+        }
+        RGB => {
+            let (r, g, b) = (s(0), s(1), s(2));
+            let alpha = if key == Some([r, g, b]) { 0 } else { 0xff };
+            alpha << 24 | to8(r) << 16 | to8(g) << 8 | to8(b)
+        }
+        _ => to8(s(3)) << 24 | to8(s(0)) << 16 | to8(s(1)) << 8 | to8(s(2)),
+    }
+}
@@ -0,0 +1,134 @@
+// PC Screen Font (PSF1 and PSF2) loader, the format of Linux console fonts.
+// Only single code points of the Unicode table are used; sequences (a base
+// character plus combining marks) are skipped.
+
+use alloc::collections::BTreeMap;
+
+use crate::gfx::font::Font;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE512: u8 = 0x01;
+const PSF1_MODEHASTAB: u8 = 0x02;
+const PSF1_MODESEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xffff;
+const PSF1_STARTSEQ: u16 = 0xfffe;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xff;
+const PSF2_STARTSEQ: u8 = 0xfe;
+const PSF2_HEADER_SIZE: usize = 32;
+
+// Larger glyphs are almost certainly a corrupt header.
+const MAX_GLYPH_SIZE: usize = 64;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PsfError {
+    BadMagic,
+    Truncated,
+    Unsupported,
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Font, PsfError> {
+    if bytes.starts_with(&PSF2_MAGIC) {
+        parse_psf2(bytes)
+    } else if bytes.starts_with(&PSF1_MAGIC) {
+        parse_psf1(bytes)
+    } else {
+        Err(PsfError::BadMagic)
+    }
+}
+
+fn parse_psf1(bytes: &[u8]) -> Result<Font, PsfError> {
+    // 4-byte header; glyphs are 8 pixels wide and `charsize` rows high.
+    let header = bytes.get(..4).ok_or(PsfError::Truncated)?;
+    let mode = header[2];
+    let height = header[3] as usize;
+    if height == 0 || height > MAX_GLYPH_SIZE {
+        return Err(PsfError::Unsupported);
+    }
+    let count = if mode & PSF1_MODE512 != 0 { 512 } else { 256 };
+    let glyphs_end = 4 + count * height;
+    let data = bytes.get(4..glyphs_end).ok_or(PsfError::Truncated)?;
+    let map = if mode & (PSF1_MODEHASTAB | PSF1_MODESEQ) != 0 {
+        Some(psf1_table(&bytes[glyphs_end..], count))
+    } else {
+        None
+    };
+    Ok(Font::new(8, height, data.to_vec(), map))
+}
+
+fn psf1_table(table: &[u8], count: usize) -> BTreeMap<char, u32> {
+    // Per glyph: UCS-2 code points, optional sequences, then 0xFFFF.
+    let mut map = BTreeMap::new();
+    let mut glyph = 0u32;
+    let mut in_seq = false;
+    for pair in table.as_chunks::<2>().0 {
+        if glyph as usize >= count {
+            break;
+        }
+        match u16::from_le_bytes([pair[0], pair[1]]) {
+            PSF1_SEPARATOR => {
+                glyph += 1;
+                in_seq = false;
+            }
+            PSF1_STARTSEQ => in_seq = true,
+            cp if !in_seq => {
+                if let Some(ch) = char::from_u32(cp as u32) {
+                    map.entry(ch).or_insert(glyph);
+                }
+            }
+            _ => {}
+        }
+    }
+    map
+}
+
+fn parse_psf2(bytes: &[u8]) -> Result<Font, PsfError> {
+    let header = bytes.get(..PSF2_HEADER_SIZE).ok_or(PsfError::Truncated)?;
+    let word = |idx: usize| {
+        let b = &header[idx * 4..idx * 4 + 4];
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize
+    };
+    let (header_size, flags, count, charsize, height, width) =
+        (word(2), word(3), word(4), word(5), word(6), word(7));
+    if word(1) != 0
+        || count == 0
+        || width == 0
+        || height == 0
+        || width > MAX_GLYPH_SIZE
+        || height > MAX_GLYPH_SIZE
+        || charsize != width.div_ceil(8) * height
+    {
+        return Err(PsfError::Unsupported);
+    }
+    let glyphs_end = count
+        .checked_mul(charsize)
+        .and_then(|size| size.checked_add(header_size))
+        .ok_or(PsfError::Truncated)?;
+    let data = bytes
+        .get(header_size..glyphs_end)
+        .ok_or(PsfError::Truncated)?;
+    let map = if flags as u32 & PSF2_HAS_UNICODE_TABLE != 0 {
+        Some(psf2_table(&bytes[glyphs_end..], count))
+    } else {
+        None
+    };
+    Ok(Font::new(width, height, data.to_vec(), map))
+}
+
+fn psf2_table(table: &[u8], count: usize) -> BTreeMap<char, u32> {
+    // Per glyph: UTF-8 code points, optional 0xFE sequences, then 0xFF.
+    let mut map = BTreeMap::new();
+    let entries = table.split(|&b| b == PSF2_SEPARATOR).take(count);
+    for (glyph, entry) in entries.enumerate() {
+        let singles = entry.split(|&b| b == PSF2_STARTSEQ).next().unwrap_or(&[]);
+        let Ok(text) = core::str::from_utf8(singles) else {
+            continue;
+        };
+        for ch in text.chars() {
+            map.entry(ch).or_insert(glyph as u32);
+        }
+    }
+    map
+}
@@ -0,0 +1,339 @@
+// Software rasterizer over a block of pixels in any `pixel::Format`: the
+// framebuffer mapped into a process, a shadow buffer or a plain Vec. All
+// drawing is clipped to the surface; colours are 0xAARRGGBB.
+
+use core::slice;
+
+use super::font::Font;
+use super::pixel::{self, Format};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, w: u32, h: u32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    fn right(&self) -> i64 {
+        self.x as i64 + self.w as i64
+    }
+
+    fn bottom(&self) -> i64 {
+        self.y as i64 + self.h as i64
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= x as i64 || bottom <= y as i64 {
+            return None;
+        }
+        Some(Rect::new(
+            x,
+            y,
+            (right - x as i64) as u32,
+            (bottom - y as i64) as u32,
+        ))
+    }
+}
+
+pub struct Surface<'a> {
+    data: &'a mut [u8],
+    width: u32,
+    height: u32,
+    // Bytes per row, at least `width` pixels.
+    pitch: u32,
+    format: Format,
+}
+
+impl<'a> Surface<'a> {
+    pub fn new(
+        data: &'a mut [u8],
+        width: u32,
+        height: u32,
+        pitch: u32,
+        format: Format,
+    ) -> Option<Self> {
+        // None if `data` is too small for the geometry.
+        let row = width as usize * format.bytes_per_pixel();
+        let needed = match height {
+            0 => 0,
+            h => (h as usize - 1) * pitch as usize + row,
+        };
+        if (pitch as usize) < row || data.len() < needed {
+            return None;
+        }
+        Some(Self {
+            data,
+            width,
+            height,
+            pitch,
+            format,
+        })
+    }
+
+    // # Safety
+    // `ptr` must point to `pitch * height` writable bytes that nothing else
+    // accesses for `'a`, such as a mapped framebuffer.
+    pub unsafe fn from_raw(
+        ptr: *mut u8,
+        width: u32,
+        height: u32,
+        pitch: u32,
+        format: Format,
+    ) -> Option<Self> {
+        let len = pitch as usize * height as usize;
+        Self::new(
+            slice::from_raw_parts_mut(ptr, len),
+            width,
+            height,
+            pitch,
+            format,
+        )
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    fn offset(&self, x: u32, y: u32) -> usize {
+        y as usize * self.pitch as usize + x as usize * self.format.bytes_per_pixel()
+    }
+
+    fn read_raw(&self, off: usize) -> u32 {
+        match self.format.bytes_per_pixel() {
+            2 => u16::from_le_bytes([self.data[off], self.data[off + 1]]) as u32,
+            _ => u32::from_le_bytes(self.data[off..off + 4].try_into().unwrap()),
+        }
+    }
+
+    fn write_raw(&mut self, off: usize, raw: u32) {
+        match self.format.bytes_per_pixel() {
+            2 => self.data[off..off + 2].copy_from_slice(&(raw as u16).to_le_bytes()),
+            _ => self.data[off..off + 4].copy_from_slice(&raw.to_le_bytes()),
+        }
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<u32> {
+        if !self.contains(x, y) {
+            return None;
+        }
+        let raw = self.read_raw(self.offset(x as u32, y as u32));
+        Some(pixel::decode(self.format, raw))
+    }
+
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: u32) {
+        // Store `color` as is, alpha included; see `blend_pixel`.
+        if self.contains(x, y) {
+            let off = self.offset(x as u32, y as u32);
+            self.write_raw(off, pixel::encode(self.format, color));
+        }
+    }
+
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if let Some(dst) = self.pixel(x, y) {
+            self.put_pixel(x, y, pixel::blend(dst, color));
+        }
+    }
+
+    pub fn clear(&mut self, color: u32) {
+        self.fill_rect(self.bounds(), color);
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: u32) {
+        // Encode once, write the first row, then copy it to the others.
+        let Some(r) = rect.intersect(&self.bounds()) else {
+            return;
+        };
+        let bpp = self.format.bytes_per_pixel();
+        let raw = pixel::encode(self.format, color);
+        let first = self.offset(r.x as u32, r.y as u32);
+        let len = r.w as usize * bpp;
+        for px in 0..r.w as usize {
+            self.write_raw(first + px * bpp, raw);
+        }
+        for row in 1..r.h as usize {
+            let start = first + row * self.pitch as usize;
+            self.data.copy_within(first..first + len, start);
+        }
+    }
+
+    pub fn blend_rect(&mut self, rect: Rect, color: u32) {
+        let Some(r) = rect.intersect(&self.bounds()) else {
+            return;
+        };
+        for y in r.y..r.y + r.h as i32 {
+            for x in r.x..r.x + r.w as i32 {
+                self.blend_pixel(x, y, color);
+            }
+        }
+    }
+
+    pub fn rect(&mut self, rect: Rect, color: u32) {
+        // One-pixel outline inside `rect`.
+        if rect.w == 0 || rect.h == 0 {
+            return;
+        }
+        let (x, y, w, h) = (rect.x, rect.y, rect.w, rect.h);
+        self.fill_rect(Rect::new(x, y, w, 1), color);
+        self.fill_rect(Rect::new(x, y + h as i32 - 1, w, 1), color);
+        self.fill_rect(Rect::new(x, y, 1, h), color);
+        self.fill_rect(Rect::new(x + w as i32 - 1, y, 1, h), color);
+    }
+
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        // Bresenham, both end points included.
+        let (mut x, mut y) = (x0, y0);
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        loop {
+            self.put_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    fn circle_points(r: i32, mut plot: impl FnMut(i32, i32)) {
+        // Midpoint circle: calls `plot(x, y)` for each point of one octant,
+        // x >= y >= 0.
+        let (mut x, mut y) = (r, 0);
+        let mut err = 1 - r;
+        while x >= y {
+            plot(x, y);
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    pub fn circle(&mut self, cx: i32, cy: i32, r: u32, color: u32) {
+        Self::circle_points(r as i32, |x, y| {
+            for (px, py) in [
+                (x, y),
+                (y, x),
+                (-y, x),
+                (-x, y),
+                (-x, -y),
+                (-y, -x),
+                (y, -x),
+                (x, -y),
+            ] {
+                self.put_pixel(cx + px, cy + py, color);
+            }
+        });
+    }
+
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, r: u32, color: u32) {
+        // Horizontal spans between mirrored outline points.
+        Self::circle_points(r as i32, |x, y| {
+            for (half, row) in [(x, y), (x, -y), (y, x), (y, -x)] {
+                let span = Rect::new(cx - half, cy + row, 2 * half as u32 + 1, 1);
+                self.fill_rect(span, color);
+            }
+        });
+    }
+
+    pub fn text(&mut self, font: &Font, x: i32, y: i32, text: &str, color: u32) -> i32 {
+        // Set pixels of each glyph in `color`, the rest left alone. Returns
+        // the x just past the last glyph.
+        let mut x = x;
+        for ch in text.chars() {
+            let glyph = font.glyph(ch);
+            for (row, bits) in glyph.chunks_exact(font.row_bytes()).enumerate() {
+                for col in 0..font.width {
+                    if bits[col / 8] & (0x80 >> (col % 8)) != 0 {
+                        self.put_pixel(x + col as i32, y + row as i32, color);
+                    }
+                }
+            }
+            x += font.width as i32;
+        }
+        x
+    }
+
+    fn clip_blit(&self, src: &Surface, src_rect: Rect, x: i32, y: i32) -> Option<(Rect, Rect)> {
+        // Source and destination rectangles of a blit after clipping
+        // against both surfaces; they have the same size.
+        let from = src_rect.intersect(&src.bounds())?;
+        let dx = x.checked_add(from.x - src_rect.x)?;
+        let dy = y.checked_add(from.y - src_rect.y)?;
+        let to = Rect::new(dx, dy, from.w, from.h).intersect(&self.bounds())?;
+        let from = Rect::new(from.x + (to.x - dx), from.y + (to.y - dy), to.w, to.h);
+        Some((from, to))
+    }
+
+    pub fn blit(&mut self, src: &Surface, src_rect: Rect, x: i32, y: i32) {
+        // Copy `src_rect` of `src` to (x, y), converting the pixel format.
+        // Alpha is copied, not applied; see `blit_blend`.
+        let Some((from, to)) = self.clip_blit(src, src_rect, x, y) else {
+            return;
+        };
+        let bpp = self.format.bytes_per_pixel();
+        let src_bpp = src.format.bytes_per_pixel();
+        for row in 0..to.h {
+            let s = src.offset(from.x as u32, from.y as u32 + row);
+            let d = self.offset(to.x as u32, to.y as u32 + row);
+            if src.format == self.format {
+                let len = to.w as usize * bpp;
+                self.data[d..d + len].copy_from_slice(&src.data[s..s + len]);
+                continue;
+            }
+            for px in 0..to.w as usize {
+                let raw = src.read_raw(s + px * src_bpp);
+                let raw = pixel::convert(src.format, self.format, raw);
+                self.write_raw(d + px * bpp, raw);
+            }
+        }
+    }
+
+    pub fn blit_blend(&mut self, src: &Surface, src_rect: Rect, x: i32, y: i32) {
+        // Like `blit`, but draws `src` over the destination using its alpha.
+        let Some((from, to)) = self.clip_blit(src, src_rect, x, y) else {
+            return;
+        };
+        for row in 0..to.h as i32 {
+            for px in 0..to.w as i32 {
+                if let Some(color) = src.pixel(from.x + px, from.y + row) {
+                    self.blend_pixel(to.x + px, to.y + row, color);
+                }
+            }
+        }
+    }
+}
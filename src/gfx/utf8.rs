@@ -0,0 +1,62 @@
+// Incremental UTF-8 decoder for terminal output. Malformed input (stray
+// continuation bytes, overlong forms, surrogates, truncated sequences)
+// decodes to U+FFFD, one per bad sequence.
+
+const REPLACEMENT: char = '\u{fffd}';
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Decoder {
+    cp: u32,
+    // Continuation bytes still expected, and the smallest code point the
+    // sequence may encode.
+    need: u8,
+    min: u32,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            cp: 0,
+            need: 0,
+            min: 0,
+        }
+    }
+
+    pub fn advance<F: FnMut(char)>(&mut self, b: u8, mut emit: F) {
+        if self.need > 0 {
+            if b & 0xc0 == 0x80 {
+                self.cp = (self.cp << 6) | (b & 0x3f) as u32;
+                self.need -= 1;
+                if self.need == 0 {
+                    let ch = char::from_u32(self.cp).filter(|_| self.cp >= self.min);
+                    emit(ch.unwrap_or(REPLACEMENT));
+                }
+                return;
+            }
+            // Cut short; `b` starts something new.
+            self.need = 0;
+            emit(REPLACEMENT);
+        }
+        match b {
+            0x00..=0x7f => emit(b as char),
+            0xc0..=0xdf => self.start(b & 0x1f, 1, 0x80),
+            0xe0..=0xef => self.start(b & 0x0f, 2, 0x800),
+            0xf0..=0xf7 => self.start(b & 0x07, 3, 0x1_0000),
+            _ => emit(REPLACEMENT),
+        }
+    }
+
+    pub fn flush<F: FnMut(char)>(&mut self, mut emit: F) {
+        // End of text: a pending partial sequence becomes U+FFFD.
+        if self.need > 0 {
+            self.need = 0;
+            emit(REPLACEMENT);
+        }
+    }
+
+    fn start(&mut self, bits: u8, need: u8, min: u32) {
+        self.cp = bits as u32;
+        self.need = need;
+        self.min = min;
+    }
+}
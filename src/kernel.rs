@@ -1,6 +1,10 @@
 pub mod interrupts;
+pub mod ipc;
+pub mod klog;
+pub mod params;
 pub mod process;
 pub mod smp;
 pub mod user;
 pub mod syscall;
+pub mod tty;
 pub mod vfs;
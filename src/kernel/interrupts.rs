@@ -8,11 +8,14 @@ use crate::drivers::keyboard;
 use crate::drivers::local_intc;
 #[cfg(feature = "rpi5")]
 use crate::drivers::gic;
-use crate::kernel::process;
+use crate::kernel::params::{BoolParam, U32Param};
+use crate::kernel::{klog, process};
 use crate::kernel::smp;
-use crate::drivers::uart;
+use crate::{kdebug, kernel_param, kinfo};
 
-const LOG_IRQ: bool = false;
+// Timer tick period, which is also the scheduling quantum.
+kernel_param!(static TICK_MS: U32Param = U32Param::range(10, 1, 1000), "tick_ms");
+kernel_param!(static LOG_IRQ: BoolParam = BoolParam::new(false), "irq.trace");
 const LOG_EVERY: usize = 200;
 static IRQ_LOG_TICKS: [AtomicUsize; smp::MAX_CPUS] = [
     AtomicUsize::new(0),
@@ -21,9 +24,29 @@ static IRQ_LOG_TICKS: [AtomicUsize; smp::MAX_CPUS] = [
     AtomicUsize::new(0),
 ];
 
-pub fn init_per_cpu(tick_ms: u64) {
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IrqSource {
+    Timer,
+    Other,
+    Spurious,
+}
+
+pub const IRQ_SOURCES: [IrqSource; 3] = [IrqSource::Timer, IrqSource::Other, IrqSource::Spurious];
+
+static IRQ_COUNTS: [[AtomicUsize; 3]; smp::MAX_CPUS] =
+    [const { [const { AtomicUsize::new(0) }; 3] }; smp::MAX_CPUS];
+
+fn count_irq(source: IrqSource) {
+    IRQ_COUNTS[smp::cpu_id()][source as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn irq_count(cpu: usize, source: IrqSource) -> usize {
+    IRQ_COUNTS[cpu][source as usize].load(Ordering::Relaxed)
+}
+
+pub fn init_per_cpu() {
     // Initialize per-core timer IRQs and enable interrupt delivery.
-    timer::init_tick(tick_ms);
+    timer::init_tick(TICK_MS.get() as u64);
     #[cfg(feature = "qemu")]
     local_intc::enable_generic_timer_irq(smp::cpu_id());
     #[cfg(feature = "rpi5")]
@@ -33,10 +56,7 @@ pub fn init_per_cpu(tick_ms: u64) {
         }
         gic::init_cpu();
     }
-    uart::with_uart(|uart| {
-        use core::fmt::Write;
-        let _ = writeln!(uart, "irq init cpu{}", smp::cpu_id());
-    });
+    kinfo!("irq init cpu{}", smp::cpu_id());
     enable_irq();
 }
 
@@ -55,9 +75,11 @@ pub extern "C" fn irq_handler(frame: *mut TrapFrame) -> *mut TrapFrame {
     #[cfg(feature = "rpi5")]
     {
         if irq_id.is_none() {
+            count_irq(IrqSource::Spurious);
             return frame;
         }
         if irq_id != Some(gic::timer_irq_id()) {
+            count_irq(IrqSource::Other);
             if let Some(id) = irq_id {
                 gic::end_irq(id);
             }
@@ -67,31 +89,29 @@ pub extern "C" fn irq_handler(frame: *mut TrapFrame) -> *mut TrapFrame {
     #[cfg(feature = "qemu")]
     {
         if !local_intc::generic_timer_pending(smp::cpu_id()) {
-            if LOG_IRQ {
+            count_irq(IrqSource::Spurious);
+            if LOG_IRQ.get() {
                 let cpu = smp::cpu_id();
                 let tick = IRQ_LOG_TICKS[cpu].fetch_add(1, Ordering::Relaxed);
                 if tick % LOG_EVERY == 0 {
-                    uart::with_uart(|uart| {
-                        use core::fmt::Write;
-                        let _ = writeln!(uart, "irq cpu{} pending=0", cpu);
-                    });
+                    kdebug!("irq cpu{} pending=0", cpu);
                 }
             }
             return frame;
         }
     }
-    if LOG_IRQ {
+    if LOG_IRQ.get() {
         let cpu = smp::cpu_id();
         let tick = IRQ_LOG_TICKS[cpu].fetch_add(1, Ordering::Relaxed);
         if tick % LOG_EVERY == 0 {
-            uart::with_uart(|uart| {
-                use core::fmt::Write;
-                let _ = writeln!(uart, "irq cpu{} pending=1", cpu);
-            });
+            kdebug!("irq cpu{} pending=1", cpu);
         }
     }
+    count_irq(IrqSource::Timer);
     keyboard::poll();
+    klog::tick();
     timer::tick();
+    process::wake_expired(timer::uptime_ms());
     let next = process::schedule_from_irq(frame);
     #[cfg(feature = "rpi5")]
     {
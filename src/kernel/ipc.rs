@@ -0,0 +1,535 @@
+// Local message channels between a user-space service and its clients,
+// plus shared-memory segments passed over them. The display server is the
+// only service: it opens /dev/displayd, and each open of /dev/display by a
+// client is a new connection.
+//
+// Clients see an ordinary message stream: every write is one message and
+// every read returns one. The server multiplexes all connections over its
+// /dev/displayd file, where each message carries a `ChanHeader` naming the
+// connection.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::kernel::process::{self, ProcessId, WaitQueue};
+use crate::kernel::vfs::devfs::{self, CharDevice, DevNum, MAJOR_DISPLAY, MAJOR_DISPLAY_CLIENT};
+use crate::kernel::vfs::file::FileId;
+use crate::kernel::vfs::{VfsError, VfsResult, POLLHUP, POLLIN, POLLOUT};
+use crate::mm::frame;
+use crate::mm::layout::{phys_to_virt, PAGE_SIZE};
+use crate::util::sync::SpinLock;
+
+pub const MAX_CONNECTIONS: usize = 16;
+// Largest message; longer writes fail with EINVAL.
+pub const MAX_MESSAGE: usize = 256;
+// Messages queued in each direction before writers block.
+const QUEUE_LEN: usize = 16;
+pub const MAX_SEGMENTS: usize = 32;
+pub const MAX_SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+// mmap offsets name segments: segment keys are multiples of this.
+pub const SEGMENT_STRIDE: u64 = 1 << 32;
+// On /dev/displayd the offset also names the connection, in multiples of
+// this, so the server can only map a segment for the client that owns it.
+const CONN_STRIDE: u64 = SEGMENT_STRIDE * MAX_SEGMENTS as u64;
+
+const MINOR_CONNECT: u32 = 0;
+const MINOR_LISTEN: u32 = 1;
+
+// Kinds of `ChanHeader` messages on /dev/displayd.
+pub const CHAN_CONNECT: u32 = 0;
+pub const CHAN_DATA: u32 = 1;
+pub const CHAN_HANGUP: u32 = 2;
+
+// ralix-specific: allocate a shared-memory segment for this connection.
+// `arg` points at a `ShmRequest`; the kernel fills in `key`.
+pub const DISPLAY_SHM_CREATE: u64 = 0x4490;
+
+// Prefix of every message read from or written to /dev/displayd.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChanHeader {
+    pub conn: u32,
+    pub kind: u32,
+    // Payload bytes after the header.
+    pub len: u32,
+}
+
+pub const CHAN_HEADER_SIZE: usize = core::mem::size_of::<ChanHeader>();
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ShmRequest {
+    pub size: u64,
+    // mmap offset of the segment, on either end of the connection.
+    pub key: u64,
+}
+
+struct Connection {
+    client_open: bool,
+    server_open: bool,
+    // The server has not been told about the connection (or its end) yet.
+    announce_connect: bool,
+    announce_hangup: bool,
+    to_server: VecDeque<Vec<u8>>,
+    to_client: VecDeque<Vec<u8>>,
+    client_wait: WaitQueue,
+}
+
+struct Segment {
+    conn: usize,
+    paddr: u64,
+    pages: usize,
+}
+
+struct Service {
+    listening: bool,
+    conns: [Option<Connection>; MAX_CONNECTIONS],
+    segments: [Option<Segment>; MAX_SEGMENTS],
+    // Where the next listener read starts looking, so one busy client
+    // cannot starve the others.
+    next: usize,
+    server_wait: WaitQueue,
+}
+
+static DISPLAY: SpinLock<Service> = SpinLock::new(Service {
+    listening: false,
+    conns: [const { None }; MAX_CONNECTIONS],
+    segments: [const { None }; MAX_SEGMENTS],
+    next: 0,
+    server_wait: WaitQueue::new(),
+});
+
+impl Service {
+    fn conn(&mut self, n: u32) -> VfsResult<&mut Connection> {
+        self.conns
+            .get_mut(n as usize)
+            .and_then(Option::as_mut)
+            .ok_or(VfsError::BadDescriptor)
+    }
+
+    fn free_if_unused(&mut self, n: usize) {
+        // Drop the connection and its segments once both ends are closed.
+        // `mmap` hands out linear-map addresses and nothing records them,
+        // so a segment cannot outlive its connection: both sides must stop
+        // touching it before their end is closed (the server hangs up only
+        // after dropping the connection's windows), or they scribble over
+        // frames that may already belong to someone else.
+        let unused = self.conns[n]
+            .as_ref()
+            .is_some_and(|conn| !conn.client_open && !conn.server_open);
+        if !unused {
+            return;
+        }
+        self.conns[n] = None;
+        for slot in self.segments.iter_mut() {
+            if slot.as_ref().is_some_and(|seg| seg.conn == n) {
+                let seg = slot.take().unwrap();
+                for page in 0..seg.pages {
+                    frame::free_frame(seg.paddr + (page * PAGE_SIZE) as u64);
+                }
+            }
+        }
+    }
+
+    fn event_ready(&self) -> bool {
+        self.conns
+            .iter()
+            .flatten()
+            .any(|conn| conn.announce_connect || !conn.to_server.is_empty() || conn.announce_hangup)
+    }
+
+    fn segment(&self, offset: u64, len: u64, conn: usize) -> VfsResult<u64> {
+        // Address of `len` bytes at `offset` in a segment of connection
+        // `conn`; other connections' segments are out of reach.
+        let idx = (offset / SEGMENT_STRIDE) as usize;
+        let within = offset % SEGMENT_STRIDE;
+        let seg = self
+            .segments
+            .get(idx)
+            .and_then(Option::as_ref)
+            .filter(|seg| seg.conn == conn)
+            .ok_or(VfsError::InvalidArgument)?;
+        let size = (seg.pages * PAGE_SIZE) as u64;
+        if within.checked_add(len).is_none_or(|end| end > size) {
+            return Err(VfsError::InvalidArgument);
+        }
+        Ok(phys_to_virt(seg.paddr) as u64 + within)
+    }
+}
+
+// /dev/display: each open connects to the server and lands on the client
+// end of the new connection.
+struct ConnectNode;
+
+static CONNECT_NODE: ConnectNode = ConnectNode;
+
+// /dev/displayd: the server's end of every connection. Only one process
+// can listen at a time.
+struct ListenNode;
+
+static LISTEN_NODE: ListenNode = ListenNode;
+
+// Client ends, reached by major with the connection number as minor.
+struct ClientEnd;
+
+static CLIENT_END: ClientEnd = ClientEnd;
+
+fn connect() -> VfsResult<DevNum> {
+    let mut service = DISPLAY.lock();
+    if !service.listening {
+        return Err(VfsError::ConnectionRefused);
+    }
+    let n = service
+        .conns
+        .iter()
+        .position(Option::is_none)
+        .ok_or(VfsError::NoSpace)?;
+    service.conns[n] = Some(Connection {
+        client_open: true,
+        server_open: true,
+        announce_connect: true,
+        announce_hangup: false,
+        to_server: VecDeque::new(),
+        to_client: VecDeque::new(),
+        client_wait: WaitQueue::new(),
+    });
+    let waiters = service.server_wait.take();
+    drop(service);
+    waiters.wake_all();
+    Ok(DevNum::new(MAJOR_DISPLAY_CLIENT, n as u32))
+}
+
+fn listen() -> VfsResult<()> {
+    let mut service = DISPLAY.lock();
+    if service.listening {
+        return Err(VfsError::Busy);
+    }
+    service.listening = true;
+    Ok(())
+}
+
+fn unlisten() {
+    // Every connection is hung up on the client side.
+    let mut service = DISPLAY.lock();
+    service.listening = false;
+    let mut waiters = Vec::new();
+    for n in 0..MAX_CONNECTIONS {
+        if let Some(conn) = service.conns[n].as_mut() {
+            conn.server_open = false;
+            waiters.push(conn.client_wait.take());
+        }
+        service.free_if_unused(n);
+    }
+    drop(service);
+    for queue in waiters {
+        queue.wake_all();
+    }
+}
+
+fn server_read(buf: &mut [u8]) -> VfsResult<usize> {
+    // The next event from any connection: a new client, a message or a
+    // client going away. Payloads longer than `buf` are truncated.
+    if buf.len() < CHAN_HEADER_SIZE {
+        return Err(VfsError::InvalidArgument);
+    }
+    let pid = process::current_pid();
+    let mut service = DISPLAY.lock();
+    let start = service.next;
+    for i in 0..MAX_CONNECTIONS {
+        let n = (start + i) % MAX_CONNECTIONS;
+        let Some(conn) = service.conns[n].as_mut() else {
+            continue;
+        };
+        let (kind, payload) = if conn.announce_connect {
+            conn.announce_connect = false;
+            (CHAN_CONNECT, None)
+        } else if let Some(msg) = conn.to_server.pop_front() {
+            (CHAN_DATA, Some(msg))
+        } else if conn.announce_hangup {
+            conn.announce_hangup = false;
+            (CHAN_HANGUP, None)
+        } else {
+            continue;
+        };
+        let waiters = conn.client_wait.take();
+        service.next = (n + 1) % MAX_CONNECTIONS;
+        drop(service);
+        waiters.wake_all();
+        let payload = payload.as_deref().unwrap_or(&[]);
+        let len = payload.len().min(buf.len() - CHAN_HEADER_SIZE);
+        let header = ChanHeader {
+            conn: n as u32,
+            kind,
+            len: len as u32,
+        };
+        unsafe { (buf.as_mut_ptr() as *mut ChanHeader).write_unaligned(header) };
+        buf[CHAN_HEADER_SIZE..CHAN_HEADER_SIZE + len].copy_from_slice(&payload[..len]);
+        return Ok(CHAN_HEADER_SIZE + len);
+    }
+    if let Some(pid) = pid {
+        service.server_wait.add(pid);
+    }
+    Err(VfsError::WouldBlock)
+}
+
+fn server_write(buf: &[u8]) -> VfsResult<usize> {
+    // CHAN_DATA sends the payload to one client; CHAN_HANGUP closes the
+    // server's end, and must follow the client's CHAN_HANGUP before the
+    // connection (and its shared memory) is freed.
+    if buf.len() < CHAN_HEADER_SIZE {
+        return Err(VfsError::InvalidArgument);
+    }
+    let header = unsafe { (buf.as_ptr() as *const ChanHeader).read_unaligned() };
+    let payload = &buf[CHAN_HEADER_SIZE..];
+    if header.len as usize != payload.len() || payload.len() > MAX_MESSAGE {
+        return Err(VfsError::InvalidArgument);
+    }
+    let pid = process::current_pid();
+    let mut service = DISPLAY.lock();
+    let conn = service.conn(header.conn)?;
+    if !conn.server_open {
+        return Err(VfsError::BadDescriptor);
+    }
+    match header.kind {
+        CHAN_DATA => {
+            if !conn.client_open {
+                return Err(VfsError::BrokenPipe);
+            }
+            if conn.to_client.len() >= QUEUE_LEN {
+                if let Some(pid) = pid {
+                    service.server_wait.add(pid);
+                }
+                return Err(VfsError::WouldBlock);
+            }
+            conn.to_client.push_back(payload.to_vec());
+        }
+        CHAN_HANGUP => {
+            conn.server_open = false;
+            conn.to_client.clear();
+        }
+        _ => return Err(VfsError::InvalidArgument),
+    }
+    let waiters = conn.client_wait.take();
+    service.free_if_unused(header.conn as usize);
+    drop(service);
+    waiters.wake_all();
+    Ok(buf.len())
+}
+
+fn client_read(n: u32, buf: &mut [u8]) -> VfsResult<usize> {
+    // One message per read; end of file once the server has hung up.
+    let pid = process::current_pid();
+    let mut service = DISPLAY.lock();
+    let conn = service.conn(n)?;
+    let Some(msg) = conn.to_client.pop_front() else {
+        if !conn.server_open {
+            return Ok(0);
+        }
+        if let Some(pid) = pid {
+            conn.client_wait.add(pid);
+        }
+        return Err(VfsError::WouldBlock);
+    };
+    let len = msg.len().min(buf.len());
+    buf[..len].copy_from_slice(&msg[..len]);
+    let waiters = service.server_wait.take();
+    drop(service);
+    waiters.wake_all();
+    Ok(len)
+}
+
+fn client_write(n: u32, buf: &[u8]) -> VfsResult<usize> {
+    if buf.len() > MAX_MESSAGE {
+        return Err(VfsError::InvalidArgument);
+    }
+    let pid = process::current_pid();
+    let mut service = DISPLAY.lock();
+    let conn = service.conn(n)?;
+    if !conn.server_open {
+        return Err(VfsError::BrokenPipe);
+    }
+    if conn.to_server.len() >= QUEUE_LEN {
+        if let Some(pid) = pid {
+            conn.client_wait.add(pid);
+        }
+        return Err(VfsError::WouldBlock);
+    }
+    conn.to_server.push_back(buf.to_vec());
+    let waiters = service.server_wait.take();
+    drop(service);
+    waiters.wake_all();
+    Ok(buf.len())
+}
+
+fn client_poll(n: u32, waiter: Option<ProcessId>) -> u16 {
+    let mut service = DISPLAY.lock();
+    let Ok(conn) = service.conn(n) else {
+        return POLLHUP;
+    };
+    let mut ready = 0;
+    if !conn.to_client.is_empty() {
+        ready |= POLLIN;
+    }
+    if !conn.server_open {
+        ready |= POLLHUP;
+    } else if conn.to_server.len() < QUEUE_LEN {
+        ready |= POLLOUT;
+    }
+    if ready & (POLLIN | POLLHUP) == 0 {
+        if let Some(pid) = waiter {
+            conn.client_wait.add(pid);
+        }
+    }
+    ready
+}
+
+fn client_close(n: u32) {
+    // The server sees CHAN_HANGUP; the connection lives until it answers.
+    let mut service = DISPLAY.lock();
+    let Ok(conn) = service.conn(n) else {
+        return;
+    };
+    conn.client_open = false;
+    conn.announce_hangup = conn.server_open;
+    service.free_if_unused(n as usize);
+    let waiters = service.server_wait.take();
+    drop(service);
+    waiters.wake_all();
+}
+
+pub fn listener_offset(conn: u32, key: u64) -> Option<u64> {
+    // Where the server maps segment `key` of connection `conn` (the one the
+    // request came in on, from its `ChanHeader`). None if `key` cannot be a
+    // segment key.
+    (key < CONN_STRIDE).then(|| conn as u64 * CONN_STRIDE + key)
+}
+
+fn create_segment(n: u32, arg: u64) -> VfsResult<u64> {
+    // Zeroed, physically contiguous memory owned by connection `n`.
+    if arg == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    let mut req = unsafe { (arg as *const ShmRequest).read_unaligned() };
+    if req.size == 0 || req.size > MAX_SEGMENT_SIZE {
+        return Err(VfsError::InvalidArgument);
+    }
+    let pages = (req.size as usize).div_ceil(PAGE_SIZE);
+    let mut service = DISPLAY.lock();
+    service.conn(n)?;
+    let idx = service
+        .segments
+        .iter()
+        .position(Option::is_none)
+        .ok_or(VfsError::NoSpace)?;
+    let paddr = frame::alloc_contiguous(pages).ok_or(VfsError::NoSpace)?;
+    unsafe { core::ptr::write_bytes(phys_to_virt(paddr) as *mut u8, 0, pages * PAGE_SIZE) };
+    service.segments[idx] = Some(Segment {
+        conn: n as usize,
+        paddr,
+        pages,
+    });
+    req.key = idx as u64 * SEGMENT_STRIDE;
+    unsafe { (arg as *mut ShmRequest).write_unaligned(req) };
+    Ok(0)
+}
+
+impl CharDevice for ConnectNode {
+    fn open(&self, _minor: u32) -> VfsResult<Option<DevNum>> {
+        connect().map(Some)
+    }
+
+    // Never reached: opening /dev/display always ends up on a client end.
+    fn read(&self, _minor: u32, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::NoDevice)
+    }
+
+    fn write(&self, _minor: u32, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::NoDevice)
+    }
+}
+
+impl CharDevice for ListenNode {
+    fn open(&self, _minor: u32) -> VfsResult<Option<DevNum>> {
+        listen().map(|_| None)
+    }
+
+    fn close(&self, _minor: u32, _file: FileId) {
+        unlisten();
+    }
+
+    fn read(&self, _minor: u32, buf: &mut [u8]) -> VfsResult<usize> {
+        server_read(buf)
+    }
+
+    fn write(&self, _minor: u32, buf: &[u8]) -> VfsResult<usize> {
+        server_write(buf)
+    }
+
+    fn poll(&self, _minor: u32, waiter: Option<ProcessId>) -> u16 {
+        // Writes only block while a client's queue is full; those are
+        // retried, so the listener always reports POLLOUT.
+        let mut service = DISPLAY.lock();
+        if service.event_ready() {
+            return POLLIN | POLLOUT;
+        }
+        if let Some(pid) = waiter {
+            service.server_wait.add(pid);
+        }
+        POLLOUT
+    }
+
+    fn mmap(&self, _minor: u32, offset: u64, len: u64) -> VfsResult<u64> {
+        // See `listener_offset`.
+        let conn = (offset / CONN_STRIDE) as usize;
+        DISPLAY.lock().segment(offset % CONN_STRIDE, len, conn)
+    }
+}
+
+impl CharDevice for ClientEnd {
+    fn open(&self, _minor: u32) -> VfsResult<Option<DevNum>> {
+        // Client ends have no node of their own; only /dev/display hands
+        // them out.
+        Err(VfsError::NoDevice)
+    }
+
+    fn close(&self, minor: u32, _file: FileId) {
+        client_close(minor);
+    }
+
+    fn read(&self, minor: u32, buf: &mut [u8]) -> VfsResult<usize> {
+        client_read(minor, buf)
+    }
+
+    fn write(&self, minor: u32, buf: &[u8]) -> VfsResult<usize> {
+        client_write(minor, buf)
+    }
+
+    fn poll(&self, minor: u32, waiter: Option<ProcessId>) -> u16 {
+        client_poll(minor, waiter)
+    }
+
+    fn ioctl(&self, minor: u32, _file: FileId, cmd: u64, arg: u64) -> VfsResult<u64> {
+        match cmd {
+            DISPLAY_SHM_CREATE => create_segment(minor, arg),
+            _ => Err(VfsError::NotTty),
+        }
+    }
+
+    fn mmap(&self, minor: u32, offset: u64, len: u64) -> VfsResult<u64> {
+        DISPLAY.lock().segment(offset, len, minor as usize)
+    }
+}
+
+pub fn register_devices() {
+    devfs::register_char(
+        "display",
+        DevNum::new(MAJOR_DISPLAY, MINOR_CONNECT),
+        &CONNECT_NODE,
+    );
+    devfs::register_char(
+        "displayd",
+        DevNum::new(MAJOR_DISPLAY, MINOR_LISTEN),
+        &LISTEN_NODE,
+    );
+    devfs::register_major(MAJOR_DISPLAY_CLIENT, &CLIENT_END);
+}
@@ -0,0 +1,692 @@
+// Kernel log: a ring of fixed-size records, one per line, that any CPU can
+// append to without taking a lock, from interrupt handlers too and before
+// any output device is up. Each sink (the UART and the framebuffer console)
+// remembers how far it has printed, so lines logged before it came up are
+// replayed once it is ready. /dev/kmsg and the syslog syscall read the ring
+// back (docs/klog.md).
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use crate::arch::aarch64::timer;
+use crate::drivers::{framebuffer, uart};
+use crate::kernel::params::{self, FnParam};
+use crate::kernel::process::{self, ProcessId, WaitQueue};
+use crate::kernel::vfs::devfs::{self, CharDevice, DevNum, MAJOR_KMSG, MAJOR_MEM};
+use crate::kernel::vfs::file::FileId;
+use crate::kernel::vfs::{VfsError, VfsResult, POLLIN, POLLOUT};
+use crate::kernel_param;
+use crate::util::sync::SpinLock;
+
+// Longer lines are split over several records.
+pub const LINE_MAX: usize = 120;
+const RECORDS: usize = 512;
+// Longest message a single write to /dev/kmsg may carry.
+const MAX_USER_MESSAGE: usize = 1024;
+const MINOR_KMSG: u32 = 11;
+const MAX_READERS: usize = 8;
+
+// syslog(2) actions (Linux values); 0-2 and 9 are not supported.
+pub const SYSLOG_ACTION_READ_ALL: u64 = 3;
+pub const SYSLOG_ACTION_READ_CLEAR: u64 = 4;
+pub const SYSLOG_ACTION_CLEAR: u64 = 5;
+pub const SYSLOG_ACTION_CONSOLE_OFF: u64 = 6;
+pub const SYSLOG_ACTION_CONSOLE_ON: u64 = 7;
+pub const SYSLOG_ACTION_CONSOLE_LEVEL: u64 = 8;
+pub const SYSLOG_ACTION_SIZE_BUFFER: u64 = 10;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(u8)]
+pub enum Level {
+    Emerg = 0,
+    Alert = 1,
+    Crit = 2,
+    Err = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+    Debug = 7,
+}
+
+impl Level {
+    fn from_u8(value: u8) -> Level {
+        match value {
+            0 => Level::Emerg,
+            1 => Level::Alert,
+            2 => Level::Crit,
+            3 => Level::Err,
+            4 => Level::Warning,
+            5 => Level::Notice,
+            6 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+}
+
+// Lines below this level reach the sinks (Linux's console_loglevel); the
+// default leaves out debug output.
+const DEFAULT_CONSOLE_LEVEL: u8 = 7;
+const MIN_CONSOLE_LEVEL: u8 = 1;
+static CONSOLE_LEVEL: AtomicU8 = AtomicU8::new(DEFAULT_CONSOLE_LEVEL);
+// Level to go back to after SYSLOG_ACTION_CONSOLE_OFF.
+static SAVED_LEVEL: AtomicU8 = AtomicU8::new(0);
+
+kernel_param!(static LOGLEVEL: FnParam = FnParam::new(set_loglevel), "loglevel");
+kernel_param!(static QUIET: FnParam = FnParam::new(|_| set_level(4)), "quiet");
+kernel_param!(static DEBUG: FnParam = FnParam::new(|_| set_level(8)), "debug");
+
+fn set_loglevel(value: Option<&'static [u8]>) -> bool {
+    value
+        .and_then(params::parse_u32)
+        .is_some_and(|level| set_level(level as u64))
+}
+
+fn set_level(level: u64) -> bool {
+    set_console_level(level).is_ok()
+}
+
+#[derive(Copy, Clone)]
+pub struct Record {
+    pub seq: u64,
+    pub level: Level,
+    // Microseconds since the counter started.
+    pub time_us: u64,
+    len: u8,
+    text: [u8; LINE_MAX],
+}
+
+impl Record {
+    const EMPTY: Record = Record {
+        seq: 0,
+        level: Level::Info,
+        time_us: 0,
+        len: 0,
+        text: [0; LINE_MAX],
+    };
+
+    pub fn text(&self) -> &[u8] {
+        &self.text[..self.len as usize]
+    }
+}
+
+struct Slot {
+    // 2 * seq + 1 while record `seq` is being written, 2 * seq + 2 once it
+    // is complete; 0 for a slot never used.
+    state: AtomicU64,
+    record: UnsafeCell<Record>,
+}
+
+// Readers check `state` on both sides of their copy and throw away a
+// record that changed under them.
+unsafe impl Sync for Slot {}
+
+static RING: [Slot; RECORDS] = [const {
+    Slot {
+        state: AtomicU64::new(0),
+        record: UnsafeCell::new(Record::EMPTY),
+    }
+}; RECORDS];
+// Sequence number of the next record.
+static NEXT: AtomicU64 = AtomicU64::new(0);
+// First record SYSLOG_ACTION_READ_ALL returns.
+static CLEARED: AtomicU64 = AtomicU64::new(0);
+
+enum Fetch {
+    Ready(Record),
+    // Claimed but not written yet.
+    Pending,
+    // Overwritten by a newer record.
+    Lost,
+}
+
+fn append(level: Level, text: &[u8]) {
+    let seq = NEXT.fetch_add(1, Ordering::Relaxed);
+    let slot = &RING[seq as usize % RECORDS];
+    slot.state.store(2 * seq + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+    let len = text.len().min(LINE_MAX);
+    let mut record = Record {
+        seq,
+        level,
+        time_us: timer::uptime_us(),
+        len: len as u8,
+        text: [0; LINE_MAX],
+    };
+    record.text[..len].copy_from_slice(&text[..len]);
+    unsafe { slot.record.get().write_volatile(record) };
+    slot.state.store(2 * seq + 2, Ordering::Release);
+}
+
+fn fetch(seq: u64) -> Fetch {
+    let slot = &RING[seq as usize % RECORDS];
+    let done = 2 * seq + 2;
+    let before = slot.state.load(Ordering::Acquire);
+    if before < done {
+        return Fetch::Pending;
+    }
+    if before > done {
+        return Fetch::Lost;
+    }
+    let record = unsafe { slot.record.get().read_volatile() };
+    fence(Ordering::Acquire);
+    if slot.state.load(Ordering::Relaxed) != done {
+        return Fetch::Lost;
+    }
+    Fetch::Ready(record)
+}
+
+fn first() -> u64 {
+    // Oldest record still in the ring.
+    NEXT.load(Ordering::Acquire).saturating_sub(RECORDS as u64)
+}
+
+// Splits formatted output into records at newlines and at LINE_MAX.
+struct LineWriter {
+    level: Level,
+    len: usize,
+    buf: [u8; LINE_MAX],
+}
+
+impl LineWriter {
+    fn new(level: Level) -> Self {
+        Self {
+            level,
+            len: 0,
+            buf: [0; LINE_MAX],
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if b == b'\n' {
+                self.emit();
+                continue;
+            }
+            if self.len == LINE_MAX {
+                self.emit();
+            }
+            self.buf[self.len] = b;
+            self.len += 1;
+        }
+    }
+
+    fn emit(&mut self) {
+        append(self.level, &self.buf[..self.len]);
+        self.len = 0;
+    }
+
+    fn finish(mut self) {
+        // A trailing newline is optional.
+        if self.len > 0 {
+            self.emit();
+        }
+    }
+}
+
+impl Write for LineWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+pub fn log(level: Level, args: fmt::Arguments) {
+    let mut line = LineWriter::new(level);
+    let _ = line.write_fmt(args);
+    line.finish();
+    flush();
+}
+
+#[macro_export]
+macro_rules! klog {
+    ($level:expr, $($arg:tt)*) => {
+        $crate::kernel::klog::log($level, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! kerr {
+    ($($arg:tt)*) => { $crate::klog!($crate::kernel::klog::Level::Err, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! kwarn {
+    ($($arg:tt)*) => { $crate::klog!($crate::kernel::klog::Level::Warning, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! kinfo {
+    ($($arg:tt)*) => { $crate::klog!($crate::kernel::klog::Level::Info, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! kdebug {
+    ($($arg:tt)*) => { $crate::klog!($crate::kernel::klog::Level::Debug, $($arg)*) };
+}
+
+// Fixed-size text buffer for formatting a record; output past the end is
+// cut off.
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = self.buf.len() - self.len;
+        let n = s.len().min(room);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        if n < s.len() {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+fn timestamp(record: &Record) -> ([u8; 24], usize) {
+    // "[    1.234567] ", as dmesg prints it.
+    let mut buf = [0u8; 24];
+    let mut out = SliceWriter {
+        buf: &mut buf,
+        len: 0,
+    };
+    let (secs, us) = (record.time_us / 1_000_000, record.time_us % 1_000_000);
+    let _ = write!(out, "[{:5}.{:06}] ", secs, us);
+    let len = out.len;
+    (buf, len)
+}
+
+// Sinks that print the log; `console=` narrows them down.
+const SINK_UART: u8 = 1 << 0;
+const SINK_CONSOLE: u8 = 1 << 1;
+static SINKS: AtomicU8 = AtomicU8::new(SINK_UART | SINK_CONSOLE);
+// Set by the first `console=`; later ones add to it.
+static SINKS_CHOSEN: AtomicBool = AtomicBool::new(false);
+// The virtual console the console sink draws to, as in `tty<n>`: 0 is
+// whichever one is in the foreground, 1 to MAX_VTS a fixed one.
+static CONSOLE_VT: AtomicUsize = AtomicUsize::new(0);
+
+kernel_param!(static CONSOLE: FnParam = FnParam::new(set_console), "console");
+
+fn set_console(value: Option<&'static [u8]>) -> bool {
+    // Linux names, with any ",options" ignored: ttyAMA<n>, ttyS<n> or
+    // serial<n> for the UART, tty<n> for the framebuffer console.
+    let Some(value) = value else {
+        return false;
+    };
+    let name = value.split(|&b| b == b',').next().unwrap_or(value);
+    let numbered = |prefix: &[u8]| {
+        name.strip_prefix(prefix)
+            .filter(|n| !n.is_empty() && n.iter().all(u8::is_ascii_digit))
+            .and_then(params::parse_u32)
+    };
+    let sink = if numbered(b"ttyAMA").is_some()
+        || numbered(b"ttyS").is_some()
+        || numbered(b"serial").is_some()
+    {
+        SINK_UART
+    } else if let Some(vt) = numbered(b"tty") {
+        if vt as usize > framebuffer::MAX_VTS {
+            return false;
+        }
+        CONSOLE_VT.store(vt as usize, Ordering::Relaxed);
+        SINK_CONSOLE
+    } else {
+        return false;
+    };
+    if SINKS_CHOSEN.swap(true, Ordering::Relaxed) {
+        SINKS.fetch_or(sink, Ordering::Relaxed);
+    } else {
+        SINKS.store(sink, Ordering::Relaxed);
+    }
+    true
+}
+
+pub fn console_vt() -> usize {
+    // The `tty<n>` chosen by `console=`, 0 if none or `tty0`.
+    CONSOLE_VT.load(Ordering::Relaxed)
+}
+
+fn sink_enabled(sink: u8) -> bool {
+    SINKS.load(Ordering::Relaxed) & sink != 0
+}
+
+// Where each sink has printed up to. Only changed with FLUSHING held.
+static UART_NEXT: AtomicU64 = AtomicU64::new(0);
+static CONSOLE_NEXT: AtomicU64 = AtomicU64::new(0);
+static FLUSHING: AtomicBool = AtomicBool::new(false);
+
+fn drain(next: &AtomicU64, end: u64, mut print: impl FnMut(&Record)) {
+    let level = CONSOLE_LEVEL.load(Ordering::Relaxed);
+    let mut seq = next.load(Ordering::Relaxed).max(first());
+    while seq < end {
+        match fetch(seq) {
+            Fetch::Pending => break,
+            Fetch::Lost => {}
+            Fetch::Ready(record) => {
+                if (record.level as u8) < level {
+                    print(&record);
+                }
+            }
+        }
+        seq += 1;
+    }
+    next.store(seq, Ordering::Relaxed);
+}
+
+fn print_uart(record: &Record) {
+    let (stamp, len) = timestamp(record);
+    for &b in stamp[..len].iter().chain(record.text()) {
+        uart::write_byte(b);
+    }
+    uart::write_byte(b'\r');
+    uart::write_byte(b'\n');
+}
+
+fn with_console_sink<F: FnOnce(&mut framebuffer::Console)>(f: F) {
+    // Skipped while the console is busy; see `flush`.
+    match CONSOLE_VT.load(Ordering::Relaxed) {
+        0 => framebuffer::try_with_console(f),
+        vt => framebuffer::try_with_vt(vt - 1, f),
+    };
+}
+
+fn print_console(console: &mut framebuffer::Console, record: &Record) {
+    let (stamp, len) = timestamp(record);
+    for &b in stamp[..len].iter().chain(record.text()) {
+        console.write_byte(b);
+    }
+    console.write_byte(b'\r');
+    console.write_byte(b'\n');
+}
+
+pub fn flush() {
+    // Bring every sink that is up to date with the ring. One CPU prints at
+    // a time; the others return at once and leave their lines to it, or to
+    // the next timer tick. The console is skipped while someone else
+    // holds it, so this is safe from interrupt handlers.
+    loop {
+        if FLUSHING
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        let end = NEXT.load(Ordering::Acquire);
+        if sink_enabled(SINK_UART) && uart::is_ready() {
+            drain(&UART_NEXT, end, print_uart);
+        }
+        if sink_enabled(SINK_CONSOLE) {
+            with_console_sink(|console| {
+                drain(&CONSOLE_NEXT, end, |record| print_console(console, record));
+            });
+        }
+        FLUSHING.store(false, Ordering::Release);
+        // Lines added meanwhile found FLUSHING taken; print them too.
+        if NEXT.load(Ordering::Acquire) == end {
+            return;
+        }
+    }
+}
+
+pub fn flush_emergency() {
+    // Push what is left out after a fatal error, without waiting for
+    // FLUSHING: its holder may be the code that failed. The console is
+    // still only written if it is free.
+    let end = NEXT.load(Ordering::Acquire);
+    if sink_enabled(SINK_UART) && uart::is_ready() {
+        drain(&UART_NEXT, end, print_uart);
+    }
+    if sink_enabled(SINK_CONSOLE) {
+        with_console_sink(|console| {
+            drain(&CONSOLE_NEXT, end, |record| print_console(console, record));
+        });
+    }
+}
+
+// Records up to here have been announced to /dev/kmsg readers.
+static WOKEN: AtomicU64 = AtomicU64::new(0);
+static READ_WAIT: SpinLock<WaitQueue> = SpinLock::new(WaitQueue::new());
+
+pub fn tick() {
+    // Called from the timer interrupt: print lines whose flush was skipped
+    // and wake /dev/kmsg readers. Waking is left to the tick because `log`
+    // may run with scheduler locks held.
+    flush();
+    let next = NEXT.load(Ordering::Acquire);
+    if next == WOKEN.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(mut queue) = READ_WAIT.try_lock() else {
+        return;
+    };
+    let waiters = queue.take();
+    drop(queue);
+    waiters.wake_all();
+    // Records still being written are announced again next tick.
+    let from = WOKEN.load(Ordering::Relaxed).max(first());
+    if (from..next).all(|seq| !matches!(fetch(seq), Fetch::Pending)) {
+        WOKEN.store(next, Ordering::Relaxed);
+    }
+}
+
+// syslog(2)
+
+fn format_syslog(record: &Record, out: &mut [u8]) -> usize {
+    // "<6>[    1.234567] text\n"
+    let mut out = SliceWriter { buf: out, len: 0 };
+    let (stamp, len) = timestamp(record);
+    let _ = write!(out, "<{}>", record.level as u8);
+    let _ = out.write_str(core::str::from_utf8(&stamp[..len]).unwrap_or(""));
+    for &b in record.text() {
+        if out.len == out.buf.len() {
+            break;
+        }
+        out.buf[out.len] = b;
+        out.len += 1;
+    }
+    let _ = out.write_str("\n");
+    out.len
+}
+
+pub fn read_all(buf: &mut [u8], clear: bool) -> VfsResult<u64> {
+    // The newest lines since the last clear that fit in `buf`, oldest first.
+    let end = NEXT.load(Ordering::Acquire);
+    let start = CLEARED.load(Ordering::Relaxed).max(first());
+    let mut line = [0u8; LINE_MAX + 32];
+    let mut from = end;
+    let mut total = 0;
+    while from > start {
+        let Fetch::Ready(record) = fetch(from - 1) else {
+            break;
+        };
+        let len = format_syslog(&record, &mut line);
+        if total + len > buf.len() {
+            break;
+        }
+        total += len;
+        from -= 1;
+    }
+    let mut pos = 0;
+    for seq in from..end {
+        let Fetch::Ready(record) = fetch(seq) else {
+            continue;
+        };
+        let len = format_syslog(&record, &mut line);
+        if pos + len > buf.len() {
+            break;
+        }
+        buf[pos..pos + len].copy_from_slice(&line[..len]);
+        pos += len;
+    }
+    if clear {
+        CLEARED.store(end, Ordering::Relaxed);
+    }
+    Ok(pos as u64)
+}
+
+pub fn clear() {
+    CLEARED.store(NEXT.load(Ordering::Acquire), Ordering::Relaxed);
+}
+
+pub fn set_console_level(level: u64) -> VfsResult<u64> {
+    if !(MIN_CONSOLE_LEVEL as u64..=8).contains(&level) {
+        return Err(VfsError::InvalidArgument);
+    }
+    CONSOLE_LEVEL.store(level as u8, Ordering::Relaxed);
+    SAVED_LEVEL.store(0, Ordering::Relaxed);
+    Ok(0)
+}
+
+pub fn console_off() {
+    // Only emergencies reach the sinks until `console_on`.
+    let old = CONSOLE_LEVEL.swap(MIN_CONSOLE_LEVEL, Ordering::Relaxed);
+    if SAVED_LEVEL.load(Ordering::Relaxed) == 0 {
+        SAVED_LEVEL.store(old, Ordering::Relaxed);
+    }
+}
+
+pub fn console_on() {
+    let saved = SAVED_LEVEL.swap(0, Ordering::Relaxed);
+    if saved != 0 {
+        CONSOLE_LEVEL.store(saved, Ordering::Relaxed);
+    }
+}
+
+pub fn buffer_size() -> u64 {
+    (RECORDS * LINE_MAX) as u64
+}
+
+// /dev/kmsg: each open gets a reader of its own (MAJOR_KMSG:n) that starts
+// at the oldest record. Reads return one record at a time in Linux's
+// "level,seq,usec,-;text\n" format; writes log a message, with an optional
+// "<level>" prefix.
+
+struct KmsgNode;
+struct KmsgReader;
+
+static KMSG_NODE: KmsgNode = KmsgNode;
+static KMSG_READER: KmsgReader = KmsgReader;
+// Next record for each open reader.
+static READERS: SpinLock<[Option<u64>; MAX_READERS]> = SpinLock::new([None; MAX_READERS]);
+
+impl CharDevice for KmsgNode {
+    fn open(&self, _minor: u32) -> VfsResult<Option<DevNum>> {
+        let mut readers = READERS.lock();
+        let idx = readers
+            .iter()
+            .position(Option::is_none)
+            .ok_or(VfsError::Busy)?;
+        readers[idx] = Some(first());
+        Ok(Some(DevNum::new(MAJOR_KMSG, idx as u32)))
+    }
+
+    // Never reached: opening /dev/kmsg always ends up on a reader.
+    fn read(&self, _minor: u32, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::NoDevice)
+    }
+
+    fn write(&self, _minor: u32, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::NoDevice)
+    }
+}
+
+fn kmsg_read(minor: u32, buf: &mut [u8]) -> VfsResult<usize> {
+    // READ_WAIT first, as in `poll`, so the tick cannot wake the readers
+    // between our check and our joining the queue.
+    let mut queue = READ_WAIT.lock();
+    let mut readers = READERS.lock();
+    let pos = readers
+        .get_mut(minor as usize)
+        .and_then(|pos| pos.as_mut())
+        .ok_or(VfsError::BadDescriptor)?;
+    loop {
+        // Like Linux, report lines lost to overwriting once with EPIPE.
+        let oldest = first();
+        if *pos < oldest {
+            *pos = oldest;
+            return Err(VfsError::BrokenPipe);
+        }
+        if *pos >= NEXT.load(Ordering::Acquire) {
+            break;
+        }
+        let record = match fetch(*pos) {
+            Fetch::Ready(record) => record,
+            Fetch::Lost => continue,
+            Fetch::Pending => break,
+        };
+        let mut out = SliceWriter {
+            buf: &mut *buf,
+            len: 0,
+        };
+        let header = write!(
+            out,
+            "{},{},{},-;",
+            record.level as u8, record.seq, record.time_us
+        );
+        let fits = header.is_ok() && out.len + record.text().len() < out.buf.len();
+        if !fits {
+            return Err(VfsError::InvalidArgument);
+        }
+        let len = out.len;
+        buf[len..len + record.text().len()].copy_from_slice(record.text());
+        buf[len + record.text().len()] = b'\n';
+        *pos += 1;
+        return Ok(len + record.text().len() + 1);
+    }
+    if let Some(pid) = process::current_pid() {
+        queue.add(pid);
+    }
+    Err(VfsError::WouldBlock)
+}
+
+fn kmsg_write(buf: &[u8]) -> VfsResult<usize> {
+    if buf.len() > MAX_USER_MESSAGE {
+        return Err(VfsError::InvalidArgument);
+    }
+    // "<N>" picks the level; user messages default to Info.
+    let (level, text) = match buf {
+        [b'<', digit @ b'0'..=b'7', b'>', rest @ ..] => (Level::from_u8(digit - b'0'), rest),
+        _ => (Level::Info, buf),
+    };
+    let mut line = LineWriter::new(level);
+    line.push(text);
+    line.finish();
+    flush();
+    Ok(buf.len())
+}
+
+impl CharDevice for KmsgReader {
+    fn read(&self, minor: u32, buf: &mut [u8]) -> VfsResult<usize> {
+        kmsg_read(minor, buf)
+    }
+
+    fn write(&self, _minor: u32, buf: &[u8]) -> VfsResult<usize> {
+        kmsg_write(buf)
+    }
+
+    fn poll(&self, minor: u32, waiter: Option<ProcessId>) -> u16 {
+        let mut queue = READ_WAIT.lock();
+        let pos = READERS.lock().get(minor as usize).copied().flatten();
+        if pos.is_some_and(|pos| pos < NEXT.load(Ordering::Acquire)) {
+            return POLLIN | POLLOUT;
+        }
+        if let Some(pid) = waiter {
+            queue.add(pid);
+        }
+        POLLOUT
+    }
+
+    fn close(&self, minor: u32, _file: FileId) {
+        if let Some(slot) = READERS.lock().get_mut(minor as usize) {
+            *slot = None;
+        }
+    }
+}
+
+pub fn register_devices() {
+    devfs::register_char("kmsg", DevNum::new(MAJOR_MEM, MINOR_KMSG), &KMSG_NODE);
+    devfs::register_major(MAJOR_KMSG, &KMSG_READER);
+}
@@ -0,0 +1,293 @@
+// Kernel command line. Any module can declare a typed parameter with
+// `kernel_param!`; the declarations are gathered in the .kparams linker
+// section and set from /chosen/bootargs at the start of `kernel_main`,
+// before anything reads them (docs/params.md).
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::mm::dtb;
+use crate::{kdebug, kinfo, kwarn};
+
+// Longer command lines are cut off here.
+pub const CMDLINE_MAX: usize = 1024;
+
+pub trait Param: Sync {
+    // Take `value`, or None for a bare `name`; false if it is not valid.
+    fn set(&self, value: Option<&'static [u8]>) -> bool;
+}
+
+// One `kernel_param!` declaration.
+pub struct Entry {
+    pub name: &'static str,
+    pub param: &'static dyn Param,
+}
+
+#[macro_export]
+macro_rules! kernel_param {
+    ($vis:vis static $ident:ident: $ty:ty = $init:expr, $name:literal) => {
+        $vis static $ident: $ty = $init;
+        const _: () = {
+            #[used]
+            #[link_section = ".kparams"]
+            static ENTRY: $crate::kernel::params::Entry = $crate::kernel::params::Entry {
+                name: $name,
+                param: &$ident,
+            };
+        };
+    };
+}
+
+// Bounds of the .kparams section (linker.ld).
+extern "C" {
+    static __kparams_start: u8;
+    static __kparams_end: u8;
+}
+
+fn entries() -> &'static [Entry] {
+    unsafe {
+        let start = core::ptr::addr_of!(__kparams_start) as usize;
+        let end = core::ptr::addr_of!(__kparams_end) as usize;
+        let count = (end - start) / core::mem::size_of::<Entry>();
+        core::slice::from_raw_parts(start as *const Entry, count)
+    }
+}
+
+// On/off switch: `name`, `name=1` or `name=on` turn it on.
+pub struct BoolParam(AtomicBool);
+
+impl BoolParam {
+    pub const fn new(default: bool) -> Self {
+        Self(AtomicBool::new(default))
+    }
+
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Param for BoolParam {
+    fn set(&self, value: Option<&'static [u8]>) -> bool {
+        let on = match value {
+            None | Some(b"1" | b"y" | b"on" | b"true") => true,
+            Some(b"0" | b"n" | b"off" | b"false") => false,
+            Some(_) => return false,
+        };
+        self.0.store(on, Ordering::Relaxed);
+        true
+    }
+}
+
+// Decimal or 0x-prefixed number, rejected outside [min, max].
+pub struct U32Param {
+    value: AtomicU32,
+    min: u32,
+    max: u32,
+}
+
+impl U32Param {
+    pub const fn range(default: u32, min: u32, max: u32) -> Self {
+        Self {
+            value: AtomicU32::new(default),
+            min,
+            max,
+        }
+    }
+
+    pub fn get(&self) -> u32 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+impl Param for U32Param {
+    fn set(&self, value: Option<&'static [u8]>) -> bool {
+        match value.and_then(parse_u32) {
+            Some(n) if (self.min..=self.max).contains(&n) => {
+                self.value.store(n, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// String value, pointing into the saved command line.
+pub struct StrParam {
+    default: &'static str,
+    ptr: AtomicUsize,
+    len: AtomicUsize,
+}
+
+impl StrParam {
+    pub const fn new(default: &'static str) -> Self {
+        Self {
+            default,
+            ptr: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get(&self) -> &'static [u8] {
+        let ptr = self.ptr.load(Ordering::Relaxed);
+        if ptr == 0 {
+            return self.default.as_bytes();
+        }
+        unsafe { core::slice::from_raw_parts(ptr as *const u8, self.len.load(Ordering::Relaxed)) }
+    }
+}
+
+impl Param for StrParam {
+    fn set(&self, value: Option<&'static [u8]>) -> bool {
+        let Some(value) = value else {
+            return false;
+        };
+        self.len.store(value.len(), Ordering::Relaxed);
+        self.ptr.store(value.as_ptr() as usize, Ordering::Relaxed);
+        true
+    }
+}
+
+// Screen size as `WxH`; unset by default.
+pub struct SizeParam(AtomicU64);
+
+impl SizeParam {
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn get(&self) -> Option<(u32, u32)> {
+        let value = self.0.load(Ordering::Relaxed);
+        if value == 0 {
+            return None;
+        }
+        Some(((value >> 32) as u32, value as u32))
+    }
+}
+
+impl Param for SizeParam {
+    fn set(&self, value: Option<&'static [u8]>) -> bool {
+        let Some(value) = value else {
+            return false;
+        };
+        let Some(x) = value.iter().position(|&b| b == b'x') else {
+            return false;
+        };
+        match (parse_u32(&value[..x]), parse_u32(&value[x + 1..])) {
+            (Some(w @ 1..), Some(h @ 1..)) => {
+                self.0.store((w as u64) << 32 | h as u64, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Anything else: the function parses the value and applies it.
+pub struct FnParam(fn(Option<&'static [u8]>) -> bool);
+
+impl FnParam {
+    pub const fn new(set: fn(Option<&'static [u8]>) -> bool) -> Self {
+        Self(set)
+    }
+}
+
+impl Param for FnParam {
+    fn set(&self, value: Option<&'static [u8]>) -> bool {
+        (self.0)(value)
+    }
+}
+
+pub fn parse_u32(value: &[u8]) -> Option<u32> {
+    let (digits, radix) = match value.strip_prefix(b"0x") {
+        Some(hex) => (hex, 16),
+        None => (value, 10),
+    };
+    u32::from_str_radix(core::str::from_utf8(digits).ok()?, radix).ok()
+}
+
+// Copy of /chosen/bootargs. Written once by `init` on the boot CPU and only
+// read after that.
+struct Cmdline {
+    buf: UnsafeCell<[u8; CMDLINE_MAX]>,
+    len: AtomicUsize,
+}
+
+unsafe impl Sync for Cmdline {}
+
+static CMDLINE: Cmdline = Cmdline {
+    buf: UnsafeCell::new([0; CMDLINE_MAX]),
+    len: AtomicUsize::new(0),
+};
+
+pub fn cmdline() -> &'static [u8] {
+    let len = CMDLINE.len.load(Ordering::Acquire);
+    unsafe { &(&*CMDLINE.buf.get())[..len] }
+}
+
+pub fn init() {
+    // Save the command line and set every parameter it names.
+    let Some(args) = dtb::bootargs() else {
+        return;
+    };
+    let len = args.len().min(CMDLINE_MAX);
+    unsafe { (&mut *CMDLINE.buf.get())[..len].copy_from_slice(&args[..len]) };
+    CMDLINE.len.store(len, Ordering::Release);
+    let line = cmdline();
+    kinfo!(
+        "Kernel command line: {}",
+        core::str::from_utf8(line).unwrap_or("<invalid utf-8>")
+    );
+    let mut rest = line;
+    while let Some((token, tail)) = next_token(rest) {
+        rest = tail;
+        apply(token);
+    }
+}
+
+fn next_token(line: &'static [u8]) -> Option<(&'static [u8], &'static [u8])> {
+    // Split off the next space-separated word; double quotes keep spaces.
+    let start = line.iter().position(|b| !b.is_ascii_whitespace())?;
+    let line = &line[start..];
+    let mut quoted = false;
+    let mut end = 0;
+    while end < line.len() && (quoted || !line[end].is_ascii_whitespace()) {
+        if line[end] == b'"' {
+            quoted = !quoted;
+        }
+        end += 1;
+    }
+    Some((&line[..end], &line[end..]))
+}
+
+fn apply(token: &'static [u8]) {
+    let (name, value) = match token.iter().position(|&b| b == b'=') {
+        Some(eq) => {
+            let value = &token[eq + 1..];
+            let value = value
+                .strip_prefix(b"\"")
+                .and_then(|v| v.strip_suffix(b"\""))
+                .unwrap_or(value);
+            (&token[..eq], Some(value))
+        }
+        None => (token, None),
+    };
+    let text = core::str::from_utf8(token).unwrap_or("<invalid utf-8>");
+    let Some(entry) = entries().iter().find(|entry| same_name(entry.name, name)) else {
+        // The Pi firmware adds Linux's own parameters; keep quiet about them.
+        kdebug!("Unknown kernel parameter {}", text);
+        return;
+    };
+    if !entry.param.set(value) {
+        kwarn!("Bad value for kernel parameter {}", text);
+    }
+}
+
+fn same_name(declared: &str, given: &[u8]) -> bool {
+    // Dashes and underscores are interchangeable, as on Linux.
+    let dash = |b: u8| if b == b'-' { b'_' } else { b };
+    declared.len() == given.len()
+        && declared
+            .bytes()
+            .zip(given.iter())
+            .all(|(a, &b)| dash(a) == dash(b))
+}
@@ -4,13 +4,17 @@ use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::arch::aarch64::trap::{TrapFrame, TRAP_FRAME_SIZE};
 use crate::kernel::smp;
-use crate::kernel::vfs::{FileDesc, FD_STDERR, FD_STDOUT};
+use crate::kernel::vfs::{self, Cwd, FileDesc, FD_STDERR, FD_STDOUT};
 use crate::mm::paging;
 use core::fmt;
 use crate::util::sync::SpinLock;
 
+mod fdtable;
 mod scheduler;
-pub use scheduler::{schedule_from_irq, start_on_cpu};
+mod wait;
+pub use fdtable::{FdTable, DEFAULT_FD_LIMIT, MAX_FDS};
+pub use scheduler::{block_current, idle_ticks, schedule_from_irq, start_on_cpu};
+pub use wait::WaitQueue;
 
 pub type ProcessEntry = extern "C" fn() -> !;
 
@@ -18,7 +22,6 @@ pub type ProcessEntry = extern "C" fn() -> !;
 pub struct ProcessId(pub u32);
 
 pub const CPU_NONE: usize = usize::MAX;
-pub const MAX_FDS: usize = 8;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ProcessMode {
@@ -34,7 +37,7 @@ pub enum ProcessState {
     Terminated,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Process {
     pub id: ProcessId,
     pub name: &'static str,
@@ -44,9 +47,17 @@ pub struct Process {
     pub context_sp: usize,
     pub running_on: usize,
     pub in_run_queue: bool,
+    // Set by a wakeup that arrives before the process has gone to sleep.
+    pub wake_pending: bool,
+    // Uptime (ms) at which the timer tick wakes a blocked process. Also keeps
+    // a sleeping syscall's deadline across its restarts.
+    pub wake_at: Option<u64>,
     pub mode: ProcessMode,
+    // One of the per-CPU idle loops; ticks that preempt it count as idle time.
+    pub idle: bool,
     pub parent: Option<ProcessId>,
-    pub fds: [Option<FileDesc>; MAX_FDS],
+    pub fds: FdTable,
+    pub cwd: Cwd,
     pub ttbr0: u64,
 }
 
@@ -99,7 +110,6 @@ impl RunQueue {
     }
 }
 
-#[derive(Copy, Clone)]
 struct ProcessTable {
     slots: [Option<Process>; MAX_PROCS],
     next_pid: u32,
@@ -109,7 +119,7 @@ struct ProcessTable {
 impl ProcessTable {
     const fn new() -> Self {
         Self {
-            slots: [None; MAX_PROCS],
+            slots: [const { None }; MAX_PROCS],
             next_pid: 1,
             run_queue: RunQueue::new(),
         }
@@ -129,7 +139,7 @@ static CURRENT: [AtomicUsize; smp::MAX_CPUS] = [
     AtomicUsize::new(INVALID_IDX),
     AtomicUsize::new(INVALID_IDX),
 ];
-static INIT_FDS: SpinLock<[Option<FileDesc>; MAX_FDS]> = SpinLock::new([None; MAX_FDS]);
+static INIT_FDS: SpinLock<FdTable> = SpinLock::new(FdTable::new());
 
 pub fn init() {
     // Reset the process table and per-CPU current pointers.
@@ -139,19 +149,25 @@ pub fn init() {
         CURRENT[cpu].store(INVALID_IDX, Ordering::Relaxed);
     }
     let mut init_fds = INIT_FDS.lock();
-    *init_fds = [None; MAX_FDS];
+    *init_fds = FdTable::new();
 }
 
 pub fn create(name: &'static str, entry: ProcessEntry, stack_top: usize) -> Option<ProcessId> {
     // Create a kernel-mode process.
     let parent = current_pid();
-    create_with_mode(name, entry, stack_top, ProcessMode::Kernel, parent)
+    create_with_mode(name, entry, stack_top, ProcessMode::Kernel, parent, false)
+}
+
+pub fn create_idle(entry: ProcessEntry) -> Option<ProcessId> {
+    // Create a kernel-mode idle process (`/proc/uptime` counts its ticks).
+    let parent = current_pid();
+    create_with_mode("idle", entry, 0, ProcessMode::Kernel, parent, true)
 }
 
 pub fn create_user(name: &'static str, entry: ProcessEntry, stack_top: usize) -> Option<ProcessId> {
     // Create a user-mode process (EL0 on hardware, EL1 on QEMU).
     let parent = current_pid();
-    create_with_mode(name, entry, stack_top, ProcessMode::User, parent)
+    create_with_mode(name, entry, stack_top, ProcessMode::User, parent, false)
 }
 
 fn create_with_mode(
@@ -160,21 +176,19 @@ fn create_with_mode(
     stack_top: usize,
     mode: ProcessMode,
     parent: Option<ProcessId>,
+    idle: bool,
 ) -> Option<ProcessId> {
     // Allocate a process slot, set up stack/context, and enqueue it.
     let mut table = PROCESS_TABLE.lock();
     let ttbr0 = paging::user_root_pa();
-    let inherited = if let Some(pid) = parent {
-        table
-            .slots
-            .iter()
-            .flatten()
-            .find(|p| p.id == pid)
-            .map(|p| p.fds)
-            .unwrap_or_else(|| *INIT_FDS.lock())
-    } else {
-        *INIT_FDS.lock()
-    };
+    let (inherited, cwd) = parent
+        .and_then(|pid| table.slots.iter().flatten().find(|p| p.id == pid))
+        .map(|p| (p.fds.clone(), p.cwd))
+        .unwrap_or_else(|| (INIT_FDS.lock().clone(), Cwd::ROOT));
+    // The child's table holds its own references to the shared open files.
+    for (_, desc) in inherited.iter() {
+        let _ = vfs::dup(&desc);
+    }
     for idx in 0..MAX_PROCS {
         if table.slots[idx].is_none() {
             let pid = table.alloc_pid();
@@ -193,42 +207,59 @@ fn create_with_mode(
                 context_sp,
                 running_on: CPU_NONE,
                 in_run_queue: true,
+                wake_pending: false,
+                wake_at: None,
                 mode,
+                idle,
                 parent,
                 fds: inherited,
+                cwd,
                 ttbr0,
             });
             table.run_queue.push(idx);
             return Some(pid);
         }
     }
+    drop(table);
+    for (_, desc) in inherited.iter() {
+        vfs::close(&desc);
+    }
     None
 }
 
 pub fn set_init_fd(fd: usize, desc: Option<FileDesc>) {
     // Configure initial FDs inherited by the first process tree.
-    if fd >= MAX_FDS {
-        return;
+    // The table takes over the caller's reference.
+    let mut init_fds = INIT_FDS.lock();
+    let old = match desc {
+        Some(desc) => init_fds.replace(fd, desc, false).flatten(),
+        None => init_fds.take(fd),
+    };
+    drop(init_fds);
+    if let Some(old) = old {
+        vfs::close(&old);
     }
-    let mut table = INIT_FDS.lock();
-    table[fd] = desc;
 }
 
 pub fn set_fd(pid: ProcessId, fd: usize, desc: Option<FileDesc>) -> bool {
     // Update a specific process's FD table.
-    if fd >= MAX_FDS {
-        return false;
-    }
     let mut table = PROCESS_TABLE.lock();
-    for slot in table.slots.iter_mut() {
-        if let Some(proc) = slot {
-            if proc.id == pid {
-                proc.fds[fd] = desc;
-                return true;
-            }
-        }
+    let proc = match table.slots.iter_mut().flatten().find(|proc| proc.id == pid) {
+        Some(proc) => proc,
+        None => return false,
+    };
+    let old = match desc {
+        Some(desc) => match proc.fds.replace(fd, desc, false) {
+            Some(old) => old,
+            None => return false,
+        },
+        None => proc.fds.take(fd),
+    };
+    drop(table);
+    if let Some(old) = old {
+        vfs::close(&old);
     }
-    false
+    true
 }
 
 pub fn current_pid() -> Option<ProcessId> {
@@ -269,34 +300,77 @@ where
     table.slots[idx].as_mut().map(f)
 }
 
-pub fn alloc_fd_current(desc: FileDesc) -> Option<usize> {
-    with_current_mut(|proc| {
-        for fd in 0..MAX_FDS {
-            if proc.fds[fd].is_none() {
-                proc.fds[fd] = Some(desc);
-                return Some(fd);
+pub fn alloc_fd_current(desc: FileDesc, min: usize, cloexec: bool) -> Option<usize> {
+    // Lowest free FD >= `min` below the process's limit.
+    with_current_mut(|proc| proc.fds.alloc(desc, min, cloexec))?
+}
+
+pub fn set_fd_current(fd: usize, desc: FileDesc, cloexec: bool) -> bool {
+    // Install `desc` at a fixed FD, closing whatever was there (dup2 semantics).
+    match with_current_mut(|proc| proc.fds.replace(fd, desc, cloexec)).flatten() {
+        Some(old) => {
+            if let Some(old) = old {
+                vfs::close(&old);
             }
+            true
         }
-        None
-    })?
+        None => false,
+    }
 }
 
 pub fn close_fd_current(fd: usize) -> bool {
-    if fd >= MAX_FDS {
-        return false;
+    // Release the open file outside the process table lock.
+    match with_current_mut(|proc| proc.fds.take(fd)).flatten() {
+        Some(desc) => {
+            vfs::close(&desc);
+            true
+        }
+        None => false,
     }
-    with_current_mut(|proc| {
-        proc.fds[fd] = None;
-        true
-    })
-    .unwrap_or(false)
 }
 
 pub fn get_fd_current(fd: usize) -> Option<FileDesc> {
-    if fd >= MAX_FDS {
-        return None;
+    with_current(|proc| proc.fds.get(fd))?
+}
+
+pub fn fd_cloexec_current(fd: usize) -> Option<bool> {
+    with_current(|proc| proc.fds.cloexec(fd))?
+}
+
+pub fn set_fd_cloexec_current(fd: usize, cloexec: bool) -> bool {
+    with_current_mut(|proc| proc.fds.set_cloexec(fd, cloexec)).unwrap_or(false)
+}
+
+pub fn fd_limit_current() -> usize {
+    with_current(|proc| proc.fds.limit()).unwrap_or(DEFAULT_FD_LIMIT)
+}
+
+pub fn set_fd_limit_current(limit: usize) -> bool {
+    with_current_mut(|proc| proc.fds.set_limit(limit)).unwrap_or(false)
+}
+
+pub fn exec_current(name: &'static str, entry: ProcessEntry) -> Option<*mut TrapFrame> {
+    // Replace the running program: FD_CLOEXEC FDs are closed, other FDs and the
+    // cwd carry over, and execution restarts at `entry` on an empty stack. The
+    // returned frame is what the syscall should resume.
+    let (stack_top, closing) = with_current_mut(|proc| {
+        proc.name = name;
+        proc.entry = entry;
+        (proc.stack_top, proc.fds.take_cloexec())
+    })?;
+    for desc in closing.iter() {
+        vfs::close(desc);
     }
-    with_current(|proc| proc.fds[fd])?
+    Some(init_context(entry, stack_top) as *mut TrapFrame)
+}
+
+pub fn current_cwd() -> Cwd {
+    // Kernel threads without a process resolve relative paths from the root.
+    with_current(|proc| proc.cwd).unwrap_or(Cwd::ROOT)
+}
+
+pub fn set_current_cwd(cwd: Cwd) -> bool {
+    with_current_mut(|proc| proc.cwd = cwd).is_some()
 }
 
 pub struct FdWriter {
@@ -321,7 +395,7 @@ pub fn write_current_fd(fd: usize, buf: &[u8]) -> usize {
         Some(desc) => desc,
         None => return 0,
     };
-    crate::kernel::vfs::write(&desc, buf)
+    crate::kernel::vfs::write(&desc, buf).unwrap_or(0)
 }
 
 pub fn write_stdout(buf: &[u8]) -> usize {
@@ -347,7 +421,7 @@ pub fn get(pid: ProcessId) -> Option<Process> {
     for slot in table.slots.iter() {
         if let Some(proc) = slot {
             if proc.id == pid {
-                return Some(*proc);
+                return Some(proc.clone());
             }
         }
     }
@@ -367,6 +441,54 @@ pub fn set_state(pid: ProcessId, state: ProcessState) -> bool {
     false
 }
 
+pub fn wake(pid: ProcessId) {
+    // Make a blocked process runnable again. If it has not gone to sleep yet,
+    // remember the wakeup so its next attempt to block returns at once.
+    let mut table = PROCESS_TABLE.lock();
+    let found = (0..MAX_PROCS).find(|&idx| table.slots[idx].as_ref().is_some_and(|p| p.id == pid));
+    let idx = match found {
+        Some(idx) => idx,
+        None => return,
+    };
+    let proc = table.slots[idx].as_mut().unwrap();
+    if proc.state != ProcessState::Blocked {
+        proc.wake_pending = true;
+        return;
+    }
+    make_ready(&mut table, idx);
+}
+
+pub fn wake_expired(now: u64) {
+    // Timer tick: wake blocked processes whose deadline has passed.
+    let mut table = PROCESS_TABLE.lock();
+    for idx in 0..MAX_PROCS {
+        let due = table.slots[idx].as_ref().is_some_and(|proc| {
+            proc.state == ProcessState::Blocked && proc.wake_at.is_some_and(|at| at <= now)
+        });
+        if due {
+            make_ready(&mut table, idx);
+        }
+    }
+}
+
+fn make_ready(table: &mut ProcessTable, idx: usize) {
+    let proc = table.slots[idx].as_mut().unwrap();
+    proc.state = ProcessState::Ready;
+    let enqueue = !proc.in_run_queue;
+    proc.in_run_queue = true;
+    if enqueue {
+        table.run_queue.push(idx);
+    }
+}
+
+pub fn take_wake_at_current() -> Option<u64> {
+    with_current_mut(|proc| proc.wake_at.take()).flatten()
+}
+
+pub fn set_wake_at_current(at: Option<u64>) {
+    let _ = with_current_mut(|proc| proc.wake_at = at);
+}
+
 pub fn for_each(mut f: impl FnMut(&Process)) {
     let table = PROCESS_TABLE.lock();
     for slot in table.slots.iter() {
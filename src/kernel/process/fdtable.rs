@@ -0,0 +1,135 @@
+use alloc::vec::Vec;
+
+use crate::kernel::vfs::FileDesc;
+
+// Hard ceiling for the per-process limit, like Linux's nr_open.
+pub const MAX_FDS: usize = 1024;
+// Limit given to the first processes; children inherit their parent's.
+pub const DEFAULT_FD_LIMIT: usize = 64;
+
+#[derive(Copy, Clone, Debug)]
+struct FdEntry {
+    desc: FileDesc,
+    cloexec: bool,
+}
+
+// A process's FD slots. The table grows on demand up to `limit`, so a
+// process holding three FDs pays for three slots.
+#[derive(Clone, Debug)]
+pub struct FdTable {
+    slots: Vec<Option<FdEntry>>,
+    limit: usize,
+}
+
+impl Default for FdTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FdTable {
+    pub const fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            limit: DEFAULT_FD_LIMIT,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: usize) -> bool {
+        // FDs already open above a lowered limit stay valid.
+        if limit == 0 || limit > MAX_FDS {
+            return false;
+        }
+        self.limit = limit;
+        true
+    }
+
+    pub fn get(&self, fd: usize) -> Option<FileDesc> {
+        self.slots
+            .get(fd)
+            .copied()
+            .flatten()
+            .map(|entry| entry.desc)
+    }
+
+    pub fn cloexec(&self, fd: usize) -> Option<bool> {
+        self.slots
+            .get(fd)
+            .copied()
+            .flatten()
+            .map(|entry| entry.cloexec)
+    }
+
+    pub fn set_cloexec(&mut self, fd: usize, cloexec: bool) -> bool {
+        match self.slots.get_mut(fd) {
+            Some(Some(entry)) => {
+                entry.cloexec = cloexec;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn alloc(&mut self, desc: FileDesc, min: usize, cloexec: bool) -> Option<usize> {
+        // Lowest free FD at or above `min`, as POSIX requires.
+        let fd =
+            (min..self.limit).find(|&fd| self.slots.get(fd).is_none_or(|slot| slot.is_none()))?;
+        self.install(fd, desc, cloexec);
+        Some(fd)
+    }
+
+    pub fn replace(
+        &mut self,
+        fd: usize,
+        desc: FileDesc,
+        cloexec: bool,
+    ) -> Option<Option<FileDesc>> {
+        // Install at a fixed FD; returns what was there, or None past the limit.
+        if fd >= self.limit {
+            return None;
+        }
+        let old = self.take(fd);
+        self.install(fd, desc, cloexec);
+        Some(old)
+    }
+
+    pub fn take(&mut self, fd: usize) -> Option<FileDesc> {
+        let desc = self.slots.get_mut(fd)?.take().map(|entry| entry.desc);
+        while let Some(None) = self.slots.last() {
+            self.slots.pop();
+        }
+        desc
+    }
+
+    pub fn take_cloexec(&mut self) -> Vec<FileDesc> {
+        // Remove the FDs marked close-on-exec; the caller closes them.
+        let fds: Vec<usize> = self
+            .iter_entries()
+            .filter(|(_, e)| e.cloexec)
+            .map(|(fd, _)| fd)
+            .collect();
+        fds.into_iter().filter_map(|fd| self.take(fd)).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, FileDesc)> + '_ {
+        self.iter_entries().map(|(fd, entry)| (fd, entry.desc))
+    }
+
+    fn iter_entries(&self) -> impl Iterator<Item = (usize, FdEntry)> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(fd, slot)| slot.map(|entry| (fd, entry)))
+    }
+
+    fn install(&mut self, fd: usize, desc: FileDesc, cloexec: bool) {
+        if self.slots.len() <= fd {
+            self.slots.resize(fd + 1, None);
+        }
+        self.slots[fd] = Some(FdEntry { desc, cloexec });
+    }
+}
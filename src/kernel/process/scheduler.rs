@@ -2,14 +2,15 @@ use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::arch::aarch64::trap::TrapFrame;
 use crate::arch::aarch64::mmu;
-use crate::drivers::uart;
+use crate::kernel::params::BoolParam;
 use crate::kernel::smp;
+use crate::{kdebug, kernel_param};
 
 use super::{
     ProcessEntry, ProcessState, ProcessTable, CPU_NONE, CURRENT, INVALID_IDX, PROCESS_TABLE,
 };
 
-const LOG_SCHED: bool = false;
+kernel_param!(static LOG_SCHED: BoolParam = BoolParam::new(false), "sched.trace");
 const LOG_EVERY: usize = 50;
 static LOG_TICKS: [AtomicUsize; smp::MAX_CPUS] = [
     AtomicUsize::new(0),
@@ -17,8 +18,25 @@ static LOG_TICKS: [AtomicUsize; smp::MAX_CPUS] = [
     AtomicUsize::new(0),
     AtomicUsize::new(0),
 ];
+static IDLE_TICKS: [AtomicUsize; smp::MAX_CPUS] = [const { AtomicUsize::new(0) }; smp::MAX_CPUS];
+
+pub fn idle_ticks(cpu: usize) -> usize {
+    // Timer ticks that preempted this CPU's idle process.
+    IDLE_TICKS[cpu].load(Ordering::Relaxed)
+}
 
 pub fn schedule_from_irq(frame: *mut TrapFrame) -> *mut TrapFrame {
+    switch(frame, false)
+}
+
+pub fn block_current(frame: *mut TrapFrame) -> *mut TrapFrame {
+    // Put the current process to sleep until `wake`, unless a wakeup already
+    // arrived. The state change and context save happen under one lock so a
+    // waker on another CPU never resumes a stale frame.
+    switch(frame, true)
+}
+
+fn switch(frame: *mut TrapFrame, block: bool) -> *mut TrapFrame {
     // Save the current context and pick the next runnable process.
     let cpu = smp::cpu_id();
     let mut log_data: Option<(usize, u32, &'static str, u32, &'static str, usize)> = None;
@@ -29,6 +47,17 @@ pub fn schedule_from_irq(frame: *mut TrapFrame) -> *mut TrapFrame {
         let current_idx = CURRENT[cpu].load(Ordering::Relaxed);
         if current_idx != INVALID_IDX {
             if let Some(proc) = &mut table.slots[current_idx] {
+                if block {
+                    if proc.wake_pending {
+                        proc.wake_pending = false;
+                        return frame;
+                    }
+                    proc.state = ProcessState::Blocked;
+                    proc.running_on = CPU_NONE;
+                }
+                if proc.idle {
+                    IDLE_TICKS[cpu].fetch_add(1, Ordering::Relaxed);
+                }
                 proc.context_sp = frame as usize;
                 if proc.state == ProcessState::Running {
                     proc.state = ProcessState::Ready;
@@ -38,7 +67,8 @@ pub fn schedule_from_irq(frame: *mut TrapFrame) -> *mut TrapFrame {
             }
         }
 
-        // Select the next runnable process from the global queue.
+        // Select the next runnable process from the global queue. With nothing
+        // else to run, a process that tried to block keeps running and retries.
         let next_idx = dequeue_next_runnable(&mut table);
         if next_idx.is_none() {
             if current_idx != INVALID_IDX {
@@ -72,7 +102,7 @@ pub fn schedule_from_irq(frame: *mut TrapFrame) -> *mut TrapFrame {
             CURRENT[cpu].store(next_idx, Ordering::Relaxed);
             result = context_sp as *mut TrapFrame;
 
-            if LOG_SCHED {
+            if LOG_SCHED.get() {
                 let tick = LOG_TICKS[cpu].fetch_add(1, Ordering::Relaxed);
                 if tick % LOG_EVERY == 0 {
                     let (from_id, from_name) = if current_idx != INVALID_IDX {
@@ -98,16 +128,12 @@ pub fn schedule_from_irq(frame: *mut TrapFrame) -> *mut TrapFrame {
 
     };
 
-    if LOG_SCHED {
+    if LOG_SCHED.get() {
         if let Some((cpu, from_id, from_name, to_id, to_name, qlen)) = log_data {
-            uart::with_uart(|uart| {
-                use core::fmt::Write;
-                let _ = writeln!(
-                    uart,
-                    "sched cpu{} {}({}) -> {}({}) qlen={}",
-                    cpu, from_name, from_id, to_name, to_id, qlen
-                );
-            });
+            kdebug!(
+                "sched cpu{} {}({}) -> {}({}) qlen={}",
+                cpu, from_name, from_id, to_name, to_id, qlen
+            );
         }
     }
 
@@ -0,0 +1,35 @@
+use alloc::vec::Vec;
+
+use super::ProcessId;
+
+// Processes sleeping on some condition. The queue lives inside the object it
+// guards, so a waiter is recorded under the same lock as the failed check.
+#[derive(Default)]
+pub struct WaitQueue {
+    pids: Vec<ProcessId>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self { pids: Vec::new() }
+    }
+
+    pub fn add(&mut self, pid: ProcessId) {
+        if !self.pids.contains(&pid) {
+            self.pids.push(pid);
+        }
+    }
+
+    pub fn take(&mut self) -> WaitQueue {
+        // Detach the waiters so they can be woken after the owner's lock is dropped.
+        WaitQueue {
+            pids: core::mem::take(&mut self.pids),
+        }
+    }
+
+    pub fn wake_all(self) {
+        for pid in self.pids {
+            super::wake(pid);
+        }
+    }
+}
@@ -1,11 +1,20 @@
 use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use crate::arch::aarch64::mmu;
+use crate::kernel::params::U32Param;
+use crate::kernel_param;
 use crate::mm::paging;
 
 pub const MAX_CPUS: usize = 4;
 const STACK_SIZE: usize = 0x4000;
 
+// CPUs to bring up; 0 and 1 both mean only the boot CPU.
+kernel_param!(
+    static MAXCPUS: U32Param = U32Param::range(MAX_CPUS as u32, 0, MAX_CPUS as u32),
+    "maxcpus"
+);
+
 #[allow(dead_code)]
 #[repr(align(16))]
 #[derive(Copy, Clone)]
@@ -23,6 +32,17 @@ extern "C" {
     static __secondary_start_ptr: u64;
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct CpuInfo {
+    pub online: bool,
+    pub midr: u64,
+    pub mpidr: u64,
+}
+
+static CPU_ONLINE: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+static CPU_MIDR: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+static CPU_MPIDR: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+
 pub fn cpu_id() -> usize {
     // Return the core ID from MPIDR_EL1 (affinity level 0).
     let mut id: usize;
@@ -41,18 +61,45 @@ pub fn current_el() -> u8 {
     ((el >> 2) & 0x3) as u8
 }
 
+pub fn record_cpu_info() {
+    // Snapshot this core's identification registers for /proc/cpuinfo.
+    let cpu = cpu_id();
+    let midr: u64;
+    let mpidr: u64;
+    unsafe {
+        asm!("mrs {0}, midr_el1", out(reg) midr, options(nomem, nostack, preserves_flags));
+        asm!("mrs {0}, mpidr_el1", out(reg) mpidr, options(nomem, nostack, preserves_flags));
+    }
+    CPU_MIDR[cpu].store(midr, Ordering::Relaxed);
+    CPU_MPIDR[cpu].store(mpidr, Ordering::Relaxed);
+    CPU_ONLINE[cpu].store(true, Ordering::Release);
+}
+
+pub fn cpu_info(cpu: usize) -> CpuInfo {
+    CpuInfo {
+        online: CPU_ONLINE[cpu].load(Ordering::Acquire),
+        midr: CPU_MIDR[cpu].load(Ordering::Relaxed),
+        mpidr: CPU_MPIDR[cpu].load(Ordering::Relaxed),
+    }
+}
+
+pub fn enabled_cpus() -> usize {
+    (MAXCPUS.get() as usize).max(1)
+}
+
 pub fn start_secondary_cores() {
-    // Release secondary cores via the spin-table mechanism.
+    // Release secondary cores via the spin-table mechanism. Cores past
+    // `maxcpus` stay parked.
     unsafe {
         let entry = __secondary_start_ptr;
-        for core in 1..MAX_CPUS {
+        for core in 1..enabled_cpus() {
             __secondary_table[core] = entry;
         }
 
         #[cfg(feature = "qemu")]
         {
             const SPIN_TABLE_BASE: usize = 0xD8;
-            for core in 1..MAX_CPUS {
+            for core in 1..enabled_cpus() {
                 let slot = (SPIN_TABLE_BASE + (core * 8)) as *mut u64;
                 core::ptr::write_volatile(slot, entry);
             }
@@ -66,15 +113,13 @@ pub fn start_secondary_cores() {
 pub extern "C" fn secondary_rust_entry(_core_id: usize) -> ! {
     // Entry point for secondary cores after boot.S releases them.
     let core_id = cpu_id();
+    record_cpu_info();
     // Switch to the final kernel page tables (TTBR1) built by CPU0.
     mmu::set_ttbr1(paging::kernel_root_pa());
     // Also switch TTBR0 so low MMIO addresses are mapped.
     mmu::set_ttbr0(paging::user_root_pa());
-    crate::drivers::uart::with_uart(|uart| {
-        use core::fmt::Write;
-        let _ = writeln!(uart, "CPU{} online", core_id);
-    });
+    crate::kinfo!("CPU{} online", core_id);
 
-    crate::kernel::interrupts::init_per_cpu(10);
+    crate::kernel::interrupts::init_per_cpu();
     crate::kernel::process::start_on_cpu(core_id);
 }
@@ -2,8 +2,10 @@ use core::arch::asm;
 
 use crate::arch::aarch64::timer;
 use crate::arch::aarch64::trap::TrapFrame;
+use crate::kernel::klog;
 use crate::kernel::process;
-use crate::kernel::vfs;
+use crate::kernel::vfs::{self, PollFd, Stat, VfsError, VfsResult, POLLERR, POLLHUP, POLLNVAL};
+use crate::mm::pagecache;
 use alloc::alloc::{alloc, dealloc, realloc, Layout};
 
 pub const SYSCALL_OPEN: u64 = 1;
@@ -14,6 +16,58 @@ pub const SYSCALL_SLEEP_MS: u64 = 5;
 pub const SYSCALL_ALLOC: u64 = 6;
 pub const SYSCALL_REALLOC: u64 = 7;
 pub const SYSCALL_FREE: u64 = 8;
+pub const SYSCALL_SYNC: u64 = 9;
+pub const SYSCALL_FSYNC: u64 = 10;
+pub const SYSCALL_GETDENTS64: u64 = 11;
+pub const SYSCALL_STAT: u64 = 12;
+pub const SYSCALL_FSTAT: u64 = 13;
+pub const SYSCALL_LSTAT: u64 = 14;
+pub const SYSCALL_MKDIR: u64 = 15;
+pub const SYSCALL_RMDIR: u64 = 16;
+pub const SYSCALL_UNLINK: u64 = 17;
+pub const SYSCALL_RENAME: u64 = 18;
+pub const SYSCALL_SYMLINK: u64 = 19;
+pub const SYSCALL_READLINK: u64 = 20;
+pub const SYSCALL_CHDIR: u64 = 21;
+pub const SYSCALL_GETCWD: u64 = 22;
+pub const SYSCALL_LSEEK: u64 = 23;
+pub const SYSCALL_PREAD64: u64 = 24;
+pub const SYSCALL_PWRITE64: u64 = 25;
+pub const SYSCALL_READV: u64 = 26;
+pub const SYSCALL_WRITEV: u64 = 27;
+pub const SYSCALL_PIPE: u64 = 28;
+pub const SYSCALL_DUP: u64 = 29;
+pub const SYSCALL_DUP2: u64 = 30;
+pub const SYSCALL_DUP3: u64 = 31;
+pub const SYSCALL_FCNTL: u64 = 32;
+pub const SYSCALL_EXEC: u64 = 33;
+pub const SYSCALL_GETRLIMIT: u64 = 34;
+pub const SYSCALL_SETRLIMIT: u64 = 35;
+pub const SYSCALL_POLL: u64 = 36;
+pub const SYSCALL_IOCTL: u64 = 37;
+pub const SYSCALL_MMAP: u64 = 38;
+pub const SYSCALL_SYSLOG: u64 = 39;
+
+// fcntl commands and FD flags (Linux values).
+pub const F_DUPFD: u64 = 0;
+pub const F_GETFD: u64 = 1;
+pub const F_SETFD: u64 = 2;
+pub const F_GETFL: u64 = 3;
+pub const F_SETFL: u64 = 4;
+pub const F_DUPFD_CLOEXEC: u64 = 1030;
+pub const FD_CLOEXEC: u64 = 1;
+
+// mmap protections and flags (Linux values). Only shared mappings of
+// devices exist.
+pub const PROT_READ: u64 = 1;
+pub const PROT_WRITE: u64 = 2;
+pub const MAP_SHARED: u64 = 1;
+
+// The only resource limit so far: the FD table size.
+pub const RLIMIT_NOFILE: u64 = 7;
+
+// Upper bound on iovecs per readv/writev call, like Linux's UIO_MAXIOV.
+const IOV_MAX: u64 = 1024;
 
 #[no_mangle]
 pub extern "C" fn sync_handler(frame: *mut TrapFrame) -> *mut TrapFrame {
@@ -30,14 +84,14 @@ pub extern "C" fn sync_handler(frame: *mut TrapFrame) -> *mut TrapFrame {
             asm!("mrs {0}, far_el1", out(reg) far, options(nomem, nostack, preserves_flags));
         }
         let elr = unsafe { (*frame).elr };
-        crate::drivers::uart::with_uart(|uart| {
-            use core::fmt::Write;
-            let _ = writeln!(
-                uart,
-                "sync fault: ec={:#x} esr={:#x} far={:#x} elr={:#x}",
-                ec, esr, far, elr
-            );
-        });
+        crate::kerr!(
+            "sync fault: ec={:#x} esr={:#x} far={:#x} elr={:#x}",
+            ec,
+            esr,
+            far,
+            elr
+        );
+        klog::flush_emergency();
         loop {
             unsafe { core::arch::asm!("wfe", options(nomem, nostack, preserves_flags)) }
         }
@@ -45,80 +99,50 @@ pub extern "C" fn sync_handler(frame: *mut TrapFrame) -> *mut TrapFrame {
 
     let tf = unsafe { &mut *frame };
     let syscall = tf.x[8];
-    // Syscall ABI: x8 = number, x0..x3 = args, x0 = return.
+    // Syscall ABI: x8 = number, x0..x5 = args, x0 = return.
     match syscall {
         SYSCALL_OPEN => {
-            let ptr = tf.x[0] as *const u8;
-            let len = tf.x[1] as usize;
-            let flags = vfs::OpenFlags::from_bits(tf.x[2]);
-            if ptr.is_null() || len == 0 {
-                tf.x[0] = u64::MAX;
-                return frame;
-            }
-            let path = unsafe { core::slice::from_raw_parts(ptr, len) };
-            let desc = match vfs::open_bytes(path, flags) {
-                Some(desc) => desc,
-                None => {
-                    tf.x[0] = u64::MAX;
+            let flags = tf.x[2];
+            let mode = tf.x[3] as u32;
+            let path = match user_bytes(tf.x[0], tf.x[1]) {
+                Ok(path) => path,
+                Err(err) => {
+                    tf.x[0] = errno(err);
+                    return frame;
+                }
+            };
+            let desc = match vfs::open_bytes(path, flags, mode) {
+                Ok(desc) => desc,
+                Err(err) => {
+                    tf.x[0] = errno(err);
                     return frame;
                 }
             };
-            match process::alloc_fd_current(desc) {
+            match process::alloc_fd_current(desc, 0, flags & vfs::O_CLOEXEC != 0) {
                 Some(fd) => tf.x[0] = fd as u64,
-                None => tf.x[0] = u64::MAX,
+                None => {
+                    vfs::close(&desc);
+                    tf.x[0] = errno(VfsError::TooManyFiles);
+                }
             }
         }
         SYSCALL_READ => {
             let fd = tf.x[0] as usize;
-            let ptr = tf.x[1] as *mut u8;
-            let len = tf.x[2] as usize;
-            if ptr.is_null() || len == 0 {
-                tf.x[0] = 0;
-                return frame;
-            }
-            let desc = match process::get_fd_current(fd) {
-                Some(desc) => desc,
-                None => {
-                    tf.x[0] = u64::MAX;
-                    return frame;
-                }
-            };
-            let buf = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
-            let read = vfs::read(&desc, buf);
-            tf.x[0] = read as u64;
+            return blocking(frame, nonblocking(fd), sys_read(fd, tf.x[1], tf.x[2]));
         }
         SYSCALL_WRITE => {
             let fd = tf.x[0] as usize;
-            let ptr = tf.x[1] as *const u8;
-            let len = tf.x[2] as usize;
-            if ptr.is_null() || len == 0 {
-                tf.x[0] = 0;
-                return frame;
-            }
-            let desc = match process::get_fd_current(fd) {
-                Some(desc) => desc,
-                None => {
-                    tf.x[0] = u64::MAX;
-                    return frame;
-                }
-            };
-            let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
-            let wrote = vfs::write(&desc, bytes);
-            tf.x[0] = wrote as u64;
+            return blocking(frame, nonblocking(fd), sys_write(fd, tf.x[1], tf.x[2]));
         }
         SYSCALL_CLOSE => {
             let fd = tf.x[0] as usize;
             if process::close_fd_current(fd) {
                 tf.x[0] = 0;
             } else {
-                tf.x[0] = u64::MAX;
+                tf.x[0] = errno(VfsError::BadDescriptor);
             }
         }
-        SYSCALL_SLEEP_MS => {
-            let ms = tf.x[0] as u64;
-            timer::delay_ms(ms);
-            tf.x[0] = 0;
-        }
+        SYSCALL_SLEEP_MS => return blocking(frame, false, sys_sleep_ms(tf.x[0])),
         SYSCALL_ALLOC => {
             let size = tf.x[0] as usize;
             let align = tf.x[1] as usize;
@@ -190,6 +214,63 @@ pub extern "C" fn sync_handler(frame: *mut TrapFrame) -> *mut TrapFrame {
             unsafe { dealloc(ptr, layout) };
             tf.x[0] = 0;
         }
+        SYSCALL_SYNC => {
+            let _ = pagecache::sync();
+            tf.x[0] = 0;
+        }
+        SYSCALL_FSYNC => {
+            let fd = tf.x[0] as usize;
+            let desc = match process::get_fd_current(fd) {
+                Some(desc) => desc,
+                None => {
+                    tf.x[0] = errno(VfsError::BadDescriptor);
+                    return frame;
+                }
+            };
+            tf.x[0] = match vfs::fsync(&desc) {
+                true => 0,
+                false => errno(VfsError::IoError),
+            };
+        }
+        SYSCALL_GETDENTS64 => tf.x[0] = ret(sys_getdents64(tf.x[0] as usize, tf.x[1], tf.x[2])),
+        SYSCALL_STAT => tf.x[0] = ret(sys_stat(tf.x[0], tf.x[1], tf.x[2], true)),
+        SYSCALL_LSTAT => tf.x[0] = ret(sys_stat(tf.x[0], tf.x[1], tf.x[2], false)),
+        SYSCALL_FSTAT => tf.x[0] = ret(sys_fstat(tf.x[0] as usize, tf.x[1])),
+        SYSCALL_MKDIR => tf.x[0] = ret(sys_mkdir(tf.x[0], tf.x[1], tf.x[2] as u32)),
+        SYSCALL_RMDIR => tf.x[0] = ret(sys_rmdir(tf.x[0], tf.x[1])),
+        SYSCALL_UNLINK => tf.x[0] = ret(sys_unlink(tf.x[0], tf.x[1])),
+        SYSCALL_RENAME => tf.x[0] = ret(sys_rename(tf.x[0], tf.x[1], tf.x[2], tf.x[3])),
+        SYSCALL_SYMLINK => tf.x[0] = ret(sys_symlink(tf.x[0], tf.x[1], tf.x[2], tf.x[3])),
+        SYSCALL_READLINK => tf.x[0] = ret(sys_readlink(tf.x[0], tf.x[1], tf.x[2], tf.x[3])),
+        SYSCALL_CHDIR => tf.x[0] = ret(sys_chdir(tf.x[0], tf.x[1])),
+        SYSCALL_GETCWD => tf.x[0] = ret(sys_getcwd(tf.x[0], tf.x[1])),
+        SYSCALL_LSEEK => tf.x[0] = ret(sys_lseek(tf.x[0] as usize, tf.x[1] as i64, tf.x[2])),
+        SYSCALL_PREAD64 => tf.x[0] = ret(sys_pread64(tf.x[0] as usize, tf.x[1], tf.x[2], tf.x[3])),
+        SYSCALL_PWRITE64 => {
+            tf.x[0] = ret(sys_pwrite64(tf.x[0] as usize, tf.x[1], tf.x[2], tf.x[3]));
+        }
+        SYSCALL_READV => {
+            let fd = tf.x[0] as usize;
+            return blocking(frame, nonblocking(fd), sys_readv(fd, tf.x[1], tf.x[2]));
+        }
+        SYSCALL_WRITEV => {
+            let fd = tf.x[0] as usize;
+            return blocking(frame, nonblocking(fd), sys_writev(fd, tf.x[1], tf.x[2]));
+        }
+        SYSCALL_PIPE => tf.x[0] = ret(sys_pipe(tf.x[0], tf.x[1])),
+        SYSCALL_DUP => tf.x[0] = ret(sys_dup(tf.x[0] as usize)),
+        SYSCALL_DUP2 => tf.x[0] = ret(sys_dup2(tf.x[0] as usize, tf.x[1] as usize)),
+        SYSCALL_DUP3 => tf.x[0] = ret(sys_dup3(tf.x[0] as usize, tf.x[1] as usize, tf.x[2])),
+        SYSCALL_FCNTL => tf.x[0] = ret(sys_fcntl(tf.x[0] as usize, tf.x[1], tf.x[2])),
+        SYSCALL_EXEC => return sys_exec(frame, tf.x[0], tf.x[1]),
+        SYSCALL_GETRLIMIT => tf.x[0] = ret(sys_getrlimit(tf.x[0], tf.x[1])),
+        SYSCALL_SETRLIMIT => tf.x[0] = ret(sys_setrlimit(tf.x[0], tf.x[1])),
+        SYSCALL_POLL => return blocking(frame, false, sys_poll(tf.x[0], tf.x[1], tf.x[2] as i64)),
+        SYSCALL_IOCTL => tf.x[0] = ret(sys_ioctl(tf.x[0] as usize, tf.x[1], tf.x[2])),
+        SYSCALL_MMAP => {
+            tf.x[0] = ret(sys_mmap(tf.x[1], tf.x[2], tf.x[3], tf.x[4] as usize, tf.x[5]));
+        }
+        SYSCALL_SYSLOG => tf.x[0] = ret(sys_syslog(tf.x[0], tf.x[1], tf.x[2])),
         _ => {
             tf.x[0] = u64::MAX;
         }
@@ -197,3 +278,463 @@ pub extern "C" fn sync_handler(frame: *mut TrapFrame) -> *mut TrapFrame {
 
     frame
 }
+
+fn errno(err: VfsError) -> u64 {
+    // File syscalls report failure as a negated errno in x0.
+    err.errno().wrapping_neg()
+}
+
+fn ret(result: VfsResult<u64>) -> u64 {
+    match result {
+        Ok(value) => value,
+        Err(err) => errno(err),
+    }
+}
+
+fn blocking(frame: *mut TrapFrame, nonblock: bool, result: VfsResult<u64>) -> *mut TrapFrame {
+    // A call that would block sleeps until woken and then re-issues the SVC
+    // (ELR points just past it), so it runs again from scratch. Nothing may
+    // have been transferred before WouldBlock is returned. O_NONBLOCK FDs get
+    // EAGAIN instead.
+    let tf = unsafe { &mut *frame };
+    if result == Err(VfsError::WouldBlock) && !nonblock {
+        tf.elr -= 4;
+        return process::block_current(frame);
+    }
+    tf.x[0] = ret(result);
+    frame
+}
+
+fn nonblocking(fd: usize) -> bool {
+    process::get_fd_current(fd).is_some_and(|desc| vfs::is_nonblocking(&desc))
+}
+
+fn user_bytes<'a>(ptr: u64, len: u64) -> VfsResult<&'a [u8]> {
+    // Borrow a user (ptr, len) buffer; user memory is still identity-accessible from EL1.
+    if ptr == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    Ok(unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) })
+}
+
+fn user_bytes_mut<'a>(ptr: u64, len: u64) -> VfsResult<&'a mut [u8]> {
+    if ptr == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    Ok(unsafe { core::slice::from_raw_parts_mut(ptr as *mut u8, len as usize) })
+}
+
+fn copy_stat(st: Stat, out: u64) -> VfsResult<u64> {
+    if out == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    unsafe { (out as *mut Stat).write_unaligned(st) };
+    Ok(0)
+}
+
+fn sys_read(fd: usize, ptr: u64, len: u64) -> VfsResult<u64> {
+    if ptr == 0 || len == 0 {
+        return Ok(0);
+    }
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    Ok(vfs::read(&desc, user_bytes_mut(ptr, len)?)? as u64)
+}
+
+fn sys_write(fd: usize, ptr: u64, len: u64) -> VfsResult<u64> {
+    if ptr == 0 || len == 0 {
+        return Ok(0);
+    }
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    Ok(vfs::write(&desc, user_bytes(ptr, len)?)? as u64)
+}
+
+fn sys_getdents64(fd: usize, ptr: u64, len: u64) -> VfsResult<u64> {
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    let buf = user_bytes_mut(ptr, len)?;
+    Ok(vfs::getdents(&desc, buf)? as u64)
+}
+
+fn sys_stat(ptr: u64, len: u64, out: u64, follow: bool) -> VfsResult<u64> {
+    let st = vfs::stat(user_bytes(ptr, len)?, follow)?;
+    copy_stat(st, out)
+}
+
+fn sys_fstat(fd: usize, out: u64) -> VfsResult<u64> {
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    copy_stat(vfs::fstat(&desc)?, out)
+}
+
+fn sys_mkdir(ptr: u64, len: u64, mode: u32) -> VfsResult<u64> {
+    vfs::mkdir(user_bytes(ptr, len)?, mode)?;
+    Ok(0)
+}
+
+fn sys_rmdir(ptr: u64, len: u64) -> VfsResult<u64> {
+    vfs::rmdir(user_bytes(ptr, len)?)?;
+    Ok(0)
+}
+
+fn sys_unlink(ptr: u64, len: u64) -> VfsResult<u64> {
+    vfs::unlink(user_bytes(ptr, len)?)?;
+    Ok(0)
+}
+
+fn sys_rename(old_ptr: u64, old_len: u64, new_ptr: u64, new_len: u64) -> VfsResult<u64> {
+    vfs::rename(user_bytes(old_ptr, old_len)?, user_bytes(new_ptr, new_len)?)?;
+    Ok(0)
+}
+
+fn sys_symlink(target_ptr: u64, target_len: u64, link_ptr: u64, link_len: u64) -> VfsResult<u64> {
+    let target = user_bytes(target_ptr, target_len)?;
+    vfs::symlink(target, user_bytes(link_ptr, link_len)?)?;
+    Ok(0)
+}
+
+fn sys_readlink(ptr: u64, len: u64, buf_ptr: u64, buf_len: u64) -> VfsResult<u64> {
+    // Like readlink(2): truncates silently and does not NUL-terminate.
+    let target = vfs::readlink(user_bytes(ptr, len)?)?;
+    let buf = user_bytes_mut(buf_ptr, buf_len)?;
+    let n = buf.len().min(target.len());
+    buf[..n].copy_from_slice(&target.as_bytes()[..n]);
+    Ok(n as u64)
+}
+
+fn sys_chdir(ptr: u64, len: u64) -> VfsResult<u64> {
+    let cwd = vfs::chdir(user_bytes(ptr, len)?)?;
+    if !process::set_current_cwd(cwd) {
+        return Err(VfsError::NotFound);
+    }
+    Ok(0)
+}
+
+fn sys_getcwd(ptr: u64, len: u64) -> VfsResult<u64> {
+    // Copies the NUL-terminated path and returns its length including the NUL.
+    let cwd = process::current_cwd();
+    let path = cwd.as_bytes();
+    let buf = user_bytes_mut(ptr, len)?;
+    if buf.len() < path.len() + 1 {
+        return Err(VfsError::Range);
+    }
+    buf[..path.len()].copy_from_slice(path);
+    buf[path.len()] = 0;
+    Ok(path.len() as u64 + 1)
+}
+
+fn sys_lseek(fd: usize, offset: i64, whence: u64) -> VfsResult<u64> {
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    vfs::lseek(&desc, offset, whence)
+}
+
+fn sys_pread64(fd: usize, ptr: u64, len: u64, offset: u64) -> VfsResult<u64> {
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    Ok(vfs::pread(&desc, user_bytes_mut(ptr, len)?, offset)? as u64)
+}
+
+fn sys_pwrite64(fd: usize, ptr: u64, len: u64, offset: u64) -> VfsResult<u64> {
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    Ok(vfs::pwrite(&desc, user_bytes(ptr, len)?, offset)? as u64)
+}
+
+fn user_iovecs<'a>(ptr: u64, count: u64) -> VfsResult<&'a [[u64; 2]]> {
+    // `struct iovec { void *base; size_t len; }` on a 64-bit target.
+    if count > IOV_MAX {
+        return Err(VfsError::InvalidArgument);
+    }
+    if ptr == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    Ok(unsafe { core::slice::from_raw_parts(ptr as *const [u64; 2], count as usize) })
+}
+
+fn sys_readv(fd: usize, iov: u64, count: u64) -> VfsResult<u64> {
+    // Fill buffers in order, stopping at the first short read. An error after
+    // some data was transferred is reported as the partial count.
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    let mut total = 0u64;
+    for &[base, len] in user_iovecs(iov, count)? {
+        if len == 0 {
+            continue;
+        }
+        let n = match user_bytes_mut(base, len).and_then(|buf| vfs::read(&desc, buf)) {
+            Ok(n) => n as u64,
+            Err(_) if total > 0 => break,
+            Err(err) => return Err(err),
+        };
+        total += n;
+        if n < len {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+fn sys_writev(fd: usize, iov: u64, count: u64) -> VfsResult<u64> {
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    let mut total = 0u64;
+    for &[base, len] in user_iovecs(iov, count)? {
+        if len == 0 {
+            continue;
+        }
+        let n = match user_bytes(base, len).and_then(|buf| vfs::write(&desc, buf)) {
+            Ok(n) => n as u64,
+            Err(_) if total > 0 => break,
+            Err(err) => return Err(err),
+        };
+        total += n;
+        if n < len {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+fn sys_pipe(out: u64, flags: u64) -> VfsResult<u64> {
+    // pipe2: stores `int fds[2]`, the read end then the write end.
+    if flags & !(vfs::O_CLOEXEC | vfs::O_NONBLOCK) != 0 {
+        return Err(VfsError::InvalidArgument);
+    }
+    if out == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    let cloexec = flags & vfs::O_CLOEXEC != 0;
+    let (read, write) = vfs::pipe(flags)?;
+    let read_fd = match process::alloc_fd_current(read, 0, cloexec) {
+        Some(fd) => fd,
+        None => {
+            vfs::close(&read);
+            vfs::close(&write);
+            return Err(VfsError::TooManyFiles);
+        }
+    };
+    let write_fd = match process::alloc_fd_current(write, 0, cloexec) {
+        Some(fd) => fd,
+        None => {
+            process::close_fd_current(read_fd);
+            vfs::close(&write);
+            return Err(VfsError::TooManyFiles);
+        }
+    };
+    unsafe { (out as *mut [i32; 2]).write_unaligned([read_fd as i32, write_fd as i32]) };
+    Ok(0)
+}
+
+fn sys_dup(fd: usize) -> VfsResult<u64> {
+    dup_from(fd, 0, false)
+}
+
+fn dup_from(fd: usize, min: usize, cloexec: bool) -> VfsResult<u64> {
+    // The new FD is the lowest free one >= `min` and shares the open file
+    // description. FD_CLOEXEC is per FD and is not copied.
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    let copy = vfs::dup(&desc);
+    match process::alloc_fd_current(copy, min, cloexec) {
+        Some(new_fd) => Ok(new_fd as u64),
+        None => {
+            vfs::close(&copy);
+            Err(VfsError::TooManyFiles)
+        }
+    }
+}
+
+fn sys_dup2(old_fd: usize, new_fd: usize) -> VfsResult<u64> {
+    // Whatever was open at `new_fd` is closed first; dup2(fd, fd) is a no-op.
+    let desc = process::get_fd_current(old_fd).ok_or(VfsError::BadDescriptor)?;
+    if old_fd == new_fd {
+        return Ok(new_fd as u64);
+    }
+    replace_fd(desc, new_fd, false)
+}
+
+fn replace_fd(desc: vfs::FileDesc, new_fd: usize, cloexec: bool) -> VfsResult<u64> {
+    // Targets at or above the FD limit are EBADF, as on Linux.
+    let copy = vfs::dup(&desc);
+    if !process::set_fd_current(new_fd, copy, cloexec) {
+        vfs::close(&copy);
+        return Err(VfsError::BadDescriptor);
+    }
+    Ok(new_fd as u64)
+}
+
+fn sys_dup3(old_fd: usize, new_fd: usize, flags: u64) -> VfsResult<u64> {
+    // Like dup2, but equal FDs are an error and O_CLOEXEC may be set.
+    if flags & !vfs::O_CLOEXEC != 0 || old_fd == new_fd {
+        return Err(VfsError::InvalidArgument);
+    }
+    let desc = process::get_fd_current(old_fd).ok_or(VfsError::BadDescriptor)?;
+    replace_fd(desc, new_fd, flags & vfs::O_CLOEXEC != 0)
+}
+
+fn sys_fcntl(fd: usize, cmd: u64, arg: u64) -> VfsResult<u64> {
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            if arg >= process::fd_limit_current() as u64 {
+                return Err(VfsError::InvalidArgument);
+            }
+            dup_from(fd, arg as usize, cmd == F_DUPFD_CLOEXEC)
+        }
+        F_GETFD => match process::fd_cloexec_current(fd) {
+            Some(true) => Ok(FD_CLOEXEC),
+            Some(false) => Ok(0),
+            None => Err(VfsError::BadDescriptor),
+        },
+        F_SETFD => {
+            if !process::set_fd_cloexec_current(fd, arg & FD_CLOEXEC != 0) {
+                return Err(VfsError::BadDescriptor);
+            }
+            Ok(0)
+        }
+        F_GETFL => vfs::status_flags(&desc),
+        F_SETFL => {
+            vfs::set_status_flags(&desc, arg)?;
+            Ok(0)
+        }
+        _ => Err(VfsError::InvalidArgument),
+    }
+}
+
+fn sys_exec(frame: *mut TrapFrame, ptr: u64, len: u64) -> *mut TrapFrame {
+    // Programs are linked into the kernel image and named, not loaded from
+    // files. On success the call does not return to the old program.
+    let result = user_bytes(ptr, len).and_then(|name| {
+        let (name, entry) = crate::user::find(name).ok_or(VfsError::NotFound)?;
+        process::exec_current(name, entry).ok_or(VfsError::NotFound)
+    });
+    match result {
+        Ok(next) => next,
+        Err(err) => {
+            unsafe { (*frame).x[0] = errno(err) };
+            frame
+        }
+    }
+}
+
+fn sys_getrlimit(resource: u64, out: u64) -> VfsResult<u64> {
+    // Fills `struct rlimit { cur, max }`.
+    if resource != RLIMIT_NOFILE {
+        return Err(VfsError::InvalidArgument);
+    }
+    if out == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    let limit = [process::fd_limit_current() as u64, process::MAX_FDS as u64];
+    unsafe { (out as *mut [u64; 2]).write_unaligned(limit) };
+    Ok(0)
+}
+
+fn sys_setrlimit(resource: u64, ptr: u64) -> VfsResult<u64> {
+    // The hard limit is fixed at MAX_FDS; only the soft limit is stored.
+    if resource != RLIMIT_NOFILE {
+        return Err(VfsError::InvalidArgument);
+    }
+    if ptr == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    let [cur, max] = unsafe { (ptr as *const [u64; 2]).read_unaligned() };
+    if cur > max || max > process::MAX_FDS as u64 {
+        return Err(VfsError::InvalidArgument);
+    }
+    if !process::set_fd_limit_current(cur as usize) {
+        return Err(VfsError::InvalidArgument);
+    }
+    Ok(0)
+}
+
+fn sys_sleep_ms(ms: u64) -> VfsResult<u64> {
+    // Sleep until the timer tick passes the deadline. The deadline survives
+    // the restart after each wakeup in `Process::wake_at`.
+    if process::current_pid().is_none() {
+        timer::delay_ms(ms);
+        return Ok(0);
+    }
+    let now = timer::uptime_ms();
+    let deadline = process::take_wake_at_current().unwrap_or(now.saturating_add(ms));
+    if now >= deadline {
+        return Ok(0);
+    }
+    process::set_wake_at_current(Some(deadline));
+    Err(VfsError::WouldBlock)
+}
+
+fn sys_poll(ptr: u64, nfds: u64, timeout_ms: i64) -> VfsResult<u64> {
+    // Fills `revents` in each `struct pollfd` and returns how many are set.
+    // Negative FDs are skipped; a negative timeout waits forever.
+    let deadline = process::take_wake_at_current();
+    if nfds > process::fd_limit_current() as u64 {
+        return Err(VfsError::InvalidArgument);
+    }
+    if ptr == 0 && nfds > 0 {
+        return Err(VfsError::BadAddress);
+    }
+    let fds: &mut [PollFd] = if nfds == 0 {
+        &mut []
+    } else {
+        unsafe { core::slice::from_raw_parts_mut(ptr as *mut PollFd, nfds as usize) }
+    };
+    let now = timer::uptime_ms();
+    let timeout = u64::try_from(timeout_ms).ok();
+    let deadline = deadline.or_else(|| timeout.map(|ms| now.saturating_add(ms)));
+    let expired = deadline.is_some_and(|at| now >= at);
+    // Only register on wait queues if this call may go to sleep.
+    let waiter = process::current_pid().filter(|_| !expired);
+    let mut ready = 0;
+    for pfd in fds.iter_mut() {
+        pfd.revents = 0;
+        if pfd.fd < 0 {
+            continue;
+        }
+        let revents = match process::get_fd_current(pfd.fd as usize) {
+            Some(desc) => vfs::poll(&desc, waiter) & (pfd.events as u16 | POLLERR | POLLHUP),
+            None => POLLNVAL,
+        };
+        pfd.revents = revents as i16;
+        if revents != 0 {
+            ready += 1;
+        }
+    }
+    if ready > 0 || expired {
+        return Ok(ready);
+    }
+    process::set_wake_at_current(deadline);
+    Err(VfsError::WouldBlock)
+}
+
+fn sys_ioctl(fd: usize, cmd: u64, arg: u64) -> VfsResult<u64> {
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    vfs::ioctl(&desc, cmd, arg)
+}
+
+fn sys_mmap(len: u64, prot: u64, flags: u64, fd: usize, offset: u64) -> VfsResult<u64> {
+    // The address hint is ignored: every process shares one address space,
+    // so the device picks where its memory appears.
+    if len == 0 || flags & MAP_SHARED == 0 || prot & !(PROT_READ | PROT_WRITE) != 0 {
+        return Err(VfsError::InvalidArgument);
+    }
+    let desc = process::get_fd_current(fd).ok_or(VfsError::BadDescriptor)?;
+    vfs::mmap(&desc, offset, len)
+}
+
+fn sys_syslog(action: u64, buf: u64, len: u64) -> VfsResult<u64> {
+    // Linux's syslog(type, bufp, len); for CONSOLE_LEVEL `len` is the level.
+    match action {
+        klog::SYSLOG_ACTION_READ_ALL | klog::SYSLOG_ACTION_READ_CLEAR => {
+            let clear = action == klog::SYSLOG_ACTION_READ_CLEAR;
+            klog::read_all(user_bytes_mut(buf, len)?, clear)
+        }
+        klog::SYSLOG_ACTION_CLEAR => {
+            klog::clear();
+            Ok(0)
+        }
+        klog::SYSLOG_ACTION_CONSOLE_OFF => {
+            klog::console_off();
+            Ok(0)
+        }
+        klog::SYSLOG_ACTION_CONSOLE_ON => {
+            klog::console_on();
+            Ok(0)
+        }
+        klog::SYSLOG_ACTION_CONSOLE_LEVEL => klog::set_console_level(len),
+        klog::SYSLOG_ACTION_SIZE_BUFFER => Ok(klog::buffer_size()),
+        _ => Err(VfsError::InvalidArgument),
+    }
+}
@@ -0,0 +1,734 @@
+pub mod pty;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::arch::aarch64::timer;
+use crate::kernel::process::{self, ProcessId, WaitQueue};
+use crate::kernel::vfs::devfs::{self, CharDevice, DevNum, MAJOR_PTS, MAJOR_TTY, MAJOR_TTYAUX};
+use crate::kernel::vfs::file::FileId;
+use crate::kernel::vfs::{VfsError, VfsResult, POLLERR, POLLHUP, POLLIN, POLLOUT};
+use crate::util::sync::SpinLock;
+
+pub const MAX_TTYS: usize = 16;
+// Input held per TTY, like Linux's N_TTY_BUF_SIZE. One byte of a full
+// canonical line is kept free for the newline.
+pub const TTY_BUF_SIZE: usize = 4096;
+
+// ioctl commands (Linux values).
+pub const TCGETS: u64 = 0x5401;
+pub const TCSETS: u64 = 0x5402;
+pub const TCSETSW: u64 = 0x5403;
+pub const TCSETSF: u64 = 0x5404;
+pub const TIOCGWINSZ: u64 = 0x5413;
+pub const TIOCSWINSZ: u64 = 0x5414;
+pub const FIONREAD: u64 = 0x541B;
+
+// c_iflag bits.
+pub const ISTRIP: u32 = 0o000040;
+pub const INLCR: u32 = 0o000100;
+pub const IGNCR: u32 = 0o000200;
+pub const ICRNL: u32 = 0o000400;
+pub const IXON: u32 = 0o002000;
+
+// c_oflag bits.
+pub const OPOST: u32 = 0o000001;
+pub const ONLCR: u32 = 0o000004;
+
+// c_cflag bits. The line is always 8N1; these are only stored.
+pub const CSIZE: u32 = 0o000060;
+pub const CS8: u32 = 0o000060;
+pub const CREAD: u32 = 0o000200;
+pub const PARENB: u32 = 0o000400;
+const B38400: u32 = 0o000017;
+
+// c_lflag bits.
+pub const ISIG: u32 = 0o000001;
+pub const ICANON: u32 = 0o000002;
+pub const ECHO: u32 = 0o000010;
+pub const ECHOE: u32 = 0o000020;
+pub const ECHOK: u32 = 0o000040;
+pub const ECHONL: u32 = 0o000100;
+pub const NOFLSH: u32 = 0o000200;
+pub const ECHOCTL: u32 = 0o001000;
+pub const ECHOKE: u32 = 0o004000;
+pub const IEXTEN: u32 = 0o100000;
+
+// c_cc indices. A control character set to 0 is disabled.
+pub const NCCS: usize = 19;
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+pub const VWERASE: usize = 14;
+
+// `struct termios` as used by the Linux ioctls.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+impl Default for Termios {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Termios {
+    pub const fn new() -> Self {
+        // Cooked mode with echo, as a freshly opened Linux terminal.
+        let mut cc = [0u8; NCCS];
+        cc[VINTR] = 0x03;
+        cc[VQUIT] = 0x1c;
+        cc[VERASE] = 0x7f;
+        cc[VKILL] = 0x15;
+        cc[VEOF] = 0x04;
+        cc[VTIME] = 0;
+        cc[VMIN] = 1;
+        cc[VSUSP] = 0x1a;
+        cc[VWERASE] = 0x17;
+        Self {
+            iflag: ICRNL | IXON,
+            oflag: OPOST | ONLCR,
+            cflag: B38400 | CS8 | CREAD,
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            line: 0,
+            cc,
+        }
+    }
+
+    pub fn make_raw(&mut self) {
+        // Same changes as cfmakeraw(3): bytes pass through unedited and unechoed.
+        self.iflag &= !(ISTRIP | INLCR | IGNCR | ICRNL | IXON);
+        self.oflag &= !OPOST;
+        self.lflag &= !(ECHO | ECHONL | ICANON | ISIG | IEXTEN);
+        self.cflag &= !(CSIZE | PARENB);
+        self.cflag |= CS8;
+        self.cc[VMIN] = 1;
+        self.cc[VTIME] = 0;
+    }
+
+    fn is_cc(&self, c: u8, idx: usize) -> bool {
+        self.cc[idx] != 0 && self.cc[idx] == c
+    }
+}
+
+// `struct winsize` for TIOCGWINSZ/TIOCSWINSZ.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct WinSize {
+    pub row: u16,
+    pub col: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+// The hardware side of a TTY. Drivers either hand received bytes to
+// `input`, or leave it to the TTY to pull them with `receive` when a
+// reader shows up.
+pub trait TtyDriver: Sync {
+    // Send bytes that have been through output processing.
+    fn write(&self, line: usize, buf: &[u8]);
+
+    // Bytes `write` can take now. Below 2 (room for a CRLF), `waiter` is
+    // woken once there is more.
+    fn write_room(&self, _line: usize, _waiter: Option<ProcessId>) -> usize {
+        usize::MAX
+    }
+
+    // Called when the TTY's device is opened, and when the last open file goes away.
+    fn open(&self, _line: usize) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn close(&self, _line: usize, _file: FileId) {}
+
+    // Pull raw input that has arrived since the last call.
+    fn receive(&self, _line: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+
+    // Whether `receive` has more input; otherwise `waiter` is woken when some arrives.
+    fn input_ready(&self, _line: usize, _waiter: Option<ProcessId>) -> bool {
+        false
+    }
+
+    // Requests the line discipline does not know about, made through `file`.
+    fn ioctl(&self, _line: usize, _file: FileId, _cmd: u64, _arg: u64) -> VfsResult<u64> {
+        Err(VfsError::NotTty)
+    }
+
+    // Terminal size until one is set with TIOCSWINSZ.
+    fn winsize(&self, _line: usize) -> WinSize {
+        WinSize {
+            row: 24,
+            col: 80,
+            ..WinSize::default()
+        }
+    }
+}
+
+struct Tty {
+    dev: DevNum,
+    driver: &'static dyn TtyDriver,
+    termios: Termios,
+    winsize: Option<WinSize>,
+    // Canonical mode: the line being edited.
+    edit: Vec<u8>,
+    // Input that read() may return.
+    ready: VecDeque<u8>,
+    // Canonical mode: lengths of the completed lines in `ready`. A zero
+    // length is an end-of-file mark from VEOF on an empty line.
+    lines: VecDeque<usize>,
+    // Set by an ISIG character; the next read fails with EINTR.
+    interrupted: bool,
+    // The other end is gone: reads drain what is left and then see EOF.
+    hung_up: bool,
+    read_wait: WaitQueue,
+}
+
+impl Tty {
+    fn canonical(&self) -> bool {
+        self.termios.lflag & ICANON != 0
+    }
+
+    fn receive(&mut self, mut c: u8, echo: &mut Vec<u8>) -> bool {
+        // Run one input byte through the line discipline. Returns true when
+        // readers have something new to see.
+        let t = self.termios;
+        if t.iflag & ISTRIP != 0 {
+            c &= 0x7f;
+        }
+        if c == b'\r' {
+            if t.iflag & IGNCR != 0 {
+                return false;
+            }
+            if t.iflag & ICRNL != 0 {
+                c = b'\n';
+            }
+        } else if c == b'\n' && t.iflag & INLCR != 0 {
+            c = b'\r';
+        }
+        if t.lflag & ISIG != 0 && (t.is_cc(c, VINTR) || t.is_cc(c, VQUIT) || t.is_cc(c, VSUSP)) {
+            // There are no signals yet: the character interrupts the reader.
+            if t.lflag & NOFLSH == 0 {
+                self.flush_input();
+            }
+            self.echo_char(c, echo);
+            self.interrupted = true;
+            return true;
+        }
+        if !self.canonical() {
+            if self.ready.len() < TTY_BUF_SIZE {
+                self.ready.push_back(c);
+                self.echo_char(c, echo);
+            }
+            return true;
+        }
+        if t.is_cc(c, VERASE) {
+            self.erase(echo);
+            false
+        } else if t.lflag & IEXTEN != 0 && t.is_cc(c, VWERASE) {
+            while self.edit.last().is_some_and(|b| b.is_ascii_whitespace()) {
+                self.erase(echo);
+            }
+            while self.edit.last().is_some_and(|b| !b.is_ascii_whitespace()) {
+                self.erase(echo);
+            }
+            false
+        } else if t.is_cc(c, VKILL) {
+            self.kill(c, echo);
+            false
+        } else if t.is_cc(c, VEOF) {
+            // Ends the line without a newline; on an empty line, reads see EOF.
+            self.finish_line();
+            true
+        } else if c == b'\n' {
+            if t.lflag & (ECHO | ECHONL) != 0 {
+                echo.push(b'\n');
+            }
+            self.edit.push(b'\n');
+            self.finish_line();
+            true
+        } else {
+            if self.ready.len() + self.edit.len() < TTY_BUF_SIZE - 1 {
+                self.edit.push(c);
+                self.echo_char(c, echo);
+            }
+            false
+        }
+    }
+
+    fn echo_char(&self, c: u8, echo: &mut Vec<u8>) {
+        // Control characters echo as ^X with ECHOCTL.
+        if self.termios.lflag & ECHO == 0 {
+            return;
+        }
+        if self.termios.lflag & ECHOCTL != 0 && is_ctl(c) {
+            echo.push(b'^');
+            echo.push(c ^ 0x40);
+        } else {
+            echo.push(c);
+        }
+    }
+
+    fn erase(&mut self, echo: &mut Vec<u8>) {
+        // Drop the last character, including all bytes of a UTF-8 sequence.
+        let Some(mut c) = self.edit.pop() else {
+            return;
+        };
+        while c & 0xc0 == 0x80 {
+            match self.edit.pop() {
+                Some(prev) => c = prev,
+                None => break,
+            }
+        }
+        let t = self.termios;
+        if t.lflag & ECHO == 0 {
+            return;
+        }
+        if t.lflag & ECHOE == 0 {
+            self.echo_char(t.cc[VERASE], echo);
+            return;
+        }
+        let width = if t.lflag & ECHOCTL != 0 && is_ctl(c) {
+            2
+        } else {
+            1
+        };
+        for _ in 0..width {
+            echo.extend_from_slice(b"\x08 \x08");
+        }
+    }
+
+    fn kill(&mut self, c: u8, echo: &mut Vec<u8>) {
+        // Discard the whole line; ECHOKE wipes it from the screen.
+        let t = self.termios;
+        if t.lflag & ECHO != 0 && t.lflag & ECHOKE != 0 && t.lflag & ECHOE != 0 {
+            while !self.edit.is_empty() {
+                self.erase(echo);
+            }
+            return;
+        }
+        self.edit.clear();
+        if t.lflag & ECHO != 0 {
+            self.echo_char(c, echo);
+            if t.lflag & ECHOK != 0 {
+                echo.push(b'\n');
+            }
+        }
+    }
+
+    fn finish_line(&mut self) {
+        self.lines.push_back(self.edit.len());
+        self.ready.extend(self.edit.drain(..));
+    }
+
+    fn flush_input(&mut self) {
+        self.edit.clear();
+        self.ready.clear();
+        self.lines.clear();
+    }
+
+    fn readable(&self) -> bool {
+        if self.canonical() {
+            !self.lines.is_empty()
+        } else {
+            !self.ready.is_empty()
+        }
+    }
+
+    fn set_termios(&mut self, termios: Termios) {
+        // Leaving canonical mode makes the partial line readable; entering
+        // it turns raw input into one line.
+        let was_canonical = self.canonical();
+        self.termios = termios;
+        if was_canonical && !self.canonical() {
+            self.ready.extend(self.edit.drain(..));
+            self.lines.clear();
+        } else if !was_canonical && self.canonical() && !self.ready.is_empty() {
+            self.lines.clear();
+            self.lines.push_back(self.ready.len());
+        }
+    }
+
+    fn take_line(&mut self, buf: &mut [u8]) -> Option<usize> {
+        // Canonical read: at most one line. The rest of a line that does not
+        // fit stays for the next read.
+        let len = self.lines.front_mut()?;
+        let n = buf.len().min(*len);
+        *len -= n;
+        if *len == 0 {
+            self.lines.pop_front();
+        }
+        for (slot, b) in buf.iter_mut().zip(self.ready.drain(..n)) {
+            *slot = b;
+        }
+        Some(n)
+    }
+
+    fn take_raw(&mut self, buf: &mut [u8], deadline: &mut Option<u64>) -> Option<usize> {
+        // Non-canonical read following VMIN and VTIME. With both set, VTIME
+        // counts from the first check that found input rather than between bytes.
+        let min = self.termios.cc[VMIN] as usize;
+        let time_ms = self.termios.cc[VTIME] as u64 * 100;
+        let avail = self.ready.len();
+        let want = min.min(buf.len());
+        let now = timer::uptime_ms();
+        let timed_out = deadline.is_some_and(|at| now >= at);
+        let done = match (min, time_ms) {
+            (0, 0) => true,
+            (_, 0) => avail >= want.max(1),
+            (0, _) => avail > 0 || timed_out,
+            _ => avail >= want.max(1) || (avail > 0 && timed_out),
+        };
+        if !done {
+            if time_ms != 0 && deadline.is_none() && (min == 0 || avail > 0) {
+                *deadline = Some(now.saturating_add(time_ms));
+            }
+            return None;
+        }
+        let n = buf.len().min(avail);
+        for (slot, b) in buf.iter_mut().zip(self.ready.drain(..n)) {
+            *slot = b;
+        }
+        *deadline = None;
+        Some(n)
+    }
+}
+
+fn is_ctl(c: u8) -> bool {
+    (c < 0x20 && c != b'\t' && c != b'\n') || c == 0x7f
+}
+
+fn process_output(termios: &Termios, buf: &[u8], room: usize, out: &mut Vec<u8>) -> usize {
+    // OPOST/ONLCR: newlines go out as CRLF. Stops before the output would
+    // exceed `room`; returns how many input bytes were used.
+    let crlf = termios.oflag & OPOST != 0 && termios.oflag & ONLCR != 0;
+    for (i, &b) in buf.iter().enumerate() {
+        let len = if crlf && b == b'\n' { 2 } else { 1 };
+        if out.len() + len > room {
+            return i;
+        }
+        if len == 2 {
+            out.push(b'\r');
+        }
+        out.push(b);
+    }
+    buf.len()
+}
+
+static TTYS: SpinLock<[Option<Tty>; MAX_TTYS]> = SpinLock::new([const { None }; MAX_TTYS]);
+
+// devfs passes drivers only the minor number, so each major gets its own
+// device object to find the TTY by (major, minor).
+struct TtyDevice {
+    major: u32,
+}
+
+static TTY_DEVICES: [TtyDevice; 3] = [
+    TtyDevice { major: MAJOR_TTY },
+    TtyDevice {
+        major: MAJOR_TTYAUX,
+    },
+    TtyDevice { major: MAJOR_PTS },
+];
+
+fn device(major: u32) -> Option<&'static TtyDevice> {
+    TTY_DEVICES.iter().find(|device| device.major == major)
+}
+
+pub fn register(name: &'static str, dev: DevNum, driver: &'static dyn TtyDriver) -> Option<usize> {
+    // Attach a line discipline to `driver` and publish it as /dev/<name>.
+    let device = device(dev.major)?;
+    let line = attach(dev, driver)?;
+    if !devfs::register_char(name, dev, device) {
+        detach(line);
+        return None;
+    }
+    Some(line)
+}
+
+pub fn attach(dev: DevNum, driver: &'static dyn TtyDriver) -> Option<usize> {
+    // Attach a line discipline without a /dev node; the device is reached
+    // through a driver registered for its major.
+    let mut ttys = TTYS.lock();
+    let line = ttys.iter().position(|slot| slot.is_none())?;
+    ttys[line] = Some(Tty {
+        dev,
+        driver,
+        termios: Termios::new(),
+        winsize: None,
+        edit: Vec::new(),
+        ready: VecDeque::new(),
+        lines: VecDeque::new(),
+        interrupted: false,
+        hung_up: false,
+        read_wait: WaitQueue::new(),
+    });
+    Some(line)
+}
+
+pub fn detach(line: usize) {
+    // Free the line; blocked readers wake up to find the device gone.
+    let tty = TTYS.lock()[line].take();
+    if let Some(tty) = tty {
+        tty.read_wait.wake_all();
+    }
+}
+
+pub fn hangup(line: usize) {
+    // Readers see EOF once the buffered input is gone; writers get EIO.
+    let mut ttys = TTYS.lock();
+    let Some(tty) = ttys[line].as_mut() else {
+        return;
+    };
+    tty.hung_up = true;
+    let waiters = tty.read_wait.take();
+    drop(ttys);
+    waiters.wake_all();
+}
+
+fn line_of(dev: DevNum) -> VfsResult<usize> {
+    TTYS.lock()
+        .iter()
+        .position(|slot| slot.as_ref().is_some_and(|tty| tty.dev == dev))
+        .ok_or(VfsError::NoDevice)
+}
+
+fn driver(line: usize) -> VfsResult<&'static dyn TtyDriver> {
+    TTYS.lock()[line]
+        .as_ref()
+        .map(|tty| tty.driver)
+        .ok_or(VfsError::NoDevice)
+}
+
+pub fn input(line: usize, bytes: &[u8]) {
+    // Feed received bytes through the line discipline, wake readers and
+    // write the echo back to the terminal.
+    let mut ttys = TTYS.lock();
+    let Some(tty) = ttys[line].as_mut() else {
+        return;
+    };
+    let mut echo = Vec::new();
+    let mut woke = false;
+    for &c in bytes {
+        woke |= tty.receive(c, &mut echo);
+    }
+    let waiters = if woke {
+        tty.read_wait.take()
+    } else {
+        WaitQueue::new()
+    };
+    let termios = tty.termios;
+    let driver = tty.driver;
+    drop(ttys);
+    waiters.wake_all();
+    if !echo.is_empty() {
+        // Echo never waits for room; what does not fit is dropped.
+        let room = driver.write_room(line, None);
+        let mut out = Vec::with_capacity(echo.len());
+        process_output(&termios, &echo, room, &mut out);
+        driver.write(line, &out);
+    }
+}
+
+fn pump(line: usize) -> VfsResult<&'static dyn TtyDriver> {
+    // Pull whatever the driver has received into the line discipline.
+    let driver = driver(line)?;
+    let mut raw = [0u8; 64];
+    loop {
+        let n = driver.receive(line, &mut raw);
+        if n == 0 {
+            break;
+        }
+        input(line, &raw[..n]);
+    }
+    Ok(driver)
+}
+
+pub fn read(line: usize, buf: &mut [u8]) -> VfsResult<usize> {
+    // Blocks (WouldBlock) until a line, or enough raw input, is ready. An
+    // ISIG character fails the read with EINTR.
+    let pid = process::current_pid();
+    let mut deadline = process::take_wake_at_current();
+    loop {
+        let driver = pump(line)?;
+        let mut ttys = TTYS.lock();
+        let tty = ttys[line].as_mut().ok_or(VfsError::NoDevice)?;
+        if tty.interrupted {
+            tty.interrupted = false;
+            return Err(VfsError::Interrupted);
+        }
+        let taken = if tty.canonical() {
+            tty.take_line(buf)
+        } else {
+            tty.take_raw(buf, &mut deadline)
+        };
+        if let Some(n) = taken {
+            return Ok(n);
+        }
+        if tty.hung_up {
+            return Ok(0);
+        }
+        if let Some(pid) = pid {
+            tty.read_wait.add(pid);
+        }
+        drop(ttys);
+        if driver.input_ready(line, pid) {
+            continue;
+        }
+        process::set_wake_at_current(deadline);
+        return Err(VfsError::WouldBlock);
+    }
+}
+
+pub fn write(line: usize, buf: &[u8]) -> VfsResult<usize> {
+    // Output is processed and handed to the driver synchronously. It only
+    // blocks while the driver has no room, and may be short.
+    let (driver, termios, hung_up) = TTYS.lock()[line]
+        .as_ref()
+        .map(|tty| (tty.driver, tty.termios, tty.hung_up))
+        .ok_or(VfsError::NoDevice)?;
+    if hung_up {
+        return Err(VfsError::IoError);
+    }
+    let room = driver.write_room(line, process::current_pid());
+    if room < 2 {
+        return Err(VfsError::WouldBlock);
+    }
+    let mut out = Vec::with_capacity(buf.len());
+    let n = process_output(&termios, buf, room, &mut out);
+    driver.write(line, &out);
+    Ok(n)
+}
+
+pub fn poll(line: usize, waiter: Option<ProcessId>) -> u16 {
+    // Readable with a completed line (or raw input) or a pending interrupt,
+    // writable while the driver has room.
+    let Ok(driver) = driver(line) else {
+        return POLLERR;
+    };
+    let writable = if driver.write_room(line, waiter) >= 2 {
+        POLLOUT
+    } else {
+        0
+    };
+    loop {
+        if pump(line).is_err() {
+            return POLLERR;
+        }
+        let mut ttys = TTYS.lock();
+        let Some(tty) = ttys[line].as_mut() else {
+            return POLLERR;
+        };
+        if tty.hung_up {
+            return POLLIN | POLLHUP;
+        }
+        if tty.interrupted || tty.readable() {
+            return POLLIN | writable;
+        }
+        if let Some(pid) = waiter {
+            tty.read_wait.add(pid);
+        }
+        drop(ttys);
+        if !driver.input_ready(line, waiter) {
+            return writable;
+        }
+    }
+}
+
+pub fn ioctl(line: usize, file: FileId, cmd: u64, arg: u64) -> VfsResult<u64> {
+    // The line discipline's own requests take a pointer to the caller's
+    // termios, winsize or int. The rest go to the driver as given; some of
+    // them take a plain value, such as KDSETMODE's mode.
+    let takes_pointer = matches!(
+        cmd,
+        TCGETS | TCSETS | TCSETSW | TCSETSF | TIOCGWINSZ | TIOCSWINSZ | FIONREAD
+    );
+    if takes_pointer && arg == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    let driver = pump(line)?;
+    let mut ttys = TTYS.lock();
+    let tty = ttys[line].as_mut().ok_or(VfsError::NoDevice)?;
+    match cmd {
+        TCGETS => unsafe { (arg as *mut Termios).write_unaligned(tty.termios) },
+        // Output is synchronous, so there is never anything to drain.
+        TCSETS | TCSETSW | TCSETSF => {
+            if cmd == TCSETSF {
+                tty.flush_input();
+            }
+            tty.set_termios(unsafe { (arg as *const Termios).read_unaligned() });
+            let waiters = tty.read_wait.take();
+            drop(ttys);
+            // Readers re-check under the new settings.
+            waiters.wake_all();
+        }
+        TIOCGWINSZ => {
+            let winsize = tty.winsize;
+            drop(ttys);
+            let winsize = winsize.unwrap_or_else(|| driver.winsize(line));
+            unsafe { (arg as *mut WinSize).write_unaligned(winsize) };
+        }
+        TIOCSWINSZ => tty.winsize = Some(unsafe { (arg as *const WinSize).read_unaligned() }),
+        FIONREAD => {
+            let avail = if tty.canonical() {
+                tty.lines.iter().sum::<usize>()
+            } else {
+                tty.ready.len()
+            };
+            unsafe { (arg as *mut i32).write_unaligned(avail as i32) };
+        }
+        _ => {
+            drop(ttys);
+            return driver.ioctl(line, file, cmd, arg);
+        }
+    }
+    Ok(0)
+}
+
+impl CharDevice for TtyDevice {
+    fn open(&self, minor: u32) -> VfsResult<Option<DevNum>> {
+        let line = line_of(DevNum::new(self.major, minor))?;
+        driver(line)?.open(line)?;
+        Ok(None)
+    }
+
+    fn close(&self, minor: u32, file: FileId) {
+        if let Ok(line) = line_of(DevNum::new(self.major, minor)) {
+            if let Ok(driver) = driver(line) {
+                driver.close(line, file);
+            }
+        }
+    }
+
+    fn read(&self, minor: u32, buf: &mut [u8]) -> VfsResult<usize> {
+        read(line_of(DevNum::new(self.major, minor))?, buf)
+    }
+
+    fn write(&self, minor: u32, buf: &[u8]) -> VfsResult<usize> {
+        write(line_of(DevNum::new(self.major, minor))?, buf)
+    }
+
+    fn poll(&self, minor: u32, waiter: Option<ProcessId>) -> u16 {
+        match line_of(DevNum::new(self.major, minor)) {
+            Ok(line) => poll(line, waiter),
+            Err(_) => POLLERR,
+        }
+    }
+
+    fn ioctl(&self, minor: u32, file: FileId, cmd: u64, arg: u64) -> VfsResult<u64> {
+        ioctl(line_of(DevNum::new(self.major, minor))?, file, cmd, arg)
+    }
+}
@@ -0,0 +1,329 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::vec::Vec;
+
+use super::TtyDriver;
+use crate::kernel::process::{self, ProcessId, WaitQueue};
+use crate::kernel::vfs::devfs::{self, CharDevice, DevNum, MAJOR_PTM, MAJOR_PTS, MAJOR_TTYAUX};
+use crate::kernel::vfs::file::FileId;
+use crate::kernel::vfs::{
+    DirEntry, VfsError, VfsResult, DT_CHR, POLLERR, POLLHUP, POLLIN, POLLOUT,
+};
+use crate::util::sync::SpinLock;
+
+pub const MAX_PTYS: usize = 8;
+// Slave output waiting for the master to read it.
+pub const PTY_BUF_SIZE: usize = 4096;
+// Inode of /dev/pts; device nodes use `devfs::ino`.
+pub const DIR_INO: u64 = 2;
+
+const MINOR_PTMX: u32 = 2;
+
+// ioctls on the master (Linux values).
+pub const TIOCGPTN: u64 = 0x8004_5430;
+pub const TIOCSPTLCK: u64 = 0x4004_5431;
+
+struct Pty {
+    // TTY line of the slave side.
+    line: usize,
+    // Output written to the slave, after output processing.
+    out: VecDeque<u8>,
+    master_open: bool,
+    // Open file descriptions of the slave, and whether the last one has
+    // been closed since the slave was first opened.
+    slave_opens: u32,
+    slave_hung_up: bool,
+    // The slave cannot be opened until the master unlocks it (unlockpt).
+    locked: bool,
+    read_wait: WaitQueue,
+    write_wait: WaitQueue,
+}
+
+static PTYS: SpinLock<[Option<Pty>; MAX_PTYS]> = SpinLock::new([const { None }; MAX_PTYS]);
+
+// The slave side: a TTY whose "hardware" is the master's read buffer.
+struct PtySlave;
+
+static PTY_SLAVE: PtySlave = PtySlave;
+
+// /dev/ptmx: each open allocates a pair and lands on its master.
+struct Ptmx;
+
+static PTMX: Ptmx = Ptmx;
+
+// Masters, reached by major with the pty number as minor.
+struct PtyMaster;
+
+static PTY_MASTER: PtyMaster = PtyMaster;
+
+fn index_of(ptys: &[Option<Pty>; MAX_PTYS], line: usize) -> Option<usize> {
+    ptys.iter()
+        .position(|slot| slot.as_ref().is_some_and(|pty| pty.line == line))
+}
+
+fn free_if_unused(ptys: &mut [Option<Pty>; MAX_PTYS], idx: usize) -> Option<usize> {
+    // Drop the pair once neither side is open; returns the line to detach.
+    let pty = ptys[idx].as_ref()?;
+    if pty.master_open || pty.slave_opens > 0 {
+        return None;
+    }
+    ptys[idx].take().map(|pty| pty.line)
+}
+
+pub fn lookup(name: &[u8]) -> Option<DevNum> {
+    // /dev/pts/<n> exists while pair n is allocated.
+    let n: usize = core::str::from_utf8(name).ok()?.parse().ok()?;
+    let ptys = PTYS.lock();
+    ptys.get(n)?.as_ref()?;
+    Some(DevNum::new(MAJOR_PTS, n as u32))
+}
+
+pub fn list() -> Vec<DirEntry> {
+    let ptys = PTYS.lock();
+    ptys.iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.is_some())
+        .map(|(n, _)| DirEntry {
+            name: format!("{}", n),
+            ino: devfs::ino(DevNum::new(MAJOR_PTS, n as u32)),
+            kind: DT_CHR,
+        })
+        .collect()
+}
+
+fn open_master() -> VfsResult<DevNum> {
+    // Allocate a pair with the slave locked, as posix_openpt does.
+    let mut ptys = PTYS.lock();
+    let n = ptys
+        .iter()
+        .position(|slot| slot.is_none())
+        .ok_or(VfsError::NoSpace)?;
+    let line =
+        super::attach(DevNum::new(MAJOR_PTS, n as u32), &PTY_SLAVE).ok_or(VfsError::NoSpace)?;
+    ptys[n] = Some(Pty {
+        line,
+        out: VecDeque::new(),
+        master_open: true,
+        slave_opens: 0,
+        slave_hung_up: false,
+        locked: true,
+        read_wait: WaitQueue::new(),
+        write_wait: WaitQueue::new(),
+    });
+    Ok(DevNum::new(MAJOR_PTM, n as u32))
+}
+
+fn close_master(n: usize) {
+    // The slave is hung up; the pair goes away with the last slave file.
+    let mut ptys = PTYS.lock();
+    let Some(pty) = ptys[n].as_mut() else {
+        return;
+    };
+    pty.master_open = false;
+    let line = pty.line;
+    let waiters = pty.write_wait.take();
+    let free = free_if_unused(&mut ptys, n);
+    drop(ptys);
+    // Hang up first so woken slave writers fail instead of waiting again.
+    super::hangup(line);
+    waiters.wake_all();
+    if let Some(line) = free {
+        super::detach(line);
+    }
+}
+
+fn read_master(n: usize, buf: &mut [u8]) -> VfsResult<usize> {
+    // Blocks while the slave has written nothing; fails with EIO once every
+    // slave file is closed and the output is drained.
+    let pid = process::current_pid();
+    let mut ptys = PTYS.lock();
+    let pty = ptys[n].as_mut().ok_or(VfsError::NoDevice)?;
+    if pty.out.is_empty() {
+        if pty.slave_hung_up {
+            return Err(VfsError::IoError);
+        }
+        if let Some(pid) = pid {
+            pty.read_wait.add(pid);
+        }
+        return Err(VfsError::WouldBlock);
+    }
+    let count = buf.len().min(pty.out.len());
+    for (slot, b) in buf.iter_mut().zip(pty.out.drain(..count)) {
+        *slot = b;
+    }
+    let waiters = pty.write_wait.take();
+    drop(ptys);
+    waiters.wake_all();
+    Ok(count)
+}
+
+fn write_master(n: usize, buf: &[u8]) -> VfsResult<usize> {
+    // Bytes written to the master are typed into the slave. Input beyond
+    // what the line discipline buffers is dropped rather than blocking.
+    let line = PTYS.lock()[n]
+        .as_ref()
+        .map(|pty| pty.line)
+        .ok_or(VfsError::NoDevice)?;
+    super::input(line, buf);
+    Ok(buf.len())
+}
+
+fn poll_master(n: usize, waiter: Option<ProcessId>) -> u16 {
+    let mut ptys = PTYS.lock();
+    let Some(pty) = ptys[n].as_mut() else {
+        return POLLERR;
+    };
+    if !pty.out.is_empty() {
+        return POLLIN | POLLOUT;
+    }
+    if pty.slave_hung_up {
+        return POLLHUP | POLLOUT;
+    }
+    if let Some(pid) = waiter {
+        pty.read_wait.add(pid);
+    }
+    POLLOUT
+}
+
+fn ioctl_master(n: usize, file: FileId, cmd: u64, arg: u64) -> VfsResult<u64> {
+    // Master-only requests, which all take a pointer; the rest (termios,
+    // window size) act on the slave.
+    if matches!(cmd, TIOCGPTN | TIOCSPTLCK | super::FIONREAD) && arg == 0 {
+        return Err(VfsError::BadAddress);
+    }
+    let mut ptys = PTYS.lock();
+    let pty = ptys[n].as_mut().ok_or(VfsError::NoDevice)?;
+    match cmd {
+        TIOCGPTN => unsafe { (arg as *mut u32).write_unaligned(n as u32) },
+        TIOCSPTLCK => pty.locked = unsafe { (arg as *const i32).read_unaligned() } != 0,
+        super::FIONREAD => unsafe { (arg as *mut i32).write_unaligned(pty.out.len() as i32) },
+        _ => {
+            let line = pty.line;
+            drop(ptys);
+            return super::ioctl(line, file, cmd, arg);
+        }
+    }
+    Ok(0)
+}
+
+impl TtyDriver for PtySlave {
+    fn write(&self, line: usize, buf: &[u8]) {
+        // Bytes beyond the buffer are dropped; `write_room` keeps regular
+        // writes within it.
+        let mut ptys = PTYS.lock();
+        let Some(idx) = index_of(&ptys, line) else {
+            return;
+        };
+        let Some(pty) = ptys[idx].as_mut() else {
+            return;
+        };
+        let room = PTY_BUF_SIZE - pty.out.len();
+        pty.out.extend(buf.iter().take(room));
+        let waiters = pty.read_wait.take();
+        drop(ptys);
+        waiters.wake_all();
+    }
+
+    fn write_room(&self, line: usize, waiter: Option<ProcessId>) -> usize {
+        let mut ptys = PTYS.lock();
+        let Some(idx) = index_of(&ptys, line) else {
+            return 0;
+        };
+        let Some(pty) = ptys[idx].as_mut() else {
+            return 0;
+        };
+        let room = PTY_BUF_SIZE - pty.out.len();
+        if room < 2 {
+            if let Some(pid) = waiter {
+                pty.write_wait.add(pid);
+            }
+        }
+        room
+    }
+
+    fn open(&self, line: usize) -> VfsResult<()> {
+        // Fails with EIO while locked or after the master is closed.
+        let mut ptys = PTYS.lock();
+        let idx = index_of(&ptys, line).ok_or(VfsError::NoDevice)?;
+        let pty = ptys[idx].as_mut().ok_or(VfsError::NoDevice)?;
+        if pty.locked || !pty.master_open {
+            return Err(VfsError::IoError);
+        }
+        pty.slave_opens += 1;
+        pty.slave_hung_up = false;
+        Ok(())
+    }
+
+    fn close(&self, line: usize, _file: FileId) {
+        // The master sees a hangup when the last slave file goes away.
+        let mut ptys = PTYS.lock();
+        let Some(idx) = index_of(&ptys, line) else {
+            return;
+        };
+        let Some(pty) = ptys[idx].as_mut() else {
+            return;
+        };
+        pty.slave_opens -= 1;
+        if pty.slave_opens > 0 {
+            return;
+        }
+        pty.slave_hung_up = true;
+        let waiters = pty.read_wait.take();
+        let free = free_if_unused(&mut ptys, idx);
+        drop(ptys);
+        waiters.wake_all();
+        if let Some(line) = free {
+            super::detach(line);
+        }
+    }
+}
+
+impl CharDevice for Ptmx {
+    fn open(&self, _minor: u32) -> VfsResult<Option<DevNum>> {
+        open_master().map(Some)
+    }
+
+    // Never reached: opening /dev/ptmx always ends up on a master.
+    fn read(&self, _minor: u32, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::NoDevice)
+    }
+
+    fn write(&self, _minor: u32, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::NoDevice)
+    }
+}
+
+impl CharDevice for PtyMaster {
+    fn open(&self, _minor: u32) -> VfsResult<Option<DevNum>> {
+        // Masters have no node of their own; only /dev/ptmx hands them out.
+        Err(VfsError::NoDevice)
+    }
+
+    fn close(&self, minor: u32, _file: FileId) {
+        close_master(minor as usize);
+    }
+
+    fn read(&self, minor: u32, buf: &mut [u8]) -> VfsResult<usize> {
+        read_master(minor as usize, buf)
+    }
+
+    fn write(&self, minor: u32, buf: &[u8]) -> VfsResult<usize> {
+        write_master(minor as usize, buf)
+    }
+
+    fn poll(&self, minor: u32, waiter: Option<ProcessId>) -> u16 {
+        poll_master(minor as usize, waiter)
+    }
+
+    fn ioctl(&self, minor: u32, file: FileId, cmd: u64, arg: u64) -> VfsResult<u64> {
+        ioctl_master(minor as usize, file, cmd, arg)
+    }
+}
+
+pub fn register_devices() {
+    devfs::register_char("ptmx", DevNum::new(MAJOR_TTYAUX, MINOR_PTMX), &PTMX);
+    devfs::register_major(MAJOR_PTM, &PTY_MASTER);
+    if let Some(slaves) = super::device(MAJOR_PTS) {
+        devfs::register_major(MAJOR_PTS, slaves);
+    }
+}
@@ -1,6 +1,22 @@
 #![allow(dead_code)]
 
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::arch::asm;
+use core::fmt::Write;
+
+pub use crate::drivers::console::{FontImage, KDSETPSF};
+pub use crate::drivers::framebuffer::{
+    FbInfo, FbMode, FBIOFLIP, FBIOGET_INFO, FBIOSET_MODE, FBIO_WAITFORVSYNC, FB_FLIP_VSYNC,
+};
+pub use crate::gfx::surface::Surface;
+pub use crate::kernel::ipc::{
+    listener_offset, ChanHeader, ShmRequest, CHAN_DATA, CHAN_HANGUP, CHAN_HEADER_SIZE,
+    DISPLAY_SHM_CREATE, MAX_MESSAGE,
+};
+pub use crate::kernel::klog::SYSLOG_ACTION_READ_ALL;
+pub use crate::kernel::tty::{Termios, WinSize};
+pub use crate::kernel::vfs::{PollFd, Stat, S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT};
 
 static mut USER_ENTRY: Option<extern "C" fn() -> !> = None;
 static mut USER_STACK_TOP: usize = 0;
@@ -13,10 +29,121 @@ pub const SYSCALL_SLEEP_MS: u64 = 5;
 pub const SYSCALL_ALLOC: u64 = 6;
 pub const SYSCALL_REALLOC: u64 = 7;
 pub const SYSCALL_FREE: u64 = 8;
+pub const SYSCALL_SYNC: u64 = 9;
+pub const SYSCALL_FSYNC: u64 = 10;
+pub const SYSCALL_GETDENTS64: u64 = 11;
+pub const SYSCALL_STAT: u64 = 12;
+pub const SYSCALL_FSTAT: u64 = 13;
+pub const SYSCALL_LSTAT: u64 = 14;
+pub const SYSCALL_MKDIR: u64 = 15;
+pub const SYSCALL_RMDIR: u64 = 16;
+pub const SYSCALL_UNLINK: u64 = 17;
+pub const SYSCALL_RENAME: u64 = 18;
+pub const SYSCALL_SYMLINK: u64 = 19;
+pub const SYSCALL_READLINK: u64 = 20;
+pub const SYSCALL_CHDIR: u64 = 21;
+pub const SYSCALL_GETCWD: u64 = 22;
+pub const SYSCALL_LSEEK: u64 = 23;
+pub const SYSCALL_PREAD64: u64 = 24;
+pub const SYSCALL_PWRITE64: u64 = 25;
+pub const SYSCALL_READV: u64 = 26;
+pub const SYSCALL_WRITEV: u64 = 27;
+pub const SYSCALL_PIPE: u64 = 28;
+pub const SYSCALL_DUP: u64 = 29;
+pub const SYSCALL_DUP2: u64 = 30;
+pub const SYSCALL_DUP3: u64 = 31;
+pub const SYSCALL_FCNTL: u64 = 32;
+pub const SYSCALL_EXEC: u64 = 33;
+pub const SYSCALL_GETRLIMIT: u64 = 34;
+pub const SYSCALL_SETRLIMIT: u64 = 35;
+pub const SYSCALL_POLL: u64 = 36;
+pub const SYSCALL_IOCTL: u64 = 37;
+pub const SYSCALL_MMAP: u64 = 38;
+pub const SYSCALL_SYSLOG: u64 = 39;
 
 pub const O_READ: u64 = 1 << 0;
 pub const O_WRITE: u64 = 1 << 1;
 pub const O_APPEND: u64 = 1 << 2;
+pub const O_CREAT: u64 = 1 << 3;
+pub const O_TRUNC: u64 = 1 << 4;
+pub const O_EXCL: u64 = 1 << 5;
+pub const O_CLOEXEC: u64 = 1 << 6;
+pub const O_NONBLOCK: u64 = 1 << 7;
+
+pub const F_DUPFD: u64 = 0;
+pub const F_GETFD: u64 = 1;
+pub const F_SETFD: u64 = 2;
+pub const F_GETFL: u64 = 3;
+pub const F_SETFL: u64 = 4;
+pub const F_DUPFD_CLOEXEC: u64 = 1030;
+pub const FD_CLOEXEC: u64 = 1;
+
+pub const RLIMIT_NOFILE: u64 = 7;
+
+pub const POLLIN: i16 = 0x001;
+pub const POLLOUT: i16 = 0x004;
+pub const POLLERR: i16 = 0x008;
+pub const POLLHUP: i16 = 0x010;
+pub const POLLNVAL: i16 = 0x020;
+
+pub const TCGETS: u64 = 0x5401;
+pub const TCSETS: u64 = 0x5402;
+pub const TIOCGWINSZ: u64 = 0x5413;
+pub const TIOCSWINSZ: u64 = 0x5414;
+pub const FIONREAD: u64 = 0x541B;
+pub const TIOCGPTN: u64 = 0x8004_5430;
+pub const TIOCSPTLCK: u64 = 0x4004_5431;
+pub const KDSETMODE: u64 = 0x4B3A;
+pub const KDGETMODE: u64 = 0x4B3B;
+pub const KD_TEXT: u64 = 0;
+pub const KD_GRAPHICS: u64 = 1;
+
+// tcsetattr actions; added to TCSETS to pick TCSETS, TCSETSW or TCSETSF.
+pub const TCSANOW: u64 = 0;
+pub const TCSADRAIN: u64 = 1;
+pub const TCSAFLUSH: u64 = 2;
+
+pub const ICRNL: u32 = 0o000400;
+pub const OPOST: u32 = 0o000001;
+pub const ONLCR: u32 = 0o000004;
+pub const ISIG: u32 = 0o000001;
+pub const ICANON: u32 = 0o000002;
+pub const ECHO: u32 = 0o000010;
+pub const ECHOE: u32 = 0o000020;
+pub const VINTR: usize = 0;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+
+// errno values; failed file syscalls return them negated.
+pub const EINTR: u64 = 4;
+pub const EAGAIN: u64 = 11;
+pub const ENOMEM: u64 = 12;
+pub const EINVAL: u64 = 22;
+pub const ECONNREFUSED: u64 = 111;
+
+pub const PROT_READ: u64 = 1;
+pub const PROT_WRITE: u64 = 2;
+pub const MAP_SHARED: u64 = 1;
+
+pub const SEEK_SET: u64 = 0;
+pub const SEEK_CUR: u64 = 1;
+pub const SEEK_END: u64 = 2;
+
+// `struct iovec` for readv/writev.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct IoVec {
+    pub base: u64,
+    pub len: u64,
+}
+
+pub fn is_error(ret: u64) -> bool {
+    // File syscalls return -errno, which lands in the top 4095 values.
+    ret > (-4096i64) as u64
+}
 
 pub fn init(entry: extern "C" fn() -> !, stack_top: usize) {
     // Record the user entry point and stack for the user-start trampoline.
@@ -48,7 +175,11 @@ pub extern "C" fn user_start() -> ! {
 }
 
 pub fn open(path: &str, flags: u64) -> u64 {
-    unsafe { syscall_open(path.as_ptr(), path.len(), flags) }
+    open_mode(path, flags, 0o666)
+}
+
+pub fn open_mode(path: &str, flags: u64, mode: u32) -> u64 {
+    unsafe { syscall_open(path.as_ptr(), path.len(), flags, mode as u64) }
 }
 
 pub fn read(fd: u64, buf: &mut [u8]) -> u64 {
@@ -56,11 +187,25 @@ pub fn read(fd: u64, buf: &mut [u8]) -> u64 {
 }
 
 pub fn write(fd: u64, s: &str) -> u64 {
-    unsafe { syscall_write(fd, s.as_ptr(), s.len()) }
+    write_bytes(fd, s.as_bytes())
 }
 
 pub fn write_bytes(fd: u64, buf: &[u8]) -> u64 {
-    unsafe { syscall_write(fd, buf.as_ptr(), buf.len()) }
+    // Retry short writes, as a pipe gives when a large write fills it, so a
+    // blocking FD takes every byte. Stops at an error (EAGAIN on an
+    // O_NONBLOCK FD included) and returns the count so far, if any.
+    let mut done = 0;
+    loop {
+        let rest = &buf[done..];
+        let ret = unsafe { syscall_write(fd, rest.as_ptr(), rest.len()) };
+        if is_error(ret) {
+            return if done == 0 { ret } else { done as u64 };
+        }
+        done += ret as usize;
+        if ret == 0 || done == buf.len() {
+            return done as u64;
+        }
+    }
 }
 
 pub fn close(fd: u64) -> u64 {
@@ -83,7 +228,341 @@ pub fn free(ptr: u64, size: usize, align: usize) -> u64 {
     unsafe { syscall_free(ptr, size as u64, align as u64) }
 }
 
-unsafe fn syscall_open(ptr: *const u8, len: usize, flags: u64) -> u64 {
+pub fn sync() -> u64 {
+    unsafe { syscall_sync() }
+}
+
+pub fn fsync(fd: u64) -> u64 {
+    unsafe { syscall_fsync(fd) }
+}
+
+pub fn getdents64(fd: u64, buf: &mut [u8]) -> u64 {
+    unsafe {
+        syscall3(
+            SYSCALL_GETDENTS64,
+            fd,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64,
+        )
+    }
+}
+
+pub fn stat(path: &str, st: &mut Stat) -> u64 {
+    unsafe {
+        syscall3(
+            SYSCALL_STAT,
+            path.as_ptr() as u64,
+            path.len() as u64,
+            st as *mut Stat as u64,
+        )
+    }
+}
+
+pub fn lstat(path: &str, st: &mut Stat) -> u64 {
+    unsafe {
+        syscall3(
+            SYSCALL_LSTAT,
+            path.as_ptr() as u64,
+            path.len() as u64,
+            st as *mut Stat as u64,
+        )
+    }
+}
+
+pub fn fstat(fd: u64, st: &mut Stat) -> u64 {
+    unsafe { syscall3(SYSCALL_FSTAT, fd, st as *mut Stat as u64, 0) }
+}
+
+pub fn mkdir(path: &str, mode: u32) -> u64 {
+    unsafe {
+        syscall3(
+            SYSCALL_MKDIR,
+            path.as_ptr() as u64,
+            path.len() as u64,
+            mode as u64,
+        )
+    }
+}
+
+pub fn rmdir(path: &str) -> u64 {
+    unsafe { syscall3(SYSCALL_RMDIR, path.as_ptr() as u64, path.len() as u64, 0) }
+}
+
+pub fn unlink(path: &str) -> u64 {
+    unsafe { syscall3(SYSCALL_UNLINK, path.as_ptr() as u64, path.len() as u64, 0) }
+}
+
+pub fn rename(old: &str, new: &str) -> u64 {
+    unsafe {
+        syscall4(
+            SYSCALL_RENAME,
+            old.as_ptr() as u64,
+            old.len() as u64,
+            new.as_ptr() as u64,
+            new.len() as u64,
+        )
+    }
+}
+
+pub fn symlink(target: &str, link: &str) -> u64 {
+    unsafe {
+        syscall4(
+            SYSCALL_SYMLINK,
+            target.as_ptr() as u64,
+            target.len() as u64,
+            link.as_ptr() as u64,
+            link.len() as u64,
+        )
+    }
+}
+
+pub fn readlink(path: &str, buf: &mut [u8]) -> u64 {
+    unsafe {
+        syscall4(
+            SYSCALL_READLINK,
+            path.as_ptr() as u64,
+            path.len() as u64,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64,
+        )
+    }
+}
+
+pub fn chdir(path: &str) -> u64 {
+    unsafe { syscall3(SYSCALL_CHDIR, path.as_ptr() as u64, path.len() as u64, 0) }
+}
+
+pub fn getcwd(buf: &mut [u8]) -> u64 {
+    unsafe { syscall3(SYSCALL_GETCWD, buf.as_mut_ptr() as u64, buf.len() as u64, 0) }
+}
+
+pub fn lseek(fd: u64, offset: i64, whence: u64) -> u64 {
+    unsafe { syscall3(SYSCALL_LSEEK, fd, offset as u64, whence) }
+}
+
+pub fn pread(fd: u64, buf: &mut [u8], offset: u64) -> u64 {
+    unsafe {
+        syscall4(
+            SYSCALL_PREAD64,
+            fd,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64,
+            offset,
+        )
+    }
+}
+
+pub fn pwrite(fd: u64, buf: &[u8], offset: u64) -> u64 {
+    unsafe {
+        syscall4(
+            SYSCALL_PWRITE64,
+            fd,
+            buf.as_ptr() as u64,
+            buf.len() as u64,
+            offset,
+        )
+    }
+}
+
+pub fn readv(fd: u64, iov: &[IoVec]) -> u64 {
+    unsafe { syscall3(SYSCALL_READV, fd, iov.as_ptr() as u64, iov.len() as u64) }
+}
+
+pub fn writev(fd: u64, iov: &[IoVec]) -> u64 {
+    unsafe { syscall3(SYSCALL_WRITEV, fd, iov.as_ptr() as u64, iov.len() as u64) }
+}
+
+pub fn pipe(fds: &mut [i32; 2]) -> u64 {
+    pipe2(fds, 0)
+}
+
+pub fn pipe2(fds: &mut [i32; 2], flags: u64) -> u64 {
+    unsafe { syscall3(SYSCALL_PIPE, fds.as_mut_ptr() as u64, flags, 0) }
+}
+
+pub fn dup(fd: u64) -> u64 {
+    unsafe { syscall3(SYSCALL_DUP, fd, 0, 0) }
+}
+
+pub fn dup2(old_fd: u64, new_fd: u64) -> u64 {
+    unsafe { syscall3(SYSCALL_DUP2, old_fd, new_fd, 0) }
+}
+
+pub fn dup3(old_fd: u64, new_fd: u64, flags: u64) -> u64 {
+    unsafe { syscall3(SYSCALL_DUP3, old_fd, new_fd, flags) }
+}
+
+pub fn fcntl(fd: u64, cmd: u64, arg: u64) -> u64 {
+    unsafe { syscall3(SYSCALL_FCNTL, fd, cmd, arg) }
+}
+
+pub fn exec(program: &str) -> u64 {
+    // Only returns on failure.
+    unsafe {
+        syscall3(
+            SYSCALL_EXEC,
+            program.as_ptr() as u64,
+            program.len() as u64,
+            0,
+        )
+    }
+}
+
+pub fn getrlimit(resource: u64, limit: &mut [u64; 2]) -> u64 {
+    unsafe { syscall3(SYSCALL_GETRLIMIT, resource, limit.as_mut_ptr() as u64, 0) }
+}
+
+pub fn setrlimit(resource: u64, limit: &[u64; 2]) -> u64 {
+    unsafe { syscall3(SYSCALL_SETRLIMIT, resource, limit.as_ptr() as u64, 0) }
+}
+
+pub fn poll(fds: &mut [PollFd], timeout_ms: i64) -> u64 {
+    // Negative timeout: wait until an FD is ready.
+    unsafe {
+        syscall3(
+            SYSCALL_POLL,
+            fds.as_mut_ptr() as u64,
+            fds.len() as u64,
+            timeout_ms as u64,
+        )
+    }
+}
+
+pub fn ioctl(fd: u64, cmd: u64, arg: u64) -> u64 {
+    unsafe { syscall3(SYSCALL_IOCTL, fd, cmd, arg) }
+}
+
+pub fn tcgetattr(fd: u64, termios: &mut Termios) -> u64 {
+    ioctl(fd, TCGETS, termios as *mut Termios as u64)
+}
+
+pub fn tcsetattr(fd: u64, action: u64, termios: &Termios) -> u64 {
+    if action > TCSAFLUSH {
+        return EINVAL.wrapping_neg();
+    }
+    ioctl(fd, TCSETS + action, termios as *const Termios as u64)
+}
+
+pub fn cfmakeraw(termios: &mut Termios) {
+    termios.make_raw();
+}
+
+pub fn get_winsize(fd: u64, winsize: &mut WinSize) -> u64 {
+    ioctl(fd, TIOCGWINSZ, winsize as *mut WinSize as u64)
+}
+
+pub fn posix_openpt(flags: u64) -> u64 {
+    // Opens a new pty master; the slave starts out locked.
+    open("/dev/ptmx", flags)
+}
+
+pub fn grantpt(_fd: u64) -> u64 {
+    // Slave ownership and modes are not tracked, so there is nothing to grant.
+    0
+}
+
+pub fn unlockpt(fd: u64) -> u64 {
+    let unlock: i32 = 0;
+    ioctl(fd, TIOCSPTLCK, &unlock as *const i32 as u64)
+}
+
+pub fn ptsname(fd: u64, out: &mut String) -> u64 {
+    // Path of the slave that belongs to master `fd`.
+    let mut n: u32 = 0;
+    let ret = ioctl(fd, TIOCGPTN, &mut n as *mut u32 as u64);
+    if is_error(ret) {
+        return ret;
+    }
+    out.clear();
+    let _ = write!(out, "/dev/pts/{}", n);
+    0
+}
+
+pub fn load_console_font(tty: u64, path: &str) -> u64 {
+    // Read a PSF font file and install it on the virtual consoles.
+    let fd = open(path, O_READ);
+    if is_error(fd) {
+        return fd;
+    }
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 512];
+    let ret = loop {
+        let n = read(fd, &mut chunk);
+        if is_error(n) || n == 0 {
+            break n;
+        }
+        data.extend_from_slice(&chunk[..n as usize]);
+    };
+    close(fd);
+    if is_error(ret) {
+        return ret;
+    }
+    let image = FontImage {
+        data: data.as_ptr(),
+        len: data.len(),
+    };
+    ioctl(tty, KDSETPSF, &image as *const FontImage as u64)
+}
+
+pub fn mmap(len: usize, prot: u64, flags: u64, fd: u64, offset: u64) -> u64 {
+    unsafe { syscall6(SYSCALL_MMAP, 0, len as u64, prot, flags, fd, offset) }
+}
+
+pub fn syslog(action: u64, buf: &mut [u8]) -> u64 {
+    // Actions that take a number (CONSOLE_LEVEL) pass it as the length.
+    unsafe {
+        syscall3(
+            SYSCALL_SYSLOG,
+            action,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64,
+        )
+    }
+}
+
+pub fn map_framebuffer(fd: u64) -> Option<(FbInfo, Surface<'static>)> {
+    // Map an open /dev/fb0 and wrap it in a surface for `gfx` drawing. Put
+    // the console in KD_GRAPHICS first, or its text will draw over yours.
+    let mut info = FbInfo::default();
+    if is_error(ioctl(fd, FBIOGET_INFO, &mut info as *mut FbInfo as u64)) {
+        return None;
+    }
+    let addr = mmap(
+        info.size as usize,
+        PROT_READ | PROT_WRITE,
+        MAP_SHARED,
+        fd,
+        0,
+    );
+    if is_error(addr) {
+        return None;
+    }
+    let surface = unsafe {
+        Surface::from_raw(
+            addr as *mut u8,
+            info.width,
+            info.height,
+            info.pitch,
+            info.pixel_format()?,
+        )?
+    };
+    Some((info, surface))
+}
+
+pub fn fb_set_mode(fd: u64, mode: &FbMode) -> u64 {
+    ioctl(fd, FBIOSET_MODE, mode as *const FbMode as u64)
+}
+
+pub fn fb_flip(fd: u64, buffer: u32, vsync: bool) -> u64 {
+    let flags = if vsync { FB_FLIP_VSYNC } else { 0 };
+    ioctl(fd, FBIOFLIP, buffer as u64 | flags)
+}
+
+pub fn fb_wait_vsync(fd: u64) -> u64 {
+    ioctl(fd, FBIO_WAITFORVSYNC, 0)
+}
+
+unsafe fn syscall_open(ptr: *const u8, len: usize, flags: u64, mode: u64) -> u64 {
     let ret: u64;
     asm!(
         "svc #0",
@@ -91,6 +570,7 @@ unsafe fn syscall_open(ptr: *const u8, len: usize, flags: u64) -> u64 {
         in("x0") ptr,
         in("x1") len,
         in("x2") flags,
+        in("x3") mode,
         lateout("x0") ret,
         options(nostack)
     );
@@ -190,3 +670,72 @@ unsafe fn syscall_free(ptr: u64, size: u64, align: u64) -> u64 {
     );
     ret
 }
+
+unsafe fn syscall_sync() -> u64 {
+    let ret: u64;
+    asm!(
+        "svc #0",
+        in("x8") SYSCALL_SYNC,
+        lateout("x0") ret,
+        options(nostack)
+    );
+    ret
+}
+
+unsafe fn syscall_fsync(fd: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "svc #0",
+        in("x8") SYSCALL_FSYNC,
+        in("x0") fd,
+        lateout("x0") ret,
+        options(nostack)
+    );
+    ret
+}
+
+unsafe fn syscall3(num: u64, a0: u64, a1: u64, a2: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "svc #0",
+        in("x8") num,
+        in("x0") a0,
+        in("x1") a1,
+        in("x2") a2,
+        lateout("x0") ret,
+        options(nostack)
+    );
+    ret
+}
+
+unsafe fn syscall4(num: u64, a0: u64, a1: u64, a2: u64, a3: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "svc #0",
+        in("x8") num,
+        in("x0") a0,
+        in("x1") a1,
+        in("x2") a2,
+        in("x3") a3,
+        lateout("x0") ret,
+        options(nostack)
+    );
+    ret
+}
+
+unsafe fn syscall6(num: u64, a0: u64, a1: u64, a2: u64, a3: u64, a4: u64, a5: u64) -> u64 {
+    let ret: u64;
+    asm!(
+        "svc #0",
+        in("x8") num,
+        in("x0") a0,
+        in("x1") a1,
+        in("x2") a2,
+        in("x3") a3,
+        in("x4") a4,
+        in("x5") a5,
+        lateout("x0") ret,
+        options(nostack)
+    );
+    ret
+}
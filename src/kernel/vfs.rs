@@ -1,6 +1,23 @@
-use crate::drivers::framebuffer;
-use crate::drivers::uart;
-use crate::drivers::keyboard;
+pub mod devfs;
+pub mod file;
+pub mod pipe;
+pub mod procfs;
+pub mod ramfs;
+pub mod sysfs;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::drivers::block::{self, DeviceId};
+use crate::kernel::process::{self, ProcessId};
+use crate::kernel::tty::pty;
+use crate::mm::pagecache;
+use devfs::DevNum;
+use file::FileId;
+use pipe::PipeEnd;
+use procfs::{ProcFile, ProcNode};
+use sysfs::{SysFile, SysNode};
 
 pub const FD_STDIN: usize = 0;
 pub const FD_STDOUT: usize = 1;
@@ -9,119 +26,1044 @@ pub const FD_STDERR: usize = 2;
 pub const O_READ: u64 = 1 << 0;
 pub const O_WRITE: u64 = 1 << 1;
 pub const O_APPEND: u64 = 1 << 2;
+pub const O_CREAT: u64 = 1 << 3;
+pub const O_TRUNC: u64 = 1 << 4;
+pub const O_EXCL: u64 = 1 << 5;
+pub const O_CLOEXEC: u64 = 1 << 6;
+pub const O_NONBLOCK: u64 = 1 << 7;
+// Status flags that F_SETFL may change on an open file description.
+const O_SETFL_MASK: u64 = O_APPEND | O_NONBLOCK;
+
+// poll(2) event bits (Linux values).
+pub const POLLIN: u16 = 0x001;
+pub const POLLOUT: u16 = 0x004;
+pub const POLLERR: u16 = 0x008;
+pub const POLLHUP: u16 = 0x010;
+pub const POLLNVAL: u16 = 0x020;
+
+pub const SEEK_SET: u64 = 0;
+pub const SEEK_CUR: u64 = 1;
+pub const SEEK_END: u64 = 2;
+
+pub const PATH_MAX: usize = 256;
+pub const NAME_MAX: usize = 255;
+const MAX_SYMLINKS: usize = 8;
+
+// File type bits of `Stat::mode`, as in POSIX.
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFBLK: u32 = 0o060000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+// Directory entry types reported by getdents64.
+pub const DT_CHR: u8 = 2;
+pub const DT_DIR: u8 = 4;
+pub const DT_BLK: u8 = 6;
+pub const DT_REG: u8 = 8;
+pub const DT_LNK: u8 = 10;
+
+// Filesystem IDs reported as `Stat::dev`.
+const FS_RAMFS: u64 = 1;
+const FS_DEVFS: u64 = 2;
+const FS_PROCFS: u64 = 3;
+const FS_SYSFS: u64 = 4;
+const FS_PIPEFS: u64 = 5;
+
+// Mount points in the ramfs root and the filesystems served below them.
+const MOUNTS: [&str; 3] = ["dev", "proc", "sys"];
 
 #[derive(Copy, Clone, Debug)]
 pub struct OpenFlags {
     pub read: bool,
     pub write: bool,
-    #[allow(dead_code)]
     pub append: bool,
+    pub nonblock: bool,
 }
 
 impl OpenFlags {
-    pub const fn new(read: bool, write: bool, append: bool) -> Self {
-        Self { read, write, append }
-    }
-
     pub const fn from_bits(bits: u64) -> Self {
         Self {
             read: bits & O_READ != 0,
             write: bits & O_WRITE != 0,
             append: bits & O_APPEND != 0,
+            nonblock: bits & O_NONBLOCK != 0,
         }
     }
+
+    pub const fn bits(self) -> u64 {
+        let mut bits = 0;
+        if self.read {
+            bits |= O_READ;
+        }
+        if self.write {
+            bits |= O_WRITE;
+        }
+        if self.append {
+            bits |= O_APPEND;
+        }
+        if self.nonblock {
+            bits |= O_NONBLOCK;
+        }
+        bits
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VfsError {
+    NotFound,
+    IoError,
+    BadDescriptor,
+    BadAddress,
+    NoDevice,
+    NoSpace,
+    NotSupported,
+    NotDir,
+    IsDir,
+    Exists,
+    NotEmpty,
+    InvalidArgument,
+    FileTableFull,
+    TooManyFiles,
+    NameTooLong,
+    ReadOnly,
+    Busy,
+    Loop,
+    Range,
+    WouldBlock,
+    IllegalSeek,
+    BrokenPipe,
+    Interrupted,
+    NotTty,
+    ConnectionRefused,
+}
+
+impl VfsError {
+    pub const fn errno(self) -> u64 {
+        // Linux errno values; syscalls return them negated.
+        match self {
+            VfsError::NotFound => 2,
+            VfsError::Interrupted => 4,
+            VfsError::IoError => 5,
+            VfsError::BadDescriptor => 9,
+            VfsError::WouldBlock => 11,
+            VfsError::BadAddress => 14,
+            VfsError::Busy => 16,
+            VfsError::Exists => 17,
+            VfsError::NoDevice => 19,
+            VfsError::NotDir => 20,
+            VfsError::IsDir => 21,
+            VfsError::InvalidArgument => 22,
+            VfsError::FileTableFull => 23,
+            VfsError::TooManyFiles => 24,
+            VfsError::NotTty => 25,
+            VfsError::NoSpace => 28,
+            VfsError::IllegalSeek => 29,
+            VfsError::ReadOnly => 30,
+            VfsError::BrokenPipe => 32,
+            VfsError::Range => 34,
+            VfsError::NameTooLong => 36,
+            VfsError::NotEmpty => 39,
+            VfsError::Loop => 40,
+            VfsError::NotSupported => 95,
+            VfsError::ConnectionRefused => 111,
+        }
+    }
+}
+
+pub type VfsResult<T> = Result<T, VfsError>;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum NodeType {
-    Dir,
-    DevFb0,
-    DevKbd0,
+    Ram(ramfs::Ino),
+    DevDir,
+    PtsDir,
+    CharDevice(DevNum),
+    BlockDevice(DeviceId),
+    Proc(ProcNode),
+    Sys(SysNode),
+    Pipe(u32),
+}
+
+impl NodeType {
+    fn is_dir(self) -> bool {
+        match self {
+            NodeType::Ram(ino) => ramfs::kind(ino) == Ok(ramfs::Kind::Dir),
+            NodeType::DevDir | NodeType::PtsDir => true,
+            NodeType::CharDevice(_) | NodeType::BlockDevice(_) | NodeType::Pipe(_) => false,
+            NodeType::Proc(node) => !matches!(node, ProcNode::File(_) | ProcNode::Fd(..)),
+            NodeType::Sys(node) => !matches!(node, SysNode::File(_)),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum FileHandle {
-    DevFb0,
-    DevKbd0,
+    File(ramfs::Ino),
+    Char(DevNum),
+    Block(DeviceId),
+    Proc(ProcFile),
+    Sys(SysFile),
+    Pipe(PipeEnd),
+    Dir(NodeType),
 }
 
+// A process FD slot: a reference to an open file description.
 #[derive(Copy, Clone, Debug)]
 pub struct FileDesc {
-    pub handle: FileHandle,
-    pub flags: OpenFlags,
+    pub file: FileId,
 }
 
-pub fn init() {}
+// `struct pollfd` as passed to poll(2).
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
 
-pub fn lookup(path: &[u8]) -> Option<NodeType> {
-    // Simple path lookup for the fixed in-memory namespace.
-    match path {
-        b"/" => Some(NodeType::Dir),
-        b"/dev" => Some(NodeType::Dir),
-        b"/dev/fb0" => Some(NodeType::DevFb0),
-        b"/dev/kbd0" => Some(NodeType::DevKbd0),
-        _ => None,
+pub struct DirEntry {
+    pub name: String,
+    pub ino: u64,
+    pub kind: u8,
+}
+
+// Laid out like the generic Linux `struct stat` so ported userland can share it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Stat {
+    pub dev: u64,
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    pub pad1: u64,
+    pub size: u64,
+    pub blksize: u32,
+    pub pad2: u32,
+    pub blocks: u64,
+    pub atime_sec: u64,
+    pub atime_nsec: u64,
+    pub mtime_sec: u64,
+    pub mtime_nsec: u64,
+    pub ctime_sec: u64,
+    pub ctime_nsec: u64,
+    pub unused: [u32; 2],
+}
+
+impl Stat {
+    pub fn set_times(&mut self, atime_ms: u64, mtime_ms: u64, ctime_ms: u64) {
+        // There is no RTC yet, so timestamps count from boot.
+        self.atime_sec = atime_ms / 1000;
+        self.atime_nsec = (atime_ms % 1000) * 1_000_000;
+        self.mtime_sec = mtime_ms / 1000;
+        self.mtime_nsec = (mtime_ms % 1000) * 1_000_000;
+        self.ctime_sec = ctime_ms / 1000;
+        self.ctime_nsec = (ctime_ms % 1000) * 1_000_000;
+    }
+}
+
+// Fixed-size path buffer so `Process` stays `Copy`.
+#[derive(Copy, Clone)]
+pub struct Cwd {
+    bytes: [u8; PATH_MAX],
+    len: usize,
+}
+
+impl Cwd {
+    pub const ROOT: Cwd = {
+        let mut bytes = [0u8; PATH_MAX];
+        bytes[0] = b'/';
+        Cwd { bytes, len: 1 }
+    };
+
+    pub fn from_path(path: &str) -> Option<Self> {
+        if path.len() > PATH_MAX || !path.starts_with('/') {
+            return None;
+        }
+        let mut cwd = Cwd::ROOT;
+        cwd.bytes[..path.len()].copy_from_slice(path.as_bytes());
+        cwd.len = path.len();
+        Some(cwd)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl core::fmt::Debug for Cwd {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:?}",
+            core::str::from_utf8(self.as_bytes()).unwrap_or("?")
+        )
+    }
+}
+
+pub fn init() {
+    // The root is a ramfs with directories for the synthetic filesystems.
+    ramfs::init();
+    for name in MOUNTS {
+        let _ = ramfs::mkdir(ramfs::ROOT_INO, name, 0o755);
+    }
+    devfs::init();
+}
+
+pub fn resolve(path: &[u8], follow: bool) -> VfsResult<String> {
+    // Turn a user path into a canonical absolute one: relative paths start at the
+    // cwd, "." and ".." are folded, and symlinks are expanded. The last component
+    // is only followed when `follow` is set and does not need to exist.
+    if path.is_empty() {
+        return Err(VfsError::NotFound);
+    }
+    if path.len() > PATH_MAX {
+        return Err(VfsError::NameTooLong);
+    }
+    let mut rest: Vec<u8> = Vec::new();
+    if path[0] != b'/' {
+        let cwd = process::current_cwd();
+        rest.extend_from_slice(cwd.as_bytes());
+        rest.push(b'/');
+    }
+    rest.extend_from_slice(path);
+
+    let mut out = String::new();
+    let mut pos = 0;
+    let mut links = 0;
+    while pos < rest.len() {
+        let end = rest[pos..]
+            .iter()
+            .position(|&b| b == b'/')
+            .map_or(rest.len(), |idx| pos + idx);
+        let name = &rest[pos..end];
+        pos = end + 1;
+        match name {
+            b"" | b"." => continue,
+            b".." => {
+                let parent = out.rfind('/').unwrap_or(0);
+                out.truncate(parent);
+                continue;
+            }
+            _ => {}
+        }
+        if name.len() > NAME_MAX {
+            return Err(VfsError::NameTooLong);
+        }
+        let name = core::str::from_utf8(name).map_err(|_| VfsError::InvalidArgument)?;
+        let dir = if out.is_empty() { "/" } else { out.as_str() };
+        if !lookup(dir.as_bytes())?.is_dir() {
+            return Err(VfsError::NotDir);
+        }
+        let parent_len = out.len();
+        out.push('/');
+        out.push_str(name);
+
+        let last = pos >= rest.len();
+        if last && !follow {
+            continue;
+        }
+        let target = match lookup(out.as_bytes()) {
+            Ok(NodeType::Ram(ino)) if ramfs::kind(ino)? == ramfs::Kind::Symlink => {
+                ramfs::readlink(ino)?
+            }
+            // /proc/<pid>/fd links are followed when they name a path; pipes
+            // and anonymous devices have nothing to follow.
+            Ok(NodeType::Proc(node @ ProcNode::Fd(..))) => match procfs::readlink(node) {
+                Ok(target) if target.starts_with('/') => target,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        links += 1;
+        if links > MAX_SYMLINKS {
+            return Err(VfsError::Loop);
+        }
+        // Splice the link target in front of the unresolved remainder.
+        out.truncate(parent_len);
+        if target.starts_with('/') {
+            out.clear();
+        }
+        let mut next = Vec::from(target.as_bytes());
+        if pos < rest.len() {
+            next.push(b'/');
+            next.extend_from_slice(&rest[pos..]);
+        }
+        rest = next;
+        pos = 0;
+    }
+    if out.is_empty() {
+        out.push('/');
+    }
+    if out.len() > PATH_MAX {
+        return Err(VfsError::NameTooLong);
+    }
+    Ok(out)
+}
+
+fn mounted_under<'a>(path: &'a [u8], mount: &str) -> Option<&'a [u8]> {
+    // Path relative to `/<mount>`, or None if it lies elsewhere.
+    let rest = path.strip_prefix(b"/")?.strip_prefix(mount.as_bytes())?;
+    match rest {
+        b"" => Some(rest),
+        _ => rest.strip_prefix(b"/"),
+    }
+}
+
+pub fn lookup(path: &[u8]) -> VfsResult<NodeType> {
+    // Resolve a canonical path: mounted filesystems first, the ramfs root otherwise.
+    if let Some(name) = mounted_under(path, "dev/pts") {
+        if name.is_empty() {
+            return Ok(NodeType::PtsDir);
+        }
+        return pty::lookup(name)
+            .map(NodeType::CharDevice)
+            .ok_or(VfsError::NotFound);
+    }
+    if let Some(name) = mounted_under(path, "dev") {
+        if name.is_empty() {
+            return Ok(NodeType::DevDir);
+        }
+        return devfs::lookup(name)
+            .map(NodeType::CharDevice)
+            .or_else(|| block::lookup(name).map(NodeType::BlockDevice))
+            .ok_or(VfsError::NotFound);
+    }
+    if let Some(rest) = mounted_under(path, "proc") {
+        return procfs::lookup(rest)
+            .map(NodeType::Proc)
+            .ok_or(VfsError::NotFound);
+    }
+    if let Some(rest) = mounted_under(path, "sys") {
+        return sysfs::lookup(rest)
+            .map(NodeType::Sys)
+            .ok_or(VfsError::NotFound);
+    }
+    ramfs::lookup_path(path).map(NodeType::Ram)
+}
+
+fn split_ram_parent(path: &str) -> VfsResult<(ramfs::Ino, &str)> {
+    // Split a canonical path into its ramfs parent directory and final name.
+    if path == "/"
+        || MOUNTS
+            .iter()
+            .any(|m| mounted_under(path.as_bytes(), m) == Some(b""))
+    {
+        return Err(VfsError::Busy);
+    }
+    if MOUNTS
+        .iter()
+        .any(|m| mounted_under(path.as_bytes(), m).is_some())
+    {
+        return Err(VfsError::ReadOnly);
+    }
+    let idx = path.rfind('/').ok_or(VfsError::InvalidArgument)?;
+    let parent = if idx == 0 { "/" } else { &path[..idx] };
+    match lookup(parent.as_bytes())? {
+        NodeType::Ram(ino) => Ok((ino, &path[idx + 1..])),
+        _ => Err(VfsError::ReadOnly),
     }
 }
 
-pub fn open_path(path: &str, flags: OpenFlags) -> Option<FileDesc> {
+pub fn open_path(path: &str, bits: u64) -> VfsResult<FileDesc> {
     // Convenience wrapper for string paths.
-    open_bytes(path.as_bytes(), flags)
-}
-
-pub fn open_bytes(path: &[u8], flags: OpenFlags) -> Option<FileDesc> {
-    // Resolve a path to a device node and create a FileDesc.
-    match lookup(path) {
-        Some(NodeType::DevFb0) => Some(FileDesc {
-            handle: FileHandle::DevFb0,
-            flags,
-        }),
-        Some(NodeType::DevKbd0) => Some(FileDesc {
-            handle: FileHandle::DevKbd0,
-            flags,
-        }),
+    open_bytes(path.as_bytes(), bits, 0)
+}
+
+pub fn open_bytes(path: &[u8], bits: u64, mode: u32) -> VfsResult<FileDesc> {
+    // Resolve (or with O_CREAT create) the target and install an open file
+    // description for it. Directories open read-only for getdents.
+    let flags = OpenFlags::from_bits(bits);
+    let create = bits & O_CREAT != 0;
+    let excl = create && bits & O_EXCL != 0;
+    // O_EXCL never follows a trailing symlink, as on Linux.
+    let path = resolve(path, !excl)?;
+    let node = match lookup(path.as_bytes()) {
+        Ok(_) if excl => return Err(VfsError::Exists),
+        Ok(node) => node,
+        Err(VfsError::NotFound) if create => {
+            let (dir, name) = split_ram_parent(&path)?;
+            NodeType::Ram(ramfs::create(dir, name, mode)?)
+        }
+        Err(err) => return Err(err),
+    };
+    let handle = match node {
+        NodeType::Ram(ino) if ramfs::kind(ino)? == ramfs::Kind::File => {
+            if bits & O_TRUNC != 0 && flags.write {
+                ramfs::truncate(ino, 0)?;
+            }
+            FileHandle::File(ino)
+        }
+        NodeType::CharDevice(dev) => return open_char(dev, flags),
+        NodeType::BlockDevice(dev) => FileHandle::Block(dev),
+        NodeType::Proc(ProcNode::File(file)) => FileHandle::Proc(file),
+        NodeType::Sys(SysNode::File(file)) => FileHandle::Sys(file),
+        _ if node.is_dir() => {
+            if flags.write {
+                return Err(VfsError::IsDir);
+            }
+            FileHandle::Dir(node)
+        }
+        _ => return Err(VfsError::NotSupported),
+    };
+    if let Some(ino) = ram_ino(handle) {
+        ramfs::open(ino)?;
+    }
+    match file::install(handle, flags) {
+        Ok(file) => Ok(FileDesc { file }),
+        Err(err) => {
+            if let Some(ino) = ram_ino(handle) {
+                ramfs::close(ino);
+            }
+            Err(err)
+        }
+    }
+}
+
+fn open_char(dev: DevNum, flags: OpenFlags) -> VfsResult<FileDesc> {
+    // The description is installed before the driver's open, so every
+    // successful open is paired with a close naming its file.
+    let file = file::install(FileHandle::Char(dev), flags)?;
+    match devfs::open(dev) {
+        Ok(dev) => {
+            file::set_handle(file, FileHandle::Char(dev));
+            Ok(FileDesc { file })
+        }
+        Err(err) => {
+            file::release(file);
+            Err(err)
+        }
+    }
+}
+
+fn ram_ino(handle: FileHandle) -> Option<ramfs::Ino> {
+    // Ramfs inodes count open descriptions so unlinked files outlive their name.
+    match handle {
+        FileHandle::File(ino) | FileHandle::Dir(NodeType::Ram(ino)) => Some(ino),
         _ => None,
     }
 }
 
-pub fn write(desc: &FileDesc, buf: &[u8]) -> usize {
-    // Write to a device handle (framebuffer or keyboard).
-    if !desc.flags.write {
-        return 0;
+fn read_handle(handle: FileHandle, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+    // Character devices are streams and ignore the offset.
+    match handle {
+        FileHandle::File(ino) => ramfs::read_at(ino, offset, buf),
+        FileHandle::Char(dev) => devfs::read(dev, buf),
+        FileHandle::Block(dev) => Ok(pagecache::read_device(dev, offset, buf)),
+        FileHandle::Proc(file) => procfs::read_at(file, offset, buf),
+        FileHandle::Sys(file) => sysfs::read_at(file, offset, buf),
+        FileHandle::Pipe(end) => pipe::read(end.id, buf),
+        FileHandle::Dir(_) => Err(VfsError::IsDir),
+    }
+}
+
+fn write_handle(handle: FileHandle, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+    match handle {
+        FileHandle::File(ino) => ramfs::write_at(ino, offset, buf),
+        FileHandle::Char(dev) => devfs::write(dev, buf),
+        FileHandle::Block(dev) => match pagecache::write_device(dev, offset, buf) {
+            // Past the end of the device, or a block that could not be read.
+            0 if !buf.is_empty() => Err(VfsError::NoSpace),
+            n => Ok(n),
+        },
+        FileHandle::Pipe(end) => pipe::write(end.id, buf),
+        FileHandle::Proc(_) | FileHandle::Sys(_) => Err(VfsError::NotSupported),
+        FileHandle::Dir(_) => Err(VfsError::IsDir),
+    }
+}
+
+fn append_handle(handle: FileHandle, buf: &[u8]) -> VfsResult<(usize, u64)> {
+    // O_APPEND: find the end and write there in one step. Returns (bytes, new end).
+    match handle {
+        FileHandle::File(ino) => ramfs::append(ino, buf),
+        _ => {
+            let end = handle_size(handle);
+            let n = write_handle(handle, end, buf)?;
+            Ok((n, end + n as u64))
+        }
+    }
+}
+
+fn handle_size(handle: FileHandle) -> u64 {
+    match handle {
+        FileHandle::File(ino) => ramfs::size(ino).unwrap_or(0),
+        FileHandle::Sys(file) => sysfs::file_len(file) as u64,
+        FileHandle::Block(dev) => block::get(dev).map_or(0, |node| node.size()),
+        FileHandle::Char(_) | FileHandle::Proc(_) | FileHandle::Pipe(_) | FileHandle::Dir(_) => 0,
+    }
+}
+
+fn check_seekable(handle: FileHandle) -> VfsResult<()> {
+    match handle {
+        FileHandle::Pipe(_) => Err(VfsError::IllegalSeek),
+        _ => Ok(()),
+    }
+}
+
+pub fn read(desc: &FileDesc, buf: &mut [u8]) -> VfsResult<usize> {
+    // Read at the shared offset of the open file description and advance it.
+    file::with_offset(desc.file, |file| {
+        if !file.flags.read {
+            return Err(VfsError::BadDescriptor);
+        }
+        let n = read_handle(file.handle, file.offset, buf)?;
+        Ok((n, file.offset + n as u64))
+    })
+}
+
+pub fn write(desc: &FileDesc, buf: &[u8]) -> VfsResult<usize> {
+    // Write at the shared offset, or at the end for O_APPEND, and advance it.
+    file::with_offset(desc.file, |file| {
+        if !file.flags.write {
+            return Err(VfsError::BadDescriptor);
+        }
+        if file.flags.append {
+            return append_handle(file.handle, buf);
+        }
+        let n = write_handle(file.handle, file.offset, buf)?;
+        Ok((n, file.offset + n as u64))
+    })
+}
+
+pub fn pread(desc: &FileDesc, buf: &mut [u8], offset: u64) -> VfsResult<usize> {
+    // Positional read; the file offset is left untouched.
+    let file = file::get(desc.file)?;
+    if !file.flags.read {
+        return Err(VfsError::BadDescriptor);
+    }
+    check_seekable(file.handle)?;
+    read_handle(file.handle, offset, buf)
+}
+
+pub fn pwrite(desc: &FileDesc, buf: &[u8], offset: u64) -> VfsResult<usize> {
+    // Positional write. As on Linux, O_APPEND still appends.
+    let file = file::get(desc.file)?;
+    if !file.flags.write {
+        return Err(VfsError::BadDescriptor);
+    }
+    check_seekable(file.handle)?;
+    if file.flags.append {
+        return append_handle(file.handle, buf).map(|(n, _)| n);
+    }
+    write_handle(file.handle, offset, buf)
+}
+
+pub fn lseek(desc: &FileDesc, offset: i64, whence: u64) -> VfsResult<u64> {
+    // Seeking past the end is allowed; the gap reads as zeroes once written.
+    file::with_offset(desc.file, |file| {
+        check_seekable(file.handle)?;
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => file.offset,
+            SEEK_END => handle_size(file.handle),
+            _ => return Err(VfsError::InvalidArgument),
+        };
+        let pos = base
+            .checked_add_signed(offset)
+            .filter(|&pos| pos <= i64::MAX as u64)
+            .ok_or(VfsError::InvalidArgument)?;
+        Ok((pos, pos))
+    })
+}
+
+pub fn status_flags(desc: &FileDesc) -> VfsResult<u64> {
+    // F_GETFL: access mode and status flags of the open file description.
+    Ok(file::get(desc.file)?.flags.bits())
+}
+
+pub fn set_status_flags(desc: &FileDesc, bits: u64) -> VfsResult<()> {
+    // F_SETFL: only O_APPEND and O_NONBLOCK can change; other bits are ignored.
+    let flags = file::get(desc.file)?.flags;
+    let bits = (flags.bits() & !O_SETFL_MASK) | (bits & O_SETFL_MASK);
+    file::set_flags(desc.file, OpenFlags::from_bits(bits));
+    Ok(())
+}
+
+pub fn poll(desc: &FileDesc, waiter: Option<ProcessId>) -> u16 {
+    // Readiness of an open file. Files that are not ready for reading or
+    // writing queue `waiter` to be woken when that changes. Regular files,
+    // directories and synthetic files are always ready.
+    let file = match file::get(desc.file) {
+        Ok(file) => file,
+        Err(_) => return POLLNVAL,
+    };
+    let ready = match file.handle {
+        FileHandle::Char(dev) => devfs::poll(dev, waiter),
+        FileHandle::Pipe(end) => pipe::poll(end, waiter),
+        _ => POLLIN | POLLOUT,
+    };
+    let mut mask = POLLERR | POLLHUP;
+    if file.flags.read {
+        mask |= POLLIN;
+    }
+    if file.flags.write {
+        mask |= POLLOUT;
+    }
+    ready & mask
+}
+
+pub fn ioctl(desc: &FileDesc, cmd: u64, arg: u64) -> VfsResult<u64> {
+    // Device-specific requests; only character devices take any.
+    match file::get(desc.file)?.handle {
+        FileHandle::Char(dev) => devfs::ioctl(dev, desc.file, cmd, arg),
+        _ => Err(VfsError::NotTty),
+    }
+}
+
+pub fn mmap(desc: &FileDesc, offset: u64, len: u64) -> VfsResult<u64> {
+    // Only character devices with memory of their own can be mapped.
+    match file::get(desc.file)?.handle {
+        FileHandle::Char(dev) => devfs::mmap(dev, offset, len),
+        _ => Err(VfsError::NoDevice),
+    }
+}
+
+pub fn is_nonblocking(desc: &FileDesc) -> bool {
+    file::get(desc.file).is_ok_and(|file| file.flags.nonblock)
+}
+
+pub fn fsync(desc: &FileDesc) -> bool {
+    // Write back the file's dirty pages in the page cache. Only block
+    // devices have any: ramfs pages are the file itself, and nothing else
+    // is cached.
+    let Ok(file) = file::get(desc.file) else {
+        return false;
+    };
+    if let FileHandle::Block(dev) = file.handle {
+        pagecache::sync_device(dev);
+    }
+    true
+}
+
+pub fn dup(desc: &FileDesc) -> FileDesc {
+    // Another FD for the same open file description (shared offset and flags).
+    file::retain(desc.file);
+    *desc
+}
+
+pub fn close(desc: &FileDesc) {
+    // Drop this FD's reference; the last one closes the underlying file.
+    match file::release(desc.file) {
+        Some(FileHandle::Pipe(end)) => pipe::close(end),
+        Some(FileHandle::Char(dev)) => devfs::close(dev, desc.file),
+        Some(handle) => {
+            if let Some(ino) = ram_ino(handle) {
+                ramfs::close(ino);
+            }
+        }
+        None => {}
+    }
+}
+
+pub fn pipe(bits: u64) -> VfsResult<(FileDesc, FileDesc)> {
+    // Open both ends of a new pipe: (read end, write end). Only O_NONBLOCK
+    // in `bits` applies to the descriptions.
+    let id = pipe::create()?;
+    let read_end = PipeEnd { id, write: false };
+    let write_end = PipeEnd { id, write: true };
+    let nonblock = bits & O_NONBLOCK;
+    let read = match file::install(
+        FileHandle::Pipe(read_end),
+        OpenFlags::from_bits(O_READ | nonblock),
+    ) {
+        Ok(file) => FileDesc { file },
+        Err(err) => {
+            pipe::close(read_end);
+            pipe::close(write_end);
+            return Err(err);
+        }
+    };
+    match file::install(
+        FileHandle::Pipe(write_end),
+        OpenFlags::from_bits(O_WRITE | nonblock),
+    ) {
+        Ok(file) => Ok((read, FileDesc { file })),
+        Err(err) => {
+            close(&read);
+            pipe::close(write_end);
+            Err(err)
+        }
+    }
+}
+
+fn node_of(handle: FileHandle) -> NodeType {
+    match handle {
+        FileHandle::File(ino) => NodeType::Ram(ino),
+        FileHandle::Char(dev) => NodeType::CharDevice(dev),
+        FileHandle::Block(dev) => NodeType::BlockDevice(dev),
+        FileHandle::Proc(file) => NodeType::Proc(ProcNode::File(file)),
+        FileHandle::Sys(file) => NodeType::Sys(SysNode::File(file)),
+        FileHandle::Pipe(end) => NodeType::Pipe(end.id),
+        FileHandle::Dir(node) => node,
     }
-    match desc.handle {
-        FileHandle::DevFb0 => {
-            let wrote = framebuffer::try_with_console(|console| {
-                for &b in buf {
-                    console.write_byte(b);
+}
+
+fn stat_node(node: NodeType) -> VfsResult<Stat> {
+    let mut st = Stat {
+        blksize: 4096,
+        ..Stat::default()
+    };
+    match node {
+        NodeType::Ram(ino) => {
+            st.dev = FS_RAMFS;
+            ramfs::stat(ino, &mut st)?;
+        }
+        NodeType::DevDir => {
+            st.dev = FS_DEVFS;
+            st.ino = 1;
+            st.mode = S_IFDIR | 0o755;
+            st.nlink = 3;
+        }
+        NodeType::PtsDir => {
+            st.dev = FS_DEVFS;
+            st.ino = pty::DIR_INO;
+            st.mode = S_IFDIR | 0o755;
+            st.nlink = 2;
+        }
+        NodeType::CharDevice(dev) => {
+            st.dev = FS_DEVFS;
+            st.ino = devfs::ino(dev);
+            st.mode = S_IFCHR | 0o666;
+            st.nlink = 1;
+            st.rdev = dev.encode();
+        }
+        NodeType::BlockDevice(id) => {
+            let node = block::get(id).ok_or(VfsError::NotFound)?;
+            st.dev = FS_DEVFS;
+            st.ino = devfs::ino(node.dev);
+            st.mode = S_IFBLK | 0o660;
+            st.nlink = 1;
+            st.rdev = node.dev.encode();
+            st.size = node.size();
+            st.blksize = node.driver.block_size() as u32;
+        }
+        NodeType::Proc(node) => {
+            st.dev = FS_PROCFS;
+            st.ino = procfs::ino(node);
+            (st.mode, st.nlink) = match node {
+                ProcNode::File(_) => (S_IFREG | 0o444, 1),
+                ProcNode::Fd(..) => {
+                    st.size = procfs::readlink(node)?.len() as u64;
+                    (S_IFLNK | 0o700, 1)
                 }
+                _ => (S_IFDIR | 0o555, 2),
+            };
+        }
+        NodeType::Sys(node) => {
+            st.dev = FS_SYSFS;
+            st.ino = sysfs::ino(node);
+            (st.mode, st.nlink) = match node {
+                SysNode::File(file) => {
+                    st.size = sysfs::file_len(file) as u64;
+                    (S_IFREG | 0o444, 1)
+                }
+                _ => (S_IFDIR | 0o755, 2),
+            };
+        }
+        NodeType::Pipe(id) => {
+            st.dev = FS_PIPEFS;
+            st.ino = pipe::ino(id);
+            st.mode = S_IFIFO | 0o600;
+            st.nlink = 1;
+        }
+    }
+    st.blocks = st.size.div_ceil(512);
+    Ok(st)
+}
+
+pub fn stat(path: &[u8], follow: bool) -> VfsResult<Stat> {
+    // stat/lstat: `follow` decides whether a trailing symlink is dereferenced.
+    let path = resolve(path, follow)?;
+    stat_node(lookup(path.as_bytes())?)
+}
+
+pub fn fstat(desc: &FileDesc) -> VfsResult<Stat> {
+    stat_node(node_of(file::get(desc.file)?.handle))
+}
+
+fn parent_ino(node: NodeType) -> VfsResult<u64> {
+    // Inode of a directory's parent, for the ".." entry.
+    match node {
+        NodeType::Ram(ino) => ramfs::parent(ino),
+        NodeType::DevDir | NodeType::Proc(ProcNode::Root) | NodeType::Sys(SysNode::Root) => {
+            Ok(ramfs::ROOT_INO)
+        }
+        NodeType::PtsDir => Ok(1),
+        NodeType::Proc(node) => Ok(procfs::ino(procfs::parent(node))),
+        NodeType::Sys(node) => Ok(sysfs::ino(sysfs::parent(node).ok_or(VfsError::NotFound)?)),
+        NodeType::CharDevice(_) | NodeType::BlockDevice(_) | NodeType::Pipe(_) => {
+            Err(VfsError::NotDir)
+        }
+    }
+}
+
+pub fn read_dir(node: NodeType) -> VfsResult<Vec<DirEntry>> {
+    // Full listing of a directory, starting with "." and "..".
+    let own = stat_node(node)?.ino;
+    let mut entries = Vec::new();
+    entries.push(DirEntry {
+        name: String::from("."),
+        ino: own,
+        kind: DT_DIR,
+    });
+    entries.push(DirEntry {
+        name: String::from(".."),
+        ino: parent_ino(node)?,
+        kind: DT_DIR,
+    });
+    match node {
+        NodeType::Ram(ino) => entries.extend(ramfs::list(ino)?),
+        NodeType::DevDir => {
+            entries.extend(devfs::list());
+            entries.extend(block::list().into_iter().map(|(_, node)| DirEntry {
+                name: String::from(node.name),
+                ino: devfs::ino(node.dev),
+                kind: DT_BLK,
+            }));
+            entries.push(DirEntry {
+                name: String::from("pts"),
+                ino: pty::DIR_INO,
+                kind: DT_DIR,
             });
-            if wrote {
-                return buf.len();
-            }
-            // Fallback to UART when framebuffer isn't available (e.g., rpi5).
-            for &b in buf {
-                if b == b'\n' {
-                    uart::write_byte(b'\r');
+        }
+        NodeType::PtsDir => entries.extend(pty::list()),
+        NodeType::Proc(node) => entries.extend(procfs::list(node)?),
+        NodeType::Sys(node) => entries.extend(sysfs::list(node)?),
+        NodeType::CharDevice(_) | NodeType::BlockDevice(_) | NodeType::Pipe(_) => {
+            return Err(VfsError::NotDir)
+        }
+    }
+    Ok(entries)
+}
+
+pub fn getdents(desc: &FileDesc, buf: &mut [u8]) -> VfsResult<usize> {
+    // Pack `linux_dirent64` records starting at the entry index kept in the
+    // file offset, and advance it past the entries returned.
+    file::with_offset(desc.file, |file| {
+        let node = match file.handle {
+            FileHandle::Dir(node) => node,
+            _ => return Err(VfsError::NotDir),
+        };
+        let entries = read_dir(node)?;
+        let mut written = 0;
+        let mut consumed = 0;
+        for (idx, entry) in entries.iter().enumerate().skip(file.offset as usize) {
+            // ino, off, reclen, type, then the NUL-terminated name padded to 8 bytes.
+            let reclen = (19 + entry.name.len() + 1 + 7) & !7;
+            if written + reclen > buf.len() {
+                if consumed == 0 {
+                    return Err(VfsError::InvalidArgument);
                 }
-                uart::write_byte(b);
+                break;
             }
-            buf.len()
+            let rec = &mut buf[written..written + reclen];
+            rec.fill(0);
+            rec[0..8].copy_from_slice(&entry.ino.to_ne_bytes());
+            rec[8..16].copy_from_slice(&((idx + 1) as u64).to_ne_bytes());
+            rec[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+            rec[18] = entry.kind;
+            rec[19..19 + entry.name.len()].copy_from_slice(entry.name.as_bytes());
+            written += reclen;
+            consumed += 1;
         }
-        FileHandle::DevKbd0 => 0,
+        Ok((written, file.offset + consumed as u64))
+    })
+}
+
+pub fn mkdir(path: &[u8], mode: u32) -> VfsResult<()> {
+    let path = resolve(path, false)?;
+    let (dir, name) = split_ram_parent(&path)?;
+    ramfs::mkdir(dir, name, mode).map(|_| ())
+}
+
+pub fn rmdir(path: &[u8]) -> VfsResult<()> {
+    let path = resolve(path, false)?;
+    let (dir, name) = split_ram_parent(&path)?;
+    if ramfs::kind(ramfs::lookup_path(path.as_bytes())?)? != ramfs::Kind::Dir {
+        return Err(VfsError::NotDir);
     }
+    ramfs::rmdir(dir, name.as_bytes())
+}
+
+pub fn unlink(path: &[u8]) -> VfsResult<()> {
+    let path = resolve(path, false)?;
+    let (dir, name) = split_ram_parent(&path)?;
+    ramfs::unlink(dir, name.as_bytes())
+}
+
+pub fn rename(old: &[u8], new: &[u8]) -> VfsResult<()> {
+    let old = resolve(old, false)?;
+    let new = resolve(new, false)?;
+    let (old_dir, old_name) = split_ram_parent(&old)?;
+    let (new_dir, new_name) = split_ram_parent(&new)?;
+    ramfs::rename(old_dir, old_name.as_bytes(), new_dir, new_name)
 }
 
-pub fn read(desc: &FileDesc, buf: &mut [u8]) -> usize {
-    // Read from a device handle (keyboard only for now).
-    if !desc.flags.read {
-        return 0;
+pub fn symlink(target: &[u8], link: &[u8]) -> VfsResult<()> {
+    // The target is stored verbatim and only interpreted when the link is followed.
+    let target = core::str::from_utf8(target).map_err(|_| VfsError::InvalidArgument)?;
+    if target.len() > PATH_MAX {
+        return Err(VfsError::NameTooLong);
     }
-    match desc.handle {
-        FileHandle::DevFb0 => 0,
-        FileHandle::DevKbd0 => keyboard::read(buf),
+    let link = resolve(link, false)?;
+    let (dir, name) = split_ram_parent(&link)?;
+    ramfs::symlink(dir, name, target).map(|_| ())
+}
+
+pub fn readlink(path: &[u8]) -> VfsResult<String> {
+    let path = resolve(path, false)?;
+    match lookup(path.as_bytes())? {
+        NodeType::Ram(ino) => ramfs::readlink(ino),
+        NodeType::Proc(node) => procfs::readlink(node),
+        _ => Err(VfsError::InvalidArgument),
     }
 }
 
-#[allow(dead_code)]
-pub fn close(_desc: &FileDesc) {}
+pub fn chdir(path: &[u8]) -> VfsResult<Cwd> {
+    // Validate a new working directory and return it in canonical form.
+    let path = resolve(path, true)?;
+    if !lookup(path.as_bytes())?.is_dir() {
+        return Err(VfsError::NotDir);
+    }
+    Cwd::from_path(&path).ok_or(VfsError::NameTooLong)
+}
+
+pub fn write_handle_path(handle: FileHandle, out: &mut String) {
+    // Render the path a handle was opened from (the /proc/<pid>/fd links).
+    match node_of(handle) {
+        NodeType::Ram(ino) => ramfs::write_path(ino, out),
+        NodeType::DevDir => out.push_str("/dev"),
+        NodeType::PtsDir => out.push_str("/dev/pts"),
+        NodeType::CharDevice(dev) => match devfs::name_of(dev) {
+            Some(name) => {
+                let _ = write!(out, "/dev/{}", name);
+            }
+            None if dev.major == devfs::MAJOR_PTS => {
+                let _ = write!(out, "/dev/pts/{}", dev.minor);
+            }
+            None if dev.major == devfs::MAJOR_PTM => out.push_str("/dev/ptmx"),
+            None => {
+                let _ = write!(out, "char:{}:{}", dev.major, dev.minor);
+            }
+        },
+        NodeType::BlockDevice(id) => match block::get(id) {
+            Some(node) => {
+                let _ = write!(out, "/dev/{}", node.name);
+            }
+            None => {
+                let _ = write!(out, "block:{}", id);
+            }
+        },
+        NodeType::Proc(node) => procfs::write_path(node, out),
+        NodeType::Sys(node) => sysfs::write_path(node, out),
+        NodeType::Pipe(id) => {
+            let _ = write!(out, "pipe:[{}]", pipe::ino(id));
+        }
+    }
+}
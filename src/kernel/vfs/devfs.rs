@@ -0,0 +1,222 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::kernel::process::ProcessId;
+use crate::kernel::vfs::file::FileId;
+use crate::kernel::vfs::{DirEntry, VfsError, VfsResult, DT_CHR, POLLERR, POLLIN, POLLOUT};
+use crate::util::sync::SpinLock;
+
+pub const MAX_DEV_NODES: usize = 32;
+pub const MAX_DEV_MAJORS: usize = 8;
+
+// Well-known major numbers (Linux-compatible where one exists).
+pub const MAJOR_MEM: u32 = 1;
+pub const MAJOR_TTY: u32 = 4;
+pub const MAJOR_TTYAUX: u32 = 5;
+pub const MAJOR_INPUT: u32 = 13;
+pub const MAJOR_FB: u32 = 29;
+pub const MAJOR_PTM: u32 = 128;
+pub const MAJOR_PTS: u32 = 136;
+pub const MAJOR_AMA: u32 = 204;
+// Local/experimental range: the display server's channels.
+pub const MAJOR_DISPLAY: u32 = 240;
+pub const MAJOR_DISPLAY_CLIENT: u32 = 241;
+// Local/experimental range: /dev/kmsg readers, one per open.
+pub const MAJOR_KMSG: u32 = 242;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DevNum {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DevNum {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    pub const fn encode(self) -> u64 {
+        // glibc `makedev` encoding, as reported in `Stat::rdev`.
+        let major = self.major as u64;
+        let minor = self.minor as u64;
+        ((major & 0xffff_f000) << 32)
+            | ((major & 0xfff) << 8)
+            | ((minor & 0xffff_ff00) << 12)
+            | (minor & 0xff)
+    }
+}
+
+pub trait CharDevice: Sync {
+    fn read(&self, minor: u32, buf: &mut [u8]) -> VfsResult<usize>;
+    fn write(&self, minor: u32, buf: &[u8]) -> VfsResult<usize>;
+
+    // Ready events; a device that is not ready queues `waiter` for a wakeup.
+    fn poll(&self, _minor: u32, _waiter: Option<ProcessId>) -> u16 {
+        POLLIN | POLLOUT
+    }
+
+    // Called on open. A cloning device such as /dev/ptmx returns the device
+    // the new file refers to instead.
+    fn open(&self, _minor: u32) -> VfsResult<Option<DevNum>> {
+        Ok(None)
+    }
+
+    // Called when the last reference to the open file `file` goes away.
+    fn close(&self, _minor: u32, _file: FileId) {}
+
+    // Device-specific requests made through `file`; `arg` is often a
+    // pointer into the caller.
+    fn ioctl(&self, _minor: u32, _file: FileId, _cmd: u64, _arg: u64) -> VfsResult<u64> {
+        Err(VfsError::NotTty)
+    }
+
+    // Map `len` bytes of device memory from `offset`; returns their address.
+    fn mmap(&self, _minor: u32, _offset: u64, _len: u64) -> VfsResult<u64> {
+        Err(VfsError::NoDevice)
+    }
+}
+
+#[derive(Copy, Clone)]
+struct DevNode {
+    name: &'static str,
+    dev: DevNum,
+    driver: &'static dyn CharDevice,
+}
+
+// A driver serving every minor of a major, for devices without a /dev node
+// of their own (pty masters, and slaves under /dev/pts).
+#[derive(Copy, Clone)]
+struct DevMajor {
+    major: u32,
+    driver: &'static dyn CharDevice,
+}
+
+static NODES: SpinLock<[Option<DevNode>; MAX_DEV_NODES]> = SpinLock::new([None; MAX_DEV_NODES]);
+static MAJORS: SpinLock<[Option<DevMajor>; MAX_DEV_MAJORS]> = SpinLock::new([None; MAX_DEV_MAJORS]);
+
+pub fn init() {
+    let mut nodes = NODES.lock();
+    *nodes = [None; MAX_DEV_NODES];
+    *MAJORS.lock() = [None; MAX_DEV_MAJORS];
+}
+
+pub fn register_char(name: &'static str, dev: DevNum, driver: &'static dyn CharDevice) -> bool {
+    // Publish a character device as /dev/<name>; names and numbers must be unique.
+    let mut nodes = NODES.lock();
+    if nodes
+        .iter()
+        .flatten()
+        .any(|node| node.name == name || node.dev == dev)
+    {
+        return false;
+    }
+    match nodes.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(DevNode { name, dev, driver });
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn register_major(major: u32, driver: &'static dyn CharDevice) -> bool {
+    // Serve every minor of `major` that has no node of its own.
+    let mut majors = MAJORS.lock();
+    if majors.iter().flatten().any(|entry| entry.major == major) {
+        return false;
+    }
+    match majors.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(DevMajor { major, driver });
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn lookup(name: &[u8]) -> Option<DevNum> {
+    let nodes = NODES.lock();
+    nodes
+        .iter()
+        .flatten()
+        .find(|node| node.name.as_bytes() == name)
+        .map(|node| node.dev)
+}
+
+pub fn name_of(dev: DevNum) -> Option<&'static str> {
+    let nodes = NODES.lock();
+    nodes
+        .iter()
+        .flatten()
+        .find(|node| node.dev == dev)
+        .map(|node| node.name)
+}
+
+pub fn ino(dev: DevNum) -> u64 {
+    // Inode 1 is the /dev directory itself; device nodes are keyed by number.
+    ((dev.major as u64) << 32) | dev.minor as u64
+}
+
+pub fn list() -> Vec<DirEntry> {
+    let nodes = *NODES.lock();
+    nodes
+        .iter()
+        .flatten()
+        .map(|node| DirEntry {
+            name: String::from(node.name),
+            ino: ino(node.dev),
+            kind: DT_CHR,
+        })
+        .collect()
+}
+
+fn driver(dev: DevNum) -> VfsResult<&'static dyn CharDevice> {
+    // A node's own driver wins over one registered for its whole major.
+    let node = NODES
+        .lock()
+        .iter()
+        .flatten()
+        .find(|node| node.dev == dev)
+        .map(|node| node.driver);
+    node.or_else(|| {
+        MAJORS
+            .lock()
+            .iter()
+            .flatten()
+            .find(|entry| entry.major == dev.major)
+            .map(|entry| entry.driver)
+    })
+    .ok_or(VfsError::NoDevice)
+}
+
+pub fn open(dev: DevNum) -> VfsResult<DevNum> {
+    // The device an open of `dev` ends up on.
+    Ok(driver(dev)?.open(dev.minor)?.unwrap_or(dev))
+}
+
+pub fn close(dev: DevNum, file: FileId) {
+    if let Ok(driver) = driver(dev) {
+        driver.close(dev.minor, file);
+    }
+}
+
+pub fn read(dev: DevNum, buf: &mut [u8]) -> VfsResult<usize> {
+    // Look the driver up without holding the table lock across the call.
+    driver(dev)?.read(dev.minor, buf)
+}
+
+pub fn write(dev: DevNum, buf: &[u8]) -> VfsResult<usize> {
+    driver(dev)?.write(dev.minor, buf)
+}
+
+pub fn poll(dev: DevNum, waiter: Option<ProcessId>) -> u16 {
+    driver(dev).map_or(POLLERR, |driver| driver.poll(dev.minor, waiter))
+}
+
+pub fn ioctl(dev: DevNum, file: FileId, cmd: u64, arg: u64) -> VfsResult<u64> {
+    driver(dev)?.ioctl(dev.minor, file, cmd, arg)
+}
+
+pub fn mmap(dev: DevNum, offset: u64, len: u64) -> VfsResult<u64> {
+    driver(dev)?.mmap(dev.minor, offset, len)
+}
@@ -0,0 +1,105 @@
+use crate::kernel::vfs::{FileHandle, OpenFlags, VfsError, VfsResult};
+use crate::util::sync::SpinLock;
+
+pub const MAX_OPEN_FILES: usize = 256;
+
+// Index into the system-wide open file table. FDs that were duplicated or
+// inherited hold the same ID and therefore share one offset.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FileId(u32);
+
+#[derive(Copy, Clone, Debug)]
+pub struct OpenFile {
+    pub handle: FileHandle,
+    pub flags: OpenFlags,
+    pub offset: u64,
+    refs: u32,
+    // Set while a call owns the offset (see `with_offset`).
+    busy: bool,
+}
+
+static FILES: SpinLock<[Option<OpenFile>; MAX_OPEN_FILES]> = SpinLock::new([None; MAX_OPEN_FILES]);
+
+pub fn install(handle: FileHandle, flags: OpenFlags) -> VfsResult<FileId> {
+    // Create an open file description holding one reference.
+    let mut files = FILES.lock();
+    let idx = files
+        .iter()
+        .position(|slot| slot.is_none())
+        .ok_or(VfsError::FileTableFull)?;
+    files[idx] = Some(OpenFile {
+        handle,
+        flags,
+        offset: 0,
+        refs: 1,
+        busy: false,
+    });
+    Ok(FileId(idx as u32))
+}
+
+pub fn get(id: FileId) -> VfsResult<OpenFile> {
+    let files = FILES.lock();
+    files[id.0 as usize].ok_or(VfsError::BadDescriptor)
+}
+
+pub fn retain(id: FileId) {
+    if let Some(file) = FILES.lock()[id.0 as usize].as_mut() {
+        file.refs += 1;
+    }
+}
+
+pub fn release(id: FileId) -> Option<FileHandle> {
+    // Drop one reference; returns the handle when the description goes away.
+    let mut files = FILES.lock();
+    let slot = &mut files[id.0 as usize];
+    let file = slot.as_mut()?;
+    file.refs -= 1;
+    if file.refs > 0 {
+        return None;
+    }
+    slot.take().map(|file| file.handle)
+}
+
+pub fn with_offset<T>(
+    id: FileId,
+    f: impl FnOnce(&OpenFile) -> VfsResult<(T, u64)>,
+) -> VfsResult<T> {
+    // Run a read/modify/write of the shared offset: `f` gets the description
+    // and returns its result with the new offset, which is kept only on
+    // success. Callers on other CPUs sharing the description spin until it
+    // is done; a syscall on this CPU runs to completion first, and one that
+    // would block returns WouldBlock, so the wait is short.
+    let file = loop {
+        let mut files = FILES.lock();
+        let file = files[id.0 as usize]
+            .as_mut()
+            .ok_or(VfsError::BadDescriptor)?;
+        if !file.busy {
+            file.busy = true;
+            break *file;
+        }
+        drop(files);
+        core::hint::spin_loop();
+    };
+    let result = f(&file);
+    if let Some(slot) = FILES.lock()[id.0 as usize].as_mut() {
+        if let Ok((_, offset)) = result {
+            slot.offset = offset;
+        }
+        slot.busy = false;
+    }
+    result.map(|(value, _)| value)
+}
+
+pub fn set_flags(id: FileId, flags: OpenFlags) {
+    if let Some(file) = FILES.lock()[id.0 as usize].as_mut() {
+        file.flags = flags;
+    }
+}
+
+pub fn set_handle(id: FileId, handle: FileHandle) {
+    // Point a fresh description at the device its open ended up on.
+    if let Some(file) = FILES.lock()[id.0 as usize].as_mut() {
+        file.handle = handle;
+    }
+}
@@ -0,0 +1,169 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::kernel::process::{self, ProcessId, WaitQueue};
+use crate::kernel::vfs::{VfsError, VfsResult, POLLERR, POLLHUP, POLLIN, POLLOUT};
+use crate::util::sync::SpinLock;
+
+pub const MAX_PIPES: usize = 32;
+pub const PIPE_SIZE: usize = 4096;
+// Writes of at most this many bytes are never interleaved with other writers.
+pub const PIPE_BUF: usize = 512;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PipeEnd {
+    pub id: u32,
+    pub write: bool,
+}
+
+struct Pipe {
+    buf: Box<[u8]>,
+    head: usize,
+    len: usize,
+    // Open file descriptions per end, not FDs: dup'd FDs share one description.
+    readers: u32,
+    writers: u32,
+    ino: u64,
+    read_wait: WaitQueue,
+    write_wait: WaitQueue,
+}
+
+static PIPES: SpinLock<[Option<Pipe>; MAX_PIPES]> = SpinLock::new([const { None }; MAX_PIPES]);
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+pub fn create() -> VfsResult<u32> {
+    // A new pipe starts with one reader and one writer.
+    let buf = vec![0u8; PIPE_SIZE].into_boxed_slice();
+    let mut pipes = PIPES.lock();
+    let idx = pipes
+        .iter()
+        .position(|slot| slot.is_none())
+        .ok_or(VfsError::FileTableFull)?;
+    pipes[idx] = Some(Pipe {
+        buf,
+        head: 0,
+        len: 0,
+        readers: 1,
+        writers: 1,
+        ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+        read_wait: WaitQueue::new(),
+        write_wait: WaitQueue::new(),
+    });
+    Ok(idx as u32)
+}
+
+pub fn read(id: u32, buf: &mut [u8]) -> VfsResult<usize> {
+    // Empty with writers left: WouldBlock, with the caller queued for a wakeup.
+    // Empty without writers: end of file.
+    let pid = process::current_pid();
+    let mut pipes = PIPES.lock();
+    let pipe = pipes[id as usize].as_mut().ok_or(VfsError::BadDescriptor)?;
+    if pipe.len == 0 {
+        if pipe.writers == 0 {
+            return Ok(0);
+        }
+        if let Some(pid) = pid {
+            pipe.read_wait.add(pid);
+        }
+        return Err(VfsError::WouldBlock);
+    }
+    let n = buf.len().min(pipe.len);
+    for (i, byte) in buf[..n].iter_mut().enumerate() {
+        *byte = pipe.buf[(pipe.head + i) % PIPE_SIZE];
+    }
+    pipe.head = (pipe.head + n) % PIPE_SIZE;
+    pipe.len -= n;
+    let waiters = pipe.write_wait.take();
+    drop(pipes);
+    waiters.wake_all();
+    Ok(n)
+}
+
+pub fn write(id: u32, buf: &[u8]) -> VfsResult<usize> {
+    // Writes up to PIPE_BUF wait for room for all of their bytes; larger ones
+    // take what fits and return a short count.
+    let pid = process::current_pid();
+    let mut pipes = PIPES.lock();
+    let pipe = pipes[id as usize].as_mut().ok_or(VfsError::BadDescriptor)?;
+    if pipe.readers == 0 {
+        return Err(VfsError::BrokenPipe);
+    }
+    let free = PIPE_SIZE - pipe.len;
+    if free == 0 || (buf.len() <= PIPE_BUF && free < buf.len()) {
+        if let Some(pid) = pid {
+            pipe.write_wait.add(pid);
+        }
+        return Err(VfsError::WouldBlock);
+    }
+    let n = buf.len().min(free);
+    let tail = pipe.head + pipe.len;
+    for (i, &byte) in buf[..n].iter().enumerate() {
+        pipe.buf[(tail + i) % PIPE_SIZE] = byte;
+    }
+    pipe.len += n;
+    let waiters = pipe.read_wait.take();
+    drop(pipes);
+    waiters.wake_all();
+    Ok(n)
+}
+
+pub fn close(end: PipeEnd) {
+    // Closing the last writer wakes readers to see EOF; closing the last
+    // reader wakes writers to see EPIPE. The buffer goes with the last end.
+    let mut pipes = PIPES.lock();
+    let slot = &mut pipes[end.id as usize];
+    let Some(pipe) = slot.as_mut() else {
+        return;
+    };
+    let waiters = if end.write {
+        pipe.writers -= 1;
+        pipe.read_wait.take()
+    } else {
+        pipe.readers -= 1;
+        pipe.write_wait.take()
+    };
+    if pipe.readers == 0 && pipe.writers == 0 {
+        *slot = None;
+    }
+    drop(pipes);
+    waiters.wake_all();
+}
+
+pub fn poll(end: PipeEnd, waiter: Option<ProcessId>) -> u16 {
+    // The read end is readable with data buffered and hangs up once every
+    // writer is gone. The write end is writable with room for an atomic
+    // PIPE_BUF write and reports an error once every reader is gone.
+    let mut pipes = PIPES.lock();
+    let Some(pipe) = pipes[end.id as usize].as_mut() else {
+        return POLLERR;
+    };
+    let mut ready = 0;
+    if end.write {
+        if pipe.readers == 0 {
+            ready |= POLLERR;
+        } else if PIPE_SIZE - pipe.len >= PIPE_BUF {
+            ready |= POLLOUT;
+        } else if let Some(pid) = waiter {
+            pipe.write_wait.add(pid);
+        }
+    } else {
+        if pipe.len > 0 {
+            ready |= POLLIN;
+        }
+        if pipe.writers == 0 {
+            ready |= POLLHUP;
+        } else if pipe.len == 0 {
+            if let Some(pid) = waiter {
+                pipe.read_wait.add(pid);
+            }
+        }
+    }
+    ready
+}
+
+pub fn ino(id: u32) -> u64 {
+    PIPES.lock()[id as usize]
+        .as_ref()
+        .map_or(0, |pipe| pipe.ino)
+}
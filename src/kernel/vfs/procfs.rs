@@ -0,0 +1,357 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::arch::aarch64::timer;
+use crate::kernel::interrupts::{self, IrqSource, IRQ_SOURCES};
+use crate::kernel::process::{self, ProcessId, ProcessMode, ProcessState, CPU_NONE};
+use crate::kernel::vfs::{self, DirEntry, VfsError, VfsResult, DT_DIR, DT_LNK, DT_REG};
+use crate::kernel::{params, smp};
+use crate::mm::layout::PAGE_SIZE;
+use crate::mm::{frame, heap, pagecache};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProcFile {
+    MemInfo,
+    Interrupts,
+    CpuInfo,
+    Uptime,
+    Cmdline,
+    Status(ProcessId),
+}
+
+// Per-process inodes live above this, four per PID.
+const PID_INO_BASE: u64 = 0x1_0000;
+// Entries of /proc/<pid>/fd live above this, 2^16 per PID (MAX_FDS is below).
+const FD_INO_BASE: u64 = 0x1_0000_0000;
+
+// Top-level files in listing order.
+const GLOBAL_FILES: [(&str, ProcFile); 5] = [
+    ("meminfo", ProcFile::MemInfo),
+    ("interrupts", ProcFile::Interrupts),
+    ("cpuinfo", ProcFile::CpuInfo),
+    ("uptime", ProcFile::Uptime),
+    ("cmdline", ProcFile::Cmdline),
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProcNode {
+    Root,
+    PidDir(ProcessId),
+    FdDir(ProcessId),
+    // /proc/<pid>/fd/<n>: a symlink to the path the descriptor was opened from.
+    Fd(ProcessId, usize),
+    File(ProcFile),
+}
+
+pub fn lookup(path: &[u8]) -> Option<ProcNode> {
+    // `path` is relative to /proc and has no leading slash.
+    let path = path.strip_suffix(b"/").unwrap_or(path);
+    if path.is_empty() {
+        return Some(ProcNode::Root);
+    }
+    let (first, rest) = match path.iter().position(|&b| b == b'/') {
+        Some(idx) => (&path[..idx], Some(&path[idx + 1..])),
+        None => (path, None),
+    };
+    match (first, rest) {
+        (b"meminfo", None) => Some(ProcNode::File(ProcFile::MemInfo)),
+        (b"interrupts", None) => Some(ProcNode::File(ProcFile::Interrupts)),
+        (b"cpuinfo", None) => Some(ProcNode::File(ProcFile::CpuInfo)),
+        (b"uptime", None) => Some(ProcNode::File(ProcFile::Uptime)),
+        (b"cmdline", None) => Some(ProcNode::File(ProcFile::Cmdline)),
+        _ => {
+            let pid = parse_pid(first)?;
+            process::get(pid)?;
+            match rest {
+                Some(b"status") => Some(ProcNode::File(ProcFile::Status(pid))),
+                Some(b"fd") => Some(ProcNode::FdDir(pid)),
+                Some(rest) => {
+                    let fd = parse_fd(rest.strip_prefix(b"fd/")?)?;
+                    process::get(pid)?.fds.get(fd)?;
+                    Some(ProcNode::Fd(pid, fd))
+                }
+                None => Some(ProcNode::PidDir(pid)),
+            }
+        }
+    }
+}
+
+pub fn read_at(file: ProcFile, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+    // Contents are regenerated from live kernel state on every read.
+    let mut text = String::new();
+    generate(file, &mut text)?;
+    let bytes = text.as_bytes();
+    let start = (offset as usize).min(bytes.len());
+    let n = buf.len().min(bytes.len() - start);
+    buf[..n].copy_from_slice(&bytes[start..start + n]);
+    Ok(n)
+}
+
+pub fn ino(node: ProcNode) -> u64 {
+    match node {
+        ProcNode::Root => 1,
+        ProcNode::File(ProcFile::MemInfo) => 2,
+        ProcNode::File(ProcFile::Interrupts) => 3,
+        ProcNode::File(ProcFile::CpuInfo) => 4,
+        ProcNode::File(ProcFile::Uptime) => 5,
+        ProcNode::File(ProcFile::Cmdline) => 6,
+        ProcNode::PidDir(pid) => PID_INO_BASE + pid.0 as u64 * 4,
+        ProcNode::File(ProcFile::Status(pid)) => PID_INO_BASE + pid.0 as u64 * 4 + 1,
+        ProcNode::FdDir(pid) => PID_INO_BASE + pid.0 as u64 * 4 + 2,
+        ProcNode::Fd(pid, fd) => FD_INO_BASE + ((pid.0 as u64) << 16) + fd as u64,
+    }
+}
+
+pub fn parent(node: ProcNode) -> ProcNode {
+    match node {
+        ProcNode::File(ProcFile::Status(pid)) | ProcNode::FdDir(pid) => ProcNode::PidDir(pid),
+        ProcNode::Fd(pid, _) => ProcNode::FdDir(pid),
+        _ => ProcNode::Root,
+    }
+}
+
+pub fn readlink(node: ProcNode) -> VfsResult<String> {
+    // Target of a /proc/<pid>/fd entry, rendered like Linux does.
+    let (pid, fd) = match node {
+        ProcNode::Fd(pid, fd) => (pid, fd),
+        _ => return Err(VfsError::InvalidArgument),
+    };
+    let proc = process::get(pid).ok_or(VfsError::NotFound)?;
+    let desc = proc.fds.get(fd).ok_or(VfsError::NotFound)?;
+    let file = vfs::file::get(desc.file)?;
+    let mut out = String::new();
+    vfs::write_handle_path(file.handle, &mut out);
+    Ok(out)
+}
+
+pub fn list(node: ProcNode) -> VfsResult<Vec<DirEntry>> {
+    // Directory contents, without "." and "..".
+    let mut out = Vec::new();
+    let mut push = |name: String, node: ProcNode, kind: u8| {
+        out.push(DirEntry {
+            name,
+            ino: ino(node),
+            kind,
+        })
+    };
+    match node {
+        ProcNode::Root => {
+            for (name, file) in GLOBAL_FILES {
+                push(String::from(name), ProcNode::File(file), DT_REG);
+            }
+            if let Some(pid) = process::current_pid() {
+                push(String::from("self"), ProcNode::PidDir(pid), DT_DIR);
+            }
+            let mut pids = Vec::new();
+            process::for_each(|proc| pids.push(proc.id));
+            for pid in pids {
+                let mut name = String::new();
+                let _ = write!(name, "{}", pid.0);
+                push(name, ProcNode::PidDir(pid), DT_DIR);
+            }
+        }
+        ProcNode::PidDir(pid) => {
+            push(
+                String::from("status"),
+                ProcNode::File(ProcFile::Status(pid)),
+                DT_REG,
+            );
+            push(String::from("fd"), ProcNode::FdDir(pid), DT_DIR);
+        }
+        ProcNode::FdDir(pid) => {
+            let proc = process::get(pid).ok_or(VfsError::NotFound)?;
+            for (fd, _) in proc.fds.iter() {
+                let mut name = String::new();
+                let _ = write!(name, "{}", fd);
+                push(name, ProcNode::Fd(pid, fd), DT_LNK);
+            }
+        }
+        ProcNode::Fd(..) | ProcNode::File(_) => return Err(VfsError::NotDir),
+    }
+    Ok(out)
+}
+
+pub fn write_path(node: ProcNode, out: &mut String) {
+    let file = match node {
+        ProcNode::Root => return out.push_str("/proc"),
+        ProcNode::PidDir(pid) => {
+            let _ = write!(out, "/proc/{}", pid.0);
+            return;
+        }
+        ProcNode::FdDir(pid) => {
+            let _ = write!(out, "/proc/{}/fd", pid.0);
+            return;
+        }
+        ProcNode::Fd(pid, fd) => {
+            let _ = write!(out, "/proc/{}/fd/{}", pid.0, fd);
+            return;
+        }
+        ProcNode::File(file) => file,
+    };
+    let _ = match file {
+        ProcFile::MemInfo => write!(out, "/proc/meminfo"),
+        ProcFile::Interrupts => write!(out, "/proc/interrupts"),
+        ProcFile::CpuInfo => write!(out, "/proc/cpuinfo"),
+        ProcFile::Uptime => write!(out, "/proc/uptime"),
+        ProcFile::Cmdline => write!(out, "/proc/cmdline"),
+        ProcFile::Status(pid) => write!(out, "/proc/{}/status", pid.0),
+    };
+}
+
+fn parse_pid(name: &[u8]) -> Option<ProcessId> {
+    if name == b"self" {
+        return process::current_pid();
+    }
+    parse_decimal(name).map(ProcessId)
+}
+
+fn parse_fd(name: &[u8]) -> Option<usize> {
+    parse_decimal(name).map(|fd| fd as usize)
+}
+
+fn parse_decimal(name: &[u8]) -> Option<u32> {
+    if name.is_empty() {
+        return None;
+    }
+    let mut value = 0u32;
+    for &b in name {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        value = value.checked_mul(10)?.checked_add((b - b'0') as u32)?;
+    }
+    Some(value)
+}
+
+fn generate(file: ProcFile, out: &mut String) -> VfsResult<()> {
+    match file {
+        ProcFile::MemInfo => gen_meminfo(out),
+        ProcFile::Interrupts => gen_interrupts(out),
+        ProcFile::CpuInfo => gen_cpuinfo(out),
+        ProcFile::Uptime => gen_uptime(out),
+        ProcFile::Cmdline => gen_cmdline(out),
+        ProcFile::Status(pid) => return gen_status(pid, out),
+    }
+    Ok(())
+}
+
+fn gen_meminfo(out: &mut String) {
+    let page_kb = (PAGE_SIZE / 1024) as u64;
+    let total = frame::total_frames() as u64 * page_kb;
+    let free = frame::free_frames() as u64 * page_kb;
+    let (heap_size, heap_used) = heap::stats();
+    let cache = pagecache::stats();
+    let _ = writeln!(out, "MemTotal:       {:>10} kB", total);
+    let _ = writeln!(out, "MemFree:        {:>10} kB", free);
+    let _ = writeln!(
+        out,
+        "Cached:         {:>10} kB",
+        cache.pages as u64 * page_kb
+    );
+    let _ = writeln!(
+        out,
+        "Dirty:          {:>10} kB",
+        cache.dirty as u64 * page_kb
+    );
+    let _ = writeln!(
+        out,
+        "Unevictable:    {:>10} kB",
+        cache.pinned as u64 * page_kb
+    );
+    let _ = writeln!(out, "HeapTotal:      {:>10} kB", heap_size / 1024);
+    let _ = writeln!(out, "HeapUsed:       {:>10} kB", heap_used / 1024);
+    let _ = writeln!(
+        out,
+        "HeapFree:       {:>10} kB",
+        (heap_size - heap_used) / 1024
+    );
+    let _ = writeln!(out, "CacheHits:      {:>10}", cache.hits);
+    let _ = writeln!(out, "CacheMisses:    {:>10}", cache.misses);
+    let _ = writeln!(out, "CacheEvictions: {:>10}", cache.evictions);
+    let _ = writeln!(out, "CacheWritebacks:{:>10}", cache.writebacks);
+}
+
+fn gen_interrupts(out: &mut String) {
+    let _ = write!(out, "     ");
+    for cpu in 0..smp::MAX_CPUS {
+        let _ = write!(out, "       CPU{}", cpu);
+    }
+    let _ = writeln!(out);
+    for source in IRQ_SOURCES {
+        let (label, name) = match source {
+            IrqSource::Timer => ("TMR", "arch_timer"),
+            IrqSource::Other => ("OTH", "unhandled"),
+            IrqSource::Spurious => ("ERR", "spurious"),
+        };
+        let _ = write!(out, "{:>4}:", label);
+        for cpu in 0..smp::MAX_CPUS {
+            let _ = write!(out, " {:>10}", interrupts::irq_count(cpu, source));
+        }
+        let _ = writeln!(out, "   {}", name);
+    }
+}
+
+fn gen_cpuinfo(out: &mut String) {
+    for cpu in 0..smp::MAX_CPUS {
+        let info = smp::cpu_info(cpu);
+        if !info.online {
+            continue;
+        }
+        let midr = info.midr;
+        let _ = writeln!(out, "processor\t: {}", cpu);
+        let _ = writeln!(out, "CPU implementer\t: {:#04x}", (midr >> 24) & 0xff);
+        let _ = writeln!(out, "CPU architecture: 8");
+        let _ = writeln!(out, "CPU variant\t: {:#x}", (midr >> 20) & 0xf);
+        let _ = writeln!(out, "CPU part\t: {:#05x}", (midr >> 4) & 0xfff);
+        let _ = writeln!(out, "CPU revision\t: {}", midr & 0xf);
+        let _ = writeln!(out, "MIDR\t\t: {:#010x}", midr);
+        let _ = writeln!(out, "MPIDR\t\t: {:#010x}", info.mpidr);
+        let _ = writeln!(out);
+    }
+}
+
+fn gen_uptime(out: &mut String) {
+    // Second field is idle time summed over all CPUs, as on Linux.
+    let up_cs = timer::uptime_ms() / 10;
+    let idle_ticks: usize = (0..smp::MAX_CPUS).map(process::idle_ticks).sum();
+    let idle_cs = idle_ticks as u64 * timer::tick_ms() / 10;
+    let _ = writeln!(
+        out,
+        "{}.{:02} {}.{:02}",
+        up_cs / 100,
+        up_cs % 100,
+        idle_cs / 100,
+        idle_cs % 100
+    );
+}
+
+fn gen_cmdline(out: &mut String) {
+    out.push_str(core::str::from_utf8(params::cmdline()).unwrap_or(""));
+    out.push('\n');
+}
+
+fn gen_status(pid: ProcessId, out: &mut String) -> VfsResult<()> {
+    let proc = process::get(pid).ok_or(VfsError::NotFound)?;
+    let state = match proc.state {
+        ProcessState::Ready => "R (ready)",
+        ProcessState::Running => "R (running)",
+        ProcessState::Blocked => "S (blocked)",
+        ProcessState::Terminated => "Z (terminated)",
+    };
+    let mode = match proc.mode {
+        ProcessMode::Kernel => "kernel",
+        ProcessMode::User => "user",
+    };
+    let _ = writeln!(out, "Name:\t{}", proc.name);
+    let _ = writeln!(out, "State:\t{}", state);
+    let _ = writeln!(out, "Pid:\t{}", proc.id.0);
+    let _ = writeln!(out, "PPid:\t{}", proc.parent.map(|p| p.0).unwrap_or(0));
+    if proc.running_on == CPU_NONE {
+        let _ = writeln!(out, "Cpu:\t-");
+    } else {
+        let _ = writeln!(out, "Cpu:\t{}", proc.running_on);
+    }
+    let _ = writeln!(out, "Mode:\t{}", mode);
+    Ok(())
+}
@@ -0,0 +1,535 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::arch::aarch64::timer;
+use crate::kernel::vfs::{
+    DirEntry, Stat, VfsError, VfsResult, DT_DIR, DT_LNK, DT_REG, NAME_MAX, S_IFDIR, S_IFLNK,
+    S_IFREG,
+};
+use crate::mm::pagecache::{self, CacheBacking};
+use crate::util::sync::SpinLock;
+
+pub type Ino = u64;
+
+pub const ROOT_INO: Ino = 1;
+// File data lives in pinned page cache frames, so cap any single file well
+// below the memory available.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Dir,
+    File,
+    Symlink,
+}
+
+enum Data {
+    Dir(Vec<(String, Ino)>),
+    // Just the size: the data is in the page cache.
+    File(u64),
+    Symlink(String),
+}
+
+struct Inode {
+    data: Data,
+    // There are no hard links, so every linked inode has exactly one parent.
+    parent: Ino,
+    linked: bool,
+    // Open file descriptions; an unlinked inode lives on until the last one closes.
+    opens: u32,
+    mode: u32,
+    atime_ms: u64,
+    mtime_ms: u64,
+    ctime_ms: u64,
+}
+
+impl Inode {
+    fn new(data: Data, parent: Ino, mode: u32) -> Self {
+        let now = timer::uptime_ms();
+        Self {
+            data,
+            parent,
+            linked: true,
+            opens: 0,
+            mode: mode & 0o7777,
+            atime_ms: now,
+            mtime_ms: now,
+            ctime_ms: now,
+        }
+    }
+
+    fn kind(&self) -> Kind {
+        match self.data {
+            Data::Dir(_) => Kind::Dir,
+            Data::File(_) => Kind::File,
+            Data::Symlink(_) => Kind::Symlink,
+        }
+    }
+}
+
+struct RamFs {
+    // Inode numbers are never reused, so a stale number simply stops resolving.
+    inodes: BTreeMap<Ino, Inode>,
+    next_ino: Ino,
+}
+
+static FS: SpinLock<RamFs> = SpinLock::new(RamFs {
+    inodes: BTreeMap::new(),
+    next_ino: ROOT_INO + 1,
+});
+
+// File data exists only in the page cache, in pinned pages, as in Linux's
+// ramfs. A page the file never wrote is a hole and fills with zeros; bytes
+// past the end of the file are kept zero (see `truncate`), so growing a file
+// needs no clearing. The cache never takes FS, so FS may be held while
+// calling into it.
+struct RamBacking;
+
+static BACKING: RamBacking = RamBacking;
+
+impl CacheBacking for RamBacking {
+    fn fill(&self, _ino: u64, _offset: u64, page: &mut [u8]) -> bool {
+        page.fill(0);
+        true
+    }
+
+    fn write_back(&self, _ino: u64, _offset: u64, _page: &[u8]) -> bool {
+        // Pinned pages are never dirty; there is nowhere to write them.
+        true
+    }
+
+    fn pinned(&self) -> bool {
+        true
+    }
+}
+
+impl RamFs {
+    fn get(&self, ino: Ino) -> VfsResult<&Inode> {
+        self.inodes.get(&ino).ok_or(VfsError::NotFound)
+    }
+
+    fn get_mut(&mut self, ino: Ino) -> VfsResult<&mut Inode> {
+        self.inodes.get_mut(&ino).ok_or(VfsError::NotFound)
+    }
+
+    fn entries(&self, dir: Ino) -> VfsResult<&Vec<(String, Ino)>> {
+        match &self.get(dir)?.data {
+            Data::Dir(entries) => Ok(entries),
+            _ => Err(VfsError::NotDir),
+        }
+    }
+
+    fn entries_mut(&mut self, dir: Ino) -> VfsResult<&mut Vec<(String, Ino)>> {
+        match &mut self.get_mut(dir)?.data {
+            Data::Dir(entries) => Ok(entries),
+            _ => Err(VfsError::NotDir),
+        }
+    }
+
+    fn child(&self, dir: Ino, name: &[u8]) -> VfsResult<Ino> {
+        self.entries(dir)?
+            .iter()
+            .find(|(entry, _)| entry.as_bytes() == name)
+            .map(|&(_, ino)| ino)
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn touch_dir(&mut self, dir: Ino) {
+        // Directory contents changed: bump mtime and ctime.
+        if let Ok(inode) = self.get_mut(dir) {
+            let now = timer::uptime_ms();
+            inode.mtime_ms = now;
+            inode.ctime_ms = now;
+        }
+    }
+
+    fn insert(&mut self, dir: Ino, name: &str, data: Data, mode: u32) -> VfsResult<Ino> {
+        // Link a new inode into `dir` under a name that must not exist yet.
+        check_name(name)?;
+        if self.child(dir, name.as_bytes()).is_ok() {
+            return Err(VfsError::Exists);
+        }
+        self.entries(dir)?;
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.inodes.insert(ino, Inode::new(data, dir, mode));
+        self.entries_mut(dir)?.push((String::from(name), ino));
+        self.touch_dir(dir);
+        Ok(ino)
+    }
+
+    fn remove_entry(&mut self, dir: Ino, name: &[u8]) -> VfsResult<Ino> {
+        let entries = self.entries_mut(dir)?;
+        let idx = entries
+            .iter()
+            .position(|(entry, _)| entry.as_bytes() == name)
+            .ok_or(VfsError::NotFound)?;
+        let (_, ino) = entries.remove(idx);
+        self.touch_dir(dir);
+        Ok(ino)
+    }
+
+    fn drop_link(&mut self, ino: Ino) -> bool {
+        // Forget an inode once it is neither linked nor open. True if it was,
+        // in which case the caller drops its cached pages.
+        let free = match self.inodes.get_mut(&ino) {
+            Some(inode) => {
+                inode.linked = false;
+                inode.ctime_ms = timer::uptime_ms();
+                inode.opens == 0
+            }
+            None => false,
+        };
+        if free {
+            self.inodes.remove(&ino);
+        }
+        free
+    }
+
+    fn is_ancestor(&self, ancestor: Ino, mut dir: Ino) -> bool {
+        // Walk parent links from `dir` up to the root.
+        loop {
+            if dir == ancestor {
+                return true;
+            }
+            match self.get(dir) {
+                Ok(inode) if inode.parent != dir => dir = inode.parent,
+                _ => return false,
+            }
+        }
+    }
+}
+
+pub fn init() {
+    // Start from an empty root directory.
+    let mut fs = FS.lock();
+    fs.inodes.clear();
+    fs.next_ino = ROOT_INO + 1;
+    fs.inodes
+        .insert(ROOT_INO, Inode::new(Data::Dir(Vec::new()), ROOT_INO, 0o755));
+}
+
+pub fn lookup_path(path: &[u8]) -> VfsResult<Ino> {
+    // Walk a canonical absolute path (no ".", ".." or symlinks) from the root.
+    let fs = FS.lock();
+    let mut ino = ROOT_INO;
+    for name in path.split(|&b| b == b'/').filter(|name| !name.is_empty()) {
+        ino = fs.child(ino, name)?;
+    }
+    Ok(ino)
+}
+
+pub fn kind(ino: Ino) -> VfsResult<Kind> {
+    Ok(FS.lock().get(ino)?.kind())
+}
+
+pub fn parent(dir: Ino) -> VfsResult<Ino> {
+    Ok(FS.lock().get(dir)?.parent)
+}
+
+pub fn size(ino: Ino) -> VfsResult<u64> {
+    match &FS.lock().get(ino)?.data {
+        Data::File(size) => Ok(*size),
+        _ => Ok(0),
+    }
+}
+
+pub fn mkdir(dir: Ino, name: &str, mode: u32) -> VfsResult<Ino> {
+    FS.lock().insert(dir, name, Data::Dir(Vec::new()), mode)
+}
+
+pub fn create(dir: Ino, name: &str, mode: u32) -> VfsResult<Ino> {
+    FS.lock().insert(dir, name, Data::File(0), mode)
+}
+
+pub fn symlink(dir: Ino, name: &str, target: &str) -> VfsResult<Ino> {
+    if target.is_empty() {
+        return Err(VfsError::NotFound);
+    }
+    FS.lock()
+        .insert(dir, name, Data::Symlink(String::from(target)), 0o777)
+}
+
+pub fn readlink(ino: Ino) -> VfsResult<String> {
+    let mut fs = FS.lock();
+    let inode = fs.get_mut(ino)?;
+    inode.atime_ms = timer::uptime_ms();
+    match &inode.data {
+        Data::Symlink(target) => Ok(target.clone()),
+        _ => Err(VfsError::InvalidArgument),
+    }
+}
+
+pub fn open(ino: Ino) -> VfsResult<()> {
+    FS.lock().get_mut(ino)?.opens += 1;
+    Ok(())
+}
+
+pub fn close(ino: Ino) {
+    let mut fs = FS.lock();
+    let free = match fs.inodes.get_mut(&ino) {
+        Some(inode) => {
+            inode.opens = inode.opens.saturating_sub(1);
+            inode.opens == 0 && !inode.linked
+        }
+        None => false,
+    };
+    if free {
+        fs.inodes.remove(&ino);
+        drop(fs);
+        pagecache::invalidate_inode(ino);
+    }
+}
+
+pub fn read_at(ino: Ino, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+    let len = {
+        let mut fs = FS.lock();
+        let inode = fs.get_mut(ino)?;
+        inode.atime_ms = timer::uptime_ms();
+        match &inode.data {
+            Data::File(size) => *size as usize,
+            Data::Dir(_) => return Err(VfsError::IsDir),
+            Data::Symlink(_) => return Err(VfsError::InvalidArgument),
+        }
+    };
+    let start = (offset as usize).min(len);
+    let n = buf.len().min(len - start);
+    match pagecache::read_inode(ino, start as u64, &BACKING, &mut buf[..n]) {
+        0 if n != 0 => Err(VfsError::NoSpace),
+        done => Ok(done),
+    }
+}
+
+pub fn write_at(ino: Ino, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+    extend_locked(&mut FS.lock(), ino, offset, buf.len())?;
+    write_cached(ino, offset, buf)
+}
+
+fn extend_locked(fs: &mut RamFs, ino: Ino, offset: u64, len: usize) -> VfsResult<()> {
+    // Grow the file for a write of `len` bytes at `offset`; a gap past the
+    // old end reads as zeros. The data itself goes through `write_cached`.
+    let inode = fs.get_mut(ino)?;
+    let size = match &mut inode.data {
+        Data::File(size) => size,
+        Data::Dir(_) => return Err(VfsError::IsDir),
+        Data::Symlink(_) => return Err(VfsError::InvalidArgument),
+    };
+    let end = offset
+        .checked_add(len as u64)
+        .filter(|&end| end <= MAX_FILE_SIZE)
+        .ok_or(VfsError::NoSpace)?;
+    *size = (*size).max(end);
+    let now = timer::uptime_ms();
+    inode.mtime_ms = now;
+    inode.ctime_ms = now;
+    Ok(())
+}
+
+fn write_cached(ino: Ino, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+    // Fails only if not even the first page could be cached.
+    match pagecache::write_inode(ino, offset, &BACKING, buf) {
+        0 if !buf.is_empty() => Err(VfsError::NoSpace),
+        done => Ok(done),
+    }
+}
+
+pub fn append(ino: Ino, buf: &[u8]) -> VfsResult<(usize, u64)> {
+    // Atomic O_APPEND write; returns (bytes written, new end of file). The
+    // range is claimed under the lock, so concurrent appends never overlap.
+    let end = {
+        let mut fs = FS.lock();
+        let end = match &fs.get(ino)?.data {
+            Data::File(size) => *size,
+            Data::Dir(_) => return Err(VfsError::IsDir),
+            Data::Symlink(_) => return Err(VfsError::InvalidArgument),
+        };
+        extend_locked(&mut fs, ino, end, buf.len())?;
+        end
+    };
+    let n = write_cached(ino, end, buf)?;
+    Ok((n, end + n as u64))
+}
+
+pub fn truncate(ino: Ino, len: u64) -> VfsResult<()> {
+    // Shrinking drops the cached pages past the new end and zeros the tail
+    // of the last one, so growing the file again reads zeros.
+    let mut fs = FS.lock();
+    let inode = fs.get_mut(ino)?;
+    let size = match &mut inode.data {
+        Data::File(size) => size,
+        Data::Dir(_) => return Err(VfsError::IsDir),
+        Data::Symlink(_) => return Err(VfsError::InvalidArgument),
+    };
+    if len > MAX_FILE_SIZE {
+        return Err(VfsError::NoSpace);
+    }
+    if len < *size {
+        pagecache::truncate_inode(ino, len);
+    }
+    *size = len;
+    let now = timer::uptime_ms();
+    inode.mtime_ms = now;
+    inode.ctime_ms = now;
+    Ok(())
+}
+
+pub fn unlink(dir: Ino, name: &[u8]) -> VfsResult<()> {
+    // Remove a non-directory entry; open descriptions keep the data alive.
+    let mut fs = FS.lock();
+    let ino = fs.child(dir, name)?;
+    if fs.get(ino)?.kind() == Kind::Dir {
+        return Err(VfsError::IsDir);
+    }
+    fs.remove_entry(dir, name)?;
+    if fs.drop_link(ino) {
+        drop(fs);
+        pagecache::invalidate_inode(ino);
+    }
+    Ok(())
+}
+
+pub fn rmdir(dir: Ino, name: &[u8]) -> VfsResult<()> {
+    let mut fs = FS.lock();
+    let ino = fs.child(dir, name)?;
+    if !fs.entries(ino)?.is_empty() {
+        return Err(VfsError::NotEmpty);
+    }
+    fs.remove_entry(dir, name)?;
+    fs.drop_link(ino);
+    Ok(())
+}
+
+pub fn rename(old_dir: Ino, old_name: &[u8], new_dir: Ino, new_name: &str) -> VfsResult<()> {
+    // Move an entry, atomically replacing a compatible existing target.
+    check_name(new_name)?;
+    let mut fs = FS.lock();
+    let ino = fs.child(old_dir, old_name)?;
+    fs.entries(new_dir)?;
+    let is_dir = fs.get(ino)?.kind() == Kind::Dir;
+    if is_dir && fs.is_ancestor(ino, new_dir) {
+        return Err(VfsError::InvalidArgument);
+    }
+    let mut replaced = None;
+    if let Ok(target) = fs.child(new_dir, new_name.as_bytes()) {
+        if target == ino {
+            return Ok(());
+        }
+        match (is_dir, fs.get(target)?.kind()) {
+            (true, Kind::Dir) => {
+                if !fs.entries(target)?.is_empty() {
+                    return Err(VfsError::NotEmpty);
+                }
+            }
+            (true, _) => return Err(VfsError::NotDir),
+            (false, Kind::Dir) => return Err(VfsError::IsDir),
+            (false, _) => {}
+        }
+        fs.remove_entry(new_dir, new_name.as_bytes())?;
+        replaced = fs.drop_link(target).then_some(target);
+    }
+    fs.remove_entry(old_dir, old_name)?;
+    fs.entries_mut(new_dir)?.push((String::from(new_name), ino));
+    fs.touch_dir(new_dir);
+    let inode = fs.get_mut(ino)?;
+    inode.ctime_ms = timer::uptime_ms();
+    inode.parent = new_dir;
+    drop(fs);
+    if let Some(target) = replaced {
+        pagecache::invalidate_inode(target);
+    }
+    Ok(())
+}
+
+pub fn list(dir: Ino) -> VfsResult<Vec<DirEntry>> {
+    let mut fs = FS.lock();
+    let mut out = Vec::new();
+    for (name, ino) in fs.entries(dir)? {
+        let kind = match fs.get(*ino)?.kind() {
+            Kind::Dir => DT_DIR,
+            Kind::File => DT_REG,
+            Kind::Symlink => DT_LNK,
+        };
+        out.push(DirEntry {
+            name: name.clone(),
+            ino: *ino,
+            kind,
+        });
+    }
+    fs.get_mut(dir)?.atime_ms = timer::uptime_ms();
+    Ok(out)
+}
+
+pub fn stat(ino: Ino, st: &mut Stat) -> VfsResult<()> {
+    let fs = FS.lock();
+    let inode = fs.get(ino)?;
+    st.ino = ino;
+    match &inode.data {
+        Data::Dir(entries) => {
+            // "." and ".." plus one ".." link from every subdirectory.
+            let subdirs = entries
+                .iter()
+                .filter(|&&(_, child)| matches!(fs.get(child).map(Inode::kind), Ok(Kind::Dir)))
+                .count();
+            st.mode = S_IFDIR | inode.mode;
+            st.nlink = 2 + subdirs as u32;
+            st.size = (entries.len() as u64) * 32;
+        }
+        Data::File(size) => {
+            st.mode = S_IFREG | inode.mode;
+            st.nlink = inode.linked as u32;
+            st.size = *size;
+        }
+        Data::Symlink(target) => {
+            st.mode = S_IFLNK | inode.mode;
+            st.nlink = 1;
+            st.size = target.len() as u64;
+        }
+    }
+    st.set_times(inode.atime_ms, inode.mtime_ms, inode.ctime_ms);
+    Ok(())
+}
+
+pub fn write_path(ino: Ino, out: &mut String) {
+    // Rebuild an absolute path by walking parent links and looking up each name.
+    let fs = FS.lock();
+    let mut names: Vec<&str> = Vec::new();
+    let mut cur = ino;
+    let mut linked = true;
+    while cur != ROOT_INO {
+        let inode = match fs.get(cur) {
+            Ok(inode) => inode,
+            Err(_) => break,
+        };
+        linked &= inode.linked;
+        match fs
+            .entries(inode.parent)
+            .ok()
+            .and_then(|entries| entries.iter().find(|&&(_, child)| child == cur))
+        {
+            Some((name, _)) => names.push(name.as_str()),
+            None => break,
+        }
+        cur = inode.parent;
+    }
+    if names.is_empty() {
+        out.push('/');
+    }
+    for name in names.iter().rev() {
+        out.push('/');
+        out.push_str(name);
+    }
+    if !linked {
+        out.push_str(" (deleted)");
+    }
+}
+
+fn check_name(name: &str) -> VfsResult<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(VfsError::InvalidArgument);
+    }
+    if name.len() > NAME_MAX {
+        return Err(VfsError::NameTooLong);
+    }
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::drivers::device::{self, Device};
+use crate::kernel::vfs::{DirEntry, VfsError, VfsResult, DT_DIR, DT_REG};
+use crate::mm::devtree::{self, Node, Prop, MAX_NODES, MAX_PROPS};
+
+const DT_BASE: &[u8] = b"firmware/devicetree/base";
+const PLATFORM: &[u8] = b"devices/platform";
+// Device tree inodes are pool indices shifted past the fixed directories;
+// properties come after all the nodes, devices after the properties with
+// four inodes each (the directory and its attributes).
+const DT_INO_BASE: u64 = 16;
+const DT_PROP_INO_BASE: u64 = DT_INO_BASE + MAX_NODES as u64;
+const DEV_INO_BASE: u64 = DT_PROP_INO_BASE + MAX_PROPS as u64;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DevAttr {
+    Driver,
+    State,
+    OfNode,
+}
+
+// Attribute files in listing order.
+const DEV_ATTRS: [(&[u8], DevAttr); 3] = [
+    (b"driver", DevAttr::Driver),
+    (b"state", DevAttr::State),
+    (b"of_node", DevAttr::OfNode),
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SysFile {
+    DtProp(Prop),
+    DevAttr(Device, DevAttr),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SysNode {
+    Root,
+    Firmware,
+    DeviceTree,
+    DtNode(Node),
+    Devices,
+    Platform,
+    Device(Device),
+    File(SysFile),
+}
+
+pub fn lookup(path: &[u8]) -> Option<SysNode> {
+    // `path` is relative to /sys and has no leading slash.
+    let path = path.strip_suffix(b"/").unwrap_or(path);
+    match path {
+        b"" => return Some(SysNode::Root),
+        b"firmware" => return Some(SysNode::Firmware),
+        b"firmware/devicetree" => return Some(SysNode::DeviceTree),
+        b"devices" => return Some(SysNode::Devices),
+        _ => {}
+    }
+    if let Some(rest) = path.strip_prefix(PLATFORM) {
+        return lookup_device(rest);
+    }
+    let rest = path.strip_prefix(DT_BASE)?;
+    let mut node = devtree::root()?;
+    if rest.is_empty() {
+        return Some(SysNode::DtNode(node));
+    }
+    let rest = rest.strip_prefix(b"/")?;
+    let mut parts = rest.split(|&b| b == b'/').peekable();
+    while let Some(part) = parts.next() {
+        if let Some(child) = node.child(part) {
+            node = child;
+            continue;
+        }
+        // Properties are leaves, so they can only be the last component.
+        if parts.peek().is_none() {
+            return node
+                .prop(part)
+                .map(|prop| SysNode::File(SysFile::DtProp(prop)));
+        }
+        return None;
+    }
+    Some(SysNode::DtNode(node))
+}
+
+fn lookup_device(rest: &[u8]) -> Option<SysNode> {
+    // `rest` follows "devices/platform": child devices, then an attribute.
+    if rest.is_empty() {
+        return Some(SysNode::Platform);
+    }
+    let rest = rest.strip_prefix(b"/")?;
+    let mut dev = None;
+    let mut parts = rest.split(|&b| b == b'/').peekable();
+    while let Some(part) = parts.next() {
+        let child = device::children(dev)
+            .into_iter()
+            .find(|child| child.name().as_bytes() == part);
+        if let Some(child) = child {
+            dev = Some(child);
+            continue;
+        }
+        let (_, attr) = DEV_ATTRS.iter().find(|(name, _)| *name == part)?;
+        if parts.peek().is_none() {
+            return Some(SysNode::File(SysFile::DevAttr(dev?, *attr)));
+        }
+        return None;
+    }
+    dev.map(SysNode::Device)
+}
+
+pub fn read_at(file: SysFile, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+    // Property files hold the raw big-endian value, exactly as in the blob;
+    // device attributes are one line of text, generated on every read.
+    let mut text = String::new();
+    let bytes = match file {
+        SysFile::DtProp(prop) => prop.value(),
+        SysFile::DevAttr(dev, attr) => {
+            generate(dev, attr, &mut text);
+            text.as_bytes()
+        }
+    };
+    let start = (offset as usize).min(bytes.len());
+    let n = buf.len().min(bytes.len() - start);
+    buf[..n].copy_from_slice(&bytes[start..start + n]);
+    Ok(n)
+}
+
+pub fn file_len(file: SysFile) -> usize {
+    match file {
+        SysFile::DtProp(prop) => prop.value().len(),
+        SysFile::DevAttr(dev, attr) => {
+            let mut text = String::new();
+            generate(dev, attr, &mut text);
+            text.len()
+        }
+    }
+}
+
+fn generate(dev: Device, attr: DevAttr, out: &mut String) {
+    let _ = match attr {
+        DevAttr::Driver => writeln!(out, "{}", dev.driver_name().unwrap_or("")),
+        DevAttr::State => writeln!(out, "{}", dev.state().as_str()),
+        DevAttr::OfNode => writeln!(out, "{}", dev.node().path()),
+    };
+}
+
+pub fn ino(node: SysNode) -> u64 {
+    match node {
+        SysNode::Root => 1,
+        SysNode::Firmware => 2,
+        SysNode::DeviceTree => 3,
+        SysNode::Devices => 4,
+        SysNode::Platform => 5,
+        SysNode::DtNode(node) => DT_INO_BASE + node.index() as u64,
+        SysNode::File(SysFile::DtProp(prop)) => DT_PROP_INO_BASE + prop.index() as u64,
+        SysNode::Device(dev) => DEV_INO_BASE + dev.index() as u64 * 4,
+        SysNode::File(SysFile::DevAttr(dev, attr)) => {
+            DEV_INO_BASE + dev.index() as u64 * 4 + 1 + attr as u64
+        }
+    }
+}
+
+pub fn parent(node: SysNode) -> Option<SysNode> {
+    // None for /sys itself, whose parent lives in another filesystem.
+    match node {
+        SysNode::Root => None,
+        SysNode::Firmware => Some(SysNode::Root),
+        SysNode::DeviceTree => Some(SysNode::Firmware),
+        SysNode::DtNode(node) => Some(node.parent().map_or(SysNode::DeviceTree, SysNode::DtNode)),
+        SysNode::File(SysFile::DtProp(prop)) => Some(SysNode::DtNode(prop.node())),
+        SysNode::Devices => Some(SysNode::Root),
+        SysNode::Platform => Some(SysNode::Devices),
+        SysNode::Device(dev) => Some(dev.parent().map_or(SysNode::Platform, SysNode::Device)),
+        SysNode::File(SysFile::DevAttr(dev, _)) => Some(SysNode::Device(dev)),
+    }
+}
+
+pub fn list(node: SysNode) -> VfsResult<Vec<DirEntry>> {
+    // Directory contents, without "." and "..": child nodes (or devices)
+    // first, then properties (or attributes).
+    let mut out = Vec::new();
+    let mut push = |name: &[u8], node: SysNode, kind: u8| {
+        out.push(DirEntry {
+            name: String::from(core::str::from_utf8(name).unwrap_or("?")),
+            ino: ino(node),
+            kind,
+        })
+    };
+    match node {
+        SysNode::Root => {
+            push(b"devices", SysNode::Devices, DT_DIR);
+            push(b"firmware", SysNode::Firmware, DT_DIR);
+        }
+        SysNode::Firmware => push(b"devicetree", SysNode::DeviceTree, DT_DIR),
+        SysNode::DeviceTree => {
+            if let Some(root) = devtree::root() {
+                push(b"base", SysNode::DtNode(root), DT_DIR);
+            }
+        }
+        SysNode::DtNode(node) => {
+            for child in node.children() {
+                push(child.name(), SysNode::DtNode(child), DT_DIR);
+            }
+            for prop in node.props() {
+                push(prop.name(), SysNode::File(SysFile::DtProp(prop)), DT_REG);
+            }
+        }
+        SysNode::Devices => push(b"platform", SysNode::Platform, DT_DIR),
+        SysNode::Platform => {
+            for child in device::children(None) {
+                push(child.name().as_bytes(), SysNode::Device(child), DT_DIR);
+            }
+        }
+        SysNode::Device(dev) => {
+            for child in device::children(Some(dev)) {
+                push(child.name().as_bytes(), SysNode::Device(child), DT_DIR);
+            }
+            for (name, attr) in DEV_ATTRS {
+                push(name, SysNode::File(SysFile::DevAttr(dev, attr)), DT_REG);
+            }
+        }
+        SysNode::File(_) => return Err(VfsError::NotDir),
+    }
+    Ok(out)
+}
+
+pub fn write_path(node: SysNode, out: &mut String) {
+    let (node, prop) = match node {
+        SysNode::Root => return out.push_str("/sys"),
+        SysNode::Firmware => return out.push_str("/sys/firmware"),
+        SysNode::DeviceTree => return out.push_str("/sys/firmware/devicetree"),
+        SysNode::Devices => return out.push_str("/sys/devices"),
+        SysNode::Platform => return out.push_str("/sys/devices/platform"),
+        SysNode::Device(dev) => return push_device_path(dev, out),
+        SysNode::File(SysFile::DevAttr(dev, attr)) => {
+            push_device_path(dev, out);
+            let (name, _) = DEV_ATTRS[attr as usize];
+            out.push('/');
+            return out.push_str(core::str::from_utf8(name).unwrap_or("?"));
+        }
+        SysNode::DtNode(node) => (node, None),
+        SysNode::File(SysFile::DtProp(prop)) => (prop.node(), Some(prop)),
+    };
+    out.push_str("/sys/firmware/devicetree/base");
+    push_path(node, out);
+    if let Some(prop) = prop {
+        out.push('/');
+        out.push_str(core::str::from_utf8(prop.name()).unwrap_or("?"));
+    }
+}
+
+fn push_path(node: Node, out: &mut String) {
+    // Append "/name" for every node below the root, outermost first.
+    let Some(parent) = node.parent() else {
+        return;
+    };
+    push_path(parent, out);
+    out.push('/');
+    out.push_str(core::str::from_utf8(node.name()).unwrap_or("?"));
+}
+
+fn push_device_path(dev: Device, out: &mut String) {
+    // "/sys/devices/platform" and then every device from the top down.
+    match dev.parent() {
+        Some(parent) => push_device_path(parent, out),
+        None => out.push_str("/sys/devices/platform"),
+    }
+    out.push('/');
+    out.push_str(&dev.name());
+}
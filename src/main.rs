@@ -15,43 +15,38 @@ mod platform;
 mod user;
 mod util;
 
-#[cfg(feature = "qemu")]
+#[cfg(any(feature = "qemu", feature = "splash"))]
 use crate::arch::aarch64::timer;
-use crate::drivers::{framebuffer, uart};
-use crate::kernel::{interrupts, process, smp, user as kuser, vfs};
+use crate::drivers::{console, device, framebuffer, keyboard, memdev, ramdisk, uart};
+use crate::kernel::params::{SizeParam, StrParam};
+use crate::kernel::{interrupts, ipc, klog, params, process, smp, tty, user as kuser, vfs};
 use crate::user::shell;
 
 global_asm!(include_str!("arch/aarch64/boot.S"));
 global_asm!(include_str!("arch/aarch64/exception.S"));
 
-#[cfg(feature = "rpi5")]
-const RP1_UART_FALLBACK: usize = 0x1c00_0300_00;
-
-#[cfg(feature = "rpi5")]
-#[inline(always)]
-unsafe fn early_uart_putc(b: u8) {
-    (RP1_UART_FALLBACK as *mut u32).write_volatile(b as u32);
-}
-
-#[cfg(feature = "rpi5")]
-fn early_uart_print(s: &str) {
-    for b in s.bytes() {
-        if b == b'\n' {
-            unsafe { early_uart_putc(b'\r'); }
-        }
-        unsafe { early_uart_putc(b); }
-    }
-}
+#[cfg(any(feature = "splash", feature = "display"))]
+static SPLASH: &[u8] = include_bytes!("../assets/splash.png");
+#[cfg(feature = "splash")]
+const SPLASH_MS: u64 = 2000;
 
 const USER_STACK_SIZE: usize = 512 * 1024;
 #[repr(align(16))]
 struct UserStack([u8; USER_STACK_SIZE]);
 static mut USER_STACK: UserStack = UserStack([0; USER_STACK_SIZE]);
 
+// First user program, by its name in `user::PROGRAMS`.
+kernel_param!(static INIT: StrParam = StrParam::new("shell"), "init");
+// Framebuffer mode to ask the firmware for before the defaults.
+kernel_param!(static FB_MODE: SizeParam = SizeParam::new(), "fb");
+
 #[no_mangle]
 pub extern "C" fn kernel_main(dtb_pa: u64) -> ! {
-    #[cfg(feature = "rpi5")]
-    early_uart_print("K0\n");
+    // Unflatten the device tree; everything below looks things up in it.
+    mm::devtree::init(dtb_pa);
+
+    // Kernel parameters come next; they decide how everything below behaves.
+    params::init();
 
     // Early UART for QEMU only; RPi5 UART base is discovered after DTB parse.
     #[cfg(feature = "qemu")]
@@ -60,7 +55,7 @@ pub extern "C" fn kernel_main(dtb_pa: u64) -> ! {
     #[cfg(feature = "rpi5")]
     {
         let mut found = false;
-        if let Some(info) = mm::dtb::find_uart(dtb_pa) {
+        if let Some(info) = mm::dtb::find_uart() {
             uart::set_base(info.addr as usize);
             uart::set_clock_hz(info.clock_hz);
             uart::set_reg_shift(info.reg_shift);
@@ -70,19 +65,18 @@ pub extern "C" fn kernel_main(dtb_pa: u64) -> ! {
         }
         if !found {
             // Fallback to RP1 UART0 base (matches firmware RP1_UART line).
-            uart::set_base(RP1_UART_FALLBACK);
+            uart::set_base(platform::board::RP1_UART_BASE);
             uart::set_reg_shift(0);
             uart::set_reg_io_width(4);
             uart::set_skip_init(true);
         }
         uart::init();
-        early_uart_print("K1\n");
     }
 
+    kinfo!("boot: dtb at {:#x}", dtb_pa);
+
     // Parse DTB, build memory map, initialize allocators, and enable paging/MMU.
     mm::init(dtb_pa);
-    #[cfg(feature = "rpi5")]
-    early_uart_print("K2\n");
 
     // Bring up UART on real hardware after MMU mappings are installed (if not already).
     #[cfg(feature = "rpi5")]
@@ -91,12 +85,29 @@ pub extern "C" fn kernel_main(dtb_pa: u64) -> ! {
     }
 
     // Process table + VFS must exist before spawning kernel/user processes.
+    smp::record_cpu_info();
     process::init();
     vfs::init();
 
+    // Drivers publish their character devices under /dev.
+    memdev::register_devices();
+    ramdisk::register_devices();
+    console::register_devices();
+    tty::pty::register_devices();
+    keyboard::register_devices();
+    ipc::register_devices();
+    klog::register_devices();
+
+    // Bind device tree nodes to their drivers; the UART and the firmware
+    // framebuffer register theirs when probed.
+    if !device::init() {
+        uart::register_devices();
+        framebuffer::register_devices();
+    }
+
     #[cfg(feature = "qemu")]
     loop {
-        if try_init_console(dtb_pa) {
+        if try_init_console() {
             break;
         }
         // QEMU framebuffer init can fail transiently; keep retrying.
@@ -105,78 +116,89 @@ pub extern "C" fn kernel_main(dtb_pa: u64) -> ! {
 
     #[cfg(not(feature = "qemu"))]
     {
-        if !try_init_console(dtb_pa) {
-            uart::with_uart(|uart| {
-                use core::fmt::Write;
-                let _ = writeln!(uart, "Framebuffer init failed; using UART");
-            });
+        if !try_init_console() {
+            kwarn!("Framebuffer init failed; using UART");
         }
     }
 
+    #[cfg(feature = "splash")]
+    show_splash();
+
     // Wire up standard FDs for the initial process tree.
-    let fb = vfs::open_path("/dev/fb0", vfs::OpenFlags::new(false, true, false));
-    let stdin = vfs::open_path("/dev/kbd0", vfs::OpenFlags::new(true, false, false));
+    let stdin = vfs::open_path("/dev/console", vfs::O_READ).ok();
+    let stdout = vfs::open_path("/dev/console", vfs::O_WRITE).ok();
     process::set_init_fd(vfs::FD_STDIN, stdin);
-    process::set_init_fd(vfs::FD_STDOUT, fb);
-    process::set_init_fd(vfs::FD_STDERR, fb);
+    process::set_init_fd(vfs::FD_STDOUT, stdout);
+    process::set_init_fd(vfs::FD_STDERR, stdout.as_ref().map(vfs::dup));
 
-    // User entry + stack setup for the shell process.
+    // User entry + stack setup for the init process (the shell by default).
     let user_sp = unsafe {
         core::ptr::addr_of!(USER_STACK.0)
             .cast::<u8>()
             .add(USER_STACK_SIZE) as usize
     };
-    kuser::init(shell::user_shell, user_sp);
+    let (init_name, init_entry) = crate::user::find(INIT.get()).unwrap_or_else(|| {
+        kwarn!(
+            "init: no program {}; starting the shell",
+            core::str::from_utf8(INIT.get()).unwrap_or("<invalid utf-8>")
+        );
+        ("shell", shell::user_shell)
+    });
+    kuser::init(init_entry, user_sp);
 
     // Log core status before releasing secondary CPUs.
-    uart::with_uart(|uart| {
-        use core::fmt::Write;
-        let _ = writeln!(uart, "CPU{} online", smp::cpu_id());
-        let _ = writeln!(uart, "Bringing up secondary cores...");
-        for core in 1..smp::MAX_CPUS {
-            let _ = writeln!(uart, "Releasing CPU{}", core);
-        }
-    });
+    kinfo!("CPU{} online", smp::cpu_id());
+    kinfo!("Bringing up secondary cores...");
+    for core in 1..smp::enabled_cpus() {
+        kinfo!("Releasing CPU{}", core);
+    }
 
-    // Create kernel idle loops and the user shell process.
-    uart::with_uart(|uart| {
-        use core::fmt::Write;
-        if let Some(pid) = process::create_user("shell", kuser::user_start, 0) {
-            let _ = writeln!(uart, "Created process {} (shell user)", pid.0);
+    // Create kernel idle loops and the init process.
+    if let Some(pid) = process::create_user(init_name, kuser::user_start, 0) {
+        kinfo!("Created process {} ({} user)", pid.0, init_name);
+    }
+    #[cfg(feature = "display")]
+    install_splash();
+    #[cfg(feature = "display")]
+    for program in ["display", "hello", "bounce", "view"] {
+        let Some((name, entry)) = crate::user::find(program.as_bytes()) else {
+            continue;
+        };
+        if let Some(pid) = process::create_user(name, entry, 0) {
+            kinfo!("Created process {} ({} user)", pid.0, name);
         }
-        for core in 0..smp::MAX_CPUS {
-            if let Some(pid) = process::create("idle", idle_loop, 0) {
-                let _ = writeln!(uart, "Created idle process {} for CPU{}", pid.0, core);
-            }
+    }
+    if let Some(pid) = process::create("flush", mm::pagecache::flush_daemon, 0) {
+        kinfo!("Created process {} (page cache flush)", pid.0);
+    }
+    for core in 0..smp::enabled_cpus() {
+        if let Some(pid) = process::create_idle(idle_loop) {
+            kinfo!("Created idle process {} for CPU{}", pid.0, core);
         }
-        process::for_each(|proc| {
-            let mode = match proc.mode {
-                process::ProcessMode::Kernel => "K",
-                process::ProcessMode::User => "U",
-            };
-            let _ = writeln!(
-                uart,
-                "Process {}: {} [{}]",
-                proc.id.0, proc.name, mode
-            );
-        });
+    }
+    process::for_each(|proc| {
+        let mode = match proc.mode {
+            process::ProcessMode::Kernel => "K",
+            process::ProcessMode::User => "U",
+        };
+        kinfo!("Process {}: {} [{}]", proc.id.0, proc.name, mode);
     });
 
     // Release secondary cores once processes are ready.
     smp::start_secondary_cores();
 
-    uart::with_uart(|uart| {
-        use core::fmt::Write;
-        let _ = writeln!(uart, "Hello, world!");
-    });
+    kinfo!("Hello, world!");
 
     // Enable per-core timer IRQs and enter the scheduler on CPU0.
-    interrupts::init_per_cpu(10);
+    interrupts::init_per_cpu();
     process::start_on_cpu(0);
 }
 
 #[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    // Record the panic and get everything still queued out on the UART.
+    kerr!("panic: {}", info);
+    klog::flush_emergency();
     halt();
 }
 
@@ -196,58 +218,79 @@ pub extern "C" fn idle_loop() -> ! {
     }
 }
 
-fn try_init_console(dtb_pa: u64) -> bool {
+fn try_init_console() -> bool {
+    // A mode given with `fb=` wins over the firmware's choice.
+    if let Some((w, h)) = FB_MODE.get() {
+        if try_mode(w, h) {
+            return true;
+        }
+    }
+
     // Prefer a firmware-provided simple framebuffer if present.
-    if let Some(info) = mm::dtb::find_simplefb(dtb_pa) {
-        uart::with_uart(|uart| {
-            use core::fmt::Write;
-            let _ = writeln!(
-                uart,
-                "simplefb: addr={:#x} size={:#x} {}x{} stride={}",
-                info.addr, info.size, info.width, info.height, info.stride
-            );
-        });
+    if let Some(info) = mm::dtb::find_simplefb() {
+        kinfo!(
+            "simplefb: addr={:#x} size={:#x} {}x{} stride={}",
+            info.addr, info.size, info.width, info.height, info.stride
+        );
         match framebuffer::init_console_from_simplefb(&info, 0x00FF_FFFF, 0x0000_0000) {
             Ok((ow, oh)) => {
-                uart::with_uart(|uart| {
-                    use core::fmt::Write;
-                    let _ = writeln!(uart, "simplefb active {}x{}", ow, oh);
-                });
+                kinfo!("simplefb active {}x{}", ow, oh);
                 return true;
             }
             Err(err) => {
-                uart::with_uart(|uart| {
-                    use core::fmt::Write;
-                    let _ = writeln!(uart, "simplefb init failed: {}", fb_err_str(err));
-                });
+                kwarn!("simplefb init failed: {}", fb_err_str(err));
             }
         }
     }
 
     // Try multiple mailbox framebuffer modes; return true on first success.
     let modes = [(1280, 1024), (1024, 768), (1920, 1080)];
-    for (w, h) in modes {
-        uart::with_uart(|uart| {
-            use core::fmt::Write;
-            let _ = writeln!(uart, "Framebuffer init attempt {}x{}", w, h);
-        });
-        match framebuffer::init_console_with_mode(w, h, 0x00FF_FFFF, 0x0000_0000) {
-            Ok((ow, oh)) => {
-                uart::with_uart(|uart| {
-                    use core::fmt::Write;
-                    let _ = writeln!(uart, "Framebuffer {}x{} -> {}x{}", w, h, ow, oh);
-                });
-                return true;
-            }
-            Err(err) => {
-                uart::with_uart(|uart| {
-                    use core::fmt::Write;
-                    let _ = writeln!(uart, "Framebuffer {}x{} failed: {}", w, h, fb_err_str(err));
-                });
-            }
+    modes.into_iter().any(|(w, h)| try_mode(w, h))
+}
+
+fn try_mode(w: u32, h: u32) -> bool {
+    kinfo!("Framebuffer init attempt {}x{}", w, h);
+    match framebuffer::init_console_with_mode(w, h, 0x00FF_FFFF, 0x0000_0000) {
+        Ok((ow, oh)) => {
+            kinfo!("Framebuffer {}x{} -> {}x{}", w, h, ow, oh);
+            true
+        }
+        Err(err) => {
+            kwarn!("Framebuffer {}x{} failed: {}", w, h, fb_err_str(err));
+            false
+        }
+    }
+}
+
+#[cfg(feature = "splash")]
+fn show_splash() {
+    // Hold the embedded splash image on screen, then hand the screen to
+    // the console. Boot output meanwhile waits in the console cells.
+    let shown = match gfx::image::decode(SPLASH) {
+        Ok(mut image) => framebuffer::show_splash(&mut image, 0x0000_0000),
+        Err(err) => {
+            kerr!("splash: {:?}", err);
+            false
         }
+    };
+    if shown {
+        timer::delay_ms(SPLASH_MS);
+        framebuffer::set_graphics(false);
+    }
+}
+
+#[cfg(feature = "display")]
+fn install_splash() {
+    // Give the `view` demo a file to show; there is no initramfs yet.
+    let bits = vfs::O_WRITE | vfs::O_CREAT | vfs::O_TRUNC;
+    let Ok(file) = vfs::open_bytes(b"/splash.png", bits, 0o644) else {
+        kwarn!("splash: cannot create /splash.png");
+        return;
+    };
+    if vfs::write(&file, SPLASH) != Ok(SPLASH.len()) {
+        kwarn!("splash: short write to /splash.png");
     }
-    false
+    vfs::close(&file);
 }
 
 fn fb_err_str(err: framebuffer::InitError) -> &'static str {
@@ -256,5 +299,6 @@ fn fb_err_str(err: framebuffer::InitError) -> &'static str {
         framebuffer::InitError::NoFramebuffer => "no framebuffer address",
         framebuffer::InitError::NoPitch => "no pitch returned",
         framebuffer::InitError::InvalidSimpleFb => "invalid simplefb data",
+        framebuffer::InitError::UnsupportedFormat => "unsupported pixel format",
     }
 }
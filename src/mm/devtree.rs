@@ -0,0 +1,765 @@
+// Device tree, unflattened once from the firmware's blob at the start of
+// `kernel_main`. Nodes and properties live in fixed pools in .bss so the tree
+// can be queried before the heap exists; names and values point into the
+// blob, which stays mapped and reserved (docs/devtree.md).
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{kinfo, kwarn};
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+// The largest Pi device tree has about 330 nodes and 2400 properties.
+pub const MAX_NODES: usize = 1024;
+pub const MAX_PROPS: usize = 8192;
+
+#[derive(Copy, Clone)]
+struct NodeRec {
+    // Offset and length of the name in the structure block.
+    name: u32,
+    name_len: u16,
+    // Indices into the node pool. The root is node 0 and never a child or
+    // sibling, so 0 means "none" for the links (and the pools stay in .bss).
+    parent: u16,
+    first_child: u16,
+    next_sibling: u16,
+    // A node's properties are contiguous in the property pool.
+    first_prop: u16,
+    prop_count: u16,
+    phandle: u32,
+}
+
+#[derive(Copy, Clone)]
+struct PropRec {
+    // Offset of the name in the strings block, of the value in the structure block.
+    name: u32,
+    value: u32,
+    len: u32,
+    node: u16,
+}
+
+const EMPTY_NODE: NodeRec = NodeRec {
+    name: 0,
+    name_len: 0,
+    parent: 0,
+    first_child: 0,
+    next_sibling: 0,
+    first_prop: 0,
+    prop_count: 0,
+    phandle: 0,
+};
+
+const EMPTY_PROP: PropRec = PropRec {
+    name: 0,
+    value: 0,
+    len: 0,
+    node: 0,
+};
+
+// Built once by `init` on the boot CPU and only read after that. The blob's
+// blocks are kept apart from the pools, which stay all-zero and so in .bss.
+struct Blocks {
+    struct_block: UnsafeCell<&'static [u8]>,
+    strings_block: UnsafeCell<&'static [u8]>,
+}
+
+struct Tree {
+    nodes: UnsafeCell<[NodeRec; MAX_NODES]>,
+    props: UnsafeCell<[PropRec; MAX_PROPS]>,
+    node_count: AtomicUsize,
+    prop_count: AtomicUsize,
+    blob_size: AtomicUsize,
+}
+
+unsafe impl Sync for Blocks {}
+unsafe impl Sync for Tree {}
+
+static BLOCKS: Blocks = Blocks {
+    struct_block: UnsafeCell::new(&[]),
+    strings_block: UnsafeCell::new(&[]),
+};
+
+static TREE: Tree = Tree {
+    nodes: UnsafeCell::new([EMPTY_NODE; MAX_NODES]),
+    props: UnsafeCell::new([EMPTY_PROP; MAX_PROPS]),
+    node_count: AtomicUsize::new(0),
+    prop_count: AtomicUsize::new(0),
+    blob_size: AtomicUsize::new(0),
+};
+
+fn nodes() -> &'static [NodeRec] {
+    let count = TREE.node_count.load(Ordering::Acquire);
+    unsafe { &(&*TREE.nodes.get())[..count] }
+}
+
+fn props() -> &'static [PropRec] {
+    let count = TREE.prop_count.load(Ordering::Acquire);
+    unsafe { &(&*TREE.props.get())[..count] }
+}
+
+fn struct_block() -> &'static [u8] {
+    unsafe { *BLOCKS.struct_block.get() }
+}
+
+fn strings_block() -> &'static [u8] {
+    unsafe { *BLOCKS.strings_block.get() }
+}
+
+pub fn init(dtb_pa: u64) -> bool {
+    // Unflatten the blob at `dtb_pa`; false if there is none or it is not a DTB.
+    if dtb_pa == 0 {
+        return false;
+    }
+    let base = dtb_pa as *const u8;
+    let header = unsafe { core::slice::from_raw_parts(base, 40) };
+    if read_be_u32(&header[0..4]) != FDT_MAGIC {
+        return false;
+    }
+    let total_size = read_be_u32(&header[4..8]) as usize;
+    let off_dt_struct = read_be_u32(&header[8..12]) as usize;
+    let off_dt_strings = read_be_u32(&header[12..16]) as usize;
+    let size_dt_strings = read_be_u32(&header[32..36]) as usize;
+    let size_dt_struct = read_be_u32(&header[36..40]) as usize;
+    let (block, strings) = unsafe {
+        (
+            core::slice::from_raw_parts(base.add(off_dt_struct), size_dt_struct),
+            core::slice::from_raw_parts(base.add(off_dt_strings), size_dt_strings),
+        )
+    };
+    let mut built = unsafe {
+        *BLOCKS.struct_block.get() = block;
+        *BLOCKS.strings_block.get() = strings;
+        Unflatten {
+            nodes: &mut *TREE.nodes.get(),
+            props: &mut *TREE.props.get(),
+            node_count: 0,
+            prop_count: 0,
+            dropped: 0,
+        }
+    };
+    built.run(block, strings);
+    TREE.blob_size.store(total_size, Ordering::Relaxed);
+    TREE.prop_count.store(built.prop_count, Ordering::Release);
+    TREE.node_count.store(built.node_count, Ordering::Release);
+    kinfo!(
+        "devtree: {} nodes, {} properties",
+        built.node_count,
+        built.prop_count
+    );
+    if built.dropped != 0 {
+        kwarn!(
+            "devtree: pools full; {} nodes and properties dropped",
+            built.dropped
+        );
+    }
+    built.node_count != 0
+}
+
+struct Unflatten<'a> {
+    nodes: &'a mut [NodeRec; MAX_NODES],
+    props: &'a mut [PropRec; MAX_PROPS],
+    node_count: usize,
+    prop_count: usize,
+    dropped: usize,
+}
+
+impl Unflatten<'_> {
+    fn run(&mut self, block: &[u8], strings: &[u8]) {
+        // One pass over the structure block. Properties precede child nodes,
+        // so each node's properties end up next to each other in `props`.
+        // `open` holds the nodes entered but not yet closed; a node that does
+        // not fit is dropped with its whole subtree.
+        let mut open: [u16; 64] = [0; 64];
+        let mut depth = 0usize;
+        let mut skip_depth = 0usize;
+        let mut offset = 0usize;
+
+        while offset + 4 <= block.len() {
+            let token = read_be_u32(&block[offset..offset + 4]);
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name_start = offset;
+                    while offset < block.len() && block[offset] != 0 {
+                        offset += 1;
+                    }
+                    let name_len = offset - name_start;
+                    offset = align4(offset + 1);
+                    if skip_depth != 0 || self.node_count == MAX_NODES || depth == open.len() {
+                        skip_depth += 1;
+                        self.dropped += 1;
+                        continue;
+                    }
+                    open[depth] = self.add_node(name_start, name_len, &open[..depth]);
+                    depth += 1;
+                }
+                FDT_END_NODE => {
+                    if skip_depth != 0 {
+                        skip_depth -= 1;
+                    } else {
+                        depth = depth.saturating_sub(1);
+                    }
+                }
+                FDT_PROP => {
+                    if offset + 8 > block.len() {
+                        break;
+                    }
+                    let len = read_be_u32(&block[offset..offset + 4]) as usize;
+                    let nameoff = read_be_u32(&block[offset + 4..offset + 8]);
+                    offset += 8;
+                    if offset + len > block.len() {
+                        break;
+                    }
+                    let value_off = offset;
+                    offset = align4(offset + len);
+                    if skip_depth != 0 || depth == 0 {
+                        continue;
+                    }
+                    if self.prop_count == MAX_PROPS {
+                        self.dropped += 1;
+                        continue;
+                    }
+                    let node = open[depth - 1];
+                    let rec = &mut self.nodes[node as usize];
+                    let name = get_string(strings, nameoff as usize);
+                    if (name == b"phandle" || name == b"linux,phandle") && len == 4 {
+                        rec.phandle = read_be_u32(&block[value_off..]);
+                    }
+                    rec.prop_count += 1;
+                    self.props[self.prop_count] = PropRec {
+                        name: nameoff,
+                        value: value_off as u32,
+                        len: len as u32,
+                        node,
+                    };
+                    self.prop_count += 1;
+                }
+                FDT_NOP => {}
+                FDT_END => break,
+                _ => break,
+            }
+        }
+    }
+
+    fn add_node(&mut self, name: usize, name_len: usize, open: &[u16]) -> u16 {
+        // Append a node as the last child of the innermost open node.
+        let index = self.node_count as u16;
+        self.nodes[self.node_count] = NodeRec {
+            name: name as u32,
+            name_len: name_len as u16,
+            first_prop: self.prop_count as u16,
+            ..EMPTY_NODE
+        };
+        self.node_count += 1;
+        let Some(&parent) = open.last() else {
+            return index;
+        };
+        self.nodes[index as usize].parent = parent;
+        let mut prev = self.nodes[parent as usize].first_child;
+        if prev == 0 {
+            self.nodes[parent as usize].first_child = index;
+            return index;
+        }
+        while self.nodes[prev as usize].next_sibling != 0 {
+            prev = self.nodes[prev as usize].next_sibling;
+        }
+        self.nodes[prev as usize].next_sibling = index;
+        index
+    }
+}
+
+pub fn blob_size() -> usize {
+    // Size of the blob in bytes, 0 if `init` found none.
+    TREE.blob_size.load(Ordering::Relaxed)
+}
+
+// Handle to a node of the unflattened tree.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Node(u16);
+
+// Handle to a property of the unflattened tree.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Prop(u16);
+
+pub fn root() -> Option<Node> {
+    // The root is the first node in the blob.
+    (!nodes().is_empty()).then_some(Node(0))
+}
+
+pub fn all_nodes() -> impl Iterator<Item = Node> {
+    // Every node, in blob (depth-first) order.
+    (0..nodes().len() as u16).map(Node)
+}
+
+pub fn find_path(path: &[u8]) -> Option<Node> {
+    // Resolve "/a/b@1/c", or "alias/rest" through /aliases. A component
+    // without a unit address matches a node with one ("serial" for "serial@7e201000").
+    let (mut node, rest) = match path.strip_prefix(b"/") {
+        Some(rest) => (root()?, rest),
+        None => {
+            let end = path.iter().position(|&b| b == b'/').unwrap_or(path.len());
+            (alias(&path[..end])?, &path[end..])
+        }
+    };
+    for part in rest.split(|&b| b == b'/').filter(|part| !part.is_empty()) {
+        node = node.child(part).or_else(|| {
+            node.children()
+                .find(|child| unit_name(child.name()) == part)
+        })?;
+    }
+    Some(node)
+}
+
+pub fn alias(name: &[u8]) -> Option<Node> {
+    let path = root()?.child(b"aliases")?.str(name)?;
+    // An alias must be a full path; this also stops alias loops.
+    path.starts_with(b"/").then(|| find_path(path))?
+}
+
+pub fn by_phandle(phandle: u32) -> Option<Node> {
+    if phandle == 0 {
+        return None;
+    }
+    nodes()
+        .iter()
+        .position(|node| node.phandle == phandle)
+        .map(|index| Node(index as u16))
+}
+
+pub fn compatible(compat: &'static [u8]) -> impl Iterator<Item = Node> {
+    // Nodes listing `compat` in their `compatible`, enabled or not.
+    all_nodes().filter(move |node| node.is_compatible(compat))
+}
+
+pub fn chosen() -> Option<Node> {
+    root()?.child(b"chosen")
+}
+
+pub fn stdout() -> Option<Node> {
+    // Node named by /chosen/stdout-path, without its ":options" suffix.
+    let path = chosen()?.str(b"stdout-path")?;
+    let end = path.iter().position(|&b| b == b':').unwrap_or(path.len());
+    find_path(&path[..end])
+}
+
+impl Node {
+    fn rec(self) -> &'static NodeRec {
+        &nodes()[self.0 as usize]
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn name(self) -> &'static [u8] {
+        let rec = self.rec();
+        &struct_block()[rec.name as usize..rec.name as usize + rec.name_len as usize]
+    }
+
+    pub fn parent(self) -> Option<Node> {
+        (self.0 != 0).then_some(Node(self.rec().parent))
+    }
+
+    pub fn children(self) -> Children {
+        Children(self.rec().first_child)
+    }
+
+    pub fn child(self, name: &[u8]) -> Option<Node> {
+        self.children().find(|child| child.name() == name)
+    }
+
+    pub fn path(self) -> NodePath {
+        // Full path for display: "/", "/soc/serial@7e201000".
+        NodePath(self)
+    }
+
+    pub fn is_descendant_of(self, ancestor: Node) -> bool {
+        let mut node = self.parent();
+        while let Some(cur) = node {
+            if cur == ancestor {
+                return true;
+            }
+            node = cur.parent();
+        }
+        false
+    }
+
+    pub fn props(self) -> impl Iterator<Item = Prop> {
+        let rec = self.rec();
+        (rec.first_prop..rec.first_prop + rec.prop_count).map(Prop)
+    }
+
+    pub fn prop(self, name: &[u8]) -> Option<Prop> {
+        self.props().find(|prop| prop.name() == name)
+    }
+
+    pub fn has(self, name: &[u8]) -> bool {
+        self.prop(name).is_some()
+    }
+
+    pub fn value(self, name: &[u8]) -> Option<&'static [u8]> {
+        self.prop(name).map(Prop::value)
+    }
+
+    pub fn u32(self, name: &[u8]) -> Option<u32> {
+        let value = self.value(name)?;
+        (value.len() >= 4).then(|| read_be_u32(value))
+    }
+
+    pub fn str(self, name: &[u8]) -> Option<&'static [u8]> {
+        // First string of the property, without its NUL.
+        self.strings(name).next()
+    }
+
+    pub fn strings(self, name: &[u8]) -> Strings {
+        Strings(self.value(name).unwrap_or(&[]))
+    }
+
+    pub fn is_compatible(self, compat: &[u8]) -> bool {
+        self.strings(b"compatible").any(|s| s == compat)
+    }
+
+    pub fn is_available(self) -> bool {
+        // No `status`, or "okay" (older trees say "ok").
+        matches!(self.str(b"status"), None | Some(b"okay" | b"ok"))
+    }
+
+    pub fn address_cells(self) -> u32 {
+        // Cells in the addresses of this node's children.
+        self.u32(b"#address-cells").unwrap_or(2)
+    }
+
+    pub fn size_cells(self) -> u32 {
+        self.u32(b"#size-cells").unwrap_or(1)
+    }
+
+    pub fn regs(self) -> Regs {
+        // (address, size) pairs in the parent's address space.
+        let (addr_cells, size_cells) = match self.parent() {
+            Some(parent) => (parent.address_cells(), parent.size_cells()),
+            None => (2, 1),
+        };
+        Regs {
+            value: self.value(b"reg").unwrap_or(&[]),
+            addr_cells,
+            size_cells,
+        }
+    }
+
+    pub fn reg(self, index: usize) -> Option<(u64, u64)> {
+        self.regs().nth(index)
+    }
+
+    pub fn phys_reg(self, index: usize) -> Option<(u64, u64)> {
+        // `reg` entry translated through the parents' `ranges` into a CPU
+        // physical address. None if some `ranges` does not cover it.
+        let (addr, size) = self.reg(index)?;
+        let mut addr = addr;
+        let mut bus = self.parent();
+        while let Some(node) = bus {
+            let mut ranges = node.ranges().peekable();
+            // A missing or empty `ranges` is an identity mapping.
+            if ranges.peek().is_some() {
+                let range =
+                    ranges.find(|r| addr >= r.child_base && addr - r.child_base < r.size)?;
+                addr = range.parent_base + (addr - range.child_base);
+            }
+            bus = node.parent();
+        }
+        Some((addr, size))
+    }
+
+    pub fn ranges(self) -> Ranges {
+        // Entries of this node's `ranges`: child address (own #address-cells),
+        // parent address (parent's #address-cells), size (own #size-cells).
+        Ranges {
+            value: self.value(b"ranges").unwrap_or(&[]),
+            child_cells: self.address_cells(),
+            parent_cells: self.parent().map_or(2, Node::address_cells),
+            size_cells: self.size_cells(),
+        }
+    }
+
+    pub fn interrupt_parent(self) -> Option<Node> {
+        // The nearest `interrupt-parent` on this node or its ancestors.
+        let mut node = Some(self);
+        while let Some(cur) = node {
+            if let Some(phandle) = cur.u32(b"interrupt-parent") {
+                return by_phandle(phandle);
+            }
+            node = cur.parent();
+        }
+        None
+    }
+
+    pub fn phandles(self, name: &[u8], cells: &'static [u8]) -> Phandles {
+        // Nodes named by a phandle list such as `clocks` or `mboxes`: each
+        // phandle is followed by as many cells as the target's `cells`
+        // property (`#clock-cells`, ...) says.
+        Phandles {
+            value: self.value(name).unwrap_or(&[]),
+            cells,
+        }
+    }
+
+    pub fn interrupts(self) -> Interrupts {
+        // Specifiers from `interrupts-extended`, or from `interrupts` against
+        // the interrupt parent.
+        if let Some(value) = self.value(b"interrupts-extended") {
+            return Interrupts {
+                value,
+                controller: None,
+            };
+        }
+        Interrupts {
+            value: self.value(b"interrupts").unwrap_or(&[]),
+            controller: self.interrupt_parent(),
+        }
+    }
+}
+
+impl Prop {
+    fn rec(self) -> &'static PropRec {
+        &props()[self.0 as usize]
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn name(self) -> &'static [u8] {
+        get_string(strings_block(), self.rec().name as usize)
+    }
+
+    pub fn value(self) -> &'static [u8] {
+        let rec = self.rec();
+        &struct_block()[rec.value as usize..(rec.value + rec.len) as usize]
+    }
+
+    pub fn node(self) -> Node {
+        Node(self.rec().node)
+    }
+}
+
+pub struct NodePath(Node);
+
+impl fmt::Display for NodePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write(node: Node, f: &mut fmt::Formatter) -> fmt::Result {
+            let Some(parent) = node.parent() else {
+                return Ok(());
+            };
+            write(parent, f)?;
+            f.write_str("/")?;
+            f.write_str(core::str::from_utf8(node.name()).unwrap_or("?"))
+        }
+        match self.0.parent() {
+            None => f.write_str("/"),
+            Some(_) => write(self.0, f),
+        }
+    }
+}
+
+pub struct Children(u16);
+
+impl Iterator for Children {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Node> {
+        if self.0 == 0 {
+            return None;
+        }
+        let node = Node(self.0);
+        self.0 = node.rec().next_sibling;
+        Some(node)
+    }
+}
+
+// The strings of a NUL-separated string list property.
+pub struct Strings(&'static [u8]);
+
+impl Iterator for Strings {
+    type Item = &'static [u8];
+
+    fn next(&mut self) -> Option<&'static [u8]> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let end = self.0.iter().position(|&b| b == 0).unwrap_or(self.0.len());
+        let s = &self.0[..end];
+        self.0 = &self.0[(end + 1).min(self.0.len())..];
+        Some(s)
+    }
+}
+
+pub struct Regs {
+    value: &'static [u8],
+    addr_cells: u32,
+    size_cells: u32,
+}
+
+impl Iterator for Regs {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<(u64, u64)> {
+        let addr_bytes = self.addr_cells as usize * 4;
+        let entry_bytes = addr_bytes + self.size_cells as usize * 4;
+        if entry_bytes == 0 || self.value.len() < entry_bytes {
+            return None;
+        }
+        let addr = read_addr_cells(&self.value[..addr_bytes], self.addr_cells);
+        let size = read_cells(&self.value[addr_bytes..entry_bytes], self.size_cells);
+        self.value = &self.value[entry_bytes..];
+        Some((addr, size))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Range {
+    pub child_base: u64,
+    pub parent_base: u64,
+    pub size: u64,
+}
+
+pub struct Ranges {
+    value: &'static [u8],
+    child_cells: u32,
+    parent_cells: u32,
+    size_cells: u32,
+}
+
+impl Iterator for Ranges {
+    type Item = Range;
+
+    fn next(&mut self) -> Option<Range> {
+        let child_bytes = self.child_cells as usize * 4;
+        let parent_end = child_bytes + self.parent_cells as usize * 4;
+        let entry_bytes = parent_end + self.size_cells as usize * 4;
+        if entry_bytes == 0 || self.value.len() < entry_bytes {
+            return None;
+        }
+        let range = Range {
+            child_base: read_addr_cells(&self.value[..child_bytes], self.child_cells),
+            parent_base: read_addr_cells(&self.value[child_bytes..parent_end], self.parent_cells),
+            size: read_cells(&self.value[parent_end..entry_bytes], self.size_cells),
+        };
+        self.value = &self.value[entry_bytes..];
+        Some(range)
+    }
+}
+
+pub struct Phandles {
+    value: &'static [u8],
+    cells: &'static [u8],
+}
+
+impl Iterator for Phandles {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Node> {
+        if self.value.len() < 4 {
+            return None;
+        }
+        let node = by_phandle(read_be_u32(self.value))?;
+        let bytes = 4 + node.u32(self.cells).unwrap_or(0) as usize * 4;
+        self.value = self.value.get(bytes..).unwrap_or(&[]);
+        Some(node)
+    }
+}
+
+// One interrupt specifier and the controller that decodes it.
+#[derive(Copy, Clone, Debug)]
+pub struct Interrupt {
+    pub controller: Node,
+    cells: &'static [u8],
+}
+
+impl Interrupt {
+    pub fn cell(&self, index: usize) -> Option<u32> {
+        let start = index * 4;
+        (start + 4 <= self.cells.len()).then(|| read_be_u32(&self.cells[start..]))
+    }
+}
+
+pub struct Interrupts {
+    value: &'static [u8],
+    // None for `interrupts-extended`, where each entry names its controller.
+    controller: Option<Node>,
+}
+
+impl Iterator for Interrupts {
+    type Item = Interrupt;
+
+    fn next(&mut self) -> Option<Interrupt> {
+        let controller = match self.controller {
+            Some(controller) => controller,
+            None => {
+                if self.value.len() < 4 {
+                    return None;
+                }
+                let controller = by_phandle(read_be_u32(self.value))?;
+                self.value = &self.value[4..];
+                controller
+            }
+        };
+        let bytes = controller.u32(b"#interrupt-cells").unwrap_or(1) as usize * 4;
+        if bytes == 0 || self.value.len() < bytes {
+            return None;
+        }
+        let cells = &self.value[..bytes];
+        self.value = &self.value[bytes..];
+        Some(Interrupt { controller, cells })
+    }
+}
+
+fn unit_name(name: &[u8]) -> &[u8] {
+    // Node name without its "@unit-address".
+    let end = name.iter().position(|&b| b == b'@').unwrap_or(name.len());
+    &name[..end]
+}
+
+fn read_cells(buf: &[u8], cells_wanted: u32) -> u64 {
+    let (cells, _) = buf.as_chunks::<4>();
+    cells
+        .iter()
+        .take(cells_wanted as usize)
+        .fold(0u64, |value, cell| {
+            (value << 32) | u32::from_be_bytes(*cell) as u64
+        })
+}
+
+fn read_addr_cells(buf: &[u8], cells: u32) -> u64 {
+    // Addresses wider than 64 bits (PCI's three cells) keep their low two cells.
+    if cells > 2 {
+        let start = (cells as usize - 2) * 4;
+        return read_cells(buf.get(start..).unwrap_or(&[]), 2);
+    }
+    read_cells(buf, cells)
+}
+
+fn get_string(strings: &[u8], offset: usize) -> &[u8] {
+    if offset >= strings.len() {
+        return &[];
+    }
+    let end = strings[offset..]
+        .iter()
+        .position(|&b| b == 0)
+        .map_or(strings.len(), |len| offset + len);
+    &strings[offset..end]
+}
+
+fn read_be_u32(buf: &[u8]) -> u32 {
+    ((buf[0] as u32) << 24) | ((buf[1] as u32) << 16) | ((buf[2] as u32) << 8) | (buf[3] as u32)
+}
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
@@ -1,29 +1,12 @@
 use crate::mm::bootalloc;
 use crate::mm::layout::{align_up, PAGE_SIZE};
+use crate::mm::pagecache;
 use crate::mm::region::{NormalizedMap, RegionKind};
 use crate::util::sync::SpinLock;
 
-#[cfg(feature = "rpi5")]
-const RP1_UART_FALLBACK: usize = 0x1c00_0300_00;
-
-#[cfg(feature = "rpi5")]
-#[inline(always)]
-unsafe fn early_uart_putc(b: u8) {
-    (RP1_UART_FALLBACK as *mut u32).write_volatile(b as u32);
-}
-
-#[cfg(feature = "rpi5")]
-fn early_uart_print(s: &str) {
-    for b in s.bytes() {
-        if b == b'\n' {
-            unsafe { early_uart_putc(b'\r'); }
-        }
-        unsafe { early_uart_putc(b); }
-    }
-}
-
 pub struct FrameAllocator {
     frame_count: usize,
+    free_count: usize,
     bitmap: &'static mut [u64],
 }
 
@@ -44,23 +27,18 @@ pub fn init(map: &NormalizedMap) {
     let bits = frame_count;
     let words = (bits + 63) / 64;
     let bytes = words * 8;
-    #[cfg(feature = "rpi5")]
-    early_uart_print("F0\n");
     let bitmap_paddr = match bootalloc::alloc(bytes, 8) {
         Some(addr) => addr,
         None => return,
     };
-    #[cfg(feature = "rpi5")]
-    early_uart_print("F1\n");
     let bitmap_ptr = bitmap_paddr as *mut u64;
     let bitmap = unsafe { core::slice::from_raw_parts_mut(bitmap_ptr, words) };
     for word in bitmap.iter_mut() {
         *word = u64::MAX;
     }
-    #[cfg(feature = "rpi5")]
-    early_uart_print("F2\n");
     let mut alloc = FrameAllocator {
         frame_count,
+        free_count: 0,
         bitmap,
     };
     for region in map.regions() {
@@ -70,32 +48,59 @@ pub fn init(map: &NormalizedMap) {
         // Mark usable RAM frames as free.
         alloc.mark_free(region.start, region.end);
     }
-    #[cfg(feature = "rpi5")]
-    early_uart_print("F3\n");
     // Reserve frames used by the boot allocator itself.
     let (boot_start, boot_end) = bootalloc::used_range();
     alloc.mark_used(boot_start, boot_end);
-    #[cfg(feature = "rpi5")]
-    early_uart_print("F4\n");
 
     let mut guard = FRAME_ALLOC.lock();
     *guard = Some(alloc);
-    #[cfg(feature = "rpi5")]
-    early_uart_print("F5\n");
 }
 
 pub fn alloc_frame() -> Option<u64> {
     // Allocate a single 4 KiB frame and return its physical address.
+    if let Some(paddr) = try_alloc_frame() {
+        return Some(paddr);
+    }
+    // Out of free frames: ask the page cache to give some back and retry.
+    if pagecache::reclaim(1) == 0 {
+        return None;
+    }
+    try_alloc_frame()
+}
+
+pub fn try_alloc_frame() -> Option<u64> {
+    // Allocate without reclaiming (used by the page cache itself).
     let mut guard = FRAME_ALLOC.lock();
     let alloc = guard.as_mut()?;
     alloc.alloc_frame()
 }
 
 pub fn alloc_contiguous(pages: usize) -> Option<u64> {
-    // Allocate a contiguous run of frames (useful for DMA).
-    let mut guard = FRAME_ALLOC.lock();
-    let alloc = guard.as_mut()?;
-    alloc.alloc_contiguous(pages)
+    // Allocate a contiguous run of frames (useful for DMA). Cached pages are
+    // scattered, so they are given back one at a time until a run opens up.
+    loop {
+        {
+            let mut guard = FRAME_ALLOC.lock();
+            let alloc = guard.as_mut()?;
+            if let Some(paddr) = alloc.alloc_contiguous(pages) {
+                return Some(paddr);
+            }
+        }
+        if pagecache::reclaim(1) == 0 {
+            return None;
+        }
+    }
+}
+
+pub fn free_frames() -> usize {
+    // Number of frames currently available for allocation.
+    let guard = FRAME_ALLOC.lock();
+    guard.as_ref().map(|alloc| alloc.free_count).unwrap_or(0)
+}
+
+pub fn total_frames() -> usize {
+    let guard = FRAME_ALLOC.lock();
+    guard.as_ref().map(|alloc| alloc.frame_count).unwrap_or(0)
 }
 
 pub fn free_frame(paddr: u64) {
@@ -186,6 +191,9 @@ impl FrameAllocator {
     fn set_bit(&mut self, idx: usize) {
         let word = idx / 64;
         let bit = idx % 64;
+        if self.bitmap[word] & (1u64 << bit) == 0 {
+            self.free_count -= 1;
+        }
         self.bitmap[word] |= 1u64 << bit;
     }
 
@@ -193,6 +201,9 @@ impl FrameAllocator {
     fn clear_bit(&mut self, idx: usize) {
         let word = idx / 64;
         let bit = idx % 64;
+        if self.bitmap[word] & (1u64 << bit) != 0 {
+            self.free_count += 1;
+        }
         self.bitmap[word] &= !(1u64 << bit);
     }
 }
@@ -39,6 +39,12 @@ pub fn init() {
     GLOBAL_ALLOC.init_kernel_heap();
 }
 
+pub fn stats() -> (usize, usize) {
+    // Return (size, used) of the kernel heap in bytes.
+    let heap = GLOBAL_ALLOC.kernel.lock();
+    (heap.size(), heap.used())
+}
+
 unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         // Use syscall-backed allocator for EL0, kernel heap for EL1.
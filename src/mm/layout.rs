@@ -5,6 +5,10 @@ pub const KERNEL_PHYS_BASE: u64 = 0x80000;
 // Use a canonical high-half VA (48-bit) with ample room for physmap.
 pub const KERNEL_VIRT_BASE: u64 = 0xFFFF_8000_0000_0000;
 pub const PHYS_MAP_BASE: u64 = KERNEL_VIRT_BASE;
+// Second view of physical memory for write-combining mappings (framebuffers),
+// above everything the physmap covers.
+pub const WC_MAP_BASE: u64 = KERNEL_VIRT_BASE + 0x40_0000_0000;
+pub const WC_MAP_SIZE: u64 = 0x40_0000_0000;
 
 #[inline(always)]
 pub const fn align_up(value: u64, align: u64) -> u64 {
@@ -0,0 +1,466 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::{RangeBounds, RangeInclusive};
+
+use crate::arch::aarch64::timer;
+use crate::drivers::block::{self, BlockDevice, DeviceId};
+use crate::kernel::user;
+use crate::mm::frame;
+use crate::mm::layout::{phys_to_virt, PAGE_SIZE};
+use crate::util::sync::SpinLock;
+
+// Upper bound on cached pages; LRU entries are evicted beyond this.
+const MAX_CACHE_PAGES: usize = 2048;
+// Start evicting before the frame allocator runs completely dry.
+const LOW_WATERMARK_FRAMES: usize = 256;
+// How often the flush daemon wakes up, and how old dirty data may get.
+pub const WRITEBACK_INTERVAL_MS: u64 = 5_000;
+const DIRTY_EXPIRE_MS: u64 = 30_000;
+
+// Device blocks are keyed by device and block number, file pages by inode
+// and page-aligned offset, so one device's or file's pages sit next to each
+// other in the map.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+enum CacheKey {
+    Block { dev: DeviceId, block: u64 },
+    Inode { ino: u64, offset: u64 },
+}
+
+impl CacheKey {
+    fn device_blocks(dev: DeviceId) -> RangeInclusive<CacheKey> {
+        CacheKey::Block { dev, block: 0 }..=CacheKey::Block {
+            dev,
+            block: u64::MAX,
+        }
+    }
+
+    fn inode_pages(ino: u64) -> RangeInclusive<CacheKey> {
+        CacheKey::Inode { ino, offset: 0 }..=CacheKey::Inode {
+            ino,
+            offset: u64::MAX,
+        }
+    }
+}
+
+pub trait CacheBacking: Sync {
+    fn fill(&self, ino: u64, offset: u64, page: &mut [u8]) -> bool;
+    fn write_back(&self, ino: u64, offset: u64, page: &[u8]) -> bool;
+
+    // A filesystem with no store behind the cache (ramfs) keeps its pages
+    // here for good: they are never written back or evicted, only dropped
+    // by `invalidate_inode`/`truncate_inode`.
+    fn pinned(&self) -> bool {
+        false
+    }
+}
+
+// Where a page's contents come from and go back to.
+#[derive(Copy, Clone)]
+enum Backing {
+    Device(&'static dyn BlockDevice),
+    File(&'static dyn CacheBacking),
+}
+
+impl Backing {
+    fn fill(self, key: CacheKey, page: &mut [u8]) -> bool {
+        match (self, key) {
+            (Backing::Device(dev), CacheKey::Block { block, .. }) => dev.read_block(block, page),
+            (Backing::File(file), CacheKey::Inode { ino, offset }) => file.fill(ino, offset, page),
+            _ => false,
+        }
+    }
+
+    fn pinned(self) -> bool {
+        match self {
+            Backing::Device(_) => false,
+            Backing::File(file) => file.pinned(),
+        }
+    }
+
+    fn write_back(self, key: CacheKey, page: &[u8]) -> bool {
+        match (self, key) {
+            (Backing::Device(dev), CacheKey::Block { block, .. }) => dev.write_block(block, page),
+            (Backing::File(file), CacheKey::Inode { ino, offset }) => {
+                file.write_back(ino, offset, page)
+            }
+            _ => false,
+        }
+    }
+}
+
+struct CacheEntry {
+    paddr: u64,
+    // A page, or one device block.
+    len: usize,
+    dirty: bool,
+    dirtied_ms: u64,
+    last_used: u64,
+    backing: Backing,
+    pinned: bool,
+}
+
+impl CacheEntry {
+    fn data(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(phys_to_virt(self.paddr) as *const u8, self.len) }
+    }
+
+    fn data_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(self.paddr) as *mut u8, self.len) }
+    }
+
+    fn mark_dirty(&mut self) {
+        // A pinned page is the only copy, so there is nothing to write back.
+        if !self.dirty && !self.pinned {
+            self.dirty = true;
+            self.dirtied_ms = timer::uptime_ms();
+        }
+    }
+
+    fn flush(&mut self, key: CacheKey, stats: &mut CacheStats) -> bool {
+        // Push dirty contents to the backing device or filesystem.
+        if !self.dirty {
+            return true;
+        }
+        if self.backing.write_back(key, self.data()) {
+            self.dirty = false;
+            stats.writebacks += 1;
+            true
+        } else {
+            stats.write_errors += 1;
+            false
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub writebacks: u64,
+    pub write_errors: u64,
+    pub pages: usize,
+    pub dirty: usize,
+    pub pinned: usize,
+}
+
+struct PageCache {
+    entries: BTreeMap<CacheKey, CacheEntry>,
+    // Pinned entries don't count against `MAX_CACHE_PAGES`.
+    pinned: usize,
+    clock: u64,
+    stats: CacheStats,
+}
+
+static CACHE: SpinLock<PageCache> = SpinLock::new(PageCache::new());
+
+impl PageCache {
+    const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            pinned: 0,
+            clock: 0,
+            stats: CacheStats {
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+                writebacks: 0,
+                write_errors: 0,
+                pages: 0,
+                dirty: 0,
+                pinned: 0,
+            },
+        }
+    }
+
+    fn lookup(&mut self, key: CacheKey, backing: Backing, len: usize) -> Option<&mut CacheEntry> {
+        // Return the entry for `key`, filling it from the backing store on a miss.
+        if self.entries.contains_key(&key) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            let paddr = self.alloc_page()?;
+            let mut entry = CacheEntry {
+                paddr,
+                len,
+                dirty: false,
+                dirtied_ms: 0,
+                last_used: 0,
+                backing,
+                pinned: backing.pinned(),
+            };
+            if !backing.fill(key, entry.data_mut()) {
+                frame::free_frame(paddr);
+                return None;
+            }
+            self.pinned += entry.pinned as usize;
+            self.entries.insert(key, entry);
+        }
+        self.clock += 1;
+        let entry = self.entries.get_mut(&key)?;
+        entry.last_used = self.clock;
+        Some(entry)
+    }
+
+    fn alloc_page(&mut self) -> Option<u64> {
+        // Recycle the LRU page when the cache is full or memory is tight.
+        if self.entries.len() - self.pinned >= MAX_CACHE_PAGES
+            || frame::free_frames() < LOW_WATERMARK_FRAMES
+        {
+            if let Some(paddr) = self.evict_lru(true) {
+                return Some(paddr);
+            }
+        }
+        if let Some(paddr) = frame::try_alloc_frame() {
+            return Some(paddr);
+        }
+        self.evict_lru(true)
+    }
+
+    fn evict_lru(&mut self, write_back: bool) -> Option<u64> {
+        // Prefer the least recently used clean page; fall back to writing one
+        // back if `write_back` allows it. Pinned pages are never chosen.
+        let victim = self
+            .entries
+            .iter()
+            .filter(|(_, e)| !e.dirty && !e.pinned)
+            .min_by_key(|(_, e)| e.last_used)
+            .map(|(key, _)| *key)
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .filter(|(_, e)| write_back && !e.pinned)
+                    .min_by_key(|(_, e)| e.last_used)
+                    .map(|(key, _)| *key)
+            })?;
+        let entry = self.entries.get_mut(&victim)?;
+        if !entry.flush(victim, &mut self.stats) {
+            return None;
+        }
+        let entry = self.entries.remove(&victim)?;
+        self.stats.evictions += 1;
+        Some(entry.paddr)
+    }
+
+    fn remove(&mut self, key: CacheKey) {
+        // Drop an entry without writing it back.
+        if let Some(entry) = self.entries.remove(&key) {
+            self.pinned -= entry.pinned as usize;
+            frame::free_frame(entry.paddr);
+        }
+    }
+
+    fn flush_range(
+        &mut self,
+        range: impl RangeBounds<CacheKey>,
+        pred: impl Fn(&CacheEntry) -> bool,
+    ) -> usize {
+        let mut flushed = 0;
+        for (key, entry) in self.entries.range_mut(range) {
+            if entry.dirty && pred(entry) && entry.flush(*key, &mut self.stats) {
+                flushed += 1;
+            }
+        }
+        flushed
+    }
+}
+
+pub fn read_inode(
+    ino: u64,
+    offset: u64,
+    backing: &'static dyn CacheBacking,
+    buf: &mut [u8],
+) -> usize {
+    // Read file data page by page; stops short if a page can't be filled.
+    let mut cache = CACHE.lock();
+    let mut done = 0usize;
+    while done < buf.len() {
+        let pos = offset + done as u64;
+        let page_off = (pos % PAGE_SIZE as u64) as usize;
+        let key = CacheKey::Inode {
+            ino,
+            offset: pos - page_off as u64,
+        };
+        let data = match cache.lookup(key, Backing::File(backing), PAGE_SIZE) {
+            Some(entry) => entry.data(),
+            None => break,
+        };
+        let n = (buf.len() - done).min(PAGE_SIZE - page_off);
+        buf[done..done + n].copy_from_slice(&data[page_off..page_off + n]);
+        done += n;
+    }
+    done
+}
+
+pub fn write_inode(ino: u64, offset: u64, backing: &'static dyn CacheBacking, buf: &[u8]) -> usize {
+    // Write file data into cached pages and mark them dirty.
+    let mut cache = CACHE.lock();
+    let mut done = 0usize;
+    while done < buf.len() {
+        let pos = offset + done as u64;
+        let page_off = (pos % PAGE_SIZE as u64) as usize;
+        let key = CacheKey::Inode {
+            ino,
+            offset: pos - page_off as u64,
+        };
+        let entry = match cache.lookup(key, Backing::File(backing), PAGE_SIZE) {
+            Some(entry) => entry,
+            None => break,
+        };
+        let n = (buf.len() - done).min(PAGE_SIZE - page_off);
+        entry.data_mut()[page_off..page_off + n].copy_from_slice(&buf[done..done + n]);
+        entry.mark_dirty();
+        done += n;
+    }
+    done
+}
+
+pub fn read_device(dev: DeviceId, offset: u64, buf: &mut [u8]) -> usize {
+    // Read device data block by block; stops at the end of the device or
+    // at a block that can't be read.
+    let Some(node) = block::get(dev) else {
+        return 0;
+    };
+    let block_size = node.driver.block_size();
+    let len = buf.len().min(node.size().saturating_sub(offset) as usize);
+    let mut cache = CACHE.lock();
+    let mut done = 0usize;
+    while done < len {
+        let pos = offset + done as u64;
+        let block_off = (pos % block_size as u64) as usize;
+        let key = CacheKey::Block {
+            dev,
+            block: pos / block_size as u64,
+        };
+        let data = match cache.lookup(key, Backing::Device(node.driver), block_size) {
+            Some(entry) => entry.data(),
+            None => break,
+        };
+        let n = (len - done).min(block_size - block_off);
+        buf[done..done + n].copy_from_slice(&data[block_off..block_off + n]);
+        done += n;
+    }
+    done
+}
+
+pub fn write_device(dev: DeviceId, offset: u64, buf: &[u8]) -> usize {
+    // Write device data into cached blocks and mark them dirty. Partial
+    // blocks are read in first.
+    let Some(node) = block::get(dev) else {
+        return 0;
+    };
+    let block_size = node.driver.block_size();
+    let len = buf.len().min(node.size().saturating_sub(offset) as usize);
+    let mut cache = CACHE.lock();
+    let mut done = 0usize;
+    while done < len {
+        let pos = offset + done as u64;
+        let block_off = (pos % block_size as u64) as usize;
+        let key = CacheKey::Block {
+            dev,
+            block: pos / block_size as u64,
+        };
+        let entry = match cache.lookup(key, Backing::Device(node.driver), block_size) {
+            Some(entry) => entry,
+            None => break,
+        };
+        let n = (len - done).min(block_size - block_off);
+        entry.data_mut()[block_off..block_off + n].copy_from_slice(&buf[done..done + n]);
+        entry.mark_dirty();
+        done += n;
+    }
+    done
+}
+
+pub fn sync_device(dev: DeviceId) -> usize {
+    // Write back dirty blocks of a single device (fsync on its node).
+    CACHE
+        .lock()
+        .flush_range(CacheKey::device_blocks(dev), |_| true)
+}
+
+pub fn sync() -> usize {
+    // Write back every dirty page (sync).
+    CACHE.lock().flush_range(.., |_| true)
+}
+
+pub fn writeback_expired() -> usize {
+    // Timer-driven write-back of pages that have been dirty for too long.
+    let now = timer::uptime_ms();
+    CACHE
+        .lock()
+        .flush_range(.., |e| now.saturating_sub(e.dirtied_ms) >= DIRTY_EXPIRE_MS)
+}
+
+pub fn invalidate_inode(ino: u64) {
+    // Drop cached pages of a file without writing them back (unlink).
+    truncate_inode(ino, 0);
+}
+
+pub fn truncate_inode(ino: u64, len: u64) {
+    // Drop cached pages of a file past `len` without writing them back, and
+    // zero the rest of the page `len` falls in.
+    let mut cache = CACHE.lock();
+    let tail = (len % PAGE_SIZE as u64) as usize;
+    let keep = len.div_ceil(PAGE_SIZE as u64) * PAGE_SIZE as u64;
+    let keys: Vec<CacheKey> = cache
+        .entries
+        .range(CacheKey::inode_pages(ino))
+        .map(|(key, _)| *key)
+        .filter(|key| matches!(key, CacheKey::Inode { offset, .. } if *offset >= keep))
+        .collect();
+    for key in keys {
+        cache.remove(key);
+    }
+    if tail != 0 {
+        let key = CacheKey::Inode {
+            ino,
+            offset: len - tail as u64,
+        };
+        if let Some(entry) = cache.entries.get_mut(&key) {
+            entry.data_mut()[tail..].fill(0);
+            entry.mark_dirty();
+        }
+    }
+}
+
+pub fn reclaim(pages: usize) -> usize {
+    // Called by the frame allocator under memory pressure. Never blocks on the
+    // cache lock: the caller may be the cache itself allocating a page. Only
+    // clean pages are given back, since writing one back may need a lock the
+    // allocating caller holds (the RAM disk allocates under its own).
+    let mut cache = match CACHE.try_lock() {
+        Some(cache) => cache,
+        None => return 0,
+    };
+    let mut freed = 0;
+    while freed < pages {
+        match cache.evict_lru(false) {
+            Some(paddr) => {
+                frame::free_frame(paddr);
+                freed += 1;
+            }
+            None => break,
+        }
+    }
+    freed
+}
+
+pub fn stats() -> CacheStats {
+    let cache = CACHE.lock();
+    let mut stats = cache.stats;
+    stats.pages = cache.entries.len();
+    stats.dirty = cache.entries.values().filter(|e| e.dirty).count();
+    stats.pinned = cache.pinned;
+    stats
+}
+
+pub extern "C" fn flush_daemon() -> ! {
+    // Kernel process that periodically writes back expired dirty pages. It
+    // sleeps through the same SVC as user processes, blocked on a `wake_at`
+    // deadline between rounds.
+    loop {
+        user::sleep_ms(WRITEBACK_INTERVAL_MS);
+        let _ = writeback_expired();
+    }
+}
@@ -1,9 +1,14 @@
 #![allow(static_mut_refs)]
 
 use crate::arch::aarch64::mmu;
-use crate::mm::layout::{align_down, align_up, phys_to_virt, virt_to_phys, KERNEL_VIRT_BASE};
+use crate::mm::layout::{
+    align_down, align_up, phys_to_virt, virt_to_phys, KERNEL_VIRT_BASE, WC_MAP_BASE, WC_MAP_SIZE,
+};
 use crate::mm::region::{NormalizedMap, RegionKind};
 use crate::platform::board;
+use crate::kernel::klog;
+use crate::util::sync::SpinLock;
+use crate::{kerr, kinfo};
 
 const L2_TABLES: usize = 1024;
 
@@ -15,44 +20,6 @@ const RP1_BASE: u64 = 0x0000_001c_0000_0000;
 #[cfg(feature = "rpi5")]
 const RP1_SIZE: u64 = 0x4000_0000; // 1 GiB
 
-#[cfg(feature = "rpi5")]
-#[inline(always)]
-fn early_uart_print(s: &str) {
-    const RP1_UART_FALLBACK: usize = 0x1c00_0300_00;
-    for b in s.bytes() {
-        if b == b'\n' {
-            unsafe { (RP1_UART_FALLBACK as *mut u32).write_volatile(b'\r' as u32) };
-        }
-        unsafe { (RP1_UART_FALLBACK as *mut u32).write_volatile(b as u32) };
-    }
-}
-
-#[cfg(feature = "rpi5")]
-#[inline(always)]
-fn early_uart_delay() {
-    for _ in 0..200_000 {
-        unsafe { core::arch::asm!("nop", options(nomem, nostack, preserves_flags)) }
-    }
-}
-
-#[cfg(feature = "rpi5")]
-fn early_uart_print_slow(s: &str) {
-    for b in s.bytes() {
-        if b == b'\n' {
-            unsafe { (0x1c00_0300_00 as *mut u32).write_volatile(b'\r' as u32) };
-            early_uart_delay();
-        }
-        unsafe { (0x1c00_0300_00 as *mut u32).write_volatile(b as u32) };
-        early_uart_delay();
-    }
-}
-
-#[cfg(feature = "rpi5")]
-fn early_mark(tag: &str) {
-    early_uart_print_slow(tag);
-    early_uart_print_slow("\n");
-}
-
 #[repr(align(4096))]
 struct PageTable([u64; 512]);
 
@@ -85,6 +52,9 @@ static mut USER_ROOT_PA: u64 = 0;
 static mut EXTRA_MMIO_BASE: u64 = 0;
 static mut EXTRA_MMIO_SIZE: u64 = 0;
 
+// Serializes changes to the kernel tables after boot.
+static MAP_LOCK: SpinLock<()> = SpinLock::new(());
+
 const DESC_BLOCK: u64 = 0b01;
 const DESC_TABLE: u64 = 0b11;
 const AF_BIT: u64 = 1 << 10;
@@ -93,6 +63,7 @@ const PXN_BIT: u64 = 1 << 53;
 
 const ATTR_DEVICE: u64 = 0;
 const ATTR_NORMAL: u64 = 1;
+const ATTR_NORMAL_NC: u64 = 2;
 
 const AP_EL1_RW: u64 = 0b00;
 const AP_EL0_RW: u64 = 0b01;
@@ -102,15 +73,7 @@ const SH_INNER: u64 = 0b11;
 
 pub fn init(map: &NormalizedMap) {
     unsafe {
-        #[cfg(feature = "rpi5")]
-        {
-            early_mark("P0");
-        }
-        #[cfg(feature = "qemu")]
-        crate::drivers::uart::with_uart(|uart| {
-            use core::fmt::Write;
-            let _ = writeln!(uart, "paging: build tables");
-        });
+        kinfo!("paging: build tables");
 
         // Initialize kernel tables (TTBR1) for higher-half mapping.
         K_L0.zero();
@@ -174,38 +137,17 @@ pub fn init(map: &NormalizedMap) {
             );
         }
 
-        #[cfg(feature = "rpi5")]
-        early_mark("P1");
-
         map_mmio();
 
-        #[cfg(feature = "rpi5")]
-        early_mark("P2");
-
         KERNEL_ROOT_PA = virt_to_phys(&K_L0 as *const _ as usize);
         USER_ROOT_PA = virt_to_phys(&U_L0 as *const _ as usize);
 
-        #[cfg(feature = "rpi5")]
-        {
-            early_mark("P3");
-        }
-        #[cfg(feature = "qemu")]
-        crate::drivers::uart::with_uart(|uart| {
-            use core::fmt::Write;
-            let _ = writeln!(
-                uart,
-                "paging: enable mmu ttbr0={:#x} ttbr1={:#x}",
-                USER_ROOT_PA, KERNEL_ROOT_PA
-            );
-        });
+        kinfo!(
+            "paging: enable mmu ttbr0={:#x} ttbr1={:#x}",
+            USER_ROOT_PA, KERNEL_ROOT_PA
+        );
         mmu::enable_mmu(USER_ROOT_PA, KERNEL_ROOT_PA);
-        #[cfg(feature = "rpi5")]
-        early_mark("P4");
-        #[cfg(feature = "qemu")]
-        crate::drivers::uart::with_uart(|uart| {
-            use core::fmt::Write;
-            let _ = writeln!(uart, "paging: mmu enabled");
-        });
+        kinfo!("paging: mmu enabled");
     }
 }
 
@@ -216,6 +158,33 @@ pub fn set_extra_mmio(base: u64, size: u64) {
     }
 }
 
+pub fn map_write_combining(pa: u64, size: u64) -> Option<usize> {
+    // Map [pa, pa + size) at `WC_MAP_BASE + pa` as normal non-cacheable
+    // memory, so bulk CPU writes are merged on their way to the device.
+    // Whole 2 MiB blocks are mapped; mapping a range again is harmless.
+    if size == 0 || pa.checked_add(size)? > WC_MAP_SIZE {
+        return None;
+    }
+    let _guard = MAP_LOCK.lock();
+    unsafe {
+        map_range_with(
+            &mut K_L1,
+            &mut K_L2_POOL,
+            &mut K_NEXT_L2,
+            WC_MAP_BASE + pa,
+            pa,
+            size,
+            ATTR_NORMAL_NC,
+            AP_EL1_RW,
+            SH_INNER,
+            true,
+        );
+        // The blocks were invalid before, so no TLB entries need dropping.
+        core::arch::asm!("dsb ishst", "isb", options(nostack, preserves_flags));
+    }
+    Some((WC_MAP_BASE + pa) as usize)
+}
+
 pub fn user_root_pa() -> u64 {
     unsafe { USER_ROOT_PA }
 }
@@ -448,10 +417,8 @@ unsafe fn get_l2_table_with<'a>(
     }
     let idx = *next_l2;
     if idx >= L2_TABLES {
-        #[cfg(feature = "rpi5")]
-        {
-            early_mark("PX");
-        }
+        kerr!("paging: out of L2 tables");
+        klog::flush_emergency();
         loop {
             core::arch::asm!("wfe", options(nomem, nostack, preserves_flags));
         }
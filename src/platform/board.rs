@@ -4,26 +4,33 @@ compile_error!("Select only one board feature: rpi5 (default) or qemu.");
 #[cfg(not(any(feature = "rpi5", feature = "qemu")))]
 compile_error!("Select a board feature: rpi5 (default) or qemu.");
 
-// Raspberry Pi 5 (BCM2712)
+// Raspberry Pi 5 (BCM2712). The device addresses are fallbacks; drivers take
+// theirs from the device tree when probed (src/drivers/device.rs).
 #[cfg(feature = "rpi5")]
 pub const SOC_BASE: usize = 0x107c_0000_00;
 #[cfg(feature = "rpi5")]
 pub const SOC_MMIO_SIZE: usize = 0x8000_0000;
+// SoC bus address 0x7c00_0000 is SOC_BASE, so each offset below is the
+// device tree unit address (mailbox@7c013880, ...) minus 0x7c00_0000.
 #[cfg(feature = "rpi5")]
-pub const GICD_BASE: usize = SOC_BASE + 0x7fff_9000;
+pub const GICD_BASE: usize = SOC_BASE + 0x03ff_9000;
 #[cfg(feature = "rpi5")]
-pub const GICC_BASE: usize = SOC_BASE + 0x7fff_c000;
+pub const GICC_BASE: usize = SOC_BASE + 0x03ff_c000;
 #[cfg(feature = "rpi5")]
-pub const MBOX_BASE: usize = SOC_BASE + 0x7c01_3880;
+pub const MBOX_BASE: usize = SOC_BASE + 0x0001_3880;
 #[cfg(feature = "rpi5")]
 #[allow(dead_code)]
-pub const UART_BASE: usize = SOC_BASE + 0x7d00_1000;
+pub const UART_BASE: usize = SOC_BASE + 0x0100_1000;
+// The RP1 debug UART as the firmware leaves it, for when the device tree
+// names no console UART.
+#[cfg(feature = "rpi5")]
+pub const RP1_UART_BASE: usize = 0x1c_0003_0000;
 #[cfg(feature = "rpi5")]
 pub const VC_MEM_BASE: u32 = 0x0000_0000; // DMA is identity-mapped on BCM2712
 #[cfg(feature = "rpi5")]
 pub const VC_MEM_MASK: u32 = 0xFFFF_FFFF;
 
-// QEMU (raspi3b)
+// QEMU (raspi3b). As above, the device tree's addresses win once probed.
 #[cfg(feature = "qemu")]
 pub const PERIPHERAL_BASE: usize = 0x3F00_0000;
 #[cfg(feature = "qemu")]
@@ -2,6 +2,16 @@
 pub enum SimpleFbFormat {
     X8R8G8B8,
     A8R8G8B8,
+    R5G6B5,
+}
+
+impl SimpleFbFormat {
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            SimpleFbFormat::X8R8G8B8 | SimpleFbFormat::A8R8G8B8 => 4,
+            SimpleFbFormat::R5G6B5 => 2,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -1 +1,20 @@
+pub mod display;
 pub mod shell;
+
+use crate::kernel::process::ProcessEntry;
+
+// Programs linked into the kernel image, by the name `exec` takes.
+const PROGRAMS: [(&str, ProcessEntry); 5] = [
+    ("shell", shell::user_shell),
+    ("display", display::server::display_server),
+    ("hello", display::demo::display_hello),
+    ("bounce", display::demo::display_bounce),
+    ("view", display::demo::display_view),
+];
+
+pub fn find(name: &[u8]) -> Option<(&'static str, ProcessEntry)> {
+    PROGRAMS
+        .iter()
+        .copied()
+        .find(|(program, _)| program.as_bytes() == name)
+}
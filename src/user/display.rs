@@ -0,0 +1,8 @@
+// Windowing in user space: a display server that owns the framebuffer and
+// composites client windows, the protocol it speaks and a client library
+// with three demo programs (docs/display.md).
+
+pub mod client;
+pub mod demo;
+pub mod proto;
+pub mod server;
@@ -0,0 +1,204 @@
+// Client side of the display protocol: connect to the server, create
+// windows backed by shared memory and wait for input events.
+
+use alloc::collections::VecDeque;
+
+use super::proto::{
+    self, CreateWindow, Damage, DestroyWindow, Error, Focus, Key, Message, MoveWindow,
+    MAX_KEY_BYTES, MAX_TITLE, MAX_WINDOW_SIZE, REQ_CREATE_WINDOW,
+};
+use crate::gfx::pixel::Format;
+use crate::gfx::surface::{Rect, Surface};
+use crate::kernel::user::{
+    self, PollFd, ShmRequest, DISPLAY_SHM_CREATE, EINVAL, MAP_SHARED, MAX_MESSAGE, O_READ, O_WRITE,
+    POLLIN, PROT_READ, PROT_WRITE,
+};
+
+// EPIPE as a positive errno, for a server that went away.
+const EPIPE: u64 = 32;
+
+pub enum Event {
+    Key {
+        window: u32,
+        bytes: [u8; MAX_KEY_BYTES],
+        len: usize,
+    },
+    Focus {
+        window: u32,
+        focused: bool,
+    },
+    // The server closed the connection; its windows are gone.
+    Hangup,
+}
+
+pub struct Window {
+    pub id: u32,
+    // Shared with the server until the connection closes. The memory is
+    // freed then, so the surface must not be used after its `Display` is
+    // dropped.
+    pub surface: Surface<'static>,
+}
+
+pub struct Display {
+    fd: u64,
+    // Events that arrived while waiting for a reply.
+    pending: VecDeque<Event>,
+}
+
+impl Display {
+    pub fn connect() -> Result<Self, u64> {
+        // ECONNREFUSED until the server is listening.
+        let fd = user::open("/dev/display", O_READ | O_WRITE);
+        if user::is_error(fd) {
+            return Err(fd.wrapping_neg());
+        }
+        Ok(Self {
+            fd,
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn send<T: Message>(&self, msg: &T) -> Result<(), u64> {
+        let ret = user::write_bytes(self.fd, proto::encode(msg));
+        if user::is_error(ret) {
+            return Err(ret.wrapping_neg());
+        }
+        Ok(())
+    }
+
+    fn receive(&self) -> Option<([u8; MAX_MESSAGE], usize)> {
+        // One message, blocking; None once the server hung up.
+        let mut buf = [0u8; MAX_MESSAGE];
+        let n = user::read(self.fd, &mut buf);
+        if user::is_error(n) || n == 0 {
+            return None;
+        }
+        Some((buf, n as usize))
+    }
+
+    pub fn create_window(&mut self, width: u32, height: u32, title: &str) -> Result<Window, u64> {
+        if !(1..=MAX_WINDOW_SIZE).contains(&width) || !(1..=MAX_WINDOW_SIZE).contains(&height) {
+            return Err(EINVAL);
+        }
+        let mut shm = ShmRequest {
+            size: width as u64 * height as u64 * 4,
+            key: 0,
+        };
+        let ret = user::ioctl(
+            self.fd,
+            DISPLAY_SHM_CREATE,
+            &mut shm as *mut ShmRequest as u64,
+        );
+        if user::is_error(ret) {
+            return Err(ret.wrapping_neg());
+        }
+        let prot = PROT_READ | PROT_WRITE;
+        let addr = user::mmap(shm.size as usize, prot, MAP_SHARED, self.fd, shm.key);
+        if user::is_error(addr) {
+            return Err(addr.wrapping_neg());
+        }
+        let title = &title.as_bytes()[..title.len().min(MAX_TITLE)];
+        let mut req = CreateWindow {
+            op: CreateWindow::OP,
+            width,
+            height,
+            title_len: title.len() as u32,
+            shm_key: shm.key,
+            ..CreateWindow::default()
+        };
+        req.title[..title.len()].copy_from_slice(title);
+        self.send(&req)?;
+        loop {
+            let (buf, n) = self.receive().ok_or(EPIPE)?;
+            let bytes = &buf[..n];
+            if let Some(created) = proto::decode::<proto::Created>(bytes) {
+                let surface = unsafe {
+                    Surface::from_raw(addr as *mut u8, width, height, width * 4, Format::X8R8G8B8)
+                }
+                .ok_or(EINVAL)?;
+                return Ok(Window {
+                    id: created.window,
+                    surface,
+                });
+            }
+            match proto::decode::<Error>(bytes) {
+                Some(err) if err.request == REQ_CREATE_WINDOW => return Err(err.errno as u64),
+                _ => self.pending.extend(parse_event(bytes)),
+            }
+        }
+    }
+
+    pub fn damage(&self, window: &Window, rect: Rect) -> Result<(), u64> {
+        // Tell the server `rect` of the window was redrawn.
+        self.send(&Damage {
+            op: Damage::OP,
+            window: window.id,
+            x: rect.x,
+            y: rect.y,
+            w: rect.w,
+            h: rect.h,
+        })
+    }
+
+    pub fn move_window(&self, window: &Window, x: i32, y: i32) -> Result<(), u64> {
+        self.send(&MoveWindow {
+            op: MoveWindow::OP,
+            window: window.id,
+            x,
+            y,
+        })
+    }
+
+    pub fn destroy_window(&self, window: Window) -> Result<(), u64> {
+        // The pixels stay mapped until the connection closes.
+        self.send(&DestroyWindow {
+            op: DestroyWindow::OP,
+            window: window.id,
+        })
+    }
+
+    pub fn next_event(&mut self, timeout_ms: i64) -> Option<Event> {
+        // Negative timeout: wait for an event. None when the timeout ran out.
+        if let Some(event) = self.pending.pop_front() {
+            return Some(event);
+        }
+        let mut fds = [PollFd {
+            fd: self.fd as i32,
+            events: POLLIN,
+            revents: 0,
+        }];
+        loop {
+            let ret = user::poll(&mut fds, timeout_ms);
+            if user::is_error(ret) || fds[0].revents == 0 {
+                return None;
+            }
+            let Some((buf, n)) = self.receive() else {
+                return Some(Event::Hangup);
+            };
+            if let Some(event) = parse_event(&buf[..n]) {
+                return Some(event);
+            }
+        }
+    }
+}
+
+impl Drop for Display {
+    fn drop(&mut self) {
+        let _ = user::close(self.fd);
+    }
+}
+
+fn parse_event(bytes: &[u8]) -> Option<Event> {
+    // Events this side understands; unknown ones are skipped.
+    if let Some(key) = proto::decode::<Key>(bytes) {
+        return Some(Event::Key {
+            window: key.window,
+            bytes: key.bytes,
+            len: (key.len as usize).min(MAX_KEY_BYTES),
+        });
+    }
+    proto::decode::<Focus>(bytes).map(|focus| Event::Focus {
+        window: focus.window,
+        focused: focus.focused != 0,
+    })
+}
@@ -0,0 +1,228 @@
+// Demo clients for the display server: `hello` echoes what is typed into
+// its window and can be moved and closed, `bounce` animates a ball and
+// `view` shows an image file.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::client::{Display, Event, Window};
+use crate::gfx::font::Font;
+use crate::gfx::image::{self, Image, MAX_IMAGE_BYTES};
+use crate::gfx::pixel;
+use crate::gfx::surface::Rect;
+use crate::kernel::user::{self, Stat, O_READ};
+use crate::kernel::vfs;
+
+const BACKGROUND: u32 = pixel::rgb(0xf0, 0xf0, 0xe8);
+const INK: u32 = pixel::rgb(0x20, 0x20, 0x20);
+const DIM: u32 = pixel::rgb(0x90, 0x90, 0x90);
+const BALL: u32 = pixel::rgb(0xd0, 0x40, 0x30);
+const FRAME_MS: i64 = 40;
+// Where `hello` puts its window, and how far a cursor key moves it.
+const HELLO_POS: (i32, i32) = (64, 320);
+const MOVE_STEP: i32 = 8;
+// Ctrl+W closes the `hello` window.
+const CLOSE: u8 = 0x17;
+// The image `view` shows; the kernel puts its splash there at boot.
+const VIEW_PATH: &str = "/splash.png";
+// Space around the image in the `view` window.
+const VIEW_MARGIN: u32 = 8;
+
+fn connect() -> Display {
+    // The server may start after us; wait for it to listen.
+    loop {
+        match Display::connect() {
+            Ok(display) => return display,
+            Err(_) => {
+                let _ = user::sleep_ms(200);
+            }
+        }
+    }
+}
+
+fn idle() -> ! {
+    // Processes cannot exit yet.
+    loop {
+        let _ = user::sleep_ms(60_000);
+    }
+}
+
+fn draw_hello(window: &mut Window, font: &Font, text: &str, focused: bool) {
+    let surface = &mut window.surface;
+    let (width, height) = (surface.width(), surface.height());
+    // Top half: a gradient, bottom half: the text typed so far.
+    for y in 0..height / 2 {
+        for x in 0..width {
+            let r = (x * 255 / width) as u8;
+            let b = (y * 255 / (height / 2)) as u8;
+            surface.put_pixel(x as i32, y as i32, pixel::rgb(r, 0x80, b));
+        }
+    }
+    let text_area = Rect::new(0, (height / 2) as i32, width, height - height / 2);
+    surface.fill_rect(text_area, BACKGROUND);
+    surface.line(0, text_area.y, width as i32 - 1, text_area.y, DIM);
+    let color = if focused { INK } else { DIM };
+    let end = surface.text(font, 8, text_area.y + 8, text, color);
+    if focused {
+        let cursor = Rect::new(end, text_area.y + 8, 2, font.height as u32);
+        surface.fill_rect(cursor, INK);
+    }
+}
+
+fn arrow(keys: &[u8]) -> Option<(i32, i32)> {
+    // Cursor keys (ESC [ A to D) as a window move.
+    match keys {
+        [0x1b, b'[', b'A', ..] => Some((0, -MOVE_STEP)),
+        [0x1b, b'[', b'B', ..] => Some((0, MOVE_STEP)),
+        [0x1b, b'[', b'C', ..] => Some((MOVE_STEP, 0)),
+        [0x1b, b'[', b'D', ..] => Some((-MOVE_STEP, 0)),
+        _ => None,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn display_hello() -> ! {
+    let mut display = connect();
+    let Ok(mut window) = display.create_window(320, 120, "hello") else {
+        idle();
+    };
+    let (mut x, mut y) = HELLO_POS;
+    let _ = display.move_window(&window, x, y);
+    let font = Font::builtin();
+    let fits = 320 / font.width - 3;
+    let mut text = String::new();
+    let mut focused = false;
+    loop {
+        draw_hello(&mut window, &font, &text, focused);
+        let _ = display.damage(&window, window.surface.bounds());
+        match display.next_event(-1) {
+            Some(Event::Key {
+                window: id,
+                bytes,
+                len,
+            }) if id == window.id => {
+                let mut keys = &bytes[..len];
+                while let Some(&byte) = keys.first() {
+                    if let Some((dx, dy)) = arrow(keys) {
+                        (x, y) = (x + dx, y + dy);
+                        let _ = display.move_window(&window, x, y);
+                        keys = &keys[3..];
+                        continue;
+                    }
+                    match byte {
+                        CLOSE => {
+                            // Dropping the connection frees the window's
+                            // memory once the server has let go of it.
+                            let _ = display.destroy_window(window);
+                            drop(display);
+                            idle();
+                        }
+                        0x08 | 0x7f => {
+                            text.pop();
+                        }
+                        b'\r' | b'\n' => text.clear(),
+                        0x20..=0x7e if text.len() < fits => text.push(byte as char),
+                        _ => {}
+                    }
+                    keys = &keys[1..];
+                }
+            }
+            Some(Event::Focus {
+                window: id,
+                focused: now,
+            }) if id == window.id => focused = now,
+            Some(Event::Hangup) => idle(),
+            _ => {}
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn display_bounce() -> ! {
+    let mut display = connect();
+    let Ok(mut window) = display.create_window(240, 160, "bounce") else {
+        idle();
+    };
+    let radius = 12;
+    let (width, height) = (240, 160);
+    let (mut x, mut y, mut dx, mut dy) = (radius, radius, 3, 2);
+    loop {
+        let surface = &mut window.surface;
+        surface.clear(BACKGROUND);
+        surface.rect(surface.bounds(), DIM);
+        surface.fill_circle(x, y, radius as u32, BALL);
+        surface.circle(x, y, radius as u32, INK);
+        let _ = display.damage(&window, window.surface.bounds());
+        // Input is ignored, but waiting on events paces the frames.
+        if let Some(Event::Hangup) = display.next_event(FRAME_MS) {
+            idle();
+        }
+        if !(radius..width - radius).contains(&(x + dx)) {
+            dx = -dx;
+        }
+        if !(radius..height - radius).contains(&(y + dy)) {
+            dy = -dy;
+        }
+        x += dx;
+        y += dy;
+    }
+}
+
+fn read_file(path: &str) -> Option<Vec<u8>> {
+    // The whole file, if it fits the limit an image is decoded under.
+    let fd = user::open(path, O_READ);
+    if user::is_error(fd) {
+        return None;
+    }
+    let mut st = Stat::default();
+    let size = if user::is_error(user::fstat(fd, &mut st)) {
+        None
+    } else {
+        usize::try_from(st.size)
+            .ok()
+            .filter(|&size| size <= MAX_IMAGE_BYTES)
+    };
+    let mut data = vec![0u8; size.unwrap_or(0)];
+    let mut done = 0;
+    while done < data.len() {
+        let n = user::read(fd, &mut data[done..]);
+        if user::is_error(n) || n == 0 {
+            break;
+        }
+        done += n as usize;
+    }
+    let _ = user::close(fd);
+    data.truncate(done);
+    size.map(|_| data)
+}
+
+fn load_image(path: &str) -> Option<Image> {
+    let bytes = read_file(path)?;
+    image::decode(&bytes).ok()
+}
+
+#[no_mangle]
+pub extern "C" fn display_view() -> ! {
+    let Some(mut image) = load_image(VIEW_PATH) else {
+        let _ = user::write(vfs::FD_STDOUT as u64, "view: cannot load /splash.png\n");
+        idle();
+    };
+    let mut display = connect();
+    let width = image.width() + 2 * VIEW_MARGIN;
+    let height = image.height() + 2 * VIEW_MARGIN;
+    let Ok(mut window) = display.create_window(width, height, "view") else {
+        idle();
+    };
+    let surface = &mut window.surface;
+    surface.clear(BACKGROUND);
+    let src = image.surface();
+    surface.blit_blend(&src, src.bounds(), VIEW_MARGIN as i32, VIEW_MARGIN as i32);
+    let _ = display.damage(&window, window.surface.bounds());
+    // Nothing to redraw: the server keeps the window contents.
+    loop {
+        if let Some(Event::Hangup) = display.next_event(-1) {
+            idle();
+        }
+    }
+}
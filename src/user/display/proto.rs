@@ -0,0 +1,146 @@
+// Display protocol: requests from clients and events from the server, one
+// per channel message. Every message is a repr(C) struct starting with its
+// opcode; both sides run on this machine, so there is no byte swapping.
+//
+// Window contents live in shared memory the client allocates on its
+// connection (DISPLAY_SHM_CREATE) and names by key in `CreateWindow`:
+// `width * height` X8R8G8B8 pixels with no row padding.
+
+use core::mem::size_of;
+
+use crate::kernel::user::MAX_MESSAGE;
+
+pub const REQ_CREATE_WINDOW: u32 = 1;
+pub const REQ_DAMAGE: u32 = 2;
+pub const REQ_DESTROY_WINDOW: u32 = 3;
+pub const REQ_MOVE_WINDOW: u32 = 4;
+
+pub const EV_CREATED: u32 = 0x101;
+pub const EV_KEY: u32 = 0x102;
+pub const EV_FOCUS: u32 = 0x103;
+pub const EV_ERROR: u32 = 0x1ff;
+
+pub const MAX_TITLE: usize = 32;
+pub const MAX_KEY_BYTES: usize = 16;
+pub const MAX_WINDOW_SIZE: u32 = 2048;
+
+// # Safety
+// Only for repr(C) structs of integers and byte arrays without padding, so
+// that any byte pattern of the right size is a valid value.
+#[allow(clippy::missing_safety_doc)]
+pub unsafe trait Message: Copy + Default {
+    const OP: u32;
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CreateWindow {
+    pub op: u32,
+    pub width: u32,
+    pub height: u32,
+    pub title_len: u32,
+    pub shm_key: u64,
+    pub title: [u8; MAX_TITLE],
+}
+
+// The client drew into `window`; the rectangle is in window coordinates.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Damage {
+    pub op: u32,
+    pub window: u32,
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DestroyWindow {
+    pub op: u32,
+    pub window: u32,
+}
+
+// Move the window's content area to (x, y) on screen.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MoveWindow {
+    pub op: u32,
+    pub window: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+// Reply to `CreateWindow`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Created {
+    pub op: u32,
+    pub window: u32,
+}
+
+// Keyboard bytes for the focused window, as the TTY would receive them.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Key {
+    pub op: u32,
+    pub window: u32,
+    pub len: u32,
+    pub bytes: [u8; MAX_KEY_BYTES],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Focus {
+    pub op: u32,
+    pub window: u32,
+    pub focused: u32,
+}
+
+// A request failed; `errno` as a positive Linux value.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Error {
+    pub op: u32,
+    pub request: u32,
+    pub errno: u32,
+}
+
+macro_rules! message {
+    ($($ty:ty => $op:expr),* $(,)?) => {
+        $(unsafe impl Message for $ty {
+            const OP: u32 = $op;
+        })*
+    };
+}
+
+message! {
+    CreateWindow => REQ_CREATE_WINDOW,
+    Damage => REQ_DAMAGE,
+    DestroyWindow => REQ_DESTROY_WINDOW,
+    MoveWindow => REQ_MOVE_WINDOW,
+    Created => EV_CREATED,
+    Key => EV_KEY,
+    Focus => EV_FOCUS,
+    Error => EV_ERROR,
+}
+
+const _: () = assert!(size_of::<CreateWindow>() <= MAX_MESSAGE);
+
+pub fn opcode(bytes: &[u8]) -> Option<u32> {
+    let op = bytes.get(..4)?;
+    Some(u32::from_ne_bytes([op[0], op[1], op[2], op[3]]))
+}
+
+pub fn encode<T: Message>(msg: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(msg as *const T as *const u8, size_of::<T>()) }
+}
+
+pub fn decode<T: Message>(bytes: &[u8]) -> Option<T> {
+    // None unless `bytes` is a whole `T` with the right opcode.
+    if bytes.len() != size_of::<T>() || opcode(bytes)? != T::OP {
+        return None;
+    }
+    Some(unsafe { (bytes.as_ptr() as *const T).read_unaligned() })
+}
@@ -0,0 +1,471 @@
+// Display server: owns /dev/fb0, composites client windows bottom to top
+// with a title bar and border each, and sends keyboard input to the window
+// on top, which has the focus. Clients connect through /dev/display; the
+// server sees all of them on /dev/displayd (docs/display.md).
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::proto::{
+    self, CreateWindow, Created, Damage, DestroyWindow, Error, Focus, Key, Message, MoveWindow,
+    MAX_KEY_BYTES, MAX_TITLE, MAX_WINDOW_SIZE, REQ_CREATE_WINDOW, REQ_DAMAGE, REQ_DESTROY_WINDOW,
+    REQ_MOVE_WINDOW,
+};
+use crate::gfx::font::Font;
+use crate::gfx::pixel::{self, Format};
+use crate::gfx::surface::{Rect, Surface};
+use crate::kernel::user::{
+    self, ChanHeader, FbInfo, FbMode, PollFd, CHAN_DATA, CHAN_HANGUP, CHAN_HEADER_SIZE, EINVAL,
+    FBIOGET_INFO, KDSETMODE, KD_GRAPHICS, MAP_SHARED, MAX_MESSAGE, O_NONBLOCK, O_READ, O_WRITE,
+    POLLIN, PROT_READ, PROT_WRITE,
+};
+use crate::kernel::vfs;
+
+const DESKTOP: u32 = pixel::rgb(0x2b, 0x4a, 0x6f);
+const FRAME_FOCUSED: u32 = pixel::rgb(0x3c, 0x78, 0xc8);
+const FRAME_UNFOCUSED: u32 = pixel::rgb(0x60, 0x60, 0x60);
+const TITLE_TEXT: u32 = pixel::rgb(0xff, 0xff, 0xff);
+const SHADOW: u32 = pixel::argb(0x60, 0, 0, 0);
+const SHADOW_OFFSET: i32 = 4;
+const BORDER: u32 = 1;
+const TITLE_HEIGHT: u32 = 16;
+// New windows cascade from the top left corner.
+const CASCADE_STEP: i32 = 32;
+const CASCADE_SLOTS: u32 = 8;
+// Alt+Tab as xterm sends it: raise the bottom window and focus it.
+const CYCLE_FOCUS: &[u8] = b"\x1b\t";
+
+struct Window {
+    id: u32,
+    conn: u32,
+    // Screen position of the content, inside the decorations.
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    title: String,
+    pixels: *mut u8,
+}
+
+impl Window {
+    fn content(&self) -> Surface<'_> {
+        unsafe {
+            Surface::from_raw(
+                self.pixels,
+                self.width,
+                self.height,
+                self.width * 4,
+                Format::X8R8G8B8,
+            )
+            .expect("window buffer matches its size")
+        }
+    }
+
+    fn frame(&self) -> Rect {
+        // Content plus border and title bar.
+        Rect::new(
+            self.x - BORDER as i32,
+            self.y - (TITLE_HEIGHT + BORDER) as i32,
+            self.width + 2 * BORDER,
+            self.height + TITLE_HEIGHT + 2 * BORDER,
+        )
+    }
+}
+
+// The framebuffer, double-buffered when the firmware allows it.
+struct Screen {
+    fd: u64,
+    // Our own console file, in KD_GRAPHICS while it stays open.
+    _console: u64,
+    info: FbInfo,
+    base: *mut u8,
+    // Buffer the next frame is drawn in.
+    back: u32,
+}
+
+impl Screen {
+    fn open() -> Option<Self> {
+        let fd = user::open("/dev/fb0", O_READ | O_WRITE);
+        if user::is_error(fd) {
+            return None;
+        }
+        // Consoles stop drawing and reading the keyboard; flipping needs it.
+        // The mode is tied to this file rather than to our inherited stdout,
+        // which the shell shares and never closes.
+        let console = user::open("/dev/console", O_WRITE);
+        if user::is_error(console) {
+            return None;
+        }
+        let _ = user::ioctl(console, KDSETMODE, KD_GRAPHICS);
+        let mut info = Self::info(fd)?;
+        if info.buffers < 2 {
+            let mode = FbMode {
+                width: info.width,
+                height: info.height,
+                depth: 32,
+                buffers: 2,
+            };
+            // On failure the old mode is back, single-buffered.
+            let _ = user::fb_set_mode(fd, &mode);
+            info = Self::info(fd)?;
+        }
+        info.pixel_format()?;
+        let addr = user::mmap(
+            info.size as usize,
+            PROT_READ | PROT_WRITE,
+            MAP_SHARED,
+            fd,
+            0,
+        );
+        if user::is_error(addr) {
+            return None;
+        }
+        let back = if info.buffers > 1 { 1 - info.front } else { 0 };
+        Some(Self {
+            fd,
+            _console: console,
+            info,
+            base: addr as *mut u8,
+            back,
+        })
+    }
+
+    fn info(fd: u64) -> Option<FbInfo> {
+        let mut info = FbInfo::default();
+        let ret = user::ioctl(fd, FBIOGET_INFO, &mut info as *mut FbInfo as u64);
+        (!user::is_error(ret)).then_some(info)
+    }
+
+    fn back_buffer(&mut self) -> Surface<'_> {
+        let info = &self.info;
+        let offset = (self.back * info.height * info.pitch) as usize;
+        let format = info.pixel_format().expect("checked at open");
+        unsafe {
+            Surface::from_raw(
+                self.base.add(offset),
+                info.width,
+                info.height,
+                info.pitch,
+                format,
+            )
+            .expect("framebuffer geometry is valid")
+        }
+    }
+
+    fn present(&mut self) {
+        // Show the buffer just drawn at the next vertical blank.
+        if self.info.buffers > 1 {
+            let _ = user::fb_flip(self.fd, self.back, true);
+            self.back = 1 - self.back;
+        }
+    }
+}
+
+struct Server {
+    listener: u64,
+    kbd: u64,
+    screen: Screen,
+    font: Font,
+    // Bottom to top; the top window has the focus.
+    windows: Vec<Window>,
+    next_id: u32,
+    dirty: bool,
+}
+
+impl Server {
+    fn open() -> Result<Self, &'static str> {
+        let listener = user::open("/dev/displayd", O_READ | O_WRITE | O_NONBLOCK);
+        if user::is_error(listener) {
+            return Err("display: cannot listen on /dev/displayd\n");
+        }
+        let kbd = user::open("/dev/kbd0", O_READ | O_NONBLOCK);
+        if user::is_error(kbd) {
+            return Err("display: cannot open /dev/kbd0\n");
+        }
+        let screen = Screen::open().ok_or("display: cannot map /dev/fb0\n")?;
+        Ok(Self {
+            listener,
+            kbd,
+            screen,
+            font: Font::builtin(),
+            windows: Vec::new(),
+            next_id: 1,
+            dirty: true,
+        })
+    }
+
+    fn focused(&self) -> Option<(u32, u32)> {
+        self.windows.last().map(|w| (w.id, w.conn))
+    }
+
+    fn send<T: Message>(&self, conn: u32, msg: &T) {
+        // Events to a client whose queue is full are dropped.
+        let body = proto::encode(msg);
+        let mut buf = [0u8; CHAN_HEADER_SIZE + MAX_MESSAGE];
+        let header = ChanHeader {
+            conn,
+            kind: CHAN_DATA,
+            len: body.len() as u32,
+        };
+        unsafe { (buf.as_mut_ptr() as *mut ChanHeader).write_unaligned(header) };
+        buf[CHAN_HEADER_SIZE..CHAN_HEADER_SIZE + body.len()].copy_from_slice(body);
+        let _ = user::write_bytes(self.listener, &buf[..CHAN_HEADER_SIZE + body.len()]);
+    }
+
+    fn error(&self, conn: u32, request: u32, errno: u64) {
+        let msg = Error {
+            op: Error::OP,
+            request,
+            errno: errno as u32,
+        };
+        self.send(conn, &msg);
+    }
+
+    fn refocus(&mut self, before: Option<(u32, u32)>) {
+        // Tell the old and new top window if the focus moved.
+        let after = self.focused();
+        if before == after {
+            return;
+        }
+        for (target, focused) in [(before, 0), (after, 1)] {
+            if let Some((window, conn)) = target {
+                let msg = Focus {
+                    op: Focus::OP,
+                    window,
+                    focused,
+                };
+                self.send(conn, &msg);
+            }
+        }
+        self.dirty = true;
+    }
+
+    fn window_mut(&mut self, conn: u32, id: u32) -> Option<&mut Window> {
+        // Clients can only reach their own windows.
+        self.windows
+            .iter_mut()
+            .find(|w| w.id == id && w.conn == conn)
+    }
+
+    fn create_window(&mut self, conn: u32, req: CreateWindow) {
+        let size_ok = (1..=MAX_WINDOW_SIZE).contains(&req.width)
+            && (1..=MAX_WINDOW_SIZE).contains(&req.height);
+        if !size_ok || req.title_len as usize > MAX_TITLE {
+            return self.error(conn, REQ_CREATE_WINDOW, EINVAL);
+        }
+        // The kernel checks that the segment is this connection's.
+        let Some(offset) = user::listener_offset(conn, req.shm_key) else {
+            return self.error(conn, REQ_CREATE_WINDOW, EINVAL);
+        };
+        let len = req.width as usize * req.height as usize * 4;
+        let prot = PROT_READ | PROT_WRITE;
+        let addr = user::mmap(len, prot, MAP_SHARED, self.listener, offset);
+        if user::is_error(addr) {
+            return self.error(conn, REQ_CREATE_WINDOW, addr.wrapping_neg());
+        }
+        let title = &req.title[..req.title_len as usize];
+        let slot = (self.next_id % CASCADE_SLOTS) as i32;
+        let before = self.focused();
+        let window = Window {
+            id: self.next_id,
+            conn,
+            x: CASCADE_STEP * (slot + 1),
+            y: CASCADE_STEP * (slot + 1) + TITLE_HEIGHT as i32,
+            width: req.width,
+            height: req.height,
+            title: String::from_utf8_lossy(title).into_owned(),
+            pixels: addr as *mut u8,
+        };
+        self.next_id += 1;
+        let created = Created {
+            op: Created::OP,
+            window: window.id,
+        };
+        self.windows.push(window);
+        self.send(conn, &created);
+        self.refocus(before);
+    }
+
+    fn request(&mut self, conn: u32, bytes: &[u8]) {
+        let op = proto::opcode(bytes).unwrap_or(0);
+        if let Some(req) = proto::decode::<CreateWindow>(bytes) {
+            self.create_window(conn, req);
+        } else if let Some(req) = proto::decode::<Damage>(bytes) {
+            // Any damage recomposites the whole screen.
+            match self.window_mut(conn, req.window) {
+                Some(_) => self.dirty = true,
+                None => self.error(conn, REQ_DAMAGE, EINVAL),
+            }
+        } else if let Some(req) = proto::decode::<MoveWindow>(bytes) {
+            match self.window_mut(conn, req.window) {
+                Some(window) => {
+                    window.x = req.x;
+                    window.y = req.y;
+                    self.dirty = true;
+                }
+                None => self.error(conn, REQ_MOVE_WINDOW, EINVAL),
+            }
+        } else if let Some(req) = proto::decode::<DestroyWindow>(bytes) {
+            let before = self.focused();
+            let count = self.windows.len();
+            self.windows
+                .retain(|w| w.id != req.window || w.conn != conn);
+            if self.windows.len() == count {
+                return self.error(conn, REQ_DESTROY_WINDOW, EINVAL);
+            }
+            self.dirty = true;
+            self.refocus(before);
+        } else {
+            self.error(conn, op, EINVAL);
+        }
+    }
+
+    fn hangup(&mut self, conn: u32) {
+        // Forget the client's windows, then let the kernel free its shared
+        // memory by closing our end.
+        let before = self.focused();
+        self.windows.retain(|w| w.conn != conn);
+        self.dirty = true;
+        self.refocus(before);
+        let header = ChanHeader {
+            conn,
+            kind: CHAN_HANGUP,
+            len: 0,
+        };
+        let bytes = unsafe {
+            core::slice::from_raw_parts(&header as *const ChanHeader as *const u8, CHAN_HEADER_SIZE)
+        };
+        let _ = user::write_bytes(self.listener, bytes);
+    }
+
+    fn keys(&mut self, mut bytes: &[u8]) {
+        // Alt+Tab is ours; everything else goes to the focused window.
+        while !bytes.is_empty() {
+            let hotkey = bytes
+                .windows(CYCLE_FOCUS.len())
+                .position(|w| w == CYCLE_FOCUS);
+            let end = hotkey.unwrap_or(bytes.len());
+            self.forward(&bytes[..end]);
+            if hotkey.is_none() {
+                break;
+            }
+            self.cycle_focus();
+            bytes = &bytes[end + CYCLE_FOCUS.len()..];
+        }
+    }
+
+    fn forward(&self, bytes: &[u8]) {
+        let Some((window, conn)) = self.focused() else {
+            return;
+        };
+        for chunk in bytes.chunks(MAX_KEY_BYTES) {
+            let mut msg = Key {
+                op: Key::OP,
+                window,
+                len: chunk.len() as u32,
+                ..Key::default()
+            };
+            msg.bytes[..chunk.len()].copy_from_slice(chunk);
+            self.send(conn, &msg);
+        }
+    }
+
+    fn cycle_focus(&mut self) {
+        if self.windows.len() < 2 {
+            return;
+        }
+        let before = self.focused();
+        let bottom = self.windows.remove(0);
+        self.windows.push(bottom);
+        self.refocus(before);
+    }
+
+    fn dispatch(&mut self) {
+        // Drain the listener and the keyboard; both are non-blocking.
+        let mut buf = [0u8; CHAN_HEADER_SIZE + MAX_MESSAGE];
+        loop {
+            let n = user::read(self.listener, &mut buf);
+            if user::is_error(n) || (n as usize) < CHAN_HEADER_SIZE {
+                break;
+            }
+            let header = unsafe { (buf.as_ptr() as *const ChanHeader).read_unaligned() };
+            let payload = &buf[CHAN_HEADER_SIZE..n as usize];
+            match header.kind {
+                CHAN_DATA => self.request(header.conn, payload),
+                CHAN_HANGUP => self.hangup(header.conn),
+                // CHAN_CONNECT: nothing to do until the client asks for a
+                // window.
+                _ => {}
+            }
+        }
+        let mut keys = [0u8; 64];
+        let n = user::read(self.kbd, &mut keys);
+        if !user::is_error(n) && n > 0 {
+            self.keys(&keys[..n as usize]);
+        }
+    }
+
+    fn composite(&mut self) {
+        let font = &self.font;
+        let top = self.windows.len().saturating_sub(1);
+        let mut screen = self.screen.back_buffer();
+        screen.clear(DESKTOP);
+        for (idx, window) in self.windows.iter().enumerate() {
+            let frame = window.frame();
+            let color = if idx == top {
+                FRAME_FOCUSED
+            } else {
+                FRAME_UNFOCUSED
+            };
+            let shadow = Rect::new(
+                frame.x + SHADOW_OFFSET,
+                frame.y + SHADOW_OFFSET,
+                frame.w,
+                frame.h,
+            );
+            screen.blend_rect(shadow, SHADOW);
+            screen.fill_rect(frame, color);
+            let fits = (window.width as usize / font.width).saturating_sub(1);
+            let title: String = window.title.chars().take(fits).collect();
+            let pad = (TITLE_HEIGHT as i32 - font.height as i32) / 2;
+            let title_y = window.y - TITLE_HEIGHT as i32 + pad;
+            screen.text(font, window.x + pad, title_y, &title, TITLE_TEXT);
+            let content = window.content();
+            screen.blit(&content, content.bounds(), window.x, window.y);
+        }
+        self.screen.present();
+        self.dirty = false;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn display_server() -> ! {
+    let mut server = match Server::open() {
+        Ok(server) => server,
+        Err(msg) => {
+            let _ = user::write(vfs::FD_STDOUT as u64, msg);
+            loop {
+                let _ = user::sleep_ms(60_000);
+            }
+        }
+    };
+    loop {
+        if server.dirty {
+            server.composite();
+        }
+        let mut fds = [
+            PollFd {
+                fd: server.listener as i32,
+                events: POLLIN,
+                revents: 0,
+            },
+            PollFd {
+                fd: server.kbd as i32,
+                events: POLLIN,
+                revents: 0,
+            },
+        ];
+        let _ = user::poll(&mut fds, -1);
+        server.dispatch();
+    }
+}
@@ -1,56 +1,409 @@
-use crate::kernel::user;
+use crate::kernel::user::{
+    self, Stat, Termios, EAGAIN, F_DUPFD_CLOEXEC, O_CREAT, O_NONBLOCK, O_READ, O_TRUNC, O_WRITE,
+    S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, TCSAFLUSH, TCSANOW,
+};
 use crate::kernel::vfs;
 use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+// Lowest FD for the shell's own copies of stdin and stdout.
+const SAVED_FD_BASE: u64 = 10;
 
 #[no_mangle]
 pub extern "C" fn user_shell() -> ! {
-    // Simple userland shell: prompt, read line, echo with String.
+    // Simple userland shell: prompt, read line, echo with String. Line
+    // editing and echo come from the TTY in canonical mode.
     let stdout = vfs::FD_STDOUT as u64;
     let stdin = vfs::FD_STDIN as u64;
-    let mut line = String::new();
     loop {
         let _ = user::write(stdout, "$ ");
-        let mut saw_cr = false;
-        loop {
-            let mut byte = [0u8; 1];
-            let read = user::read(stdin, &mut byte);
-            if read == 0 || read == u64::MAX {
-                let _ = user::sleep_ms(10);
-                continue;
-            }
-            let mut b = byte[0];
-            if b == b'\r' {
-                saw_cr = true;
-                b = b'\n';
-            } else if b == b'\n' {
-                if saw_cr {
-                    saw_cr = false;
-                    continue;
-                }
-            } else {
-                saw_cr = false;
-            }
-            if b == b'\n' || b == b'\r' {
-                let _ = user::write(stdout, "\n");
-                let _ = user::write(stdout, "String: ");
-                let _ = user::write(stdout, line.as_str());
-                let _ = user::write(stdout, "\n");
-                line.clear();
-                break;
-            }
-            if b == 0x08 || b == 0x7f {
-                if !line.is_empty() {
-                    line.pop();
-                    let _ = user::write(stdout, "\u{8} \u{8}");
-                }
-                continue;
-            }
-            if line.len() < 128 {
-                line.push(b as char);
-                let mut echo = [0u8; 1];
-                echo[0] = b;
-                let _ = user::write_bytes(stdout, &echo);
+        let mut buf = [0u8; 256];
+        let read = user::read(stdin, &mut buf);
+        if read == user::EINTR.wrapping_neg() {
+            // Ctrl-C: the TTY already echoed ^C; start a fresh prompt.
+            let _ = user::write(stdout, "\n");
+            continue;
+        }
+        if user::is_error(read) {
+            // Not a blocking TTY: sleep until input arrives rather than spinning.
+            let mut fds = [user::PollFd {
+                fd: stdin as i32,
+                events: user::POLLIN,
+                revents: 0,
+            }];
+            let _ = user::poll(&mut fds, -1);
+            continue;
+        }
+        if read == 0 {
+            // Ctrl-D on an empty line.
+            let _ = user::write(stdout, "\n");
+            continue;
+        }
+        let input = &buf[..read as usize];
+        let input = input.strip_suffix(b"\n").unwrap_or(input);
+        let input = core::str::from_utf8(input).unwrap_or("<invalid utf-8>");
+        run_line(input);
+    }
+}
+
+fn run_line(input: &str) {
+    // `a | b | c > file`. There is no fork, so the shell runs the commands
+    // itself one after another, moving each one's stdin and stdout into
+    // place with dup2. A command's output therefore has to fit in the pipe
+    // (4 KiB) before the next one reads it; the rest is dropped.
+    let stdout = vfs::FD_STDOUT as u64;
+    let (pipeline, target) = match input.split_once('>') {
+        Some((pipeline, target)) => (pipeline, Some(target.trim())),
+        None => (input, None),
+    };
+    let commands: Vec<&str> = pipeline.split('|').map(str::trim).collect();
+    if commands.len() == 1 && target.is_none() {
+        run_command(stdout, input);
+        return;
+    }
+    let bad_target = target.is_some_and(|path| path.is_empty() || path.contains([' ', '>']));
+    if bad_target || commands.iter().any(|command| command.is_empty()) {
+        let _ = user::write(stdout, "sh: syntax error\n");
+        return;
+    }
+    // Keep the terminal's stdin and stdout to put back afterwards, above
+    // the low FDs and closed on exec so a program never inherits them.
+    let stdin = vfs::FD_STDIN as u64;
+    let saved_in = user::fcntl(stdin, F_DUPFD_CLOEXEC, SAVED_FD_BASE);
+    let saved_out = user::fcntl(stdout, F_DUPFD_CLOEXEC, SAVED_FD_BASE);
+    let ret = if user::is_error(saved_in) {
+        saved_in
+    } else if user::is_error(saved_out) {
+        saved_out
+    } else {
+        run_pipeline(&commands, target, saved_out)
+    };
+    for (fd, saved) in [(stdin, saved_in), (stdout, saved_out)] {
+        if !user::is_error(saved) {
+            let _ = user::dup2(saved, fd);
+            user::close(saved);
+        }
+    }
+    if user::is_error(ret) {
+        let mut msg = String::new();
+        let _ = writeln!(msg, "sh: error {}", ret.wrapping_neg());
+        let _ = user::write(stdout, &msg);
+    }
+}
+
+fn run_pipeline(commands: &[&str], target: Option<&str>, terminal: u64) -> u64 {
+    let stdin = vfs::FD_STDIN as u64;
+    let stdout = vfs::FD_STDOUT as u64;
+    let (last, first) = commands.split_last().expect("a pipeline has a command");
+    for command in first {
+        // Non-blocking: nothing reads the pipe while the command runs, so
+        // filling it must not put the shell to sleep.
+        let mut fds = [0i32; 2];
+        let ret = user::pipe2(&mut fds, O_NONBLOCK);
+        if user::is_error(ret) {
+            return ret;
+        }
+        let (read_end, write_end) = (fds[0] as u64, fds[1] as u64);
+        let ret = user::dup2(write_end, stdout);
+        user::close(write_end);
+        if user::is_error(ret) {
+            user::close(read_end);
+            return ret;
+        }
+        run_command(stdout, command);
+        let ret = user::dup2(read_end, stdin);
+        user::close(read_end);
+        if user::is_error(ret) {
+            return ret;
+        }
+    }
+    // Replacing stdout closes the last pipe's write end, so the last
+    // command sees end of file once it has read everything.
+    let out = match target {
+        Some(path) => user::open_mode(path, O_WRITE | O_CREAT | O_TRUNC, 0o644),
+        None => terminal,
+    };
+    if user::is_error(out) {
+        return out;
+    }
+    let ret = user::dup2(out, stdout);
+    if out != terminal {
+        user::close(out);
+    }
+    if user::is_error(ret) {
+        return ret;
+    }
+    run_command(stdout, last);
+    0
+}
+
+fn run_command(stdout: u64, input: &str) {
+    // A builtin, or else the line echoed back.
+    if builtin(stdout, input) {
+        return;
+    }
+    let mut line = String::new();
+    line.push_str("String: ");
+    line.push_str(input);
+    line.push('\n');
+    let _ = user::write(stdout, &line);
+}
+
+fn builtin(stdout: u64, input: &str) -> bool {
+    // Run `input` if it is a builtin with the right arguments; failures
+    // print the errno.
+    let mut words = input.split_ascii_whitespace();
+    let cmd = words.next().unwrap_or("");
+    let args: Vec<&str> = words.collect();
+    let ret = match (cmd, args.as_slice()) {
+        ("dmesg", []) => {
+            dmesg(stdout);
+            0
+        }
+        ("pwd", []) => pwd(stdout),
+        ("cd", []) => user::chdir("/"),
+        ("cd", [path]) => user::chdir(path),
+        ("ls", []) => ls(stdout, "."),
+        ("ls", [path]) => ls(stdout, path),
+        ("cat", []) => copy(vfs::FD_STDIN as u64, stdout),
+        ("cat", [path]) => cat(stdout, path),
+        ("mkdir", [path]) => user::mkdir(path, 0o755),
+        ("rmdir", [path]) => user::rmdir(path),
+        ("rm", [path]) => user::unlink(path),
+        ("mv", [old, new]) => user::rename(old, new),
+        ("ln", ["-s", target, link]) => user::symlink(target, link),
+        ("keys", []) => keys(stdout),
+        ("pty", []) => pty(stdout),
+        _ => return false,
+    };
+    if user::is_error(ret) {
+        let mut msg = String::new();
+        let _ = writeln!(msg, "{}: error {}", cmd, ret.wrapping_neg());
+        let _ = user::write(stdout, &msg);
+    }
+    true
+}
+
+fn pwd(stdout: u64) -> u64 {
+    let mut buf = [0u8; vfs::PATH_MAX + 1];
+    let len = user::getcwd(&mut buf);
+    if user::is_error(len) {
+        return len;
+    }
+    // The length counts the NUL; print a newline in its place.
+    let len = len as usize;
+    buf[len - 1] = b'\n';
+    user::write_bytes(stdout, &buf[..len])
+}
+
+fn ls(stdout: u64, path: &str) -> u64 {
+    // List a directory, or show a single entry for anything else. Names
+    // starting with a dot are skipped.
+    let mut st = Stat::default();
+    let ret = user::stat(path, &mut st);
+    if user::is_error(ret) {
+        return ret;
+    }
+    if st.mode & S_IFMT != S_IFDIR {
+        return ls_entry(stdout, path, path);
+    }
+    let fd = user::open(path, O_READ);
+    if user::is_error(fd) {
+        return fd;
+    }
+    let mut buf = vec![0u8; 4096];
+    let ret = loop {
+        let len = user::getdents64(fd, &mut buf);
+        if user::is_error(len) || len == 0 {
+            break len;
+        }
+        let mut pos = 0;
+        while pos < len as usize {
+            // linux_dirent64: ino, off, reclen, type, then the name.
+            let rec = &buf[pos..];
+            let reclen = u16::from_ne_bytes([rec[16], rec[17]]) as usize;
+            let name = &rec[19..reclen];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            pos += reclen;
+            let name = match core::str::from_utf8(name) {
+                Ok(name) if !name.starts_with('.') => name,
+                _ => continue,
+            };
+            let mut full = String::from(path);
+            if !full.ends_with('/') {
+                full.push('/');
             }
+            full.push_str(name);
+            let _ = ls_entry(stdout, &full, name);
+        }
+    };
+    user::close(fd);
+    ret
+}
+
+fn ls_entry(stdout: u64, path: &str, name: &str) -> u64 {
+    // Type, size and name, plus the target of a symlink.
+    let mut st = Stat::default();
+    let ret = user::lstat(path, &mut st);
+    if user::is_error(ret) {
+        return ret;
+    }
+    let kind = match st.mode & S_IFMT {
+        S_IFDIR => 'd',
+        S_IFLNK => 'l',
+        S_IFCHR => 'c',
+        S_IFBLK => 'b',
+        S_IFIFO => 'p',
+        _ => '-',
+    };
+    let mut line = String::new();
+    let _ = write!(line, "{} {:>8} {}", kind, st.size, name);
+    if kind == 'l' {
+        let mut target = [0u8; vfs::PATH_MAX];
+        let len = user::readlink(path, &mut target);
+        if !user::is_error(len) {
+            let target = core::str::from_utf8(&target[..len as usize]).unwrap_or("?");
+            let _ = write!(line, " -> {}", target);
+        }
+    }
+    line.push('\n');
+    user::write(stdout, &line)
+}
+
+fn cat(stdout: u64, path: &str) -> u64 {
+    let fd = user::open(path, O_READ);
+    if user::is_error(fd) {
+        return fd;
+    }
+    let ret = copy(fd, stdout);
+    user::close(fd);
+    ret
+}
+
+fn copy(fd: u64, stdout: u64) -> u64 {
+    // Copy `fd` to stdout until end of file.
+    let mut buf = [0u8; 512];
+    loop {
+        let len = user::read(fd, &mut buf);
+        if user::is_error(len) || len == 0 {
+            break len;
         }
+        let _ = user::write_bytes(stdout, &buf[..len as usize]);
+    }
+}
+
+fn keys(stdout: u64) -> u64 {
+    // Show the bytes each key sends, with stdin in raw mode until 'q'.
+    let stdin = vfs::FD_STDIN as u64;
+    let mut saved = Termios::default();
+    let ret = user::tcgetattr(stdin, &mut saved);
+    if user::is_error(ret) {
+        return ret;
+    }
+    let mut raw = saved;
+    user::cfmakeraw(&mut raw);
+    let ret = user::tcsetattr(stdin, TCSAFLUSH, &raw);
+    if user::is_error(ret) {
+        return ret;
+    }
+    // Raw mode also turns off output processing, hence the explicit CRs.
+    let _ = user::write(stdout, "press keys, q quits\r\n");
+    let mut buf = [0u8; 16];
+    let ret = loop {
+        let len = user::read(stdin, &mut buf);
+        if user::is_error(len) || len == 0 {
+            break len;
+        }
+        let input = &buf[..len as usize];
+        let mut line = String::new();
+        for b in input {
+            let _ = write!(line, "{:02x} ", b);
+        }
+        line.push_str("\r\n");
+        let _ = user::write(stdout, &line);
+        if input.contains(&b'q') {
+            break 0;
+        }
+    };
+    let _ = user::tcsetattr(stdin, TCSANOW, &saved);
+    ret
+}
+
+fn pty(stdout: u64) -> u64 {
+    // Send a line through a new pseudo-terminal, then a byte in raw mode.
+    // Both ends are non-blocking, so a missing reply fails with EAGAIN.
+    let master = user::posix_openpt(O_READ | O_WRITE | O_NONBLOCK);
+    if user::is_error(master) {
+        return master;
+    }
+    let mut name = String::new();
+    let mut ret = user::grantpt(master);
+    if !user::is_error(ret) {
+        ret = user::unlockpt(master);
+    }
+    if !user::is_error(ret) {
+        ret = user::ptsname(master, &mut name);
+    }
+    if user::is_error(ret) {
+        user::close(master);
+        return ret;
+    }
+    let slave = user::open(&name, O_READ | O_WRITE | O_NONBLOCK);
+    if user::is_error(slave) {
+        user::close(master);
+        return slave;
+    }
+    let ret = pty_exchange(stdout, &name, master, slave);
+    user::close(slave);
+    user::close(master);
+    ret
+}
+
+fn pty_exchange(stdout: u64, name: &str, master: u64, slave: u64) -> u64 {
+    let mut out = String::new();
+    let mut buf = [0u8; 64];
+    // Canonical mode: the slave reads the whole line, the master gets the echo.
+    let _ = user::write(master, "ping\n");
+    for (side, fd) in [("slave", slave), ("master", master)] {
+        let len = user::read(fd, &mut buf);
+        if user::is_error(len) {
+            return len;
+        }
+        let text = core::str::from_utf8(&buf[..len as usize]).unwrap_or("?");
+        let _ = writeln!(out, "{} {}: {:?}", name, side, text);
+    }
+    // Raw mode: a single byte is readable at once and is not echoed.
+    let mut termios = Termios::default();
+    let ret = user::tcgetattr(slave, &mut termios);
+    if user::is_error(ret) {
+        return ret;
+    }
+    user::cfmakeraw(&mut termios);
+    let ret = user::tcsetattr(slave, TCSANOW, &termios);
+    if user::is_error(ret) {
+        return ret;
+    }
+    let _ = user::write(master, "x");
+    let len = user::read(slave, &mut buf);
+    if user::is_error(len) {
+        return len;
+    }
+    let text = core::str::from_utf8(&buf[..len as usize]).unwrap_or("?");
+    let _ = writeln!(out, "{} slave, raw: {:?}", name, text);
+    let len = user::read(master, &mut buf);
+    if len != EAGAIN.wrapping_neg() {
+        let _ = writeln!(out, "{} master, raw: unexpected echo", name);
+    }
+    user::write(stdout, &out)
+}
+
+fn dmesg(stdout: u64) {
+    // Print the kernel log, as many of the newest lines as fit.
+    let mut buf = vec![0u8; 64 * 1024];
+    let len = user::syslog(user::SYSLOG_ACTION_READ_ALL, &mut buf);
+    if !user::is_error(len) {
+        let _ = user::write_bytes(stdout, &buf[..len as usize]);
     }
 }